@@ -0,0 +1,67 @@
+use criterion::Criterion;
+use prost::Message;
+
+#[derive(Clone, PartialEq, Message)]
+struct Uncached {
+    #[prost(message, optional, boxed, tag = "1")]
+    child: Option<Box<Uncached>>,
+    #[prost(string, tag = "2")]
+    name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Cached {
+    #[prost(message, optional, boxed, tag = "1")]
+    child: Option<Box<Cached>>,
+    #[prost(string, tag = "2")]
+    name: String,
+    #[prost(cached_size)]
+    cached_size: prost::CachedSize,
+}
+
+const DEPTH: usize = 64;
+
+fn benchmark_nested(criterion: &mut Criterion) {
+    let mut uncached = Uncached::default();
+    let mut cached = Cached::default();
+    for i in 0..DEPTH {
+        uncached = Uncached {
+            child: Some(Box::new(uncached)),
+            name: i.to_string(),
+        };
+        cached = Cached {
+            child: Some(Box::new(cached)),
+            name: i.to_string(),
+            ..Cached::default()
+        };
+    }
+    assert_eq!(uncached.encode_to_vec(), cached.encode_to_vec());
+
+    let mut group = criterion.benchmark_group("cached_size/encode_nested");
+    group.bench_function("uncached", |b| {
+        let mut buf = Vec::with_capacity(uncached.encoded_len());
+        b.iter(|| {
+            buf.clear();
+            uncached.encode(&mut buf).unwrap();
+            criterion::black_box(&buf);
+        })
+    });
+    group.bench_function("cached", |b| {
+        let mut buf = Vec::with_capacity(cached.encoded_len());
+        b.iter(|| {
+            buf.clear();
+            cached.encode(&mut buf).unwrap();
+            criterion::black_box(&buf);
+        })
+    });
+    group.finish();
+}
+
+fn main() {
+    let mut criterion = Criterion::default().configure_from_args();
+
+    // Benchmark encoding a message nested 64 deep, with and without cached lengths.
+    benchmark_nested(&mut criterion);
+
+    criterion.final_summary();
+}
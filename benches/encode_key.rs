@@ -0,0 +1,137 @@
+use criterion::Criterion;
+use prost::Message;
+
+/// Fifteen scalar fields, whose tags have single byte keys.
+#[derive(Clone, PartialEq, Message)]
+struct LowTags {
+    #[prost(uint32, tag = "1")]
+    a: u32,
+    #[prost(uint32, tag = "2")]
+    b: u32,
+    #[prost(uint32, tag = "3")]
+    c: u32,
+    #[prost(uint32, tag = "4")]
+    d: u32,
+    #[prost(uint32, tag = "5")]
+    e: u32,
+    #[prost(bool, tag = "6")]
+    f: bool,
+    #[prost(bool, tag = "7")]
+    g: bool,
+    #[prost(bool, tag = "8")]
+    h: bool,
+    #[prost(fixed32, tag = "9")]
+    i: u32,
+    #[prost(fixed32, tag = "10")]
+    j: u32,
+    #[prost(fixed64, tag = "11")]
+    k: u64,
+    #[prost(fixed64, tag = "12")]
+    l: u64,
+    #[prost(sint64, tag = "13")]
+    m: i64,
+    #[prost(sint64, tag = "14")]
+    n: i64,
+    #[prost(int32, tag = "15")]
+    o: i32,
+}
+
+/// The same fields, with tags which have two byte keys.
+#[derive(Clone, PartialEq, Message)]
+struct HighTags {
+    #[prost(uint32, tag = "16")]
+    a: u32,
+    #[prost(uint32, tag = "17")]
+    b: u32,
+    #[prost(uint32, tag = "18")]
+    c: u32,
+    #[prost(uint32, tag = "19")]
+    d: u32,
+    #[prost(uint32, tag = "20")]
+    e: u32,
+    #[prost(bool, tag = "21")]
+    f: bool,
+    #[prost(bool, tag = "22")]
+    g: bool,
+    #[prost(bool, tag = "23")]
+    h: bool,
+    #[prost(fixed32, tag = "24")]
+    i: u32,
+    #[prost(fixed32, tag = "25")]
+    j: u32,
+    #[prost(fixed64, tag = "26")]
+    k: u64,
+    #[prost(fixed64, tag = "27")]
+    l: u64,
+    #[prost(sint64, tag = "28")]
+    m: i64,
+    #[prost(sint64, tag = "29")]
+    n: i64,
+    #[prost(int32, tag = "30")]
+    o: i32,
+}
+
+fn benchmark_keys(criterion: &mut Criterion) {
+    let low = LowTags {
+        a: 1,
+        b: 2,
+        c: 3,
+        d: 4,
+        e: 5,
+        f: true,
+        g: true,
+        h: true,
+        i: 9,
+        j: 10,
+        k: 11,
+        l: 12,
+        m: -13,
+        n: -14,
+        o: 15,
+    };
+    let high = HighTags {
+        a: low.a,
+        b: low.b,
+        c: low.c,
+        d: low.d,
+        e: low.e,
+        f: low.f,
+        g: low.g,
+        h: low.h,
+        i: low.i,
+        j: low.j,
+        k: low.k,
+        l: low.l,
+        m: low.m,
+        n: low.n,
+        o: low.o,
+    };
+    assert_eq!(high.encoded_len(), low.encoded_len() + 15);
+
+    let mut buf = Vec::with_capacity(high.encoded_len());
+    let mut group = criterion.benchmark_group("encode_key");
+    group.bench_function("low_tags", |b| {
+        b.iter(|| {
+            buf.clear();
+            criterion::black_box(&low).encode(&mut buf).unwrap();
+            criterion::black_box(&buf);
+        })
+    });
+    group.bench_function("high_tags", |b| {
+        b.iter(|| {
+            buf.clear();
+            criterion::black_box(&high).encode(&mut buf).unwrap();
+            criterion::black_box(&buf);
+        })
+    });
+    group.finish();
+}
+
+fn main() {
+    let mut criterion = Criterion::default().configure_from_args();
+
+    // Benchmark encoding messages of fifteen scalar fields, with one and two byte keys.
+    benchmark_keys(&mut criterion);
+
+    criterion.final_summary();
+}
@@ -0,0 +1,39 @@
+use criterion::Criterion;
+use prost::Message;
+
+#[derive(Clone, PartialEq, Message)]
+struct Small {
+    #[prost(uint64, tag = "1")]
+    id: u64,
+    #[prost(fixed32, tag = "2")]
+    flags: u32,
+    #[prost(int32, tag = "3")]
+    count: i32,
+}
+
+fn benchmark_small(criterion: &mut Criterion) {
+    let message = Small {
+        id: 1 << 50,
+        flags: 0xdead_beef,
+        count: 1,
+    };
+    assert_eq!(message.encoded_len(), 16);
+
+    let mut group = criterion.benchmark_group("encode_small");
+    group.bench_function("encode_to_vec", |b| {
+        b.iter(|| criterion::black_box(message.encode_to_vec()))
+    });
+    group.bench_function("encode_to_array", |b| {
+        b.iter(|| criterion::black_box(message.encode_to_array::<16>().unwrap()))
+    });
+    group.finish();
+}
+
+fn main() {
+    let mut criterion = Criterion::default().configure_from_args();
+
+    // Benchmark encoding a 16 byte message, to the heap and to the stack.
+    benchmark_small(&mut criterion);
+
+    criterion.final_summary();
+}
@@ -0,0 +1,81 @@
+use criterion::Criterion;
+use prost::bytes::Buf;
+use prost::encoding::{decode_key, message, DecodeContext};
+use prost::Message;
+
+#[derive(Clone, PartialEq, Message)]
+struct Item {
+    #[prost(uint64, tag = "1")]
+    id: u64,
+    #[prost(string, tag = "2")]
+    name: String,
+    #[prost(uint32, repeated, tag = "3")]
+    values: Vec<u32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Batch {
+    #[prost(message, repeated, tag = "1")]
+    items: Vec<Item>,
+}
+
+const ITEMS: usize = 100_000;
+
+fn benchmark_repeated(criterion: &mut Criterion) {
+    let batch = Batch {
+        items: (0..ITEMS)
+            .map(|i| Item {
+                id: i as u64,
+                name: format!("item-{}", i),
+                values: (0..8).collect(),
+            })
+            .collect(),
+    };
+    let buf = batch.encode_to_vec();
+
+    let mut group = criterion.benchmark_group("merge_repeated/100k");
+    group.sample_size(20);
+    group.bench_function("merge_repeated", |b| {
+        let mut items: Vec<Item> = Vec::new();
+        b.iter(|| {
+            items.clear();
+            let mut buf = &buf[..];
+            while buf.has_remaining() {
+                let (_, wire_type) = decode_key(&mut buf).unwrap();
+                message::merge_repeated(wire_type, &mut items, &mut buf, DecodeContext::default())
+                    .unwrap();
+            }
+            criterion::black_box(&items);
+        })
+    });
+    group.bench_function("merge_repeated_reuse", |b| {
+        let mut items: Vec<Item> = Vec::new();
+        let mut pool = Vec::new();
+        b.iter(|| {
+            pool.append(&mut items);
+            let mut buf = &buf[..];
+            while buf.has_remaining() {
+                let (_, wire_type) = decode_key(&mut buf).unwrap();
+                message::merge_repeated_reuse(
+                    wire_type,
+                    &mut items,
+                    &mut pool,
+                    &mut buf,
+                    DecodeContext::default(),
+                )
+                .unwrap();
+            }
+            criterion::black_box(&items);
+        })
+    });
+    group.finish();
+}
+
+fn main() {
+    let mut criterion = Criterion::default().configure_from_args();
+
+    // Benchmark decoding 100k repeated messages, with and without reusing the decoded elements.
+    benchmark_repeated(&mut criterion);
+
+    criterion.final_summary();
+}
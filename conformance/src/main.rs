@@ -82,12 +82,12 @@ fn handle_request(request: ConformanceRequest) -> conformance_response::Result {
         }
         Some(conformance_request::Payload::JspbPayload(_)) => {
             return conformance_response::Result::Skipped(
-                "JSON input is not supported".to_string(),
+                "JSPB input is not supported".to_string(),
             );
         }
         Some(conformance_request::Payload::TextPayload(_)) => {
             return conformance_response::Result::Skipped(
-                "JSON input is not supported".to_string(),
+                "TEXT_FORMAT input is not supported".to_string(),
             );
         }
         Some(conformance_request::Payload::ProtobufPayload(buf)) => buf,
@@ -0,0 +1,57 @@
+#![no_main]
+
+use std::collections::BTreeMap;
+
+use libfuzzer_sys::fuzz_target;
+use prost::bytes::Bytes;
+use prost::{Message, Oneof};
+
+/// A message exercising the decoders of nested, recursive, repeated, packed, bytes and map
+/// fields, and oneofs.
+#[derive(Clone, PartialEq, Message)]
+struct Tree {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(bytes = "vec", tag = "2")]
+    data: Vec<u8>,
+    #[prost(bytes = "bytes", optional, tag = "3")]
+    shared: Option<Bytes>,
+    #[prost(sint64, repeated, tag = "4")]
+    values: Vec<i64>,
+    #[prost(fixed32, repeated, packed = "false", tag = "5")]
+    ids: Vec<u32>,
+    #[prost(message, repeated, tag = "6")]
+    children: Vec<Tree>,
+    #[prost(message, optional, boxed, tag = "7")]
+    parent: Option<Box<Tree>>,
+    #[prost(btree_map = "string, message", tag = "8")]
+    index: BTreeMap<String, Tree>,
+    #[prost(group, optional, tag = "9")]
+    group: Option<Group>,
+    #[prost(oneof = "Choice", tags = "10, 11")]
+    choice: Option<Choice>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Group {
+    #[prost(double, repeated, tag = "12")]
+    weights: Vec<f64>,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+enum Choice {
+    #[prost(uint32, tag = "10")]
+    Number(u32),
+    #[prost(message, tag = "11")]
+    Tree(Box<Tree>),
+}
+
+fuzz_target!(|data: &[u8]| {
+    // Decoding must fail cleanly, and anything which decodes must re-encode to bytes which
+    // decode and re-encode to the same bytes. Values are not compared, since they may hold NaNs.
+    if let Ok(tree) = Tree::decode(data) {
+        let encoded = tree.encode_to_vec();
+        assert_eq!(encoded.len(), tree.encoded_len());
+        assert_eq!(Tree::decode(&*encoded).unwrap().encode_to_vec(), encoded);
+    }
+});
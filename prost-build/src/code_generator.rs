@@ -29,6 +29,9 @@ enum Syntax {
 pub struct CodeGenerator<'a> {
     config: &'a mut Config,
     package: String,
+    /// The package of the file, which unlike `package` does not include the enclosing messages
+    /// of nested types.
+    file_package: String,
     source_info: SourceCodeInfo,
     syntax: Syntax,
     message_graph: &'a MessageGraph,
@@ -68,9 +71,11 @@ impl<'a> CodeGenerator<'a> {
             Some(s) => panic!("unknown syntax: {}", s),
         };
 
+        let package = file.package.unwrap_or_default();
         let mut code_gen = CodeGenerator {
             config,
-            package: file.package.unwrap_or_default(),
+            file_package: package.clone(),
+            package,
             source_info,
             syntax,
             message_graph,
@@ -162,6 +167,11 @@ impl<'a> CodeGenerator<'a> {
                 }
             });
 
+        let has_required = message
+            .field
+            .iter()
+            .any(|field| field.label() == Label::Required);
+
         // Split the fields into a vector of the normal fields, and oneof fields.
         // Path indexes are preserved so that comments can be retrieved.
         type Fields = Vec<(FieldDescriptorProto, usize)>;
@@ -180,6 +190,27 @@ impl<'a> CodeGenerator<'a> {
                 }
             });
 
+        // `#[prost(case)]` derives a `{Oneof}Case` enum in the message's module, and a
+        // `which_{oneof}` method on the message, so it is only set on the oneofs for which neither
+        // name is taken.
+        let module_types: HashSet<String> = nested_types
+            .iter()
+            .map(|(nested_type, _)| to_upper_camel(nested_type.name()))
+            .chain(message.enum_type.iter().map(|e| to_upper_camel(e.name())))
+            .chain(message.oneof_decl.iter().map(|o| to_upper_camel(o.name())))
+            .collect();
+        let oneof_cases: Vec<bool> = message
+            .oneof_decl
+            .iter()
+            .map(|oneof| {
+                let which = format!("which_{}", to_snake(oneof.name()));
+                !module_types.contains(&format!("{}Case", to_upper_camel(oneof.name())))
+                    && !fields
+                        .iter()
+                        .any(|(field, _)| to_snake(field.name()) == which)
+            })
+            .collect();
+
         self.append_doc(&fq_message_name, None);
         self.append_type_attributes(&fq_message_name);
         self.push_indent();
@@ -187,6 +218,25 @@ impl<'a> CodeGenerator<'a> {
             "#[derive(Clone, PartialEq, {}::Message)]\n",
             self.config.prost_path.as_deref().unwrap_or("::prost")
         ));
+        if !message.reserved_range.is_empty()
+            && self
+                .config
+                .reject_reserved_tags
+                .get(&fq_message_name)
+                .next()
+                .is_some()
+        {
+            self.push_indent();
+            self.buf.push_str(&format!(
+                "#[prost(reserved_tags = \"{}\")]\n",
+                tag_ranges(
+                    message
+                        .reserved_range
+                        .iter()
+                        .map(|range| (range.start(), range.end()))
+                )
+            ));
+        }
         self.push_indent();
         self.buf.push_str("pub struct ");
         self.buf.push_str(&to_upper_camel(&message_name));
@@ -220,15 +270,73 @@ impl<'a> CodeGenerator<'a> {
             };
 
             self.path.push(idx);
-            self.append_oneof_field(&message_name, &fq_message_name, oneof, fields);
+            self.append_oneof_field(
+                &message_name,
+                &fq_message_name,
+                oneof,
+                fields,
+                oneof_cases[idx as usize],
+            );
             self.path.pop();
         }
         self.path.pop();
 
+        if !message.extension_range.is_empty()
+            && self
+                .config
+                .retain_extensions
+                .get(&fq_message_name)
+                .next()
+                .is_some()
+        {
+            self.push_indent();
+            self.buf.push_str(&format!(
+                "#[prost(extensions = \"{}\")]\n",
+                tag_ranges(
+                    message
+                        .extension_range
+                        .iter()
+                        .map(|range| (range.start(), range.end()))
+                )
+            ));
+            self.push_indent();
+            self.buf.push_str(&format!(
+                "pub extensions: {}::ExtensionSet,\n",
+                self.config.prost_path.as_deref().unwrap_or("::prost")
+            ));
+        }
+
+        if has_required
+            && self
+                .config
+                .track_required_fields
+                .get(&fq_message_name)
+                .next()
+                .is_some()
+        {
+            self.push_indent();
+            self.buf.push_str("#[prost(presence)]\n");
+            self.push_indent();
+            self.buf.push_str(&format!(
+                "pub presence: {}::Presence,\n",
+                self.config.prost_path.as_deref().unwrap_or("::prost")
+            ));
+        }
+
         self.depth -= 1;
         self.push_indent();
         self.buf.push_str("}\n");
 
+        if self
+            .config
+            .type_names
+            .get(&fq_message_name)
+            .next()
+            .is_some()
+        {
+            self.append_type_name(&message_name, &fq_message_name);
+        }
+
         if !message.enum_type.is_empty() || !nested_types.is_empty() || !oneof_fields.is_empty() {
             self.push_mod(&message_name);
             self.path.push(3);
@@ -254,13 +362,37 @@ impl<'a> CodeGenerator<'a> {
                     Some(fields) => fields,
                     None => continue,
                 };
-                self.append_oneof(&fq_message_name, oneof, idx, fields);
+                let case = oneof_cases[idx as usize];
+                self.append_oneof(&fq_message_name, oneof, idx, fields, case);
             }
 
             self.pop_mod();
         }
     }
 
+    fn append_type_name(&mut self, message_name: &str, fq_message_name: &str) {
+        self.push_indent();
+        self.buf.push_str(&format!(
+            "impl {}::Name for {} {{\n",
+            self.config.prost_path.as_deref().unwrap_or("::prost"),
+            to_upper_camel(message_name)
+        ));
+        self.depth += 1;
+        self.push_indent();
+        self.buf.push_str(&format!(
+            "const NAME: &'static str = \"{}\";\n",
+            type_name(&self.file_package, fq_message_name)
+        ));
+        self.push_indent();
+        self.buf.push_str(&format!(
+            "const PACKAGE: &'static str = \"{}\";\n",
+            self.file_package
+        ));
+        self.depth -= 1;
+        self.push_indent();
+        self.buf.push_str("}\n");
+    }
+
     fn append_type_attributes(&mut self, fq_message_name: &str) {
         assert_eq!(b'.', fq_message_name.as_bytes()[0]);
         for attribute in self.config.type_attributes.get(fq_message_name) {
@@ -349,6 +481,7 @@ impl<'a> CodeGenerator<'a> {
         if boxed {
             self.buf.push_str(", boxed");
         }
+        self.append_json_name(&field);
         self.buf.push_str(", tag=\"");
         self.buf.push_str(&field.number().to_string());
 
@@ -406,6 +539,17 @@ impl<'a> CodeGenerator<'a> {
         self.buf.push_str(",\n");
     }
 
+    /// Appends a `json_name` field attribute if the field's JSON name was set with the
+    /// `json_name` option, i.e. if it is not the default lowerCamelCase form of its name.
+    fn append_json_name(&mut self, field: &FieldDescriptorProto) {
+        if let Some(ref json_name) = field.json_name {
+            if *json_name != default_json_name(field.name()) {
+                self.buf
+                    .push_str(&format!(", json_name=\"{}\"", json_name.escape_default()));
+            }
+        }
+    }
+
     fn append_map_field(
         &mut self,
         fq_message_name: &str,
@@ -436,12 +580,14 @@ impl<'a> CodeGenerator<'a> {
         let value_tag = self.map_value_type_tag(value);
 
         self.buf.push_str(&format!(
-            "#[prost({}=\"{}, {}\", tag=\"{}\")]\n",
+            "#[prost({}=\"{}, {}\"",
             map_type.annotation(),
             key_tag,
             value_tag,
-            field.number()
         ));
+        self.append_json_name(&field);
+        self.buf
+            .push_str(&format!(", tag=\"{}\")]\n", field.number()));
         self.append_field_attributes(fq_message_name, field.name());
         self.push_indent();
         self.buf.push_str(&format!(
@@ -459,6 +605,7 @@ impl<'a> CodeGenerator<'a> {
         fq_message_name: &str,
         oneof: &OneofDescriptorProto,
         fields: &[(FieldDescriptorProto, usize)],
+        case: bool,
     ) {
         let name = format!(
             "{}::{}",
@@ -468,12 +615,13 @@ impl<'a> CodeGenerator<'a> {
         self.append_doc(fq_message_name, None);
         self.push_indent();
         self.buf.push_str(&format!(
-            "#[prost(oneof=\"{}\", tags=\"{}\")]\n",
+            "#[prost(oneof=\"{}\", tags=\"{}\"{})]\n",
             name,
             fields
                 .iter()
                 .map(|&(ref field, _)| field.number())
-                .join(", ")
+                .join(", "),
+            if case { ", case" } else { "" }
         ));
         self.append_field_attributes(fq_message_name, oneof.name());
         self.push_indent();
@@ -490,6 +638,7 @@ impl<'a> CodeGenerator<'a> {
         oneof: OneofDescriptorProto,
         idx: i32,
         fields: Vec<(FieldDescriptorProto, usize)>,
+        case: bool,
     ) {
         self.path.push(8);
         self.path.push(idx);
@@ -504,6 +653,10 @@ impl<'a> CodeGenerator<'a> {
             "#[derive(Clone, PartialEq, {}::Oneof)]\n",
             self.config.prost_path.as_deref().unwrap_or("::prost")
         ));
+        if case {
+            self.push_indent();
+            self.buf.push_str("#[prost(case)]\n");
+        }
         self.push_indent();
         self.buf.push_str("pub enum ");
         self.buf.push_str(&to_upper_camel(oneof.name()));
@@ -520,11 +673,10 @@ impl<'a> CodeGenerator<'a> {
 
             self.push_indent();
             let ty_tag = self.field_type_tag(&field);
-            self.buf.push_str(&format!(
-                "#[prost({}, tag=\"{}\")]\n",
-                ty_tag,
-                field.number()
-            ));
+            self.buf.push_str(&format!("#[prost({}", ty_tag));
+            self.append_json_name(&field);
+            self.buf
+                .push_str(&format!(", tag=\"{}\")]\n", field.number()));
             self.append_field_attributes(&oneof_name, field.name());
 
             self.push_indent();
@@ -629,6 +781,9 @@ impl<'a> CodeGenerator<'a> {
             self.append_doc(&fq_proto_enum_name, Some(variant.proto_name));
             self.append_field_attributes(&fq_proto_enum_name, variant.proto_name);
             self.push_indent();
+            self.buf
+                .push_str(&format!("#[prost(name = \"{}\")]\n", variant.proto_name));
+            self.push_indent();
             self.buf.push_str(&variant.generated_variant_name);
             self.buf.push_str(" = ");
             self.buf.push_str(&variant.proto_number.to_string());
@@ -1051,6 +1206,24 @@ fn strip_enum_prefix(prefix: &str, name: &str) -> String {
     }
 }
 
+/// Returns the JSON name `protoc` gives a field without a `json_name` option, i.e. its name with
+/// the underscores removed and the character following each underscore in uppercase.
+fn default_json_name(name: &str) -> String {
+    let mut json_name = String::with_capacity(name.len());
+    let mut capitalize_next = false;
+    for c in name.chars() {
+        if c == '_' {
+            capitalize_next = true;
+        } else if capitalize_next {
+            json_name.extend(c.to_uppercase());
+            capitalize_next = false;
+        } else {
+            json_name.push(c);
+        }
+    }
+    json_name
+}
+
 struct EnumVariantMapping<'a> {
     path_idx: usize,
     proto_name: &'a str,
@@ -1132,6 +1305,40 @@ impl BytesType {
     }
 }
 
+/// Formats the reserved or extension ranges of a message for the `reserved_tags` and
+/// `extensions` attributes.
+///
+/// Descriptor ranges exclude their end, while the attributes' ranges are inclusive.
+fn tag_ranges<I>(ranges: I) -> String
+where
+    I: IntoIterator<Item = (i32, i32)>,
+{
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            let end = end - 1;
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{} to {}", start, end)
+            }
+        })
+        .join(", ")
+}
+
+/// Returns the name of a message relative to its file's package, e.g. `Outer.Inner` for
+/// `.package.Outer.Inner`.
+fn type_name<'a>(package: &str, fq_message_name: &'a str) -> &'a str {
+    let name = fq_message_name.trim_start_matches('.');
+    if package.is_empty() {
+        name
+    } else {
+        name.strip_prefix(package)
+            .and_then(|name| name.strip_prefix('.'))
+            .unwrap_or(name)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1163,6 +1370,22 @@ mod tests {
         unescape_c_escape_string(r#"\x1"#);
     }
 
+    #[test]
+    fn test_tag_ranges() {
+        assert_eq!(
+            tag_ranges(vec![(2, 3), (9, 12), (1000, 536_870_912)]),
+            "2, 9 to 11, 1000 to 536870911"
+        );
+    }
+
+    #[test]
+    fn test_type_name() {
+        assert_eq!(type_name("foo.bar", ".foo.bar.Baz"), "Baz");
+        assert_eq!(type_name("foo.bar", ".foo.bar.Baz.Qux"), "Baz.Qux");
+        assert_eq!(type_name("", ".Baz"), "Baz");
+        assert_eq!(type_name("", "..Baz.Qux"), "Baz.Qux");
+    }
+
     #[test]
     fn test_strip_enum_prefix() {
         assert_eq!(strip_enum_prefix("Foo", "FooBar"), "Bar");
@@ -1171,4 +1394,13 @@ mod tests {
         assert_eq!(strip_enum_prefix("Foo", "Bar"), "Bar");
         assert_eq!(strip_enum_prefix("Foo", "Foo1"), "Foo1");
     }
+
+    #[test]
+    fn test_default_json_name() {
+        assert_eq!(default_json_name("foo"), "foo");
+        assert_eq!(default_json_name("foo_bar"), "fooBar");
+        assert_eq!(default_json_name("foo_bar_1"), "fooBar1");
+        assert_eq!(default_json_name("fooBar"), "fooBar");
+        assert_eq!(default_json_name("_foo"), "Foo");
+    }
 }
@@ -251,6 +251,10 @@ pub struct Config {
     default_package_filename: String,
     protoc_args: Vec<OsString>,
     disable_comments: PathMap<()>,
+    reject_reserved_tags: PathMap<()>,
+    retain_extensions: PathMap<()>,
+    track_required_fields: PathMap<()>,
+    type_names: PathMap<()>,
     skip_protoc_run: bool,
     include_file: Option<PathBuf>,
     prost_path: Option<String>,
@@ -512,6 +516,149 @@ impl Config {
         self
     }
 
+    /// Configure the code generator to reject reserved field tags when decoding messages.
+    ///
+    /// By default, a field with a tag which the message declares `reserved` is skipped like any
+    /// other unknown field. Messages matching one of the `paths` instead fail to decode with an
+    /// error when they encounter a reserved tag, which usually indicates that the peer is using a
+    /// retired version of the schema.
+    ///
+    /// # Arguments
+    ///
+    /// **`paths`** - paths to specific messages or packages whose reserved tags should be
+    /// rejected. Paths are matched as in [`btree_map`](Self::btree_map).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # let mut config = prost_build::Config::new();
+    /// // Reject reserved tags in one message.
+    /// config.reject_reserved_tags(&[".my_messages.MyMessageType"]);
+    ///
+    /// // Reject reserved tags in every message.
+    /// config.reject_reserved_tags(&["."]);
+    /// ```
+    pub fn reject_reserved_tags<I, S>(&mut self, paths: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.reject_reserved_tags.clear();
+        for matcher in paths {
+            self.reject_reserved_tags
+                .insert(matcher.as_ref().to_string(), ());
+        }
+        self
+    }
+
+    /// Configure the code generator to retain proto2 extensions when decoding messages.
+    ///
+    /// By default, fields with tags in a message's `extensions` ranges are treated as unknown
+    /// fields. Messages matching one of the `paths` which declare extension ranges are instead
+    /// generated with an `extensions` field of type `prost::ExtensionSet`, which stores those
+    /// fields and re-emits them when the message is encoded. Individual extensions are read and
+    /// written with the set's typed accessors, using types implementing `prost::Extension`.
+    ///
+    /// # Arguments
+    ///
+    /// **`paths`** - paths to specific messages or packages whose extensions should be retained.
+    /// Paths are matched as in [`btree_map`](Self::btree_map).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # let mut config = prost_build::Config::new();
+    /// // Retain extensions of one message.
+    /// config.retain_extensions(&[".my_messages.MyMessageType"]);
+    ///
+    /// // Retain extensions of every message.
+    /// config.retain_extensions(&["."]);
+    /// ```
+    pub fn retain_extensions<I, S>(&mut self, paths: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.retain_extensions.clear();
+        for matcher in paths {
+            self.retain_extensions
+                .insert(matcher.as_ref().to_string(), ());
+        }
+        self
+    }
+
+    /// Configure the code generator to track which proto2 `required` fields are set.
+    ///
+    /// Required fields are generated as plain values, so by default a message decoded without
+    /// one of its required fields can not be told apart from one which holds the field's default
+    /// value. Messages matching one of the `paths` which declare required fields are instead
+    /// generated with a `presence` field of type `prost::Presence`, which records the required
+    /// fields as they are decoded. `Message::check_initialized` then reports the first missing
+    /// required field of the message, or of the messages nested in it.
+    ///
+    /// # Arguments
+    ///
+    /// **`paths`** - paths to specific messages or packages whose required fields should be
+    /// tracked. Paths are matched as in [`btree_map`](Self::btree_map).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # let mut config = prost_build::Config::new();
+    /// // Track the required fields of one message.
+    /// config.track_required_fields(&[".my_messages.MyMessageType"]);
+    ///
+    /// // Track the required fields of every message.
+    /// config.track_required_fields(&["."]);
+    /// ```
+    pub fn track_required_fields<I, S>(&mut self, paths: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.track_required_fields.clear();
+        for matcher in paths {
+            self.track_required_fields
+                .insert(matcher.as_ref().to_string(), ());
+        }
+        self
+    }
+
+    /// Configure the code generator to implement `prost::Name` for messages.
+    ///
+    /// Messages matching one of the `paths` are generated with an implementation of
+    /// `prost::Name` holding the message's name and the package of the `.proto` file it is
+    /// declared in. The name of a nested message includes its enclosing messages, e.g.
+    /// `Outer.Inner`. This makes `Name::full_name` and `Name::type_url` available, as used by
+    /// `prost_types::Any::pack` and `prost_types::Any::unpack`.
+    ///
+    /// # Arguments
+    ///
+    /// **`paths`** - paths to specific messages or packages which should implement `Name`.
+    /// Paths are matched as in [`btree_map`](Self::btree_map).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # let mut config = prost_build::Config::new();
+    /// // Implement `Name` for one message.
+    /// config.type_names(&[".my_messages.MyMessageType"]);
+    ///
+    /// // Implement `Name` for every message.
+    /// config.type_names(&["."]);
+    /// ```
+    pub fn type_names<I, S>(&mut self, paths: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.type_names.clear();
+        for matcher in paths {
+            self.type_names.insert(matcher.as_ref().to_string(), ());
+        }
+        self
+    }
+
     /// Declare an externally provided Protobuf package or type.
     ///
     /// `extern_path` allows `prost` types in external crates to be referenced in generated code.
@@ -1078,6 +1225,10 @@ impl default::Default for Config {
             default_package_filename: "_".to_string(),
             protoc_args: Vec::new(),
             disable_comments: PathMap::default(),
+            reject_reserved_tags: PathMap::default(),
+            retain_extensions: PathMap::default(),
+            track_required_fields: PathMap::default(),
+            type_names: PathMap::default(),
             skip_protoc_run: false,
             include_file: None,
             prost_path: None,
@@ -1101,6 +1252,10 @@ impl fmt::Debug for Config {
             .field("default_package_filename", &self.default_package_filename)
             .field("protoc_args", &self.protoc_args)
             .field("disable_comments", &self.disable_comments)
+            .field("reject_reserved_tags", &self.reject_reserved_tags)
+            .field("retain_extensions", &self.retain_extensions)
+            .field("track_required_fields", &self.track_required_fields)
+            .field("type_names", &self.type_names)
             .field("prost_path", &self.prost_path)
             .finish()
     }
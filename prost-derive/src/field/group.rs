@@ -3,7 +3,7 @@ use proc_macro2::TokenStream;
 use quote::{quote, ToTokens};
 use syn::Meta;
 
-use crate::field::{set_bool, set_option, tag_attr, word_attr, Label};
+use crate::field::{reflect_repeated, set_bool, set_option, tag_attr, word_attr, Label};
 
 #[derive(Clone)]
 pub struct Field {
@@ -71,19 +71,34 @@ impl Field {
     }
 
     pub fn encode(&self, ident: TokenStream) -> TokenStream {
+        self.encode_with(ident, quote!(::prost::encoding::group::encode))
+    }
+
+    /// Returns a statement which encodes the group field, reusing the cached lengths of the
+    /// embedded messages.
+    pub fn encode_cached(&self, ident: TokenStream) -> TokenStream {
+        self.encode_with(ident, quote!(::prost::encoding::group::encode_cached))
+    }
+
+    /// Returns a statement which encodes the group field with each group in canonical form.
+    pub fn encode_canonical(&self, ident: TokenStream) -> TokenStream {
+        self.encode_with(ident, quote!(::prost::encoding::group::encode_canonical))
+    }
+
+    fn encode_with(&self, ident: TokenStream, encode: TokenStream) -> TokenStream {
         let tag = self.tag;
         match self.label {
             Label::Optional => quote! {
                 if let Some(ref msg) = #ident {
-                    ::prost::encoding::group::encode(#tag, msg, buf);
+                    #encode(#tag, msg, buf);
                 }
             },
             Label::Required => quote! {
-                ::prost::encoding::group::encode(#tag, &#ident, buf);
+                #encode(#tag, &#ident, buf);
             },
             Label::Repeated => quote! {
                 for msg in &#ident {
-                    ::prost::encoding::group::encode(#tag, msg, buf);
+                    #encode(#tag, msg, buf);
                 }
             },
         }
@@ -124,6 +139,100 @@ impl Field {
         }
     }
 
+    /// Returns a statement which writes the group field to a JSON writer.
+    pub fn json(&self, key: &str, ident: TokenStream) -> TokenStream {
+        match self.label {
+            Label::Optional => quote! {
+                if let Some(ref msg) = #ident {
+                    writer.key(#key);
+                    writer.write_message(msg);
+                }
+            },
+            Label::Required => quote! {
+                writer.key(#key);
+                writer.write_message(&#ident);
+            },
+            Label::Repeated => quote! {
+                if !#ident.is_empty() {
+                    writer.key(#key);
+                    writer.begin_array();
+                    for msg in &#ident {
+                        writer.write_message(msg);
+                    }
+                    writer.end_array();
+                }
+            },
+        }
+    }
+
+    /// Returns a statement which writes the group field to a text format writer.
+    pub fn text_format(&self, name: &str, ident: TokenStream) -> TokenStream {
+        match self.label {
+            Label::Optional => quote! {
+                if let Some(ref msg) = #ident {
+                    writer.key(#name);
+                    writer.write_message(msg);
+                }
+            },
+            Label::Required => quote! {
+                writer.key(#name);
+                writer.write_message(&#ident);
+            },
+            Label::Repeated => quote! {
+                for msg in &#ident {
+                    writer.key(#name);
+                    writer.write_message(msg);
+                }
+            },
+        }
+    }
+
+    /// Returns a statement which passes the field to a `FieldVisitor`.
+    pub fn reflect(&self, name: &str, ident: TokenStream) -> TokenStream {
+        let tag = self.tag;
+        match self.label {
+            Label::Optional => quote! {
+                if let Some(ref msg) = #ident {
+                    visitor.visit_message(#tag, #name, msg);
+                }
+            },
+            Label::Required => quote! {
+                visitor.visit_message(#tag, #name, &#ident);
+            },
+            Label::Repeated => reflect_repeated(
+                tag,
+                name,
+                ident,
+                quote!(msg),
+                quote!(visitor.visit_message(#tag, #name, msg)),
+            ),
+        }
+    }
+
+    /// Returns an expression which evaluates to the result of checking the required fields of
+    /// the field's messages.
+    pub fn check_initialized(&self, ident: TokenStream) -> TokenStream {
+        match self.label {
+            Label::Optional => quote! {
+                #ident.as_ref().map_or(::core::result::Result::Ok(()), ::prost::Message::check_initialized)
+            },
+            Label::Required => quote! {
+                ::prost::Message::check_initialized(&#ident)
+            },
+            Label::Repeated => quote! {
+                #ident.iter().try_for_each(::prost::Message::check_initialized)
+            },
+        }
+    }
+
+    pub fn is_default(&self, ident: TokenStream) -> TokenStream {
+        match self.label {
+            Label::Optional => quote!(#ident.is_none()),
+            Label::Required => quote!(::prost::Message::is_default(&#ident)),
+            Label::Repeated => quote!(#ident.is_empty()),
+        }
+    }
+
     pub fn clear(&self, ident: TokenStream) -> TokenStream {
         match self.label {
             Label::Optional => quote!(#ident = ::core::option::Option::None),
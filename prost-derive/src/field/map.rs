@@ -3,7 +3,7 @@ use proc_macro2::{Span, TokenStream};
 use quote::quote;
 use syn::{Ident, Lit, Meta, MetaNameValue, NestedMeta};
 
-use crate::field::{scalar, set_option, tag_attr};
+use crate::field::{reflect_repeated, scalar, set_bool, set_option, tag_attr, word_attr};
 
 #[derive(Clone, Debug)]
 pub enum MapTy {
@@ -41,6 +41,8 @@ fn fake_scalar(ty: scalar::Ty) -> scalar::Field {
         ty,
         kind,
         tag: 0, // Not used here
+        unknown: scalar::Unknown::Preserve,
+        newtype: None,
     }
 }
 
@@ -50,16 +52,21 @@ pub struct Field {
     pub key_ty: scalar::Ty,
     pub value_ty: ValueTy,
     pub tag: u32,
+    /// Whether the entries are always encoded in ascending key order.
+    pub deterministic: bool,
 }
 
 impl Field {
     pub fn new(attrs: &[Meta], inferred_tag: Option<u32>) -> Result<Option<Field>, Error> {
         let mut types = None;
         let mut tag = None;
+        let mut deterministic = false;
 
         for attr in attrs {
             if let Some(t) = tag_attr(attr)? {
                 set_option(&mut tag, t, "duplicate tag attributes")?;
+            } else if word_attr("deterministic", attr) {
+                set_bool(&mut deterministic, "duplicate deterministic attribute")?;
             } else if let Some(map_ty) = attr
                 .path()
                 .get_ident()
@@ -118,6 +125,7 @@ impl Field {
                 key_ty,
                 value_ty,
                 tag,
+                deterministic,
             }),
             _ => None,
         })
@@ -127,56 +135,103 @@ impl Field {
         Field::new(attrs, None)
     }
 
-    /// Returns a statement which encodes the map field.
+    /// Returns a statement which encodes the map field, with the entries in ascending key order if
+    /// the field is deterministic.
     pub fn encode(&self, ident: TokenStream) -> TokenStream {
+        self.encode_with(ident, false, quote!(::prost::encoding::message::encode))
+    }
+
+    /// Returns a statement which encodes the map field, reusing the cached lengths of message
+    /// values.
+    pub fn encode_cached(&self, ident: TokenStream) -> TokenStream {
+        self.encode_with(
+            ident,
+            false,
+            quote!(::prost::encoding::message::encode_cached),
+        )
+    }
+
+    /// Returns a statement which encodes the map field canonically, with the entries in ascending
+    /// key order and message values in canonical form.
+    pub fn encode_canonical(&self, ident: TokenStream) -> TokenStream {
+        self.encode_with(
+            ident,
+            true,
+            quote!(::prost::encoding::message::encode_canonical),
+        )
+    }
+
+    fn encode_with(
+        &self,
+        ident: TokenStream,
+        canonical: bool,
+        encode_message: TokenStream,
+    ) -> TokenStream {
         let tag = self.tag;
         let key_mod = self.key_ty.module();
         let ke = quote!(::prost::encoding::#key_mod::encode);
         let kl = quote!(::prost::encoding::#key_mod::encoded_len);
         let module = self.map_ty.module();
-        match &self.value_ty {
-            ValueTy::Scalar(scalar::Ty::Enumeration(ty)) => {
-                let default = quote!(#ty::default() as i32);
-                quote! {
-                    ::prost::encoding::#module::encode_with_default(
-                        #ke,
-                        #kl,
-                        ::prost::encoding::int32::encode,
-                        ::prost::encoding::int32::encoded_len,
-                        &(#default),
-                        #tag,
-                        &#ident,
-                        buf,
-                    );
-                }
-            }
+        let (ve, vl, default) = match &self.value_ty {
+            ValueTy::Scalar(scalar::Ty::Enumeration(ty)) => (
+                quote!(::prost::encoding::int32::encode),
+                quote!(::prost::encoding::int32::encoded_len),
+                Some(quote!(#ty::default() as i32)),
+            ),
             ValueTy::Scalar(value_ty) => {
                 let val_mod = value_ty.module();
-                let ve = quote!(::prost::encoding::#val_mod::encode);
-                let vl = quote!(::prost::encoding::#val_mod::encoded_len);
-                quote! {
-                    ::prost::encoding::#module::encode(
-                        #ke,
-                        #kl,
-                        #ve,
-                        #vl,
-                        #tag,
-                        &#ident,
-                        buf,
-                    );
-                }
+                (
+                    quote!(::prost::encoding::#val_mod::encode),
+                    quote!(::prost::encoding::#val_mod::encoded_len),
+                    None,
+                )
             }
-            ValueTy::Message => quote! {
+            ValueTy::Message => (
+                encode_message,
+                quote!(::prost::encoding::message::encoded_len),
+                None,
+            ),
+        };
+        // `BTreeMap` entries are already iterated in key order.
+        if (canonical || self.deterministic) && matches!(self.map_ty, MapTy::HashMap) {
+            let default = default.unwrap_or_else(|| quote!(::core::default::Default::default()));
+            quote! {
+                ::prost::encoding::#module::encode_sorted_with_default(
+                    #ke,
+                    #kl,
+                    #ve,
+                    #vl,
+                    &(#default),
+                    #tag,
+                    &#ident,
+                    buf,
+                );
+            }
+        } else if let Some(default) = default {
+            quote! {
+                ::prost::encoding::#module::encode_with_default(
+                    #ke,
+                    #kl,
+                    #ve,
+                    #vl,
+                    &(#default),
+                    #tag,
+                    &#ident,
+                    buf,
+                );
+            }
+        } else {
+            quote! {
                 ::prost::encoding::#module::encode(
                     #ke,
                     #kl,
-                    ::prost::encoding::message::encode,
-                    ::prost::encoding::message::encoded_len,
+                    #ve,
+                    #vl,
                     #tag,
                     &#ident,
                     buf,
                 );
-            },
+            }
         }
     }
 
@@ -252,6 +307,88 @@ impl Field {
         }
     }
 
+    /// Returns a statement which writes the map to a JSON writer, as an object.
+    pub fn json(&self, key: &str, ident: TokenStream) -> TokenStream {
+        let write_key = match self.key_ty {
+            scalar::Ty::String(..) => quote!(writer.key(&(key)[..])),
+            _ => quote!(writer.key_display(key)),
+        };
+        let write_value = match &self.value_ty {
+            ValueTy::Scalar(ty) => ty.write(quote!(value)),
+            ValueTy::Message => quote!(writer.write_message(value)),
+        };
+        quote! {
+            if !#ident.is_empty() {
+                writer.key(#key);
+                writer.begin_object();
+                for (key, value) in &#ident {
+                    #write_key;
+                    #write_value;
+                }
+                writer.end_object();
+            }
+        }
+    }
+
+    /// Returns a statement which writes the map to a text format writer, as a repeated field of
+    /// entry messages.
+    pub fn text_format(&self, name: &str, ident: TokenStream) -> TokenStream {
+        let write_key = self.key_ty.write(quote!(key));
+        let write_value = match &self.value_ty {
+            ValueTy::Scalar(ty) => ty.write(quote!(value)),
+            ValueTy::Message => quote!(writer.write_message(value)),
+        };
+        quote! {
+            for (key, value) in &#ident {
+                writer.key(#name);
+                writer.begin_message();
+                writer.key("key");
+                #write_key;
+                writer.key("value");
+                #write_value;
+                writer.end_message();
+            }
+        }
+    }
+
+    /// Returns a statement which passes the map to a `FieldVisitor`, as a repeated field of entry
+    /// messages.
+    pub fn reflect(&self, name: &str, ident: TokenStream) -> TokenStream {
+        let tag = self.tag;
+        let visit_key = self.key_ty.visit(1, "key", quote!(key));
+        let visit_value = match &self.value_ty {
+            ValueTy::Scalar(ty) => ty.visit(2, "value", quote!(value)),
+            ValueTy::Message => quote!(visitor.visit_message(2, "value", value)),
+        };
+        reflect_repeated(
+            tag,
+            name,
+            ident,
+            quote!((key, value)),
+            quote! {
+                visitor.visit_message(
+                    #tag,
+                    #name,
+                    &::prost::reflect::MapEntry(|visitor: &mut dyn ::prost::reflect::FieldVisitor| {
+                        #visit_key;
+                        #visit_value;
+                    }),
+                )
+            },
+        )
+    }
+
+    /// Returns an expression which evaluates to the result of checking the required fields of
+    /// the map's message values, or `None` if the values are scalars.
+    pub fn check_initialized(&self, ident: TokenStream) -> Option<TokenStream> {
+        match self.value_ty {
+            ValueTy::Scalar(..) => None,
+            ValueTy::Message => Some(quote! {
+                #ident.values().try_for_each(::prost::Message::check_initialized)
+            }),
+        }
+    }
+
     pub fn clear(&self, ident: TokenStream) -> TokenStream {
         quote!(#ident.clear())
     }
@@ -318,18 +455,18 @@ impl Field {
             }
         };
         match &self.value_ty {
-            ValueTy::Scalar(ty) => {
-                if let scalar::Ty::Bytes(_) = *ty {
-                    return quote! {
-                        struct #wrapper_name<'a>(&'a dyn ::core::fmt::Debug);
-                        impl<'a> ::core::fmt::Debug for #wrapper_name<'a> {
-                            fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
-                                self.0.fmt(f)
-                            }
-                        }
-                    };
+            // The bytes value type is only known to the compiler, so the wrapper is generic over
+            // it, as for messages.
+            ValueTy::Scalar(scalar::Ty::Bytes(..)) => quote! {
+                struct #wrapper_name<'a, V: 'a>(&'a ::#libname::collections::#type_name<#key, V>);
+                impl<'a, V> ::core::fmt::Debug for #wrapper_name<'a, V>
+                where
+                    V: ::core::convert::AsRef<[u8]> + 'a,
+                {
+                    #fmt
                 }
-
+            },
+            ValueTy::Scalar(ty) => {
                 let value = ty.rust_type();
                 quote! {
                     struct #wrapper_name<'a>(&'a ::#libname::collections::#type_name<#key, #value>);
@@ -365,7 +502,7 @@ fn key_ty_from_str(s: &str) -> Result<scalar::Ty, Error> {
         | scalar::Ty::Sfixed32
         | scalar::Ty::Sfixed64
         | scalar::Ty::Bool
-        | scalar::Ty::String => Ok(ty),
+        | scalar::Ty::String(..) => Ok(ty),
         _ => bail!("invalid map key type: {}", s),
     }
 }
@@ -7,7 +7,7 @@ mod scalar;
 use std::fmt;
 use std::slice;
 
-use anyhow::{bail, Error};
+use anyhow::{anyhow, bail, Error};
 use proc_macro2::TokenStream;
 use quote::quote;
 use syn::{Attribute, Lit, LitBool, Meta, MetaList, MetaNameValue, NestedMeta};
@@ -32,7 +32,10 @@ impl Field {
     /// If the meta items are invalid, an error will be returned.
     /// If the field should be ignored, `None` is returned.
     pub fn new(attrs: Vec<Attribute>, inferred_tag: Option<u32>) -> Result<Option<Field>, Error> {
-        let attrs = prost_attrs(attrs);
+        let mut attrs = prost_attrs(attrs);
+        attrs.retain(|attr| {
+            !attr.path().is_ident("json_name") && !attr.path().is_ident("alias_tag")
+        });
 
         // TODO: check for ignore attribute.
 
@@ -58,7 +61,8 @@ impl Field {
     /// If the meta items are invalid, an error will be returned.
     /// If the field should be ignored, `None` is returned.
     pub fn new_oneof(attrs: Vec<Attribute>) -> Result<Option<Field>, Error> {
-        let attrs = prost_attrs(attrs);
+        let mut attrs = prost_attrs(attrs);
+        attrs.retain(|attr| !attr.path().is_ident("json_name"));
 
         // TODO: check for ignore attribute.
 
@@ -87,6 +91,39 @@ impl Field {
         }
     }
 
+    /// Returns the wire types the field may be decoded from, or `None` for oneof fields, whose
+    /// variants are checked by the oneof's `merge`.
+    pub fn wire_types(&self) -> Option<Vec<TokenStream>> {
+        match *self {
+            Field::Scalar(ref scalar) => Some(scalar.wire_types()),
+            Field::Message(..) => Some(vec![quote!(::prost::encoding::message::WIRE_TYPE)]),
+            Field::Map(..) => Some(vec![quote!(::prost::encoding::WireType::LengthDelimited)]),
+            Field::Group(..) => Some(vec![quote!(::prost::encoding::group::WIRE_TYPE)]),
+            Field::Oneof(..) => None,
+        }
+    }
+
+    /// Returns an expression which merges the field, with an error naming the tag if the wire
+    /// type is not one of the field's.
+    ///
+    /// The wire type is checked once: up front for map fields, whose merge functions do not take
+    /// it, and by the merge functions of the other fields, whose errors then name the tag.
+    pub fn merge_checked(&self, ident: TokenStream) -> TokenStream {
+        let merge = self.merge(ident);
+        match (self, self.wire_types()) {
+            (Field::Map(..), Some(wire_types)) => quote! {
+                ::prost::encoding::check_field_wire_type(tag, &[#(#wire_types),*], wire_type)
+                    .and_then(|()| #merge)
+            },
+            (_, Some(wire_types)) => quote! {
+                #merge.map_err(|error| {
+                    ::prost::encoding::field_merge_error(tag, &[#(#wire_types),*], wire_type, error)
+                })
+            },
+            (_, None) => merge,
+        }
+    }
+
     /// Returns a statement which encodes the field.
     pub fn encode(&self, ident: TokenStream) -> TokenStream {
         match *self {
@@ -98,6 +135,32 @@ impl Field {
         }
     }
 
+    /// Returns a statement which encodes the field like `encode`, reusing the cached lengths of
+    /// embedded messages.
+    pub fn encode_cached(&self, ident: TokenStream) -> TokenStream {
+        match *self {
+            Field::Scalar(ref scalar) => scalar.encode(ident),
+            Field::Message(ref message) => message.encode_cached(ident),
+            Field::Map(ref map) => map.encode_cached(ident),
+            Field::Oneof(ref oneof) => oneof.encode_cached(ident),
+            Field::Group(ref group) => group.encode_cached(ident),
+        }
+    }
+
+    /// Returns a statement which encodes the field in canonical form.
+    ///
+    /// Oneof fields are encoded at the position of each of their tags, with
+    /// `oneof::Field::encode_canonical`.
+    pub fn encode_canonical(&self, ident: TokenStream) -> TokenStream {
+        match *self {
+            Field::Scalar(ref scalar) => scalar.encode(ident),
+            Field::Message(ref message) => message.encode_canonical(ident),
+            Field::Map(ref map) => map.encode_canonical(ident),
+            Field::Oneof(..) => unreachable!("oneof fields are encoded by tag"),
+            Field::Group(ref group) => group.encode_canonical(ident),
+        }
+    }
+
     /// Returns an expression which evaluates to the result of merging a decoded
     /// value into the field.
     pub fn merge(&self, ident: TokenStream) -> TokenStream {
@@ -121,6 +184,76 @@ impl Field {
         }
     }
 
+    /// Returns a statement which reports the encoded length of the field to `record`, followed by
+    /// the lengths of the fields of its embedded messages, see `Message::encode_with_stats`.
+    pub fn field_lens(&self, ident: TokenStream) -> TokenStream {
+        let nested = match *self {
+            Field::Oneof(ref oneof) => return oneof.field_lens(ident),
+            Field::Message(ref message) => nested_field_lens(message.label, ident.clone()),
+            Field::Group(ref group) => nested_field_lens(group.label, ident.clone()),
+            Field::Scalar(..) | Field::Map(..) => quote!(),
+        };
+        let tag = self.tags()[0];
+        let encoded_len = self.encoded_len(ident);
+        quote! {
+            let len = #encoded_len;
+            if len != 0 {
+                path.push(#tag);
+                record(path, len);
+                #nested
+                path.pop();
+            }
+        }
+    }
+
+    /// Returns `true` if the field is a proto2 `required` field.
+    pub fn is_required(&self) -> bool {
+        match *self {
+            Field::Scalar(ref scalar) => matches!(scalar.kind, scalar::Kind::Required(..)),
+            Field::Message(ref message) => message.label == Label::Required,
+            Field::Group(ref group) => group.label == Label::Required,
+            Field::Map(..) | Field::Oneof(..) => false,
+        }
+    }
+
+    /// Returns `true` if the field may hold several values, i.e. it is a repeated or map field.
+    pub fn is_repeated(&self) -> bool {
+        match *self {
+            Field::Scalar(ref scalar) => {
+                matches!(scalar.kind, scalar::Kind::Repeated | scalar::Kind::Packed)
+            }
+            Field::Message(ref message) => message.label == Label::Repeated,
+            Field::Group(ref group) => group.label == Label::Repeated,
+            Field::Map(..) => true,
+            Field::Oneof(..) => false,
+        }
+    }
+
+    /// Returns an expression which evaluates to the result of checking the required fields of
+    /// the messages held by the field, or `None` if the field can not hold messages.
+    pub fn check_initialized(&self, ident: TokenStream) -> Option<TokenStream> {
+        match *self {
+            Field::Scalar(..) => None,
+            Field::Message(ref message) => Some(message.check_initialized(ident)),
+            Field::Map(ref map) => map.check_initialized(ident),
+            Field::Oneof(ref oneof) => Some(oneof.check_initialized(ident)),
+            Field::Group(ref group) => Some(group.check_initialized(ident)),
+        }
+    }
+
+    /// Returns an expression which evaluates to `true` if the field holds its default value: a
+    /// singular field equal to its default, an unset optional field or oneof, or an empty
+    /// repeated field or map.
+    pub fn is_default(&self, ident: TokenStream) -> TokenStream {
+        match *self {
+            Field::Scalar(ref scalar) => scalar.is_default(ident),
+            Field::Message(ref message) => message.is_default(ident),
+            Field::Map(..) => quote!(#ident.is_empty()),
+            Field::Oneof(..) => quote!(#ident.is_none()),
+            Field::Group(ref group) => group.is_default(ident),
+        }
+    }
+
     /// Returns a statement which clears the field.
     pub fn clear(&self, ident: TokenStream) -> TokenStream {
         match *self {
@@ -132,6 +265,42 @@ impl Field {
         }
     }
 
+    /// Returns a statement which writes the field to a JSON writer as the object member `key`,
+    /// unless the field is unset.
+    pub fn json(&self, key: &str, ident: TokenStream) -> TokenStream {
+        match *self {
+            Field::Scalar(ref scalar) => scalar.json(key, ident),
+            Field::Message(ref message) => message.json(key, ident),
+            Field::Map(ref map) => map.json(key, ident),
+            Field::Oneof(ref oneof) => oneof.json(ident),
+            Field::Group(ref group) => group.json(key, ident),
+        }
+    }
+
+    /// Returns a statement which writes the field to a text format writer under `name`, unless
+    /// the field is unset.
+    pub fn text_format(&self, name: &str, ident: TokenStream) -> TokenStream {
+        match *self {
+            Field::Scalar(ref scalar) => scalar.text_format(name, ident),
+            Field::Message(ref message) => message.text_format(name, ident),
+            Field::Map(ref map) => map.text_format(name, ident),
+            Field::Oneof(ref oneof) => oneof.text_format(ident),
+            Field::Group(ref group) => group.text_format(name, ident),
+        }
+    }
+
+    /// Returns a statement which passes the field to a `FieldVisitor` under `name`, unless the
+    /// field is unset.
+    pub fn reflect(&self, name: &str, ident: TokenStream) -> TokenStream {
+        match *self {
+            Field::Scalar(ref scalar) => scalar.reflect(name, ident),
+            Field::Message(ref message) => message.reflect(name, ident),
+            Field::Map(ref map) => map.reflect(name, ident),
+            Field::Oneof(ref oneof) => oneof.reflect(ident),
+            Field::Group(ref group) => group.reflect(name, ident),
+        }
+    }
+
     pub fn default(&self) -> TokenStream {
         match *self {
             Field::Scalar(ref scalar) => scalar.default(),
@@ -168,11 +337,94 @@ impl Field {
         match *self {
             Field::Scalar(ref scalar) => scalar.methods(ident),
             Field::Map(ref map) => map.methods(ident),
+            Field::Oneof(ref oneof) => Some(oneof.methods(ident)),
             _ => None,
         }
     }
 }
 
+/// A message field which is not itself encoded, but which is maintained by the derived `Message`
+/// implementation.
+#[derive(Clone, PartialEq, Eq)]
+pub enum SpecialField {
+    /// The message's unknown field list, i.e. `#[prost(unknown_fields)]`.
+    UnknownFields,
+    /// The message's cached encoded length, i.e. `#[prost(cached_size)]`.
+    CachedSize,
+    /// The message's extension set, i.e. `#[prost(extensions = "...")]`, with the inclusive tag
+    /// ranges reserved for extensions.
+    Extensions(Vec<(u32, u32)>),
+    /// The message's record of set required fields, i.e. `#[prost(presence)]`.
+    Presence,
+}
+
+impl SpecialField {
+    /// Returns the special field marked by the field attributes, if any.
+    pub fn from_attrs(attrs: &[Attribute]) -> Result<Option<SpecialField>, Error> {
+        let attrs = prost_attrs(attrs.to_vec());
+        let extensions = attrs.iter().find_map(|attr| match *attr {
+            Meta::NameValue(MetaNameValue {
+                ref path,
+                lit: Lit::Str(ref lit),
+                ..
+            }) if path.is_ident("extensions") => Some(lit.value()),
+            _ => None,
+        });
+        let special = if attrs.iter().any(|attr| word_attr("unknown_fields", attr)) {
+            SpecialField::UnknownFields
+        } else if attrs.iter().any(|attr| word_attr("cached_size", attr)) {
+            SpecialField::CachedSize
+        } else if attrs.iter().any(|attr| word_attr("presence", attr)) {
+            SpecialField::Presence
+        } else if let Some(extensions) = extensions {
+            SpecialField::Extensions(tag_ranges(&extensions)?)
+        } else {
+            return Ok(None);
+        };
+        if attrs.len() > 1 {
+            bail!(
+                "{} attribute may not be combined with other attributes",
+                special.as_str()
+            );
+        }
+        Ok(Some(special))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            SpecialField::UnknownFields => "unknown_fields",
+            SpecialField::CachedSize => "cached_size",
+            SpecialField::Extensions(..) => "extensions",
+            SpecialField::Presence => "presence",
+        }
+    }
+}
+
+/// The largest tag allowed by Protobuf, i.e. `2^29 - 1`.
+const MAX_TAG: u32 = (1 << 29) - 1;
+
+/// Parses a list of inclusive tag ranges, e.g. `"2, 9 to 11, 1000 to max"`.
+pub fn tag_ranges(value: &str) -> Result<Vec<(u32, u32)>, Error> {
+    let mut ranges = Vec::new();
+    for range in value.split(',') {
+        let mut bounds = range.splitn(2, " to ").map(str::trim);
+        let start = bounds.next().unwrap_or_default();
+        let end = bounds.next().unwrap_or(start);
+        let parse = |bound: &str| match bound {
+            "max" => Ok(MAX_TAG),
+            _ => bound
+                .parse::<u32>()
+                .map_err(|_| anyhow!("invalid tag range: {:?}", range.trim())),
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start == 0 || start > end || end > MAX_TAG {
+            bail!("invalid tag range: {:?}", range.trim());
+        }
+        ranges.push((start, end));
+    }
+    Ok(ranges)
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Label {
     /// An optional field.
@@ -224,7 +476,7 @@ impl fmt::Display for Label {
 }
 
 /// Get the items belonging to the 'prost' list attribute, e.g. `#[prost(foo, bar="baz")]`.
-fn prost_attrs(attrs: Vec<Attribute>) -> Vec<Meta> {
+pub fn prost_attrs(attrs: Vec<Attribute>) -> Vec<Meta> {
     attrs
         .iter()
         .flat_map(Attribute::parse_meta)
@@ -247,6 +499,132 @@ fn prost_attrs(attrs: Vec<Attribute>) -> Vec<Meta> {
         .collect()
 }
 
+/// Returns the Protobuf name of a field from its Rust identifier, i.e. the identifier in
+/// snake_case. Message fields are already named in snake_case, while oneof variants are named in
+/// UpperCamelCase.
+pub fn proto_name(ident: &str) -> String {
+    let ident = ident.strip_prefix("r#").unwrap_or(ident);
+    let mut name = String::with_capacity(ident.len());
+    for (i, c) in ident.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                name.push('_');
+            }
+            name.extend(c.to_lowercase());
+        } else {
+            name.push(c);
+        }
+    }
+    name
+}
+
+/// Returns the JSON name of a field, i.e. the lowerCamelCase form of its Protobuf name.
+///
+/// Like `protoc`, this removes underscores and converts the character following each underscore
+/// to uppercase.
+pub fn json_name(proto_name: &str) -> String {
+    let mut name = String::with_capacity(proto_name.len());
+    let mut capitalize_next = false;
+    for c in proto_name.chars() {
+        if c == '_' {
+            capitalize_next = true;
+        } else if capitalize_next {
+            name.extend(c.to_uppercase());
+            capitalize_next = false;
+        } else {
+            name.push(c);
+        }
+    }
+    name
+}
+
+/// Returns the JSON name set by the field attributes, i.e. `#[prost(json_name = "...")]`, if any.
+pub fn json_name_attr(attrs: &[Attribute]) -> Result<Option<String>, Error> {
+    let mut json_name = None;
+    for attr in prost_attrs(attrs.to_vec()) {
+        if !attr.path().is_ident("json_name") {
+            continue;
+        }
+        match attr {
+            Meta::NameValue(MetaNameValue {
+                lit: Lit::Str(ref lit),
+                ..
+            }) if !lit.value().is_empty() => set_option(
+                &mut json_name,
+                lit.value(),
+                "duplicate json_name attributes",
+            )?,
+            _ => bail!("invalid json_name attribute: {:?}", attr),
+        }
+    }
+    Ok(json_name)
+}
+
+/// Returns the tags listed by the field attributes, i.e. `#[prost(alias_tag = "...")]`, which the
+/// field is decoded from besides its own tag.
+pub fn alias_tags_attr(attrs: &[Attribute]) -> Result<Vec<u32>, Error> {
+    let mut alias_tags = Vec::new();
+    for attr in prost_attrs(attrs.to_vec()) {
+        if !attr.path().is_ident("alias_tag") {
+            continue;
+        }
+        match attr {
+            Meta::NameValue(MetaNameValue {
+                lit: Lit::Str(ref lit),
+                ..
+            }) => {
+                for tag in lit.value().split(',') {
+                    match tag.trim().parse::<u32>() {
+                        Ok(tag) if tag != 0 && tag <= MAX_TAG => alias_tags.push(tag),
+                        _ => bail!("invalid alias_tag attribute: {:?}", lit.value()),
+                    }
+                }
+            }
+            _ => bail!("invalid alias_tag attribute: {:?}", attr),
+        }
+    }
+    Ok(alias_tags)
+}
+
+/// Returns a statement which passes each element of the repeated field or map `ident` to a
+/// `FieldVisitor` with `visit`, between calls to `begin_repeated` and `end_repeated`, unless it
+/// is empty. `pattern` binds the elements.
+pub fn reflect_repeated(
+    tag: u32,
+    name: &str,
+    ident: TokenStream,
+    pattern: TokenStream,
+    visit: TokenStream,
+) -> TokenStream {
+    quote! {
+        if !#ident.is_empty() {
+            visitor.begin_repeated(#tag, #name, #ident.len());
+            for #pattern in &#ident {
+                #visit;
+            }
+            visitor.end_repeated(#tag, #name);
+        }
+    }
+}
+
+/// Returns a statement which reports the lengths of the fields of the embedded messages held by
+/// the message or group field `ident`.
+fn nested_field_lens(label: Label, ident: TokenStream) -> TokenStream {
+    match label {
+        Label::Optional => quote! {
+            if let Some(ref msg) = #ident {
+                ::prost::Message::field_lens(msg, path, record);
+            }
+        },
+        Label::Required => quote!(::prost::Message::field_lens(&#ident, path, record);),
+        Label::Repeated => quote! {
+            for msg in &#ident {
+                ::prost::Message::field_lens(msg, path, record);
+            }
+        },
+    }
+}
+
 pub fn set_option<T>(option: &mut Option<T>, value: T, message: &str) -> Result<(), Error>
 where
     T: fmt::Debug,
@@ -1,7 +1,7 @@
 use anyhow::{bail, Error};
-use proc_macro2::TokenStream;
+use proc_macro2::{Span, TokenStream};
 use quote::quote;
-use syn::{parse_str, Lit, Meta, MetaNameValue, NestedMeta, Path};
+use syn::{parse_str, Ident, Lit, Meta, MetaNameValue, NestedMeta, Path, PathArguments};
 
 use crate::field::{set_option, tags_attr};
 
@@ -9,12 +9,15 @@ use crate::field::{set_option, tags_attr};
 pub struct Field {
     pub ty: Path,
     pub tags: Vec<u32>,
+    /// Whether the oneof derives a case enum, and so the message has a `which_` method.
+    pub case: bool,
 }
 
 impl Field {
     pub fn new(attrs: &[Meta]) -> Result<Option<Field>, Error> {
         let mut ty = None;
         let mut tags = None;
+        let mut case = false;
         let mut unknown_attrs = Vec::new();
 
         for attr in attrs {
@@ -41,6 +44,8 @@ impl Field {
                 set_option(&mut ty, t, "duplicate oneof attribute")?;
             } else if let Some(t) = tags_attr(attr)? {
                 set_option(&mut tags, t, "duplicate tags attributes")?;
+            } else if matches!(*attr, Meta::Path(ref path) if path.is_ident("case")) {
+                case = true;
             } else {
                 unknown_attrs.push(attr);
             }
@@ -65,7 +70,7 @@ impl Field {
             None => bail!("oneof field is missing a tags attribute"),
         };
 
-        Ok(Some(Field { ty, tags }))
+        Ok(Some(Field { ty, tags, case }))
     }
 
     /// Returns a statement which encodes the oneof field.
@@ -77,6 +82,26 @@ impl Field {
         }
     }
 
+    /// Returns a statement which encodes the oneof field, reusing the cached lengths of embedded
+    /// messages.
+    pub fn encode_cached(&self, ident: TokenStream) -> TokenStream {
+        quote! {
+            if let Some(ref oneof) = #ident {
+                oneof.encode_cached(buf)
+            }
+        }
+    }
+
+    /// Returns a statement which encodes the oneof field canonically, if its current variant has
+    /// the given tag.
+    pub fn encode_canonical(&self, ident: TokenStream, tag: u32) -> TokenStream {
+        quote! {
+            if let Some(ref oneof) = #ident {
+                oneof.encode_canonical(#tag, buf)
+            }
+        }
+    }
+
     /// Returns an expression which evaluates to the result of decoding the oneof field.
     pub fn merge(&self, ident: TokenStream) -> TokenStream {
         let ty = &self.ty;
@@ -93,7 +118,98 @@ impl Field {
         }
     }
 
+    /// Returns a statement which reports the encoded length of the active field of the oneof to
+    /// `record`, see `Message::encode_with_stats`.
+    pub fn field_lens(&self, ident: TokenStream) -> TokenStream {
+        quote! {
+            if let Some(ref oneof) = #ident {
+                oneof.field_lens(path, record);
+            }
+        }
+    }
+
+    /// Returns a statement which writes the oneof field to a JSON writer.
+    pub fn json(&self, ident: TokenStream) -> TokenStream {
+        quote! {
+            if let Some(ref oneof) = #ident {
+                oneof.write_json(writer);
+            }
+        }
+    }
+
+    /// Returns a statement which writes the oneof field to a text format writer.
+    pub fn text_format(&self, ident: TokenStream) -> TokenStream {
+        quote! {
+            if let Some(ref oneof) = #ident {
+                oneof.write_text_format(writer);
+            }
+        }
+    }
+
+    /// Returns a statement which passes the active field of the oneof to a `FieldVisitor`.
+    pub fn reflect(&self, ident: TokenStream) -> TokenStream {
+        quote! {
+            if let Some(ref oneof) = #ident {
+                oneof.reflect_fields(visitor);
+            }
+        }
+    }
+
+    /// Returns an expression which evaluates to the result of checking the required fields of
+    /// the oneof field's active variant.
+    pub fn check_initialized(&self, ident: TokenStream) -> TokenStream {
+        let ty = &self.ty;
+        quote! {
+            #ident.as_ref().map_or(::core::result::Result::Ok(()), #ty::check_initialized)
+        }
+    }
+
     pub fn clear(&self, ident: TokenStream) -> TokenStream {
         quote!(#ident = ::core::option::Option::None)
     }
+
+    /// Returns the `clear_` method of the oneof field, and its `which_` method if the field has the
+    /// `case` attribute.
+    pub fn methods(&self, ident: &TokenStream) -> TokenStream {
+        let ident_str = ident.to_string();
+        let ident_str = ident_str.strip_prefix("r#").unwrap_or(&ident_str);
+
+        let which = if self.case {
+            let which = Ident::new(&format!("which_{}", ident_str), Span::call_site());
+            let which_doc = format!(
+                "Returns the case of `{}`, or `None` if it is unset.",
+                ident_str
+            );
+            let case_ty = self.case_ty();
+            quote! {
+                #[doc=#which_doc]
+                pub fn #which(&self) -> ::core::option::Option<#case_ty> {
+                    self.#ident.as_ref().map(|oneof| oneof.case())
+                }
+            }
+        } else {
+            quote!()
+        };
+        let clear = Ident::new(&format!("clear_{}", ident_str), Span::call_site());
+        let clear_doc = format!("Unsets `{}`, whichever of its fields is set.", ident_str);
+        quote! {
+            #which
+
+            #[doc=#clear_doc]
+            pub fn #clear(&mut self) {
+                self.#ident = ::core::option::Option::None;
+            }
+        }
+    }
+
+    /// Returns the path of the case enum derived alongside the oneof, e.g. `foo::BarCase` for
+    /// the oneof `foo::Bar`.
+    fn case_ty(&self) -> Path {
+        let mut ty = self.ty.clone();
+        if let Some(last) = ty.segments.last_mut() {
+            last.ident = Ident::new(&format!("{}Case", last.ident), last.ident.span());
+            last.arguments = PathArguments::None;
+        }
+        ty
+    }
 }
@@ -8,7 +8,7 @@ use syn::{
     parse_str, Ident, Index, Lit, LitByteStr, Meta, MetaList, MetaNameValue, NestedMeta, Path,
 };
 
-use crate::field::{bool_attr, set_option, tag_attr, Label};
+use crate::field::{bool_attr, reflect_repeated, set_option, tag_attr, Label};
 
 /// A scalar protobuf field.
 #[derive(Clone)]
@@ -16,15 +16,38 @@ pub struct Field {
     pub ty: Ty,
     pub kind: Kind,
     pub tag: u32,
+    pub unknown: Unknown,
+    /// The path of the module generated by `prost::newtype_field!` for the type of the field,
+    /// which wraps a value of `ty`.
+    pub newtype: Option<Path>,
 }
 
 impl Field {
     pub fn new(attrs: &[Meta], inferred_tag: Option<u32>) -> Result<Option<Field>, Error> {
+        match Field::parse(attrs, inferred_tag)? {
+            // A non-zero field has no default value for a message to be initialized with.
+            Some(Field {
+                ty: Ty::NonZero(..),
+                kind: Kind::Plain(..),
+                ..
+            })
+            | Some(Field {
+                ty: Ty::NonZero(..),
+                kind: Kind::Required(..),
+                ..
+            }) => bail!("non_zero fields must be optional or repeated"),
+            field => Ok(field),
+        }
+    }
+
+    fn parse(attrs: &[Meta], inferred_tag: Option<u32>) -> Result<Option<Field>, Error> {
         let mut ty = None;
         let mut label = None;
         let mut packed = None;
         let mut default = None;
         let mut tag = None;
+        let mut unknown = None;
+        let mut newtype = None;
 
         let mut unknown_attrs = Vec::new();
 
@@ -39,6 +62,10 @@ impl Field {
                 set_option(&mut label, l, "duplicate label attributes")?;
             } else if let Some(d) = DefaultValue::from_attr(attr)? {
                 set_option(&mut default, d, "duplicate default attributes")?;
+            } else if let Some(u) = Unknown::from_attr(attr)? {
+                set_option(&mut unknown, u, "duplicate unknown attributes")?;
+            } else if let Some(n) = newtype_attr(attr)? {
+                set_option(&mut newtype, n, "duplicate newtype attributes")?;
             } else {
                 unknown_attrs.push(attr);
             }
@@ -60,6 +87,34 @@ impl Field {
             None => bail!("missing tag attribute"),
         };
 
+        if unknown.is_some() && !matches!(ty, Ty::Enumeration(..)) {
+            bail!("unknown attribute may only be applied to enumeration fields");
+        }
+        let unknown = unknown.unwrap_or(Unknown::Preserve);
+
+        if newtype.is_some() {
+            match ty {
+                Ty::Enumeration(..)
+                | Ty::NonZero(..)
+                | Ty::Char
+                | Ty::String(StringTy::Boxed)
+                | Ty::String(StringTy::Arc)
+                | Ty::Bytes(BytesTy::Boxed)
+                | Ty::Bytes(BytesTy::Array)
+                | Ty::Bytes(BytesTy::IpAddr)
+                | Ty::Bytes(BytesTy::SocketAddr) => {
+                    bail!(
+                        "newtype attribute may only be applied to numeric, string, \
+                         and vec or bytes bytes fields"
+                    )
+                }
+                _ => (),
+            }
+            if default.is_some() {
+                bail!("newtype fields may not have a default value");
+            }
+        }
+
         let has_default = default.is_some();
         let default = default.map_or_else(
             || Ok(DefaultValue::new(&ty)),
@@ -88,11 +143,17 @@ impl Field {
             (Some(Label::Repeated), _, false) => Kind::Repeated,
         };
 
-        Ok(Some(Field { ty, kind, tag }))
+        Ok(Some(Field {
+            ty,
+            kind,
+            tag,
+            unknown,
+            newtype,
+        }))
     }
 
     pub fn new_oneof(attrs: &[Meta]) -> Result<Option<Field>, Error> {
-        if let Some(mut field) = Field::new(attrs, None)? {
+        if let Some(mut field) = Field::parse(attrs, None)? {
             match field.kind {
                 Kind::Plain(default) => {
                     field.kind = Kind::Required(default);
@@ -108,20 +169,21 @@ impl Field {
     }
 
     pub fn encode(&self, ident: TokenStream) -> TokenStream {
-        let module = self.ty.module();
+        let module = self.module();
         let encode_fn = match self.kind {
             Kind::Plain(..) | Kind::Optional(..) | Kind::Required(..) => quote!(encode),
             Kind::Repeated => quote!(encode_repeated),
             Kind::Packed => quote!(encode_packed),
         };
-        let encode_fn = quote!(::prost::encoding::#module::#encode_fn);
+        let encode_fn = quote!(#module::#encode_fn);
         let tag = self.tag;
 
         match self.kind {
             Kind::Plain(ref default) => {
                 let default = default.typed();
+                let value = self.comparable(&ident);
                 quote! {
-                    if #ident != #default {
+                    if #value != #default {
                         #encode_fn(#tag, &#ident, buf);
                     }
                 }
@@ -140,42 +202,105 @@ impl Field {
     /// Returns an expression which evaluates to the result of merging a decoded
     /// scalar value into the field.
     pub fn merge(&self, ident: TokenStream) -> TokenStream {
-        let module = self.ty.module();
+        let module = self.module();
         let merge_fn = match self.kind {
             Kind::Plain(..) | Kind::Optional(..) | Kind::Required(..) => quote!(merge),
             Kind::Repeated | Kind::Packed => quote!(merge_repeated),
         };
-        let merge_fn = quote!(::prost::encoding::#module::#merge_fn);
+        let merge_fn = quote!(#module::#merge_fn);
 
-        match self.kind {
+        let merge = match self.kind {
             Kind::Plain(..) | Kind::Required(..) | Kind::Repeated | Kind::Packed => quote! {
                 #merge_fn(wire_type, #ident, buf, ctx)
             },
-            Kind::Optional(..) => quote! {
-                #merge_fn(wire_type,
-                          #ident.get_or_insert_with(::core::default::Default::default),
-                          buf,
-                          ctx)
+            Kind::Optional(..) if matches!(self.ty, Ty::NonZero(..) | Ty::Char) => {
+                // Decode into a placeholder, so that an invalid value leaves the field unchanged.
+                let placeholder = if self.ty.has_default_fn() {
+                    quote!(#module::default())
+                } else {
+                    quote!(::core::default::Default::default())
+                };
+                quote! {
+                    {
+                        let mut decoded = #placeholder;
+                        #merge_fn(wire_type, &mut decoded, buf, ctx)
+                            .map(|()| *#ident = ::core::option::Option::Some(decoded))
+                    }
+                }
+            }
+            Kind::Optional(..) => {
+                let default = if self.ty.has_default_fn() || self.newtype.is_some() {
+                    quote!(#module::default)
+                } else {
+                    quote!(::core::default::Default::default)
+                };
+                quote! {
+                    #merge_fn(wire_type,
+                              #ident.get_or_insert_with(#default),
+                              buf,
+                              ctx)
+                }
+            }
+        };
+
+        match (&self.ty, self.unknown) {
+            (Ty::Enumeration(ref ty), Unknown::Clamp) => self.clamp(ty, ident, merge),
+            _ => merge,
+        }
+    }
+
+    /// Wraps `merge`, which decodes into the enumeration field `ident`, so that the decoded
+    /// values which are not variants of `ty` are replaced by its default variant.
+    fn clamp(&self, ty: &Path, ident: TokenStream, merge: TokenStream) -> TokenStream {
+        let clamp = quote! {
+            |value: &mut i32| if !#ty::is_valid(*value) {
+                *value = <#ty as ::core::default::Default>::default() as i32;
+            }
+        };
+        match self.kind {
+            Kind::Plain(..) | Kind::Required(..) => quote!(#merge.map(|()| (#clamp)(#ident))),
+            Kind::Optional(..) => quote!(#merge.map(|()| #ident.iter_mut().for_each(#clamp))),
+            Kind::Repeated | Kind::Packed => quote! {
+                {
+                    // Only the values decoded by this merge are clamped.
+                    let len = #ident.len();
+                    #merge.map(|()| #ident[len..].iter_mut().for_each(#clamp))
+                }
             },
         }
     }
 
+    /// Returns the wire types the field may be decoded from. Repeated numeric fields accept both
+    /// the packed and unpacked encodings, regardless of how they are encoded.
+    pub fn wire_types(&self) -> Vec<TokenStream> {
+        let module = self.module();
+        let wire_type = quote!(#module::WIRE_TYPE);
+        match self.kind {
+            Kind::Repeated | Kind::Packed if self.ty.is_numeric() => vec![
+                wire_type,
+                quote!(::prost::encoding::WireType::LengthDelimited),
+            ],
+            _ => vec![wire_type],
+        }
+    }
+
     /// Returns an expression which evaluates to the encoded length of the field.
     pub fn encoded_len(&self, ident: TokenStream) -> TokenStream {
-        let module = self.ty.module();
+        let module = self.module();
         let encoded_len_fn = match self.kind {
             Kind::Plain(..) | Kind::Optional(..) | Kind::Required(..) => quote!(encoded_len),
             Kind::Repeated => quote!(encoded_len_repeated),
             Kind::Packed => quote!(encoded_len_packed),
         };
-        let encoded_len_fn = quote!(::prost::encoding::#module::#encoded_len_fn);
+        let encoded_len_fn = quote!(#module::#encoded_len_fn);
         let tag = self.tag;
 
         match self.kind {
             Kind::Plain(ref default) => {
                 let default = default.typed();
+                let value = self.comparable(&ident);
                 quote! {
-                    if #ident != #default {
+                    if #value != #default {
                         #encoded_len_fn(#tag, &#ident)
                     } else {
                         0
@@ -191,32 +316,257 @@ impl Field {
         }
     }
 
-    pub fn clear(&self, ident: TokenStream) -> TokenStream {
+    pub fn is_default(&self, ident: TokenStream) -> TokenStream {
         match self.kind {
             Kind::Plain(ref default) | Kind::Required(ref default) => {
                 let default = default.typed();
-                match self.ty {
-                    Ty::String | Ty::Bytes(..) => quote!(#ident.clear()),
-                    _ => quote!(#ident = #default),
-                }
+                let value = self.comparable(&ident);
+                quote!(#value == #default)
             }
+            Kind::Optional(..) => quote!(#ident.is_none()),
+            Kind::Repeated | Kind::Packed => quote!(#ident.is_empty()),
+        }
+    }
+
+    pub fn clear(&self, ident: TokenStream) -> TokenStream {
+        match self.kind {
+            Kind::Plain(..) | Kind::Required(..) if self.newtype.is_some() => {
+                let module = self.module();
+                quote!(#ident = #module::default())
+            }
+            Kind::Plain(ref default) | Kind::Required(ref default) => match self.ty {
+                Ty::String(StringTy::String)
+                | Ty::Bytes(BytesTy::Vec)
+                | Ty::Bytes(BytesTy::Bytes) => quote!(#ident.clear()),
+                Ty::String(StringTy::Boxed)
+                | Ty::String(StringTy::Arc)
+                | Ty::Bytes(BytesTy::Boxed) => {
+                    let default = self.owned_default(default);
+                    quote!(#ident = #default)
+                }
+                _ => {
+                    let default = default.typed();
+                    quote!(#ident = #default)
+                }
+            },
             Kind::Optional(_) => quote!(#ident = ::core::option::Option::None),
             Kind::Repeated | Kind::Packed => quote!(#ident.clear()),
         }
     }
 
+    /// Returns a statement which writes the field to a JSON writer, unless it holds its default
+    /// value.
+    pub fn json(&self, key: &str, ident: TokenStream) -> TokenStream {
+        match self.kind {
+            Kind::Plain(ref default) => {
+                let default = default.typed();
+                let value = self.comparable(&ident);
+                let write = self.write(quote!(&#ident));
+                quote! {
+                    if #value != #default {
+                        writer.key(#key);
+                        #write;
+                    }
+                }
+            }
+            Kind::Optional(..) => {
+                let write = self.write(quote!(value));
+                quote! {
+                    if let ::core::option::Option::Some(ref value) = #ident {
+                        writer.key(#key);
+                        #write;
+                    }
+                }
+            }
+            Kind::Required(..) => {
+                let write = self.write(quote!(&#ident));
+                quote! {
+                    writer.key(#key);
+                    #write;
+                }
+            }
+            Kind::Repeated | Kind::Packed => {
+                let write = self.write(quote!(value));
+                quote! {
+                    if !#ident.is_empty() {
+                        writer.key(#key);
+                        writer.begin_array();
+                        for value in &#ident {
+                            #write;
+                        }
+                        writer.end_array();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns a statement which writes the field to a text format writer, unless it holds its
+    /// default value.
+    pub fn text_format(&self, name: &str, ident: TokenStream) -> TokenStream {
+        match self.kind {
+            Kind::Plain(ref default) => {
+                let default = default.typed();
+                let value = self.comparable(&ident);
+                let write = self.write(quote!(&#ident));
+                quote! {
+                    if #value != #default {
+                        writer.key(#name);
+                        #write;
+                    }
+                }
+            }
+            Kind::Optional(..) => {
+                let write = self.write(quote!(value));
+                quote! {
+                    if let ::core::option::Option::Some(ref value) = #ident {
+                        writer.key(#name);
+                        #write;
+                    }
+                }
+            }
+            Kind::Required(..) => {
+                let write = self.write(quote!(&#ident));
+                quote! {
+                    writer.key(#name);
+                    #write;
+                }
+            }
+            Kind::Repeated | Kind::Packed => {
+                let write = self.write(quote!(value));
+                quote! {
+                    for value in &#ident {
+                        writer.key(#name);
+                        #write;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns a statement which passes the field to a `FieldVisitor`, unless it holds its default
+    /// value.
+    pub fn reflect(&self, name: &str, ident: TokenStream) -> TokenStream {
+        let tag = self.tag;
+        match self.kind {
+            Kind::Plain(ref default) => {
+                let default = default.typed();
+                let value = self.comparable(&ident);
+                let visit = self.visit(tag, name, quote!(&#ident));
+                quote! {
+                    if #value != #default {
+                        #visit;
+                    }
+                }
+            }
+            Kind::Optional(..) => {
+                let visit = self.visit(tag, name, quote!(value));
+                quote! {
+                    if let ::core::option::Option::Some(ref value) = #ident {
+                        #visit;
+                    }
+                }
+            }
+            Kind::Required(..) => {
+                let visit = self.visit(tag, name, quote!(&#ident));
+                quote!(#visit;)
+            }
+            Kind::Repeated | Kind::Packed => reflect_repeated(
+                tag,
+                name,
+                ident,
+                quote!(value),
+                self.visit(tag, name, quote!(value)),
+            ),
+        }
+    }
+
     /// Returns an expression which evaluates to the default value of the field.
     pub fn default(&self) -> TokenStream {
         match self.kind {
-            Kind::Plain(ref value) | Kind::Required(ref value) => value.owned(),
+            Kind::Plain(ref value) | Kind::Required(ref value) => self.owned_default(value),
             Kind::Optional(_) => quote!(::core::option::Option::None),
             Kind::Repeated | Kind::Packed => quote!(::prost::alloc::vec::Vec::new()),
         }
     }
 
+    /// Returns the path of the module holding the encoding functions of the field.
+    fn module(&self) -> TokenStream {
+        match self.newtype {
+            Some(ref path) => quote!(#path),
+            None => {
+                let module = self.ty.module();
+                quote!(::prost::encoding::#module)
+            }
+        }
+    }
+
+    /// Returns an expression for the value referenced by `value`, as the Rust type of the field's
+    /// Protobuf type. A newtype is converted by its module; numeric values are converted to the
+    /// type named by the caller, and other values are borrowed.
+    fn proto_value(&self, value: TokenStream) -> TokenStream {
+        match self.newtype {
+            Some(ref path) if self.ty.is_numeric() => {
+                let ty = self.ty.rust_type();
+                quote!(#path::to_proto::<#ty>(#value))
+            }
+            Some(ref path) => quote!(#path::to_proto(#value)),
+            None => value,
+        }
+    }
+
+    /// Like `Ty::comparable`, for the field `ident`.
+    fn comparable(&self, ident: &TokenStream) -> TokenStream {
+        if self.newtype.is_some() {
+            self.proto_value(quote!(&#ident))
+        } else {
+            self.ty.comparable(ident)
+        }
+    }
+
+    /// Like `Ty::write`, for the value of the field referenced by `value`.
+    fn write(&self, value: TokenStream) -> TokenStream {
+        if self.newtype.is_some() && self.ty.is_numeric() {
+            let value = self.proto_value(value);
+            self.ty.write(quote!(&#value))
+        } else {
+            self.ty.write(self.proto_value(value))
+        }
+    }
+
+    /// Like `Ty::visit`, for the value of the field referenced by `value`.
+    fn visit(&self, tag: u32, name: &str, value: TokenStream) -> TokenStream {
+        if self.newtype.is_some() && self.ty.is_numeric() {
+            let value = self.proto_value(value);
+            self.ty.visit(tag, name, quote!(&#value))
+        } else {
+            self.ty.visit(tag, name, self.proto_value(value))
+        }
+    }
+
+    /// Returns an expression which evaluates to `default`, as the type of the field.
+    fn owned_default(&self, default: &DefaultValue) -> TokenStream {
+        if self.newtype.is_some() {
+            let module = self.module();
+            return quote!(#module::default());
+        }
+        match (&self.ty, default) {
+            // `Arc<str>` only implements `Default` from Rust 1.80.
+            (Ty::String(StringTy::Arc), DefaultValue::String(value)) => {
+                quote!(::prost::alloc::sync::Arc::<str>::from(#value))
+            }
+            _ => default.owned(),
+        }
+    }
+
     /// An inner debug wrapper, around the base type.
     fn debug_inner(&self, wrap_name: TokenStream) -> TokenStream {
-        if let Ty::Enumeration(ref ty) = self.ty {
+        if self.newtype.is_some() {
+            // A newtype is formatted by its own `Debug` implementation.
+            quote! {
+                fn #wrap_name<T>(v: T) -> T { v }
+            }
+        } else if let Ty::Enumeration(ref ty) = self.ty {
             quote! {
                 struct #wrap_name<'a>(&'a i32);
                 impl<'a> ::core::fmt::Debug for #wrap_name<'a> {
@@ -228,6 +578,15 @@ impl Field {
                     }
                 }
             }
+        } else if matches!(self.ty, Ty::Bytes(..)) && !self.ty.is_address() {
+            quote! {
+                fn #wrap_name<T>(v: &T) -> ::prost::DebugBytes<'_>
+                where
+                    T: ::core::convert::AsRef<[u8]> + ?Sized,
+                {
+                    ::prost::DebugBytes(v.as_ref())
+                }
+            }
         } else {
             quote! {
                 fn #wrap_name<T>(v: T) -> T { v }
@@ -239,11 +598,19 @@ impl Field {
     pub fn debug(&self, wrapper_name: TokenStream) -> TokenStream {
         let wrapper = self.debug_inner(quote!(Inner));
         let inner_ty = self.ty.rust_type();
+        // The length of a fixed-size bytes field is only known to the compiler, so its wrapper is
+        // generic over the length.
+        let (impl_generics, ty_generics) = if self.ty == Ty::Bytes(BytesTy::Array) {
+            (quote!(<'a, const N: usize>), quote!(<'a, N>))
+        } else {
+            (quote!(<'a>), quote!(<'a>))
+        };
         match self.kind {
             Kind::Plain(_) | Kind::Required(_) => self.debug_inner(wrapper_name),
+            _ if self.newtype.is_some() => self.debug_inner(wrapper_name),
             Kind::Optional(_) => quote! {
-                struct #wrapper_name<'a>(&'a ::core::option::Option<#inner_ty>);
-                impl<'a> ::core::fmt::Debug for #wrapper_name<'a> {
+                struct #wrapper_name #impl_generics(&'a ::core::option::Option<#inner_ty>);
+                impl #impl_generics ::core::fmt::Debug for #wrapper_name #ty_generics {
                     fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
                         #wrapper
                         ::core::fmt::Debug::fmt(&self.0.as_ref().map(Inner), f)
@@ -252,8 +619,8 @@ impl Field {
             },
             Kind::Repeated | Kind::Packed => {
                 quote! {
-                    struct #wrapper_name<'a>(&'a ::prost::alloc::vec::Vec<#inner_ty>);
-                    impl<'a> ::core::fmt::Debug for #wrapper_name<'a> {
+                    struct #wrapper_name #impl_generics(&'a ::prost::alloc::vec::Vec<#inner_ty>);
+                    impl #impl_generics ::core::fmt::Debug for #wrapper_name #ty_generics {
                         fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
                             let mut vec_builder = f.debug_list();
                             for v in self.0 {
@@ -346,10 +713,19 @@ impl Field {
                     }
                 }
             })
+        } else if self.ty == Ty::Bytes(BytesTy::Array) {
+            // The default value of a fixed-size bytes field can not be returned by reference
+            // without knowing its length, so no getter is generated.
+            None
+        } else if let Ty::NonZero(..) = self.ty {
+            // A non-zero field has no default value to return when it is unset.
+            None
+        } else if self.newtype.is_some() {
+            None
         } else if let Kind::Optional(ref default) = self.kind {
             let ty = self.ty.rust_ref_type();
 
-            let match_some = if self.ty.is_numeric() {
+            let match_some = if self.ty.is_numeric() || self.ty.is_address() {
                 quote!(::core::option::Option::Some(val) => val,)
             } else {
                 quote!(::core::option::Option::Some(ref val) => &val[..],)
@@ -391,15 +767,48 @@ pub enum Ty {
     Sfixed32,
     Sfixed64,
     Bool,
-    String,
+    String(StringTy),
     Bytes(BytesTy),
+    NonZero(NonZeroTy),
+    /// A `char`, which is encoded as a `uint32` holding its code point.
+    Char,
     Enumeration(Path),
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StringTy {
+    String,
+    Boxed,
+    Arc,
+}
+
+impl StringTy {
+    fn try_from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "string" => Ok(StringTy::String),
+            "boxed" => Ok(StringTy::Boxed),
+            "arc" => Ok(StringTy::Arc),
+            _ => bail!("Invalid string type: {}", s),
+        }
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        match self {
+            StringTy::String => quote! { ::prost::alloc::string::String },
+            StringTy::Boxed => quote! { ::prost::alloc::boxed::Box<str> },
+            StringTy::Arc => quote! { ::prost::alloc::sync::Arc<str> },
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum BytesTy {
     Vec,
     Bytes,
+    Boxed,
+    Array,
+    IpAddr,
+    SocketAddr,
 }
 
 impl BytesTy {
@@ -407,6 +816,10 @@ impl BytesTy {
         match s {
             "vec" => Ok(BytesTy::Vec),
             "bytes" => Ok(BytesTy::Bytes),
+            "boxed" => Ok(BytesTy::Boxed),
+            "array" => Ok(BytesTy::Array),
+            "ip_addr" => Ok(BytesTy::IpAddr),
+            "socket_addr" => Ok(BytesTy::SocketAddr),
             _ => bail!("Invalid bytes type: {}", s),
         }
     }
@@ -415,6 +828,54 @@ impl BytesTy {
         match self {
             BytesTy::Vec => quote! { ::prost::alloc::vec::Vec<u8> },
             BytesTy::Bytes => quote! { ::prost::bytes::Bytes },
+            BytesTy::Boxed => quote! { ::prost::alloc::boxed::Box<[u8]> },
+            BytesTy::Array => quote! { [u8; N] },
+            BytesTy::IpAddr => quote! { ::std::net::IpAddr },
+            BytesTy::SocketAddr => quote! { ::std::net::SocketAddr },
+        }
+    }
+}
+
+/// A non-zero integer type, such as `NonZeroU32`, which is encoded like its integer counterpart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NonZeroTy {
+    Int32,
+    Int64,
+    Uint32,
+    Uint64,
+}
+
+impl NonZeroTy {
+    fn from_path(path: &Path) -> Option<Self> {
+        if path.is_ident("int32") {
+            Some(NonZeroTy::Int32)
+        } else if path.is_ident("int64") {
+            Some(NonZeroTy::Int64)
+        } else if path.is_ident("uint32") {
+            Some(NonZeroTy::Uint32)
+        } else if path.is_ident("uint64") {
+            Some(NonZeroTy::Uint64)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the integer type the non-zero type is encoded as.
+    fn int_ty(self) -> Ty {
+        match self {
+            NonZeroTy::Int32 => Ty::Int32,
+            NonZeroTy::Int64 => Ty::Int64,
+            NonZeroTy::Uint32 => Ty::Uint32,
+            NonZeroTy::Uint64 => Ty::Uint64,
+        }
+    }
+
+    fn rust_type(self) -> TokenStream {
+        match self {
+            NonZeroTy::Int32 => quote!(::core::num::NonZeroI32),
+            NonZeroTy::Int64 => quote!(::core::num::NonZeroI64),
+            NonZeroTy::Uint32 => quote!(::core::num::NonZeroU32),
+            NonZeroTy::Uint64 => quote!(::core::num::NonZeroU64),
         }
     }
 }
@@ -435,13 +896,30 @@ impl Ty {
             Meta::Path(ref name) if name.is_ident("sfixed32") => Ty::Sfixed32,
             Meta::Path(ref name) if name.is_ident("sfixed64") => Ty::Sfixed64,
             Meta::Path(ref name) if name.is_ident("bool") => Ty::Bool,
-            Meta::Path(ref name) if name.is_ident("string") => Ty::String,
+            Meta::Path(ref name) if name.is_ident("string") => Ty::String(StringTy::String),
             Meta::Path(ref name) if name.is_ident("bytes") => Ty::Bytes(BytesTy::Vec),
+            Meta::NameValue(MetaNameValue {
+                ref path,
+                lit: Lit::Str(ref l),
+                ..
+            }) if path.is_ident("string") => Ty::String(StringTy::try_from_str(&l.value())?),
             Meta::NameValue(MetaNameValue {
                 ref path,
                 lit: Lit::Str(ref l),
                 ..
             }) if path.is_ident("bytes") => Ty::Bytes(BytesTy::try_from_str(&l.value())?),
+            Meta::NameValue(MetaNameValue {
+                ref path,
+                lit: Lit::Str(ref l),
+                ..
+            }) if NonZeroTy::from_path(path).is_some() => {
+                let ty = NonZeroTy::from_path(path).unwrap();
+                match &*l.value() {
+                    "non_zero" => Ty::NonZero(ty),
+                    "char" if ty == NonZeroTy::Uint32 => Ty::Char,
+                    other => bail!("Invalid {} type: {}", ty.int_ty(), other),
+                }
+            }
             Meta::NameValue(MetaNameValue {
                 ref path,
                 lit: Lit::Str(ref l),
@@ -485,7 +963,7 @@ impl Ty {
             "sfixed32" => Ty::Sfixed32,
             "sfixed64" => Ty::Sfixed64,
             "bool" => Ty::Bool,
-            "string" => Ty::String,
+            "string" => Ty::String(StringTy::String),
             "bytes" => Ty::Bytes(BytesTy::Vec),
             s if s.len() > enumeration_len && &s[..enumeration_len] == "enumeration" => {
                 let s = &s[enumeration_len..].trim();
@@ -521,8 +999,10 @@ impl Ty {
             Ty::Sfixed32 => "sfixed32",
             Ty::Sfixed64 => "sfixed64",
             Ty::Bool => "bool",
-            Ty::String => "string",
+            Ty::String(..) => "string",
             Ty::Bytes(..) => "bytes",
+            Ty::NonZero(ty) => ty.int_ty().as_str(),
+            Ty::Char => "uint32",
             Ty::Enumeration(..) => "enum",
         }
     }
@@ -530,7 +1010,7 @@ impl Ty {
     // TODO: rename to 'owned_type'.
     pub fn rust_type(&self) -> TokenStream {
         match self {
-            Ty::String => quote!(::prost::alloc::string::String),
+            Ty::String(ty) => ty.rust_type(),
             Ty::Bytes(ty) => ty.rust_type(),
             _ => self.rust_ref_type(),
         }
@@ -552,8 +1032,12 @@ impl Ty {
             Ty::Sfixed32 => quote!(i32),
             Ty::Sfixed64 => quote!(i64),
             Ty::Bool => quote!(bool),
-            Ty::String => quote!(&str),
+            Ty::String(..) => quote!(&str),
+            Ty::Bytes(BytesTy::IpAddr) => quote!(::std::net::IpAddr),
+            Ty::Bytes(BytesTy::SocketAddr) => quote!(::std::net::SocketAddr),
             Ty::Bytes(..) => quote!(&[u8]),
+            Ty::NonZero(ty) => ty.rust_type(),
+            Ty::Char => quote!(char),
             Ty::Enumeration(..) => quote!(i32),
         }
     }
@@ -561,13 +1045,117 @@ impl Ty {
     pub fn module(&self) -> Ident {
         match *self {
             Ty::Enumeration(..) => Ident::new("int32", Span::call_site()),
+            Ty::String(StringTy::Boxed) => Ident::new("boxed_str", Span::call_site()),
+            Ty::String(StringTy::Arc) => Ident::new("arc_str", Span::call_site()),
+            Ty::Bytes(BytesTy::Array) => Ident::new("bytes_array", Span::call_site()),
+            Ty::Bytes(BytesTy::IpAddr) => Ident::new("ip_addr", Span::call_site()),
+            Ty::Bytes(BytesTy::SocketAddr) => Ident::new("socket_addr", Span::call_site()),
+            Ty::NonZero(ty) => Ident::new(
+                &format!("non_zero_{}", ty.int_ty().as_str()),
+                Span::call_site(),
+            ),
+            Ty::Char => Ident::new("char_uint32", Span::call_site()),
             _ => Ident::new(self.as_str(), Span::call_site()),
         }
     }
 
+    /// Returns an expression which writes the value referenced by `value` to a JSON or text
+    /// format writer, which share the names of their value methods.
+    pub fn write(&self, value: TokenStream) -> TokenStream {
+        match *self {
+            Ty::Double => quote!(writer.write_f64(*#value)),
+            Ty::Float => quote!(writer.write_f32(*#value)),
+            Ty::Int32 | Ty::Sint32 | Ty::Sfixed32 => quote!(writer.write_i32(*#value)),
+            Ty::Int64 | Ty::Sint64 | Ty::Sfixed64 => quote!(writer.write_i64(*#value)),
+            Ty::Uint32 | Ty::Fixed32 => quote!(writer.write_u32(*#value)),
+            Ty::Uint64 | Ty::Fixed64 => quote!(writer.write_u64(*#value)),
+            Ty::Bool => quote!(writer.write_bool(*#value)),
+            Ty::String(..) => quote!(writer.write_str(&(#value)[..])),
+            Ty::Bytes(BytesTy::IpAddr) => {
+                quote!(writer.write_bytes(&::prost::encoding::ip_addr::to_bytes(#value)))
+            }
+            Ty::Bytes(BytesTy::SocketAddr) => {
+                quote!(writer.write_bytes(&::prost::encoding::socket_addr::to_bytes(#value)))
+            }
+            Ty::Bytes(..) => quote!(writer.write_bytes(&(#value)[..])),
+            Ty::NonZero(ty) => ty.int_ty().write(quote!(&(#value).get())),
+            Ty::Char => Ty::Uint32.write(quote!(&u32::from(*#value))),
+            Ty::Enumeration(ref ty) => quote!(writer.write_enum::<#ty>(*#value)),
+        }
+    }
+
+    /// Returns an expression which passes the value referenced by `value` to a `FieldVisitor`, as
+    /// the field `tag` named `name`.
+    pub fn visit(&self, tag: u32, name: &str, value: TokenStream) -> TokenStream {
+        match *self {
+            Ty::Double => quote!(visitor.visit_f64(#tag, #name, *#value)),
+            Ty::Float => quote!(visitor.visit_f32(#tag, #name, *#value)),
+            Ty::Int32 | Ty::Sint32 | Ty::Sfixed32 => {
+                quote!(visitor.visit_i32(#tag, #name, *#value))
+            }
+            Ty::Int64 | Ty::Sint64 | Ty::Sfixed64 => {
+                quote!(visitor.visit_i64(#tag, #name, *#value))
+            }
+            Ty::Uint32 | Ty::Fixed32 => quote!(visitor.visit_u32(#tag, #name, *#value)),
+            Ty::Uint64 | Ty::Fixed64 => quote!(visitor.visit_u64(#tag, #name, *#value)),
+            Ty::Bool => quote!(visitor.visit_bool(#tag, #name, *#value)),
+            Ty::String(..) => quote!(visitor.visit_str(#tag, #name, &(#value)[..])),
+            Ty::Bytes(BytesTy::IpAddr) => quote! {
+                visitor.visit_bytes(#tag, #name, &::prost::encoding::ip_addr::to_bytes(#value))
+            },
+            Ty::Bytes(BytesTy::SocketAddr) => quote! {
+                visitor.visit_bytes(#tag, #name, &::prost::encoding::socket_addr::to_bytes(#value))
+            },
+            Ty::Bytes(..) => quote!(visitor.visit_bytes(#tag, #name, &(#value)[..])),
+            Ty::NonZero(ty) => ty.int_ty().visit(tag, name, quote!(&(#value).get())),
+            Ty::Char => Ty::Uint32.visit(tag, name, quote!(&u32::from(*#value))),
+            Ty::Enumeration(ref ty) => quote! {
+                visitor.visit_enum(
+                    #tag,
+                    #name,
+                    *#value,
+                    <#ty as ::prost::EnumName>::proto_name(*#value),
+                )
+            },
+        }
+    }
+
     /// Returns false if the scalar type is length delimited (i.e., `string` or `bytes`).
     pub fn is_numeric(&self) -> bool {
-        !matches!(self, Ty::String | Ty::Bytes(..))
+        !matches!(self, Ty::String(..) | Ty::Bytes(..))
+    }
+
+    /// Returns true if the type is an IP or socket address, which is `Copy` and is returned by
+    /// value from getters.
+    fn is_address(&self) -> bool {
+        matches!(
+            self,
+            Ty::Bytes(BytesTy::IpAddr) | Ty::Bytes(BytesTy::SocketAddr)
+        )
+    }
+
+    /// Returns true if the default value of the type is returned by the `default` function of its
+    /// encoding module, rather than by `Default::default`.
+    fn has_default_fn(&self) -> bool {
+        matches!(
+            self,
+            Ty::Bytes(BytesTy::Array)
+                | Ty::Bytes(BytesTy::IpAddr)
+                | Ty::Bytes(BytesTy::SocketAddr)
+                | Ty::NonZero(..)
+                | Ty::String(StringTy::Arc)
+        )
+    }
+
+    /// Returns an expression for the field `ident` which can be compared to its typed default
+    /// value. Boxed and shared types are only comparable to their default once dereferenced.
+    fn comparable(&self, ident: &TokenStream) -> TokenStream {
+        match self {
+            Ty::String(StringTy::Boxed) | Ty::String(StringTy::Arc) | Ty::Bytes(BytesTy::Boxed) => {
+                quote!(&*#ident)
+            }
+            _ => quote!(#ident),
+        }
     }
 }
 
@@ -583,6 +1171,50 @@ impl fmt::Display for Ty {
     }
 }
 
+/// How an enumeration field decodes values which are not variants of the enumeration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unknown {
+    /// Unknown values are kept as they were decoded, for forward compatibility.
+    Preserve,
+    /// Unknown values are replaced by the default variant.
+    Clamp,
+}
+
+/// Parses the `newtype = "path"` attribute, naming the module generated by `newtype_field!`.
+fn newtype_attr(attr: &Meta) -> Result<Option<Path>, Error> {
+    if !attr.path().is_ident("newtype") {
+        return Ok(None);
+    }
+    match *attr {
+        Meta::NameValue(MetaNameValue {
+            lit: Lit::Str(ref lit),
+            ..
+        }) => parse_str::<Path>(&lit.value())
+            .map(Some)
+            .map_err(|error| anyhow!("invalid newtype attribute: {}", error)),
+        _ => bail!("invalid newtype attribute: {:?}", attr),
+    }
+}
+
+impl Unknown {
+    fn from_attr(attr: &Meta) -> Result<Option<Unknown>, Error> {
+        if !attr.path().is_ident("unknown") {
+            return Ok(None);
+        }
+        match *attr {
+            Meta::NameValue(MetaNameValue {
+                lit: Lit::Str(ref lit),
+                ..
+            }) => match &*lit.value() {
+                "preserve" => Ok(Some(Unknown::Preserve)),
+                "clamp" => Ok(Some(Unknown::Clamp)),
+                other => bail!("invalid unknown attribute: {}", other),
+            },
+            _ => bail!("invalid unknown attribute: {:?}", attr),
+        }
+    }
+}
+
 /// Scalar Protobuf field types.
 #[derive(Clone)]
 pub enum Kind {
@@ -610,6 +1242,11 @@ pub enum DefaultValue {
     Bool(bool),
     String(String),
     Bytes(Vec<u8>),
+    BytesArray,
+    IpAddr,
+    SocketAddr,
+    NonZero(Ident),
+    Char(char),
     Enumeration(TokenStream),
     Path(Path),
 }
@@ -659,9 +1296,13 @@ impl DefaultValue {
             Lit::Int(ref lit) if *ty == Ty::Double => DefaultValue::F64(lit.base10_parse()?),
 
             Lit::Bool(ref lit) if *ty == Ty::Bool => DefaultValue::Bool(lit.value),
-            Lit::Str(ref lit) if *ty == Ty::String => DefaultValue::String(lit.value()),
+            Lit::Char(ref lit) if *ty == Ty::Char => DefaultValue::Char(lit.value()),
+            Lit::Str(ref lit) if matches!(ty, Ty::String(..)) => DefaultValue::String(lit.value()),
             Lit::ByteStr(ref lit)
-                if *ty == Ty::Bytes(BytesTy::Bytes) || *ty == Ty::Bytes(BytesTy::Vec) =>
+                if matches!(
+                    ty,
+                    Ty::Bytes(BytesTy::Bytes) | Ty::Bytes(BytesTy::Vec) | Ty::Bytes(BytesTy::Boxed)
+                ) =>
             {
                 DefaultValue::Bytes(lit.value())
             }
@@ -768,8 +1409,13 @@ impl DefaultValue {
             Ty::Uint64 | Ty::Fixed64 => DefaultValue::U64(0),
 
             Ty::Bool => DefaultValue::Bool(false),
-            Ty::String => DefaultValue::String(String::new()),
+            Ty::String(..) => DefaultValue::String(String::new()),
+            Ty::Bytes(BytesTy::Array) => DefaultValue::BytesArray,
+            Ty::Bytes(BytesTy::IpAddr) => DefaultValue::IpAddr,
+            Ty::Bytes(BytesTy::SocketAddr) => DefaultValue::SocketAddr,
             Ty::Bytes(..) => DefaultValue::Bytes(Vec::new()),
+            Ty::NonZero(..) => DefaultValue::NonZero(ty.module()),
+            Ty::Char => DefaultValue::Char('\0'),
             Ty::Enumeration(ref path) => DefaultValue::Enumeration(quote!(#path::default())),
         }
     }
@@ -777,7 +1423,7 @@ impl DefaultValue {
     pub fn owned(&self) -> TokenStream {
         match *self {
             DefaultValue::String(ref value) if value.is_empty() => {
-                quote!(::prost::alloc::string::String::new())
+                quote!(::core::default::Default::default())
             }
             DefaultValue::String(ref value) => quote!(#value.into()),
             DefaultValue::Bytes(ref value) if value.is_empty() => {
@@ -816,6 +1462,19 @@ impl ToTokens for DefaultValue {
                 let byte_str = LitByteStr::new(value, Span::call_site());
                 tokens.append_all(quote!(#byte_str as &[u8]));
             }
+            DefaultValue::BytesArray => {
+                tokens.append_all(quote!(::prost::encoding::bytes_array::default()))
+            }
+            DefaultValue::IpAddr => {
+                tokens.append_all(quote!(::prost::encoding::ip_addr::default()))
+            }
+            DefaultValue::SocketAddr => {
+                tokens.append_all(quote!(::prost::encoding::socket_addr::default()))
+            }
+            DefaultValue::NonZero(ref module) => {
+                tokens.append_all(quote!(::prost::encoding::#module::default()))
+            }
+            DefaultValue::Char(value) => value.to_tokens(tokens),
             DefaultValue::Enumeration(ref value) => value.to_tokens(tokens),
             DefaultValue::Path(ref value) => value.to_tokens(tokens),
         }
@@ -5,23 +5,28 @@
 extern crate alloc;
 extern crate proc_macro;
 
-use anyhow::{bail, Error};
+use anyhow::{anyhow, bail, Error};
 use itertools::Itertools;
 use proc_macro::TokenStream;
 use proc_macro2::Span;
 use quote::quote;
 use syn::{
-    punctuated::Punctuated, Data, DataEnum, DataStruct, DeriveInput, Expr, Fields, FieldsNamed,
-    FieldsUnnamed, Ident, Index, Variant,
+    punctuated::Punctuated, Attribute, Data, DataEnum, DataStruct, DeriveInput, Expr, Fields,
+    FieldsNamed, FieldsUnnamed, Ident, Index, Lit, Meta, MetaNameValue, Variant,
 };
 
 mod field;
-use crate::field::Field;
+use crate::field::{
+    alias_tags_attr, json_name, json_name_attr, prost_attrs, proto_name, tag_ranges, Field,
+    SpecialField,
+};
 
 fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
     let input: DeriveInput = syn::parse(input)?;
 
     let ident = input.ident;
+    let (reserved, try_from_bytes) = message_attrs(input.attrs)
+        .map_err(|err| err.context(format!("invalid message {}", ident)))?;
 
     let variant_data = match input.data {
         Data::Struct(variant_data) => variant_data,
@@ -32,6 +37,17 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
     let generics = &input.generics;
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
+    // The serializations recurse into the fields, so they require each type parameter to
+    // implement them, as the standard derives do.
+    let json_generics = bounded_generics(generics, quote!(::prost::json::ToJson));
+    let (json_impl_generics, _, json_where_clause) = json_generics.split_for_impl();
+    let text_format_generics =
+        bounded_generics(generics, quote!(::prost::text_format::ToTextFormat));
+    let (text_format_impl_generics, _, text_format_where_clause) =
+        text_format_generics.split_for_impl();
+    let reflect_generics = bounded_generics(generics, quote!(::prost::reflect::Reflect));
+    let (reflect_impl_generics, _, reflect_where_clause) = reflect_generics.split_for_impl();
+
     let (is_struct, fields) = match variant_data {
         DataStruct {
             fields: Fields::Named(FieldsNamed { named: fields, .. }),
@@ -51,6 +67,13 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
     };
 
     let mut next_tag: u32 = 1;
+    let mut unknown_fields = None;
+    let mut cached_size = None;
+    let mut extensions = None;
+    let mut presence = None;
+    let mut extension_ranges = Vec::new();
+    let mut json_names = Vec::new();
+    let mut alias_tags = Vec::new();
     let mut fields = fields
         .into_iter()
         .enumerate()
@@ -62,9 +85,66 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
                 };
                 quote!(#index)
             });
+            match SpecialField::from_attrs(&field.attrs) {
+                Ok(Some(special)) => {
+                    let slot = match special {
+                        SpecialField::UnknownFields => &mut unknown_fields,
+                        SpecialField::CachedSize => &mut cached_size,
+                        SpecialField::Presence => &mut presence,
+                        SpecialField::Extensions(ref ranges) => {
+                            extension_ranges = ranges.clone();
+                            &mut extensions
+                        }
+                    };
+                    if slot.is_some() {
+                        return Some(Err(anyhow!(
+                            "message {} has multiple {} fields",
+                            ident,
+                            special.as_str()
+                        )));
+                    }
+                    *slot = Some(field_ident);
+                    return None;
+                }
+                Ok(None) => (),
+                Err(err) => {
+                    return Some(Err(err.context(format!(
+                        "invalid message field {}.{}",
+                        ident, field_ident
+                    ))))
+                }
+            }
+            let attrs = json_name_attr(&field.attrs)
+                .and_then(|json_name| Ok((json_name, alias_tags_attr(&field.attrs)?)));
+            let (json_name, field_alias_tags) = match attrs {
+                Ok(attrs) => attrs,
+                Err(err) => {
+                    return Some(Err(err.context(format!(
+                        "invalid message field {}.{}",
+                        ident, field_ident
+                    ))))
+                }
+            };
             match Field::new(field.attrs, Some(next_tag)) {
+                Ok(Some(Field::Oneof(..))) if json_name.is_some() => Some(Err(anyhow!(
+                    "invalid message field {}.{}: json_name may not be set on oneof fields, \
+                     whose variants are named by the oneof",
+                    ident,
+                    field_ident
+                ))),
+                Ok(Some(Field::Oneof(..))) if !field_alias_tags.is_empty() => Some(Err(anyhow!(
+                    "invalid message field {}.{}: alias_tag may not be set on oneof fields",
+                    ident,
+                    field_ident
+                ))),
                 Ok(Some(field)) => {
                     next_tag = field.tags().iter().max().map(|t| t + 1).unwrap_or(next_tag);
+                    if let Some(json_name) = json_name {
+                        json_names.push((field_ident.to_string(), json_name));
+                    }
+                    if !field_alias_tags.is_empty() {
+                        alias_tags.push((field_ident.to_string(), field_alias_tags));
+                    }
                     Some(Ok((field_ident, field)))
                 }
                 Ok(None) => None,
@@ -85,15 +165,62 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
     fields.sort_by_key(|&(_, ref field)| field.tags().into_iter().min().unwrap());
     let fields = fields;
 
-    let mut tags = fields
-        .iter()
-        .flat_map(|&(_, ref field)| field.tags())
-        .collect::<Vec<_>>();
-    let num_tags = tags.len();
-    tags.sort_unstable();
-    tags.dedup();
-    if tags.len() != num_tags {
-        bail!("message {} has fields with duplicate tags", ident);
+    // The fields' own tags, followed by the alias tags they are also decoded from.
+    let field_tags =
+        fields
+            .iter()
+            .flat_map(|(field_ident, field)| {
+                field
+                    .tags()
+                    .into_iter()
+                    .map(move |tag| (tag, field_ident.to_string()))
+            })
+            .chain(alias_tags.iter().flat_map(|(field_name, tags)| {
+                tags.iter().map(move |&tag| (tag, field_name.clone()))
+            }))
+            .collect::<Vec<_>>();
+    if let Some((tag, first, second)) = duplicate_tag(&field_tags) {
+        bail!(
+            "message {} has fields with duplicate tag {}: {} and {}",
+            ident,
+            tag,
+            first,
+            second
+        );
+    }
+    if let Some((tag, field_ident)) = field_tags.iter().find(|&&(tag, _)| {
+        reserved
+            .iter()
+            .any(|&(start, end)| start <= tag && tag <= end)
+    }) {
+        bail!(
+            "message {} has a field with reserved tag {}: {}",
+            ident,
+            tag,
+            field_ident
+        );
+    }
+    let tags = field_tags.iter().map(|&(tag, _)| tag).collect::<Vec<_>>();
+    if let Some(tag) = tags.iter().find(|&&tag| {
+        extension_ranges
+            .iter()
+            .any(|&(start, end)| start <= tag && tag <= end)
+    }) {
+        bail!(
+            "message {} has a field with tag {} in an extension range",
+            ident,
+            tag
+        );
+    }
+    if extension_ranges.iter().any(|&(start, end)| {
+        reserved
+            .iter()
+            .any(|&(reserved_start, reserved_end)| start <= reserved_end && reserved_start <= end)
+    }) {
+        bail!(
+            "message {} has an extension range overlapping a reserved tag",
+            ident
+        );
     }
 
     let encoded_len = fields
@@ -104,9 +231,60 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
         .iter()
         .map(|&(ref field_ident, ref field)| field.encode(quote!(self.#field_ident)));
 
+    let encode_cached = fields
+        .iter()
+        .map(|(field_ident, field)| field.encode_cached(quote!(self.#field_ident)));
+
+    // The canonical encoding writes the singular fields before the repeated and map fields, each
+    // in tag order. Oneof fields are written at the position of their active variant's tag, so
+    // each oneof is encoded once for each of its tags.
+    let mut encode_canonical = fields
+        .iter()
+        .flat_map(|(field_ident, field)| match field {
+            Field::Oneof(oneof) => field
+                .tags()
+                .into_iter()
+                .map(|tag| {
+                    let encode = oneof.encode_canonical(quote!(self.#field_ident), tag);
+                    ((false, tag), encode)
+                })
+                .collect(),
+            _ => vec![(
+                (field.is_repeated(), field.tags()[0]),
+                field.encode_canonical(quote!(self.#field_ident)),
+            )],
+        })
+        .collect::<Vec<_>>();
+    encode_canonical.sort_by_key(|(key, _)| *key);
+    let encode_canonical = encode_canonical.into_iter().map(|(_, encode)| encode);
+
+    // Each field is counted under its own tag, in declaration order, followed by the extensions
+    // and unknown fields, which are counted under theirs.
+    let field_lens = fields
+        .iter()
+        .map(|(field_ident, field)| field.field_lens(quote!(self.#field_ident)));
+    let field_lens_special = extensions
+        .iter()
+        .chain(unknown_fields.iter())
+        .map(|field_ident| quote!(self.#field_ident.field_lens(path, record);));
+
     let merge = fields.iter().map(|&(ref field_ident, ref field)| {
-        let merge = field.merge(quote!(value));
-        let tags = field.tags().into_iter().map(|tag| quote!(#tag));
+        let mut merge = field.merge_checked(quote!(value));
+        // Required fields are recorded once decoded, if the message tracks their presence.
+        if let (true, Some(presence)) = (field.is_required(), &presence) {
+            let tag = field.tags()[0];
+            merge = quote!(#merge.map(|()| self.#presence.insert(#tag)));
+        }
+        let field_name = field_ident.to_string();
+        let field_alias_tags = alias_tags
+            .iter()
+            .filter(|(name, _)| *name == field_name)
+            .flat_map(|(_, tags)| tags.iter().cloned());
+        let tags = field
+            .tags()
+            .into_iter()
+            .chain(field_alias_tags)
+            .map(|tag| quote!(#tag));
         let tags = Itertools::intersperse(tags, quote!(|));
 
         quote! {
@@ -120,6 +298,167 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
         }
     });
 
+    // Reserved tags are rejected rather than treated as unknown fields, since they indicate that
+    // the peer is using a field which this version of the schema has retired.
+    let merge_reserved = if reserved.is_empty() {
+        quote!()
+    } else {
+        let patterns = tag_patterns(&reserved);
+        quote! {
+            #patterns => ::core::result::Result::Err(::prost::DecodeError::new(
+                ::prost::alloc::format!("field tag {} is reserved", tag),
+            )),
+        }
+    };
+
+    // The `TryFrom<&[u8]>` impl is opt-in, since it would conflict with a hand-written one.
+    let try_from_bytes = if try_from_bytes {
+        // The impl is generic over the lifetime of the slice.
+        let mut try_from_generics = generics.clone();
+        try_from_generics
+            .params
+            .insert(0, syn::parse_quote!('__prost_buf));
+        let (try_from_impl_generics, _, _) = try_from_generics.split_for_impl();
+        quote! {
+            impl #try_from_impl_generics ::core::convert::TryFrom<&'__prost_buf [u8]> for #ident #ty_generics #where_clause {
+                type Error = ::prost::DecodeError;
+
+                fn try_from(buf: &'__prost_buf [u8]) -> ::core::result::Result<Self, Self::Error> {
+                    ::prost::Message::decode(buf)
+                }
+            }
+        }
+    } else {
+        quote!()
+    };
+
+    let clear_presence = presence
+        .iter()
+        .map(|field_ident| quote!(self.#field_ident.clear();));
+
+    // Fields with tags in the extension ranges are stored in the extension set, and re-emitted
+    // after the other known fields.
+    let (encode_extensions, encoded_len_extensions, merge_extensions, clear_extensions) =
+        match extensions {
+            Some(ref field_ident) => {
+                let patterns = tag_patterns(&extension_ranges);
+                (
+                    quote!(self.#field_ident.encode_raw(buf);),
+                    quote!(.saturating_add(self.#field_ident.encoded_len())),
+                    quote!(#patterns => self.#field_ident.merge_field(tag, wire_type, buf, ctx),),
+                    quote!(self.#field_ident.clear();),
+                )
+            }
+            None => (quote!(), quote!(), quote!(), quote!()),
+        };
+
+    let encode_canonical_special = extensions
+        .iter()
+        .chain(unknown_fields.iter())
+        .map(|field_ident| quote!(self.#field_ident.encode_canonical_raw(buf);));
+
+    // Unrecognized fields are either retained in the unknown field list, or skipped.
+    let (encode_unknown, encoded_len_unknown, merge_unknown, clear_unknown) = match unknown_fields {
+        Some(ref field_ident) => (
+            quote!(self.#field_ident.encode_raw(buf);),
+            quote!(.saturating_add(self.#field_ident.encoded_len())),
+            quote!(self.#field_ident.merge_field(tag, wire_type, buf, ctx)),
+            quote!(self.#field_ident.clear();),
+        ),
+        None => (
+            quote!(),
+            quote!(),
+            quote!(::prost::encoding::skip_field(wire_type, tag, buf, ctx)),
+            quote!(),
+        ),
+    };
+
+    // Extensions and unknown fields are only written in the text format, by tag number, since
+    // the JSON mapping has no representation for them without their names.
+    let text_format_unknown = extensions
+        .iter()
+        .chain(unknown_fields.iter())
+        .map(|field_ident| quote!(self.#field_ident.write_text_format(writer);));
+
+    // The computed length is stored in the cached size, and reused when the message is nested.
+    let (encoded_len_cached, cached_encoded_len) = match cached_size {
+        Some(ref field_ident) => (
+            quote! {
+                self.#field_ident.set(len);
+            },
+            quote! {
+                #[inline]
+                fn cached_encoded_len(&self) -> usize {
+                    match self.#field_ident.get() {
+                        ::core::option::Option::Some(len) => len,
+                        ::core::option::Option::None => ::prost::Message::encoded_len(self),
+                    }
+                }
+            },
+        ),
+        None => (quote!(), quote!()),
+    };
+
+    // Required fields are checked if the message tracks their presence, and then the required
+    // fields of nested messages are checked, at each level naming the field which holds them.
+    let check_initialized = fields
+        .iter()
+        .flat_map(|(field_ident, field)| {
+            let missing = match presence {
+                Some(ref presence) if field.is_required() => {
+                    let tag = field.tags()[0];
+                    Some(quote! {
+                        if !self.#presence.contains(#tag) {
+                            let mut error = ::prost::DecodeError::new("missing required field");
+                            error.push(STRUCT_NAME, stringify!(#field_ident));
+                            return ::core::result::Result::Err(error);
+                        }
+                    })
+                }
+                _ => None,
+            };
+            let nested = field
+                .check_initialized(quote!(self.#field_ident))
+                .map(|check| {
+                    quote! {
+                        #check.map_err(|mut error| {
+                            error.push(STRUCT_NAME, stringify!(#field_ident));
+                            error
+                        })?;
+                    }
+                });
+            missing.into_iter().chain(nested)
+        })
+        .collect::<Vec<_>>();
+    let check_initialized = if check_initialized.is_empty() {
+        quote!()
+    } else {
+        quote! {
+            fn check_initialized(&self) -> ::core::result::Result<(), ::prost::DecodeError> {
+                const STRUCT_NAME: &'static str = stringify!(#ident);
+                #(#check_initialized)*
+                ::core::result::Result::Ok(())
+            }
+        }
+    };
+
+    let json = fields.iter().map(|(field_ident, field)| {
+        let field_name = field_ident.to_string();
+        let name = custom_json_name(&json_names, &field_name)
+            .unwrap_or_else(|| json_name(&proto_name(&field_name)));
+        field.json(&name, quote!(self.#field_ident))
+    });
+
+    let text_format = fields.iter().map(|(field_ident, field)| {
+        let name = proto_name(&field_ident.to_string());
+        field.text_format(&name, quote!(self.#field_ident))
+    });
+
+    let reflect = fields.iter().map(|(field_ident, field)| {
+        let name = proto_name(&field_ident.to_string());
+        field.reflect(&name, quote!(self.#field_ident))
+    });
+
     let struct_name = if fields.is_empty() {
         quote!()
     } else {
@@ -128,6 +467,14 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
         )
     };
 
+    let is_default = fields
+        .iter()
+        .map(|(field_ident, field)| field.is_default(quote!(self.#field_ident)));
+    let is_default_special = extensions
+        .iter()
+        .chain(unknown_fields.iter())
+        .map(|field_ident| quote!(self.#field_ident.is_empty()));
+
     let clear = fields
         .iter()
         .map(|&(ref field_ident, ref field)| field.clear(quote!(self.#field_ident)));
@@ -137,10 +484,29 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
             let value = field.default();
             quote!(#field_ident: #value,)
         });
+        let default_unknown = unknown_fields
+            .iter()
+            .chain(cached_size.iter())
+            .chain(extensions.iter())
+            .chain(presence.iter())
+            .map(|field_ident| quote!(#field_ident: ::core::default::Default::default(),));
         quote! {#ident {
             #(#default)*
+            #(#default_unknown)*
         }}
     } else {
+        if unknown_fields.is_some() {
+            bail!("unknown_fields is not supported on tuple struct {}", ident);
+        }
+        if cached_size.is_some() {
+            bail!("cached_size is not supported on tuple struct {}", ident);
+        }
+        if extensions.is_some() {
+            bail!("extensions is not supported on tuple struct {}", ident);
+        }
+        if presence.is_some() {
+            bail!("presence is not supported on tuple struct {}", ident);
+        }
         let default = fields.iter().map(|(_, field)| {
             let value = field.default();
             quote!(#value,)
@@ -179,17 +545,63 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
              };
         }
     });
+    let debug_unknown = extensions
+        .iter()
+        .chain(unknown_fields.iter())
+        .map(|field_ident| {
+            quote! {
+                let builder = builder.field(stringify!(#field_ident), &self.#field_ident);
+            }
+        });
     let debug_builder = if is_struct {
         quote!(f.debug_struct(stringify!(#ident)))
     } else {
         quote!(f.debug_tuple(stringify!(#ident)))
     };
 
+    let field_tag = if alias_tags.is_empty() {
+        quote!()
+    } else {
+        // Alias tags may not be set on oneof fields, so each field with alias tags has one tag.
+        let alias_arms = alias_tags.iter().flat_map(|(field_name, tags)| {
+            let field_tag = fields
+                .iter()
+                .find(|(field_ident, _)| field_ident.to_string() == *field_name)
+                .map(|(_, field)| field.tags()[0])
+                .unwrap();
+            tags.iter()
+                .map(move |alias_tag| quote!(#alias_tag => #field_tag))
+        });
+        quote! {
+            fn field_tag(tag: u32) -> u32 {
+                match tag {
+                    #(#alias_arms,)*
+                    _ => tag,
+                }
+            }
+        }
+    };
+
     let expanded = quote! {
         impl #impl_generics ::prost::Message for #ident #ty_generics #where_clause {
             #[allow(unused_variables)]
             fn encode_raw<B>(&self, buf: &mut B) where B: ::prost::bytes::BufMut {
                 #(#encode)*
+                #encode_extensions
+                #encode_unknown
+            }
+
+            #[allow(unused_variables)]
+            fn encode_cached_raw<B>(&self, buf: &mut B) where B: ::prost::bytes::BufMut {
+                #(#encode_cached)*
+                #encode_extensions
+                #encode_unknown
+            }
+
+            #[allow(unused_variables)]
+            fn encode_canonical_raw<B>(&self, buf: &mut B) where B: ::prost::bytes::BufMut {
+                #(#encode_canonical)*
+                #(#encode_canonical_special)*
             }
 
             #[allow(unused_variables)]
@@ -204,17 +616,69 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
                 #struct_name
                 match tag {
                     #(#merge)*
-                    _ => ::prost::encoding::skip_field(wire_type, tag, buf, ctx),
+                    #merge_reserved
+                    #merge_extensions
+                    _ => #merge_unknown,
                 }
             }
 
+            #field_tag
+
             #[inline]
             fn encoded_len(&self) -> usize {
-                0 #(+ #encoded_len)*
+                // Saturates on overflow, see `::prost::encoding::sum_len`.
+                let len = 0usize
+                    #(.saturating_add(#encoded_len))*
+                    #encoded_len_extensions
+                    #encoded_len_unknown;
+                #encoded_len_cached
+                len
+            }
+
+            #cached_encoded_len
+
+            #[allow(unused_variables)]
+            fn field_lens(
+                &self,
+                path: &mut ::prost::alloc::vec::Vec<u32>,
+                record: &mut dyn FnMut(&[u32], usize),
+            ) {
+                #({ #field_lens })*
+                #(#field_lens_special)*
+            }
+
+            #check_initialized
+
+            fn is_default(&self) -> bool {
+                true #(&& #is_default)* #(&& #is_default_special)*
             }
 
             fn clear(&mut self) {
                 #(#clear;)*
+                #clear_extensions
+                #clear_unknown
+                #(#clear_presence)*
+            }
+        }
+
+        impl #json_impl_generics ::prost::json::ToJson for #ident #ty_generics #json_where_clause {
+            fn write_json(&self, writer: &mut ::prost::json::JsonWriter) {
+                writer.begin_object();
+                #(#json)*
+                writer.end_object();
+            }
+        }
+
+        impl #text_format_impl_generics ::prost::text_format::ToTextFormat for #ident #ty_generics #text_format_where_clause {
+            fn write_text_format(&self, writer: &mut ::prost::text_format::TextFormatWriter) {
+                #(#text_format)*
+                #(#text_format_unknown)*
+            }
+        }
+
+        impl #reflect_impl_generics ::prost::reflect::Reflect for #ident #ty_generics #reflect_where_clause {
+            fn reflect(&self, visitor: &mut dyn ::prost::reflect::FieldVisitor) {
+                #(#reflect)*
             }
         }
 
@@ -228,16 +692,88 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
             fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
                 let mut builder = #debug_builder;
                 #(#debugs;)*
+                #(#debug_unknown)*
                 builder.finish()
             }
         }
 
+        #try_from_bytes
+
         #methods
     };
 
     Ok(expanded.into())
 }
 
+/// Returns the first tag shared by two of the named fields, and the names of the first two fields
+/// which share it, in the order given.
+fn duplicate_tag(field_tags: &[(u32, String)]) -> Option<(u32, &str, &str)> {
+    field_tags.iter().enumerate().find_map(|(i, (tag, first))| {
+        field_tags[i + 1..]
+            .iter()
+            .find(|(other, _)| other == tag)
+            .map(|(_, second)| (*tag, &first[..], &second[..]))
+    })
+}
+
+/// Returns a copy of the generics with each type parameter bounded by a trait.
+fn bounded_generics(generics: &syn::Generics, bound: proc_macro2::TokenStream) -> syn::Generics {
+    let mut bounded = generics.clone();
+    let where_clause = bounded.make_where_clause();
+    for param in generics.type_params() {
+        let ident = &param.ident;
+        where_clause
+            .predicates
+            .push(syn::parse_quote!(#ident: #bound));
+    }
+    bounded
+}
+
+/// Returns the JSON name set with `#[prost(json_name = "...")]` on the named field, if any.
+fn custom_json_name(json_names: &[(String, String)], field_name: &str) -> Option<String> {
+    json_names
+        .iter()
+        .find(|(name, _)| name == field_name)
+        .map(|(_, json_name)| json_name.clone())
+}
+
+/// Parses the `#[prost(...)]` attributes of a message: the inclusive tag ranges listed by
+/// `reserved_tags = "..."`, e.g. `"2, 9 to 11"`, and whether `try_from_bytes` is set.
+fn message_attrs(attrs: Vec<Attribute>) -> Result<(Vec<(u32, u32)>, bool), Error> {
+    let mut reserved = Vec::new();
+    let mut try_from_bytes = false;
+    for attr in prost_attrs(attrs) {
+        match attr {
+            Meta::NameValue(MetaNameValue {
+                ref path,
+                lit: Lit::Str(ref lit),
+                ..
+            }) if path.is_ident("reserved_tags") => reserved.extend(tag_ranges(&lit.value())?),
+            Meta::Path(ref path) if path.is_ident("try_from_bytes") => try_from_bytes = true,
+            _ => bail!("unknown attribute for message: {:?}", attr),
+        }
+    }
+    Ok((reserved, try_from_bytes))
+}
+
+/// Returns the `match` patterns matching tags in the inclusive ranges.
+fn tag_patterns(ranges: &[(u32, u32)]) -> proc_macro2::TokenStream {
+    let patterns = ranges.iter().map(|&(start, end)| {
+        if start == end {
+            quote!(#start)
+        } else {
+            quote!(#start..=#end)
+        }
+    });
+    let patterns = Itertools::intersperse(patterns, quote!(|));
+    quote!(#(#patterns)*)
+}
+
+/// Derives `prost::Message` for a struct.
+///
+/// Each field is annotated with its Protobuf type and, optionally, its tag and label, e.g.
+/// `#[prost(int32, optional, tag = "1")]`. Tags which are omitted are inferred sequentially from
+/// the previous field's tag.
 #[proc_macro_derive(Message, attributes(prost))]
 pub fn message(input: TokenStream) -> TokenStream {
     try_message(input).unwrap()
@@ -257,8 +793,9 @@ fn try_enumeration(input: TokenStream) -> Result<TokenStream, Error> {
     };
 
     // Map the variants into 'fields'.
-    let mut variants: Vec<(Ident, Expr)> = Vec::new();
+    let mut variants: Vec<(Ident, Expr, String)> = Vec::new();
     for Variant {
+        attrs,
         ident,
         fields,
         discriminant,
@@ -272,8 +809,13 @@ fn try_enumeration(input: TokenStream) -> Result<TokenStream, Error> {
             }
         }
 
+        let name = match variant_name(attrs)? {
+            Some(name) => name,
+            None => ident.to_string(),
+        };
+
         match discriminant {
-            Some((_, expr)) => variants.push((ident, expr)),
+            Some((_, expr)) => variants.push((ident, expr, name)),
             None => bail!("Enumeration variants must have a disriminant"),
         }
     }
@@ -286,10 +828,13 @@ fn try_enumeration(input: TokenStream) -> Result<TokenStream, Error> {
 
     let is_valid = variants
         .iter()
-        .map(|&(_, ref value)| quote!(#value => true));
+        .map(|&(_, ref value, _)| quote!(#value => true));
     let from = variants.iter().map(
-        |&(ref variant, ref value)| quote!(#value => ::core::option::Option::Some(#ident::#variant)),
+        |&(ref variant, ref value, _)| quote!(#value => ::core::option::Option::Some(#ident::#variant)),
     );
+    let proto_name = variants
+        .iter()
+        .map(|(_, value, name)| quote!(#value => ::core::option::Option::Some(#name)));
 
     let is_valid_doc = format!("Returns `true` if `value` is a variant of `{}`.", ident);
     let from_i32_doc = format!(
@@ -316,6 +861,15 @@ fn try_enumeration(input: TokenStream) -> Result<TokenStream, Error> {
             }
         }
 
+        impl #impl_generics ::prost::EnumName for #ident #ty_generics #where_clause {
+            fn proto_name(value: i32) -> ::core::option::Option<&'static str> {
+                match value {
+                    #(#proto_name,)*
+                    _ => ::core::option::Option::None,
+                }
+            }
+        }
+
         impl #impl_generics ::core::default::Default for #ident #ty_generics #where_clause {
             fn default() -> #ident {
                 #ident::#default
@@ -332,6 +886,26 @@ fn try_enumeration(input: TokenStream) -> Result<TokenStream, Error> {
     Ok(expanded.into())
 }
 
+/// Returns the Protobuf name of an enumeration variant, from its `#[prost(name = "...")]`
+/// attribute.
+fn variant_name(attrs: Vec<Attribute>) -> Result<Option<String>, Error> {
+    let mut name = None;
+    for attr in prost_attrs(attrs) {
+        match attr {
+            Meta::NameValue(MetaNameValue {
+                ref path,
+                lit: Lit::Str(ref lit),
+                ..
+            }) if path.is_ident("name") => {
+                field::set_option(&mut name, lit.value(), "duplicate name attributes")?
+            }
+            _ => bail!("unknown attribute for enumeration variant: {:?}", attr),
+        }
+    }
+    Ok(name)
+}
+
+/// Derives conversions between a fieldless enum and the `i32` values of a Protobuf enumeration.
 #[proc_macro_derive(Enumeration, attributes(prost))]
 pub fn enumeration(input: TokenStream) -> TokenStream {
     try_enumeration(input).unwrap()
@@ -341,6 +915,9 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
     let input: DeriveInput = syn::parse(input)?;
 
     let ident = input.ident;
+    let vis = input.vis;
+    let case = oneof_case_attr(input.attrs)
+        .map_err(|err| err.context(format!("invalid oneof {}", ident)))?;
 
     let variants = match input.data {
         Data::Enum(DataEnum { variants, .. }) => variants,
@@ -351,8 +928,20 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
     let generics = &input.generics;
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
+    // The serializations recurse into the fields, so they require each type parameter to
+    // implement them, as the standard derives do.
+    let json_generics = bounded_generics(generics, quote!(::prost::json::ToJson));
+    let (json_impl_generics, _, json_where_clause) = json_generics.split_for_impl();
+    let text_format_generics =
+        bounded_generics(generics, quote!(::prost::text_format::ToTextFormat));
+    let (text_format_impl_generics, _, text_format_where_clause) =
+        text_format_generics.split_for_impl();
+    let reflect_generics = bounded_generics(generics, quote!(::prost::reflect::Reflect));
+    let (reflect_impl_generics, _, reflect_where_clause) = reflect_generics.split_for_impl();
+
     // Map the variants into 'fields'.
     let mut fields: Vec<(Ident, Field)> = Vec::new();
+    let mut json_names = Vec::new();
     for Variant {
         attrs,
         ident: variant_ident,
@@ -370,15 +959,23 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
         if variant_fields.len() != 1 {
             bail!("Oneof enum variants must have a single field");
         }
+        if let Some(json_name) = json_name_attr(&attrs).map_err(|err| {
+            err.context(format!(
+                "invalid oneof variant {}::{}",
+                ident, variant_ident
+            ))
+        })? {
+            json_names.push((variant_ident.to_string(), json_name));
+        }
         match Field::new_oneof(attrs)? {
             Some(field) => fields.push((variant_ident, field)),
             None => bail!("invalid oneof variant: oneof variants may not be ignored"),
         }
     }
 
-    let mut tags = fields
+    let variant_tags = fields
         .iter()
-        .flat_map(|&(ref variant_ident, ref field)| -> Result<u32, Error> {
+        .map(|(variant_ident, field)| -> Result<(u32, String), Error> {
             if field.tags().len() > 1 {
                 bail!(
                     "invalid oneof variant {}::{}: oneof variants may only have a single tag",
@@ -386,13 +983,17 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
                     variant_ident
                 );
             }
-            Ok(field.tags()[0])
+            Ok((field.tags()[0], variant_ident.to_string()))
         })
-        .collect::<Vec<_>>();
-    tags.sort_unstable();
-    tags.dedup();
-    if tags.len() != fields.len() {
-        panic!("invalid oneof {}: variants have duplicate tags", ident);
+        .collect::<Result<Vec<_>, _>>()?;
+    if let Some((tag, first, second)) = duplicate_tag(&variant_tags) {
+        bail!(
+            "invalid oneof {}: variants have duplicate tag {}: {} and {}",
+            ident,
+            tag,
+            first,
+            second
+        );
     }
 
     let encode = fields.iter().map(|&(ref variant_ident, ref field)| {
@@ -400,21 +1001,37 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
         quote!(#ident::#variant_ident(ref value) => { #encode })
     });
 
+    let encode_cached = fields.iter().map(|(variant_ident, field)| {
+        let encode = field.encode_cached(quote!(*value));
+        quote!(#ident::#variant_ident(ref value) => { #encode })
+    });
+
+    let encode_canonical = fields.iter().map(|(variant_ident, field)| {
+        let tag = field.tags()[0];
+        let encode = field.encode_canonical(quote!(*value));
+        quote!(#ident::#variant_ident(ref value) if tag == #tag => { #encode })
+    });
+
     let merge = fields.iter().map(|&(ref variant_ident, ref field)| {
         let tag = field.tags()[0];
         let merge = field.merge(quote!(value));
+        let default = field.default();
+        let wire_types = field.wire_types().expect("oneof variants can not be oneof fields");
         quote! {
             #tag => {
-                match field {
+                let merged = match field {
                     ::core::option::Option::Some(#ident::#variant_ident(ref mut value)) => {
                         #merge
                     },
                     _ => {
-                        let mut owned_value = ::core::default::Default::default();
+                        let mut owned_value = #default;
                         let value = &mut owned_value;
                         #merge.map(|_| *field = ::core::option::Option::Some(#ident::#variant_ident(owned_value)))
                     },
-                }
+                };
+                merged.map_err(|error| {
+                    ::prost::encoding::field_merge_error(tag, &[#(#wire_types),*], wire_type, error)
+                })
             }
         }
     });
@@ -424,6 +1041,44 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
         quote!(#ident::#variant_ident(ref value) => #encoded_len)
     });
 
+    let field_lens = fields.iter().map(|(variant_ident, field)| {
+        let field_lens = field.field_lens(quote!(*value));
+        quote!(#ident::#variant_ident(ref value) => { #field_lens })
+    });
+
+    let check_initialized =
+        fields.iter().map(
+            |(variant_ident, field)| match field.check_initialized(quote!(*value)) {
+                Some(check) => quote! {
+                    #ident::#variant_ident(ref value) => #check.map_err(|mut error| {
+                        error.push(stringify!(#ident), stringify!(#variant_ident));
+                        error
+                    })
+                },
+                None => quote!(#ident::#variant_ident(_) => ::core::result::Result::Ok(())),
+            },
+        );
+
+    let json = fields.iter().map(|(variant_ident, field)| {
+        let variant_name = variant_ident.to_string();
+        let name = custom_json_name(&json_names, &variant_name)
+            .unwrap_or_else(|| json_name(&proto_name(&variant_name)));
+        let json = field.json(&name, quote!(*value));
+        quote!(#ident::#variant_ident(ref value) => { #json })
+    });
+
+    let text_format = fields.iter().map(|(variant_ident, field)| {
+        let name = proto_name(&variant_ident.to_string());
+        let text_format = field.text_format(&name, quote!(*value));
+        quote!(#ident::#variant_ident(ref value) => { #text_format })
+    });
+
+    let reflect = fields.iter().map(|(variant_ident, field)| {
+        let name = proto_name(&variant_ident.to_string());
+        let reflect = field.reflect(&name, quote!(*value));
+        quote!(#ident::#variant_ident(ref value) => { #reflect })
+    });
+
     let debug = fields.iter().map(|&(ref variant_ident, ref field)| {
         let wrapper = field.debug(quote!(*value));
         quote!(#ident::#variant_ident(ref value) => {
@@ -434,7 +1089,56 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
         })
     });
 
+    // The case enum is opt-in, since its name may be taken by another type in the same module.
+    let case = if case {
+        let case_ident = Ident::new(&format!("{}Case", ident), ident.span());
+        let case_doc = format!(
+            "The fields of `{}`, identifying which one is set without its value.",
+            ident
+        );
+        let case_variants = fields.iter().map(|(variant_ident, _)| {
+            let doc = format!("The `{}` field.", variant_ident);
+            quote!(#[doc=#doc] #variant_ident)
+        });
+        let case = fields.iter().map(
+            |(variant_ident, _)| quote!(#ident::#variant_ident(_) => #case_ident::#variant_ident),
+        );
+        let case_tag = fields.iter().map(|(variant_ident, field)| {
+            let tag = field.tags()[0];
+            quote!(#case_ident::#variant_ident => #tag)
+        });
+        quote! {
+            #[doc=#case_doc]
+            #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+            #vis enum #case_ident {
+                #(#case_variants,)*
+            }
+
+            impl #case_ident {
+                /// Returns the tag of the field.
+                pub fn tag(self) -> u32 {
+                    match self {
+                        #(#case_tag,)*
+                    }
+                }
+            }
+
+            impl #impl_generics #ident #ty_generics #where_clause {
+                /// Returns which field is set.
+                pub fn case(&self) -> #case_ident {
+                    match *self {
+                        #(#case,)*
+                    }
+                }
+            }
+        }
+    } else {
+        quote!()
+    };
+
     let expanded = quote! {
+        #case
+
         impl #impl_generics #ident #ty_generics #where_clause {
             /// Encodes the message to a buffer.
             pub fn encode<B>(&self, buf: &mut B) where B: ::prost::bytes::BufMut {
@@ -443,6 +1147,22 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
                 }
             }
 
+            /// Encodes the message to a buffer, reusing the cached lengths of embedded messages.
+            pub fn encode_cached<B>(&self, buf: &mut B) where B: ::prost::bytes::BufMut {
+                match *self {
+                    #(#encode_cached,)*
+                }
+            }
+
+            /// Encodes the message to a buffer in canonical form, if the active field has the
+            /// given tag.
+            pub fn encode_canonical<B>(&self, tag: u32, buf: &mut B) where B: ::prost::bytes::BufMut {
+                match *self {
+                    #(#encode_canonical,)*
+                    _ => (),
+                }
+            }
+
             /// Decodes an instance of the message from a buffer, and merges it into self.
             pub fn merge<B>(
                 field: &mut ::core::option::Option<#ident #ty_generics>,
@@ -465,6 +1185,53 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
                     #(#encoded_len,)*
                 }
             }
+
+            /// Reports the encoded length of the active field to `record`, followed by the lengths
+            /// of the fields of its embedded messages.
+            pub fn field_lens(
+                &self,
+                path: &mut ::prost::alloc::vec::Vec<u32>,
+                record: &mut dyn FnMut(&[u32], usize),
+            ) {
+                match *self {
+                    #(#field_lens,)*
+                }
+            }
+
+            /// Checks the required fields of the message held by the active field, if any.
+            pub fn check_initialized(&self) -> ::core::result::Result<(), ::prost::DecodeError> {
+                match *self {
+                    #(#check_initialized,)*
+                }
+            }
+        }
+
+        impl #json_impl_generics #ident #ty_generics #json_where_clause {
+            /// Writes the active field to a JSON writer, as a member of the enclosing object.
+            pub fn write_json(&self, writer: &mut ::prost::json::JsonWriter) {
+                match *self {
+                    #(#json,)*
+                }
+            }
+        }
+
+        impl #text_format_impl_generics #ident #ty_generics #text_format_where_clause {
+            /// Writes the active field to a text format writer, as a field of the enclosing
+            /// message.
+            pub fn write_text_format(&self, writer: &mut ::prost::text_format::TextFormatWriter) {
+                match *self {
+                    #(#text_format,)*
+                }
+            }
+        }
+
+        impl #reflect_impl_generics #ident #ty_generics #reflect_where_clause {
+            /// Passes the active field to a `FieldVisitor`, as a field of the enclosing message.
+            pub fn reflect_fields(&self, visitor: &mut dyn ::prost::reflect::FieldVisitor) {
+                match *self {
+                    #(#reflect,)*
+                }
+            }
         }
 
         impl #impl_generics ::core::fmt::Debug for #ident #ty_generics #where_clause {
@@ -479,6 +1246,19 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
     Ok(expanded.into())
 }
 
+/// Returns whether the `#[prost(case)]` attribute is set on a oneof.
+fn oneof_case_attr(attrs: Vec<Attribute>) -> Result<bool, Error> {
+    let mut case = false;
+    for attr in prost_attrs(attrs) {
+        match attr {
+            Meta::Path(ref path) if path.is_ident("case") => case = true,
+            _ => bail!("unknown attribute for oneof: {:?}", attr),
+        }
+    }
+    Ok(case)
+}
+
+/// Derives the encoding functions used by a `#[prost(oneof = "...")]` message field.
 #[proc_macro_derive(Oneof, attributes(prost))]
 pub fn oneof(input: TokenStream) -> TokenStream {
     try_oneof(input).unwrap()
@@ -852,12 +852,10 @@ mod tests {
 
         #[test]
         fn check_duration_parse_to_string_roundtrip(
-            duration in core::time::Duration::arbitrary(),
+            seconds in 0..=315_576_000_000u64,
+            nanos in 0..1_000_000_000u32,
         ) {
-            let duration = match Duration::try_from(duration) {
-                Ok(duration) => duration,
-                Err(_) => return Err(TestCaseError::reject("duration out of range")),
-            };
+            let duration = Duration::try_from(core::time::Duration::new(seconds, nanos)).unwrap();
 
             prop_assert_eq!(
                 &duration,
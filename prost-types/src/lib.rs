@@ -18,6 +18,9 @@ use core::i64;
 use core::str::FromStr;
 use core::time;
 
+use prost::alloc::string::String;
+use prost::{DecodeError, Name};
+
 include!("protobuf.rs");
 pub mod compiler {
     include!("compiler.rs");
@@ -32,6 +35,15 @@ mod datetime;
 const NANOS_PER_SECOND: i32 = 1_000_000_000;
 const NANOS_MAX: i32 = NANOS_PER_SECOND - 1;
 
+/// The largest magnitude of a valid `Duration` in seconds, approximately 10,000 years.
+const DURATION_SECONDS_MAX: i64 = 315_576_000_000;
+
+/// The seconds of the earliest valid `Timestamp`, `0001-01-01T00:00:00Z`.
+const TIMESTAMP_SECONDS_MIN: i64 = -62_135_596_800;
+
+/// The seconds of the latest valid `Timestamp`, `9999-12-31T23:59:59Z`.
+const TIMESTAMP_SECONDS_MAX: i64 = 253_402_300_799;
+
 impl Duration {
     /// Normalizes the duration to a canonical format.
     ///
@@ -78,32 +90,50 @@ impl Duration {
                 self.nanos = -NANOS_MAX;
             }
         }
-        // TODO: should this be checked?
-        // debug_assert!(self.seconds >= -315_576_000_000 && self.seconds <= 315_576_000_000,
-        //               "invalid duration: {:?}", self);
+    }
+
+    /// Returns `true` if the duration is valid according to the Protobuf specification: the
+    /// seconds are within ±315,576,000,000, the nanos are within ±999,999,999, and the nanos are
+    /// zero or have the same sign as the seconds.
+    ///
+    /// Invalid durations can be decoded, but are rejected by the conversion to
+    /// `std::time::Duration`. Call [`Duration::normalize`] first to convert a duration whose
+    /// nanos are out of range or do not match the sign of the seconds.
+    pub fn is_valid(&self) -> bool {
+        (-DURATION_SECONDS_MAX..=DURATION_SECONDS_MAX).contains(&self.seconds)
+            && (-NANOS_MAX..=NANOS_MAX).contains(&self.nanos)
+            && !(self.seconds < 0 && self.nanos > 0)
+            && !(self.seconds > 0 && self.nanos < 0)
     }
 }
 
 impl TryFrom<time::Duration> for Duration {
     type Error = DurationError;
 
-    /// Converts a `std::time::Duration` to a `Duration`, failing if the duration is too large.
+    /// Converts a `std::time::Duration` to a `Duration`, failing if the duration is longer than
+    /// the 315,576,000,000 seconds allowed by the Protobuf specification.
     fn try_from(duration: time::Duration) -> Result<Duration, DurationError> {
         let seconds = i64::try_from(duration.as_secs()).map_err(|_| DurationError::OutOfRange)?;
         let nanos = duration.subsec_nanos() as i32;
 
-        let mut duration = Duration { seconds, nanos };
-        duration.normalize();
-        Ok(duration)
+        let duration = Duration { seconds, nanos };
+        if duration.is_valid() {
+            Ok(duration)
+        } else {
+            Err(DurationError::OutOfRange)
+        }
     }
 }
 
 impl TryFrom<Duration> for time::Duration {
     type Error = DurationError;
 
-    /// Converts a `Duration` to a `std::time::Duration`, failing if the duration is negative.
-    fn try_from(mut duration: Duration) -> Result<time::Duration, DurationError> {
-        duration.normalize();
+    /// Converts a `Duration` to a `std::time::Duration`, failing if the duration is negative, or
+    /// with `OutOfRange` if it is not valid according to [`Duration::is_valid`].
+    fn try_from(duration: Duration) -> Result<time::Duration, DurationError> {
+        if !duration.is_valid() {
+            return Err(DurationError::OutOfRange);
+        }
         if duration.seconds >= 0 {
             Ok(time::Duration::new(
                 duration.seconds as u64,
@@ -157,10 +187,12 @@ pub enum DurationError {
     /// original negative `prost_types::Duration`.
     NegativeDuration(time::Duration),
 
-    /// Indicates failure to convert a `std::time::Duration` to a `prost_types::Duration`.
+    /// Indicates failure to convert between a `std::time::Duration` and a
+    /// `prost_types::Duration` because the `prost_types::Duration` is not valid.
     ///
     /// Converting a `std::time::Duration` to a `prost_types::Duration` fails if the magnitude
-    /// exceeds that representable by `prost_types::Duration`.
+    /// exceeds the range of valid durations, and converting a `prost_types::Duration` to a
+    /// `std::time::Duration` fails if it is not valid, see [`Duration::is_valid`].
     OutOfRange,
 }
 
@@ -226,10 +258,18 @@ impl Timestamp {
                 self.nanos = 0;
             }
         }
+    }
 
-        // TODO: should this be checked?
-        // debug_assert!(self.seconds >= -62_135_596_800 && self.seconds <= 253_402_300_799,
-        //               "invalid timestamp: {:?}", self);
+    /// Returns `true` if the timestamp is valid according to the Protobuf specification: it is
+    /// within `0001-01-01T00:00:00Z` to `9999-12-31T23:59:59.999999999Z`, and the nanos are
+    /// within 0 to 999,999,999. Timestamps before the Unix epoch count their nanos forward from
+    /// the seconds, so the nanos of a valid timestamp are never negative.
+    ///
+    /// Invalid timestamps can be decoded, but are rejected by the conversion to `SystemTime`.
+    /// Call [`Timestamp::normalize`] first to convert a timestamp whose nanos are out of range.
+    pub fn is_valid(&self) -> bool {
+        (TIMESTAMP_SECONDS_MIN..=TIMESTAMP_SECONDS_MAX).contains(&self.seconds)
+            && (0..=NANOS_MAX).contains(&self.nanos)
     }
 
     /// Creates a new `Timestamp` at the start of the provided UTC date.
@@ -332,6 +372,11 @@ pub enum TimestampError {
 
     /// Indicates an error when constructing a timestamp due to invalid date or time data.
     InvalidDateTime,
+
+    /// Indicates that a [`Timestamp`] could not be converted to
+    /// [`SystemTime`][std::time::SystemTime] because it is not valid, see
+    /// [`Timestamp::is_valid`].
+    InvalidTimestamp(Timestamp),
 }
 
 impl fmt::Display for TimestampError {
@@ -350,6 +395,13 @@ impl fmt::Display for TimestampError {
             TimestampError::InvalidDateTime => {
                 write!(f, "invalid date or time")
             }
+            TimestampError::InvalidTimestamp(timestamp) => {
+                write!(
+                    f,
+                    "{}s {}ns is not a valid timestamp",
+                    timestamp.seconds, timestamp.nanos
+                )
+            }
         }
     }
 }
@@ -361,9 +413,12 @@ impl std::error::Error for TimestampError {}
 impl TryFrom<Timestamp> for std::time::SystemTime {
     type Error = TimestampError;
 
-    fn try_from(mut timestamp: Timestamp) -> Result<std::time::SystemTime, Self::Error> {
-        let orig_timestamp = timestamp.clone();
-        timestamp.normalize();
+    /// Converts a `Timestamp` to a `SystemTime`, failing if the timestamp is not valid according
+    /// to [`Timestamp::is_valid`], or if the time is not representable by the platform.
+    fn try_from(timestamp: Timestamp) -> Result<std::time::SystemTime, Self::Error> {
+        if !timestamp.is_valid() {
+            return Err(TimestampError::InvalidTimestamp(timestamp));
+        }
 
         let system_time = if timestamp.seconds >= 0 {
             std::time::UNIX_EPOCH.checked_add(time::Duration::from_secs(timestamp.seconds as u64))
@@ -381,7 +436,7 @@ impl TryFrom<Timestamp> for std::time::SystemTime {
             system_time.checked_add(time::Duration::from_nanos(timestamp.nanos as u64))
         });
 
-        system_time.ok_or(TimestampError::OutOfSystemRange(orig_timestamp))
+        system_time.ok_or(TimestampError::OutOfSystemRange(timestamp))
     }
 }
 
@@ -399,6 +454,112 @@ impl fmt::Display for Timestamp {
     }
 }
 
+macro_rules! impl_name {
+    ($($ty:ident),*) => {
+        $(
+            impl Name for $ty {
+                const NAME: &'static str = stringify!($ty);
+                const PACKAGE: &'static str = "google.protobuf";
+            }
+        )*
+    };
+}
+
+impl_name!(
+    Any,
+    Api,
+    Duration,
+    Enum,
+    EnumValue,
+    Field,
+    FieldMask,
+    ListValue,
+    Method,
+    Mixin,
+    Option,
+    SourceContext,
+    Struct,
+    Timestamp,
+    Type,
+    Value
+);
+
+impl Any {
+    /// Packs a message into an `Any`, identified by the message's type URL.
+    pub fn pack<M>(message: &M) -> Any
+    where
+        M: Name,
+    {
+        Any {
+            type_url: M::type_url(),
+            value: message.encode_to_vec(),
+        }
+    }
+
+    /// Returns `true` if the `Any` holds a message of type `M`, i.e. if the last segment of the
+    /// type URL is the message's full name.
+    pub fn is<M>(&self) -> bool
+    where
+        M: Name,
+    {
+        match self.type_url.rsplit_once('/') {
+            Some((_, name)) => name == M::full_name(),
+            None => false,
+        }
+    }
+
+    /// Decodes the message held by the `Any`, failing if it is not of type `M`.
+    pub fn unpack<M>(&self) -> Result<M, AnyError>
+    where
+        M: Name + Default,
+    {
+        if !self.is::<M>() {
+            return Err(AnyError::TypeMismatch {
+                type_url: self.type_url.clone(),
+                expected: M::full_name(),
+            });
+        }
+        M::decode(&*self.value).map_err(AnyError::Decode)
+    }
+}
+
+/// An error unpacking a message from an [`Any`].
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum AnyError {
+    /// Indicates that the `Any` holds a message of a different type than the one requested. The
+    /// `type_url` is that of the `Any`, and `expected` is the full name of the requested type.
+    TypeMismatch { type_url: String, expected: String },
+
+    /// Indicates that the `Any`'s value could not be decoded as the requested type.
+    Decode(DecodeError),
+}
+
+impl fmt::Display for AnyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnyError::TypeMismatch { type_url, expected } => {
+                write!(
+                    f,
+                    "expected an Any holding {}, found {}",
+                    expected, type_url
+                )
+            }
+            AnyError::Decode(error) => error.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AnyError {
+    fn source(&self) -> core::option::Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AnyError::TypeMismatch { .. } => None,
+            AnyError::Decode(error) => Some(error),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::{self, SystemTime, UNIX_EPOCH};
@@ -430,12 +591,11 @@ mod tests {
 
         #[test]
         fn check_duration_roundtrip(
-            std_duration in time::Duration::arbitrary(),
+            seconds in 0..=315_576_000_000u64,
+            nanos in 0..1_000_000_000u32,
         ) {
-            let prost_duration = match Duration::try_from(std_duration) {
-                Ok(duration) => duration,
-                Err(_) => return Err(TestCaseError::reject("duration out of range")),
-            };
+            let std_duration = time::Duration::new(seconds, nanos);
+            let prost_duration = Duration::try_from(std_duration).unwrap();
             prop_assert_eq!(time::Duration::try_from(prost_duration.clone()).unwrap(), std_duration);
 
             if std_duration != time::Duration::default() {
@@ -598,6 +758,142 @@ mod tests {
         }
     }
 
+    #[test]
+    fn check_any_pack_unpack() {
+        let duration = Duration {
+            seconds: 3,
+            nanos: 500,
+        };
+        let any = Any::pack(&duration);
+        assert_eq!(any.type_url, "type.googleapis.com/google.protobuf.Duration");
+        assert!(any.is::<Duration>());
+        assert!(!any.is::<Timestamp>());
+        assert_eq!(any.unpack::<Duration>().unwrap(), duration);
+
+        assert_eq!(
+            any.unpack::<Timestamp>(),
+            Err(AnyError::TypeMismatch {
+                type_url: "type.googleapis.com/google.protobuf.Duration".to_string(),
+                expected: "google.protobuf.Timestamp".to_string(),
+            })
+        );
+
+        // Only the last segment of the URL identifies the type.
+        let any = Any {
+            type_url: "example.com/types/google.protobuf.Duration".to_string(),
+            ..any
+        };
+        assert_eq!(any.unpack::<Duration>().unwrap(), duration);
+
+        let any = Any {
+            type_url: "google.protobuf.Duration".to_string(),
+            ..any
+        };
+        assert!(!any.is::<Duration>());
+
+        let any = Any {
+            type_url: Duration::type_url(),
+            value: vec![0x08],
+        };
+        assert!(matches!(any.unpack::<Duration>(), Err(AnyError::Decode(_))));
+    }
+
+    #[test]
+    fn check_duration_is_valid() {
+        #[rustfmt::skip] // Don't mangle the table formatting.
+        let cases = [
+            //          seconds            nanos  valid
+            (                 0,               0, true),
+            (                 1,     999_999_999, true),
+            (                -1,    -999_999_999, true),
+            (                 0,    -999_999_999, true),
+            (                 0,   1_000_000_000, false),
+            (                 1,              -1, false),
+            (                -1,               1, false),
+            ( 315_576_000_000,     999_999_999, true),
+            (-315_576_000_000,    -999_999_999, true),
+            ( 315_576_000_001,               0, false),
+            (-315_576_000_001,               0, false),
+        ];
+        for &(seconds, nanos, valid) in cases.iter() {
+            assert_eq!(
+                Duration { seconds, nanos }.is_valid(),
+                valid,
+                "{}s {}ns",
+                seconds,
+                nanos
+            );
+        }
+
+        assert_eq!(
+            Duration::try_from(time::Duration::new(315_576_000_000, 999_999_999)).unwrap(),
+            Duration {
+                seconds: 315_576_000_000,
+                nanos: 999_999_999,
+            }
+        );
+        assert_eq!(
+            Duration::try_from(time::Duration::from_secs(315_576_000_001)),
+            Err(DurationError::OutOfRange)
+        );
+        assert_eq!(
+            Duration::try_from(time::Duration::MAX),
+            Err(DurationError::OutOfRange)
+        );
+
+        for &(seconds, nanos) in [(0, 1_000_000_000), (1, -1), (-1, 1), (315_576_000_001, 0)].iter()
+        {
+            assert_eq!(
+                time::Duration::try_from(Duration { seconds, nanos }),
+                Err(DurationError::OutOfRange),
+                "{}s {}ns",
+                seconds,
+                nanos
+            );
+        }
+    }
+
+    #[test]
+    fn check_timestamp_is_valid() {
+        let min = Timestamp::date(1, 1, 1).unwrap();
+        let max = Timestamp::date_time_nanos(9999, 12, 31, 23, 59, 59, 999_999_999).unwrap();
+        assert!(min.is_valid());
+        assert!(max.is_valid());
+        assert!(Timestamp::default().is_valid());
+        assert!(Timestamp {
+            seconds: -1,
+            nanos: 999_999_999
+        }
+        .is_valid());
+
+        let invalid = [
+            Timestamp {
+                seconds: min.seconds - 1,
+                nanos: 999_999_999,
+            },
+            Timestamp {
+                seconds: max.seconds + 1,
+                nanos: 0,
+            },
+            Timestamp {
+                seconds: 0,
+                nanos: -1,
+            },
+            Timestamp {
+                seconds: 0,
+                nanos: 1_000_000_000,
+            },
+        ];
+        for timestamp in invalid.iter() {
+            assert!(!timestamp.is_valid(), "{:?}", timestamp);
+            #[cfg(feature = "std")]
+            assert_eq!(
+                SystemTime::try_from(timestamp.clone()),
+                Err(TimestampError::InvalidTimestamp(timestamp.clone()))
+            );
+        }
+    }
+
     #[cfg(feature = "std")]
     #[test]
     fn check_timestamp_normalize() {
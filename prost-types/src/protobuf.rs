@@ -178,35 +178,53 @@ pub mod field_descriptor_proto {
     pub enum Type {
         /// 0 is reserved for errors.
         /// Order is weird for historical reasons.
+        #[prost(name = "TYPE_DOUBLE")]
         Double = 1,
+        #[prost(name = "TYPE_FLOAT")]
         Float = 2,
         /// Not ZigZag encoded.  Negative numbers take 10 bytes.  Use TYPE_SINT64 if
         /// negative values are likely.
+        #[prost(name = "TYPE_INT64")]
         Int64 = 3,
+        #[prost(name = "TYPE_UINT64")]
         Uint64 = 4,
         /// Not ZigZag encoded.  Negative numbers take 10 bytes.  Use TYPE_SINT32 if
         /// negative values are likely.
+        #[prost(name = "TYPE_INT32")]
         Int32 = 5,
+        #[prost(name = "TYPE_FIXED64")]
         Fixed64 = 6,
+        #[prost(name = "TYPE_FIXED32")]
         Fixed32 = 7,
+        #[prost(name = "TYPE_BOOL")]
         Bool = 8,
+        #[prost(name = "TYPE_STRING")]
         String = 9,
         /// Tag-delimited aggregate.
         /// Group type is deprecated and not supported in proto3. However, Proto3
         /// implementations should still be able to parse the group wire format and
         /// treat group fields as unknown fields.
+        #[prost(name = "TYPE_GROUP")]
         Group = 10,
         /// Length-delimited aggregate.
+        #[prost(name = "TYPE_MESSAGE")]
         Message = 11,
         /// New in version 2.
+        #[prost(name = "TYPE_BYTES")]
         Bytes = 12,
+        #[prost(name = "TYPE_UINT32")]
         Uint32 = 13,
+        #[prost(name = "TYPE_ENUM")]
         Enum = 14,
+        #[prost(name = "TYPE_SFIXED32")]
         Sfixed32 = 15,
+        #[prost(name = "TYPE_SFIXED64")]
         Sfixed64 = 16,
         /// Uses ZigZag encoding.
+        #[prost(name = "TYPE_SINT32")]
         Sint32 = 17,
         /// Uses ZigZag encoding.
+        #[prost(name = "TYPE_SINT64")]
         Sint64 = 18,
     }
     impl Type {
@@ -241,8 +259,11 @@ pub mod field_descriptor_proto {
     #[repr(i32)]
     pub enum Label {
         /// 0 is reserved for errors
+        #[prost(name = "LABEL_OPTIONAL")]
         Optional = 1,
+        #[prost(name = "LABEL_REQUIRED")]
         Required = 2,
+        #[prost(name = "LABEL_REPEATED")]
         Repeated = 3,
     }
     impl Label {
@@ -494,12 +515,15 @@ pub mod file_options {
     #[repr(i32)]
     pub enum OptimizeMode {
         /// Generate complete code for parsing, serialization,
+        #[prost(name = "SPEED")]
         Speed = 1,
         /// etc.
         ///
         /// Use ReflectionOps to implement these methods.
+        #[prost(name = "CODE_SIZE")]
         CodeSize = 2,
         /// Generate code using MessageLite and the lite runtime.
+        #[prost(name = "LITE_RUNTIME")]
         LiteRuntime = 3,
     }
     impl OptimizeMode {
@@ -652,8 +676,11 @@ pub mod field_options {
     #[repr(i32)]
     pub enum CType {
         /// Default mode.
+        #[prost(name = "STRING")]
         String = 0,
+        #[prost(name = "CORD")]
         Cord = 1,
+        #[prost(name = "STRING_PIECE")]
         StringPiece = 2,
     }
     impl CType {
@@ -673,10 +700,13 @@ pub mod field_options {
     #[repr(i32)]
     pub enum JsType {
         /// Use the default type.
+        #[prost(name = "JS_NORMAL")]
         JsNormal = 0,
         /// Use JavaScript strings.
+        #[prost(name = "JS_STRING")]
         JsString = 1,
         /// Use JavaScript numbers.
+        #[prost(name = "JS_NUMBER")]
         JsNumber = 2,
     }
     impl JsType {
@@ -771,10 +801,13 @@ pub mod method_options {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
     #[repr(i32)]
     pub enum IdempotencyLevel {
+        #[prost(name = "IDEMPOTENCY_UNKNOWN")]
         IdempotencyUnknown = 0,
         /// implies idempotent
+        #[prost(name = "NO_SIDE_EFFECTS")]
         NoSideEffects = 1,
         /// idempotent, but may have side effects
+        #[prost(name = "IDEMPOTENT")]
         Idempotent = 2,
     }
     impl IdempotencyLevel {
@@ -1210,42 +1243,61 @@ pub mod field {
     #[repr(i32)]
     pub enum Kind {
         /// Field type unknown.
+        #[prost(name = "TYPE_UNKNOWN")]
         TypeUnknown = 0,
         /// Field type double.
+        #[prost(name = "TYPE_DOUBLE")]
         TypeDouble = 1,
         /// Field type float.
+        #[prost(name = "TYPE_FLOAT")]
         TypeFloat = 2,
         /// Field type int64.
+        #[prost(name = "TYPE_INT64")]
         TypeInt64 = 3,
         /// Field type uint64.
+        #[prost(name = "TYPE_UINT64")]
         TypeUint64 = 4,
         /// Field type int32.
+        #[prost(name = "TYPE_INT32")]
         TypeInt32 = 5,
         /// Field type fixed64.
+        #[prost(name = "TYPE_FIXED64")]
         TypeFixed64 = 6,
         /// Field type fixed32.
+        #[prost(name = "TYPE_FIXED32")]
         TypeFixed32 = 7,
         /// Field type bool.
+        #[prost(name = "TYPE_BOOL")]
         TypeBool = 8,
         /// Field type string.
+        #[prost(name = "TYPE_STRING")]
         TypeString = 9,
         /// Field type group. Proto2 syntax only, and deprecated.
+        #[prost(name = "TYPE_GROUP")]
         TypeGroup = 10,
         /// Field type message.
+        #[prost(name = "TYPE_MESSAGE")]
         TypeMessage = 11,
         /// Field type bytes.
+        #[prost(name = "TYPE_BYTES")]
         TypeBytes = 12,
         /// Field type uint32.
+        #[prost(name = "TYPE_UINT32")]
         TypeUint32 = 13,
         /// Field type enum.
+        #[prost(name = "TYPE_ENUM")]
         TypeEnum = 14,
         /// Field type sfixed32.
+        #[prost(name = "TYPE_SFIXED32")]
         TypeSfixed32 = 15,
         /// Field type sfixed64.
+        #[prost(name = "TYPE_SFIXED64")]
         TypeSfixed64 = 16,
         /// Field type sint32.
+        #[prost(name = "TYPE_SINT32")]
         TypeSint32 = 17,
         /// Field type sint64.
+        #[prost(name = "TYPE_SINT64")]
         TypeSint64 = 18,
     }
     impl Kind {
@@ -1282,12 +1334,16 @@ pub mod field {
     #[repr(i32)]
     pub enum Cardinality {
         /// For fields with unknown cardinality.
+        #[prost(name = "CARDINALITY_UNKNOWN")]
         Unknown = 0,
         /// For optional fields.
+        #[prost(name = "CARDINALITY_OPTIONAL")]
         Optional = 1,
         /// For required fields. Proto2 syntax only.
+        #[prost(name = "CARDINALITY_REQUIRED")]
         Required = 2,
         /// For repeated fields.
+        #[prost(name = "CARDINALITY_REPEATED")]
         Repeated = 3,
     }
     impl Cardinality {
@@ -1359,8 +1415,10 @@ pub struct Option {
 #[repr(i32)]
 pub enum Syntax {
     /// Syntax `proto2`.
+    #[prost(name = "SYNTAX_PROTO2")]
     Proto2 = 0,
     /// Syntax `proto3`.
+    #[prost(name = "SYNTAX_PROTO3")]
     Proto3 = 1,
 }
 impl Syntax {
@@ -1882,13 +1940,14 @@ pub struct Struct {
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Value {
     /// The kind of value.
-    #[prost(oneof="value::Kind", tags="1, 2, 3, 4, 5, 6")]
+    #[prost(oneof="value::Kind", tags="1, 2, 3, 4, 5, 6", case)]
     pub kind: ::core::option::Option<value::Kind>,
 }
 /// Nested message and enum types in `Value`.
 pub mod value {
     /// The kind of value.
     #[derive(Clone, PartialEq, ::prost::Oneof)]
+    #[prost(case)]
     pub enum Kind {
         /// Represents a null value.
         #[prost(enumeration="super::NullValue", tag="1")]
@@ -1927,6 +1986,7 @@ pub struct ListValue {
 #[repr(i32)]
 pub enum NullValue {
     /// Null value.
+    #[prost(name = "NULL_VALUE")]
     NullValue = 0,
 }
 impl NullValue {
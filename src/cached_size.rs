@@ -0,0 +1,91 @@
+//! Memoization of a message's encoded length.
+
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Sentinel stored when no length has been cached.
+///
+/// A length of zero is never cached, since every length must be distinguishable from it: an
+/// empty message holds no embedded messages, so its length is cheap to recompute. Lengths which
+/// saturate at `usize::MAX` are cached like any other.
+const UNSET: usize = 0;
+
+/// The most recently computed encoded length of a message.
+///
+/// Encoding a message with nested messages requires the length of every nested message to be
+/// known before it is written, so without memoization the lengths of deeply nested messages are
+/// computed once per level of nesting. Messages opt in to caching by including a field of this
+/// type annotated with `#[prost(cached_size)]`: every call to `Message::encoded_len` stores the
+/// computed length, and the encoder reuses it when the message is nested in another.
+///
+/// The cache is not invalidated when the message is modified, so it is only read right after it
+/// has been refreshed: the encoding methods on `Message`, such as `encode` and `encode_to_vec`,
+/// call `encoded_len`, which refreshes the cache of every nested message, and then encode the
+/// message reusing the cached lengths. `Message::encode_raw` and the functions in
+/// `prost::encoding` compute the lengths of nested messages afresh, so they may be called on a
+/// message which was modified after its length was last computed.
+///
+/// The cached length is not part of the message's value: all `CachedSize`s compare equal.
+pub struct CachedSize {
+    len: AtomicUsize,
+}
+
+impl CachedSize {
+    /// Creates an empty `CachedSize`.
+    pub const fn new() -> CachedSize {
+        CachedSize {
+            len: AtomicUsize::new(UNSET),
+        }
+    }
+
+    /// Returns the cached length, if a non-zero length has been stored.
+    pub fn get(&self) -> Option<usize> {
+        match self.len.load(Ordering::Relaxed) {
+            UNSET => None,
+            len => Some(len),
+        }
+    }
+
+    /// Stores a newly computed length.
+    pub fn set(&self, len: usize) {
+        self.len.store(len, Ordering::Relaxed);
+    }
+
+    /// Discards the cached length.
+    pub fn clear(&self) {
+        self.len.store(UNSET, Ordering::Relaxed);
+    }
+}
+
+impl Default for CachedSize {
+    fn default() -> CachedSize {
+        CachedSize::new()
+    }
+}
+
+impl Clone for CachedSize {
+    fn clone(&self) -> CachedSize {
+        CachedSize {
+            len: AtomicUsize::new(self.len.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl fmt::Debug for CachedSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CachedSize").field(&self.get()).finish()
+    }
+}
+
+impl PartialEq for CachedSize {
+    fn eq(&self, _other: &CachedSize) -> bool {
+        true
+    }
+}
+
+impl Eq for CachedSize {}
+
+impl Hash for CachedSize {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
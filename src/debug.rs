@@ -0,0 +1,39 @@
+//! Legible `Debug` output for `bytes` fields.
+
+use core::fmt;
+
+use crate::text_format::write_quoted_bytes;
+
+/// The number of leading bytes written by `DebugBytes`.
+const PREVIEW_LEN: usize = 32;
+
+/// A wrapper which formats a `bytes` value for `Debug` output.
+///
+/// The value is written as a quoted string with octal escape sequences, as in the text format.
+/// Values longer than 32 bytes are truncated, and followed by their total length, so that
+/// messages holding large binary values remain legible in logs:
+///
+/// ```rust
+/// use prost::DebugBytes;
+///
+/// assert_eq!(format!("{:?}", DebugBytes(b"a\x00\xff")), r#""a\000\377""#);
+/// assert_eq!(
+///     format!("{:?}", DebugBytes(&[b'x'; 1000])),
+///     r#""xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx"... (1000 bytes)"#
+/// );
+/// ```
+///
+/// The `Debug` implementations derived for messages format their `bytes` fields with it.
+#[derive(Clone, Copy)]
+pub struct DebugBytes<'a>(pub &'a [u8]);
+
+impl fmt::Debug for DebugBytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.len() <= PREVIEW_LEN {
+            write_quoted_bytes(f, self.0)
+        } else {
+            write_quoted_bytes(f, &self.0[..PREVIEW_LEN])?;
+            write!(f, "... ({} bytes)", self.0.len())
+        }
+    }
+}
@@ -0,0 +1,541 @@
+//! Streams of length-delimited messages.
+
+use core::fmt;
+use core::marker::PhantomData;
+#[cfg(feature = "std")]
+use core::slice;
+
+use bytes::{Buf, BufMut};
+
+use crate::{DecodeError, EncodeError, Message};
+
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+#[cfg(all(feature = "std", feature = "tokio"))]
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+#[cfg(feature = "std")]
+use crate::decode_length_delimiter;
+
+/// An iterator which decodes successive length-delimited messages from a buffer.
+///
+/// This reads the common "stream of records" format, in which each message is preceded by its
+/// length as a varint, as written by [Message::encode_length_delimited] or by Java's
+/// `writeDelimitedTo`.
+///
+/// Iteration ends when the buffer is exhausted. If a message fails to decode, including when the
+/// buffer ends part way through a message, the error is yielded and iteration ends, since the
+/// position of the next message can no longer be determined.
+pub struct LengthDelimitedDecoder<M, B> {
+    buf: B,
+    failed: bool,
+    _message: PhantomData<fn() -> M>,
+}
+
+impl<M, B> LengthDelimitedDecoder<M, B>
+where
+    M: Message + Default,
+    B: Buf,
+{
+    /// Creates a decoder which reads length-delimited messages from `buf`.
+    pub fn new(buf: B) -> LengthDelimitedDecoder<M, B> {
+        LengthDelimitedDecoder {
+            buf,
+            failed: false,
+            _message: PhantomData,
+        }
+    }
+
+    /// Returns a reference to the underlying buffer.
+    pub fn get_ref(&self) -> &B {
+        &self.buf
+    }
+
+    /// Consumes the decoder, returning the underlying buffer.
+    pub fn into_inner(self) -> B {
+        self.buf
+    }
+}
+
+impl<M, B> Iterator for LengthDelimitedDecoder<M, B>
+where
+    M: Message + Default,
+    B: Buf,
+{
+    type Item = Result<M, DecodeError>;
+
+    fn next(&mut self) -> Option<Result<M, DecodeError>> {
+        if self.failed || !self.buf.has_remaining() {
+            return None;
+        }
+        let result = M::decode_length_delimited(&mut self.buf);
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+impl<M, B> fmt::Debug for LengthDelimitedDecoder<M, B>
+where
+    B: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LengthDelimitedDecoder")
+            .field("buf", &self.buf)
+            .field("failed", &self.failed)
+            .finish()
+    }
+}
+
+/// An iterator which reads successive length-delimited messages from an `io::Read`.
+///
+/// This reads the same format as [`LengthDelimitedDecoder`], but from a reader such as a file or a
+/// socket, so that a large stream of messages can be processed without holding all of it in
+/// memory. Only one message is buffered at a time. The reader is read from in small pieces while
+/// decoding length delimiters, so wrapping it in an `io::BufReader` is recommended.
+///
+/// Iteration ends when the reader reaches its end between two messages. If the reader ends part
+/// way through a message, an error of kind `UnexpectedEof` is yielded, and messages which fail
+/// to decode yield an error of kind `InvalidData`. Iteration ends after any error.
+#[cfg(feature = "std")]
+pub struct LengthDelimitedReader<M, R> {
+    reader: R,
+    failed: bool,
+    _message: PhantomData<fn() -> M>,
+}
+
+#[cfg(feature = "std")]
+impl<M, R> LengthDelimitedReader<M, R>
+where
+    M: Message + Default,
+    R: Read,
+{
+    /// Creates a reader which reads length-delimited messages from `reader`.
+    pub fn new(reader: R) -> LengthDelimitedReader<M, R> {
+        LengthDelimitedReader {
+            reader,
+            failed: false,
+            _message: PhantomData,
+        }
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Returns a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Consumes the decoder, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads the next message, or returns `None` if the reader is at its end.
+    fn read_message(&mut self) -> io::Result<Option<M>> {
+        // The delimiter is read one byte at a time, since its length is only known once its
+        // last byte, which has the high bit clear, has been read.
+        let mut delimiter = [0u8; 10];
+        let mut delimiter_len = 0;
+        loop {
+            let mut byte = 0;
+            match self.reader.read(slice::from_mut(&mut byte)) {
+                Ok(0) if delimiter_len == 0 => return Ok(None),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(_) => (),
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            }
+            if delimiter_len == delimiter.len() {
+                return Err(DecodeError::new("varint too long").into());
+            }
+            delimiter[delimiter_len] = byte;
+            delimiter_len += 1;
+            if byte < 0x80 {
+                break;
+            }
+        }
+        let len = decode_length_delimiter(&delimiter[..delimiter_len])?;
+
+        // Read through `take` rather than into a buffer of `len` bytes, so that a corrupt
+        // delimiter can not cause a large allocation before the end of the reader is found.
+        let mut buf = Vec::new();
+        (&mut self.reader).take(len as u64).read_to_end(&mut buf)?;
+        if buf.len() < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(Some(M::decode(&*buf)?))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<M, R> Iterator for LengthDelimitedReader<M, R>
+where
+    M: Message + Default,
+    R: Read,
+{
+    type Item = io::Result<M>;
+
+    fn next(&mut self) -> Option<io::Result<M>> {
+        if self.failed {
+            return None;
+        }
+        let result = self.read_message().transpose();
+        self.failed = matches!(result, Some(Err(_)));
+        result
+    }
+}
+
+#[cfg(feature = "std")]
+impl<M, R> fmt::Debug for LengthDelimitedReader<M, R>
+where
+    R: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LengthDelimitedReader")
+            .field("reader", &self.reader)
+            .field("failed", &self.failed)
+            .finish()
+    }
+}
+
+/// Reads the next length-delimited message from an async reader, or returns `None` if the reader
+/// is at its end.
+///
+/// This reads the same format as [`LengthDelimitedReader`], from a `tokio::io::AsyncRead`, and is
+/// called once per message, so that a large stream can be processed without holding all of it in
+/// memory. The delimiter is read one byte at a time, so wrapping the reader in a
+/// `tokio::io::BufReader` is recommended. If the reader ends part way through a message, an error
+/// of kind `UnexpectedEof` is returned, and a message which fails to decode returns an error of
+/// kind `InvalidData`; the stream can not be read any further after an error.
+///
+/// ```rust,ignore
+/// while let Some(record) = prost::decode_length_delimited_from::<Record, _>(&mut reader).await? {
+///     process(record);
+/// }
+/// ```
+#[cfg(all(feature = "std", feature = "tokio"))]
+pub async fn decode_length_delimited_from<M, R>(reader: &mut R) -> io::Result<Option<M>>
+where
+    M: Message + Default,
+    R: AsyncRead + Unpin,
+{
+    let mut delimiter = [0u8; 10];
+    let mut delimiter_len = 0;
+    loop {
+        let mut byte = 0;
+        if reader.read(slice::from_mut(&mut byte)).await? == 0 {
+            if delimiter_len == 0 {
+                return Ok(None);
+            }
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if delimiter_len == delimiter.len() {
+            return Err(DecodeError::new("varint too long").into());
+        }
+        delimiter[delimiter_len] = byte;
+        delimiter_len += 1;
+        if byte < 0x80 {
+            break;
+        }
+    }
+    let len = decode_length_delimiter(&delimiter[..delimiter_len])?;
+
+    let mut buf = Vec::new();
+    reader.take(len as u64).read_to_end(&mut buf).await?;
+    if buf.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(Some(M::decode(&*buf)?))
+}
+
+/// A sink which encodes successive messages to a buffer, each preceded by its length.
+///
+/// This writes the format read by [`LengthDelimitedDecoder`], and by Java's `parseDelimitedFrom`.
+/// Each message is written with [Message::encode_length_delimited].
+pub struct LengthDelimitedEncoder<M, B> {
+    buf: B,
+    _message: PhantomData<fn(&M)>,
+}
+
+impl<M, B> LengthDelimitedEncoder<M, B>
+where
+    M: Message,
+    B: BufMut,
+{
+    /// Creates an encoder which appends length-delimited messages to `buf`.
+    pub fn new(buf: B) -> LengthDelimitedEncoder<M, B> {
+        LengthDelimitedEncoder {
+            buf,
+            _message: PhantomData,
+        }
+    }
+
+    /// Appends the message to the buffer, preceded by its length.
+    ///
+    /// An error will be returned if the buffer does not have sufficient capacity, in which case
+    /// nothing is written.
+    pub fn write(&mut self, message: &M) -> Result<(), EncodeError> {
+        message.encode_length_delimited(&mut self.buf)
+    }
+
+    /// Returns a reference to the underlying buffer.
+    pub fn get_ref(&self) -> &B {
+        &self.buf
+    }
+
+    /// Returns a mutable reference to the underlying buffer.
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.buf
+    }
+
+    /// Consumes the encoder, returning the underlying buffer.
+    pub fn into_inner(self) -> B {
+        self.buf
+    }
+}
+
+impl<M, B> fmt::Debug for LengthDelimitedEncoder<M, B>
+where
+    B: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LengthDelimitedEncoder")
+            .field("buf", &self.buf)
+            .finish()
+    }
+}
+
+/// A sink which writes successive messages to an `io::Write`, each preceded by its length.
+///
+/// This writes the format read by [`LengthDelimitedReader`], such as a file of records. Each
+/// message is written with [Message::encode_length_delimited_to_writer]. The writer is never
+/// flushed, so wrapping it in an `io::BufWriter` and flushing it once done is recommended.
+#[cfg(feature = "std")]
+pub struct LengthDelimitedWriter<M, W> {
+    writer: W,
+    _message: PhantomData<fn(&M)>,
+}
+
+#[cfg(feature = "std")]
+impl<M, W> LengthDelimitedWriter<M, W>
+where
+    M: Message,
+    W: Write,
+{
+    /// Creates a writer which writes length-delimited messages to `writer`.
+    pub fn new(writer: W) -> LengthDelimitedWriter<M, W> {
+        LengthDelimitedWriter {
+            writer,
+            _message: PhantomData,
+        }
+    }
+
+    /// Writes the message to the writer, preceded by its length.
+    ///
+    /// Any error returned by the writer is returned, in which case the message may have been
+    /// partially written, and the stream can not be read past the previous message.
+    pub fn write(&mut self, message: &M) -> io::Result<()> {
+        message.encode_length_delimited_to_writer(&mut self.writer)
+    }
+
+    /// Returns a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Returns a mutable reference to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Consumes the writer, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(feature = "std")]
+impl<M, W> fmt::Debug for LengthDelimitedWriter<M, W>
+where
+    W: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LengthDelimitedWriter")
+            .field("writer", &self.writer)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    fn encode(values: &[&str]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for value in values {
+            value.to_string().encode_length_delimited(&mut buf).unwrap();
+        }
+        buf
+    }
+
+    #[test]
+    fn decode_stream() {
+        let buf = encode(&["one", "", "three"]);
+        let decoded = LengthDelimitedDecoder::<String, _>::new(&buf[..])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(decoded, ["one", "", "three"]);
+
+        assert_eq!(LengthDelimitedDecoder::<String, _>::new(&[][..]).count(), 0);
+    }
+
+    #[test]
+    fn decode_truncated_stream() {
+        let buf = encode(&["one", "two"]);
+        let mut decoder = LengthDelimitedDecoder::<String, _>::new(&buf[..buf.len() - 1]);
+        assert_eq!(decoder.next().unwrap().unwrap(), "one");
+        decoder.next().unwrap().expect_err("truncated message");
+        assert!(decoder.next().is_none());
+
+        // A length prefix with no body.
+        let mut decoder = LengthDelimitedDecoder::<String, _>::new(&[0x05][..]);
+        decoder.next().unwrap().expect_err("missing message body");
+        assert!(decoder.next().is_none());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn read_stream() {
+        use std::io::ErrorKind;
+
+        let buf = encode(&["one", "", &"x".repeat(300)]);
+        let decoded = LengthDelimitedReader::<String, _>::new(&buf[..])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(decoded, ["one", "", &"x".repeat(300)]);
+        assert_eq!(LengthDelimitedReader::<String, _>::new(&[][..]).count(), 0);
+
+        // Truncated in the body, and in the two byte delimiter, of the last message.
+        for &len in &[buf.len() - 1, 8] {
+            let mut reader = LengthDelimitedReader::<String, _>::new(&buf[..len]);
+            assert_eq!(reader.next().unwrap().unwrap(), "one");
+            assert_eq!(reader.next().unwrap().unwrap(), "");
+            let error = reader.next().unwrap().unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+            assert!(reader.next().is_none());
+        }
+
+        // A delimiter far longer than the stream, and an invalid message body.
+        for buf in &[&[0xff, 0xff, 0xff, 0xff, 0x0f][..], &[0x01, 0x80][..]] {
+            let mut reader = LengthDelimitedReader::<String, _>::new(*buf);
+            reader.next().unwrap().unwrap_err();
+            assert!(reader.next().is_none());
+        }
+
+        let mut reader = LengthDelimitedReader::<String, _>::new(&[0xff; 11][..]);
+        let error = reader.next().unwrap().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    /// Polls a future which never has to wait, such as one reading from a slice, to completion.
+    #[cfg(all(feature = "std", feature = "tokio"))]
+    fn block_on<F: core::future::Future>(future: F) -> F::Output {
+        use alloc::boxed::Box;
+        use core::ptr;
+        use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(
+            |_| RawWaker::new(ptr::null(), &VTABLE),
+            |_| (),
+            |_| (),
+            |_| (),
+        );
+        let waker = unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) };
+        match Box::pin(future)
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+        {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("future is not ready"),
+        }
+    }
+
+    #[cfg(all(feature = "std", feature = "tokio"))]
+    #[test]
+    fn decode_async_stream() {
+        use std::io::ErrorKind;
+
+        let long = "x".repeat(300);
+        let buf = encode(&["one", "", &long]);
+        let mut reader = &buf[..];
+        let mut decoded = Vec::new();
+        while let Some(value) =
+            block_on(decode_length_delimited_from::<String, _>(&mut reader)).unwrap()
+        {
+            decoded.push(value);
+        }
+        assert_eq!(decoded, ["one", "", &long]);
+
+        // Truncated in the body, and in the two byte delimiter, of the last message.
+        for &len in &[buf.len() - 1, 8] {
+            let mut reader = &buf[..len];
+            for &expected in &["one", ""] {
+                let value = block_on(decode_length_delimited_from::<String, _>(&mut reader));
+                assert_eq!(value.unwrap().unwrap(), expected);
+            }
+            let error = block_on(decode_length_delimited_from::<String, _>(&mut reader));
+            assert_eq!(error.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        }
+
+        // A varint delimiter which is too long, and an invalid message body.
+        for buf in &[&[0xff; 11][..], &[0x01, 0x80][..]] {
+            let error = block_on(decode_length_delimited_from::<String, _>(&mut &buf[..]));
+            assert_eq!(error.unwrap_err().kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn encode_stream() {
+        let long = "x".repeat(300);
+        let mut encoder = LengthDelimitedEncoder::new(Vec::new());
+        for value in &["one", "", &long] {
+            encoder.write(&value.to_string()).unwrap();
+        }
+        assert_eq!(encoder.get_ref()[..], encode(&["one", "", &long])[..]);
+
+        // A message which does not fit is not written at all.
+        let mut buf = [0u8; 12];
+        let mut encoder = LengthDelimitedEncoder::new(&mut buf[..]);
+        encoder.write(&"one".to_string()).unwrap();
+        let error = encoder.write(&"four".to_string()).unwrap_err();
+        assert_eq!(error.required_capacity(), 7);
+        assert_eq!(error.remaining(), 6);
+        encoder.write(&"two".to_string()).unwrap();
+        assert_eq!(buf, *b"\x05\x0a\x03one\x05\x0a\x03two");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn write_stream() {
+        let long = "x".repeat(300);
+        let mut writer = LengthDelimitedWriter::new(Vec::new());
+        for value in &["one", "", &long] {
+            writer.write(&value.to_string()).unwrap();
+        }
+        let buf = writer.into_inner();
+        assert_eq!(buf, encode(&["one", "", &long]));
+
+        let decoded = LengthDelimitedReader::<String, _>::new(&buf[..])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(decoded, ["one", "", &long]);
+    }
+}
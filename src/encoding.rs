@@ -2,11 +2,13 @@
 //!
 //! Meant to be used only from `Message` implementations.
 
-#![allow(clippy::implicit_hasher, clippy::ptr_arg)]
+#![allow(clippy::implicit_hasher, clippy::ptr_arg, clippy::borrowed_box)]
 
+use alloc::boxed::Box;
 use alloc::collections::BTreeMap;
 use alloc::format;
 use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::cmp::min;
 use core::convert::TryFrom;
@@ -39,6 +41,10 @@ where
 }
 
 /// Decodes a LEB128-encoded variable length integer from the buffer.
+///
+/// The varint need not be contiguous: when it may continue past the end of the current chunk of
+/// the buffer, it is read one byte at a time, so a buffer made of several chunks, such as a
+/// `bytes::buf::Chain`, may split it at any position.
 #[inline]
 pub fn decode_varint<B>(buf: &mut B) -> Result<u64, DecodeError>
 where
@@ -152,7 +158,11 @@ fn decode_varint_slice(bytes: &[u8]) -> Result<(u64, usize), DecodeError> {
 
     // We have overrun the maximum size of a varint (10 bytes) or the final byte caused an overflow.
     // Assume the data is corrupt.
-    Err(DecodeError::new("invalid varint"))
+    if b >= 0x80 {
+        Err(DecodeError::new("varint too long"))
+    } else {
+        Err(DecodeError::new("invalid varint"))
+    }
 }
 
 /// Decodes a LEB128-encoded variable length integer from the buffer, advancing the buffer as
@@ -168,7 +178,8 @@ where
     B: Buf,
 {
     let mut value = 0;
-    for count in 0..min(10, buf.remaining()) {
+    let max_count = min(10, buf.remaining());
+    for count in 0..max_count {
         let byte = buf.get_u8();
         value |= u64::from(byte & 0x7F) << (count * 7);
         if byte <= 0x7F {
@@ -182,7 +193,12 @@ where
         }
     }
 
-    Err(DecodeError::new("invalid varint"))
+    // Either ten bytes with the continuation bit set, or a buffer which ends within the varint.
+    if max_count == 10 {
+        Err(DecodeError::new("varint too long"))
+    } else {
+        Err(DecodeError::new("invalid varint"))
+    }
 }
 
 /// Additional information passed to every decode/merge function.
@@ -190,16 +206,30 @@ where
 /// The context should be passed by value and can be freely cloned. When passing
 /// to a function which is decoding a nested object, then use `enter_recursion`.
 #[derive(Clone, Debug)]
-#[cfg_attr(feature = "no-recursion-limit", derive(Default))]
 pub struct DecodeContext {
     /// How many times we can recurse in the current decode stack before we hit
     /// the recursion limit.
     ///
-    /// The recursion limit is defined by `RECURSION_LIMIT` and cannot be
-    /// customized. The recursion limit can be ignored by building the Prost
-    /// crate with the `no-recursion-limit` feature.
+    /// The recursion limit defaults to `RECURSION_LIMIT`, and may be customized
+    /// with `DecodeContext::with_recursion_limit`. The recursion limit can be
+    /// ignored by building the Prost crate with the `no-recursion-limit` feature.
     #[cfg(not(feature = "no-recursion-limit"))]
     recurse_count: u32,
+
+    /// The maximum length of any length-delimited value, in bytes.
+    ///
+    /// Unlimited by default, and may be set with `DecodeContext::with_length_limit`.
+    max_len: usize,
+
+    /// The number of bytes remaining in the buffer at the end of the innermost length-delimited
+    /// value being decoded, which the values nested in it may not extend past. Zero outside of
+    /// length-delimited values, and reset to zero by `Message::merge_with_context`, since it only
+    /// describes the buffer being decoded.
+    enclosing_end: usize,
+
+    /// The interner sharing the decoded values of `Arc<str>` fields, if any.
+    #[cfg(feature = "std")]
+    interner: Option<crate::Interner>,
 }
 
 #[cfg(not(feature = "no-recursion-limit"))]
@@ -208,11 +238,71 @@ impl Default for DecodeContext {
     fn default() -> DecodeContext {
         DecodeContext {
             recurse_count: crate::RECURSION_LIMIT,
+            max_len: usize::MAX,
+            enclosing_end: 0,
+            #[cfg(feature = "std")]
+            interner: None,
+        }
+    }
+}
+
+#[cfg(feature = "no-recursion-limit")]
+impl Default for DecodeContext {
+    #[inline]
+    fn default() -> DecodeContext {
+        DecodeContext {
+            max_len: usize::MAX,
+            enclosing_end: 0,
+            #[cfg(feature = "std")]
+            interner: None,
         }
     }
 }
 
 impl DecodeContext {
+    /// Creates a new `DecodeContext` which allows nested messages, groups, and
+    /// maps to be decoded up to `limit` levels deep.
+    ///
+    /// This is useful for trusted inputs which legitimately nest deeper than the
+    /// default limit of 100. The limit has no effect when Prost is built with the
+    /// `no-recursion-limit` feature.
+    #[cfg(not(feature = "no-recursion-limit"))]
+    #[inline]
+    pub fn with_recursion_limit(limit: u32) -> DecodeContext {
+        DecodeContext {
+            recurse_count: limit,
+            ..DecodeContext::default()
+        }
+    }
+
+    #[cfg(feature = "no-recursion-limit")]
+    #[inline]
+    pub fn with_recursion_limit(_limit: u32) -> DecodeContext {
+        DecodeContext::default()
+    }
+
+    /// Limits the length of every length-delimited value decoded with this context, including
+    /// nested messages, strings, bytes and packed repeated fields, to `max_len` bytes.
+    ///
+    /// The length prefix of each value is checked against the limit before any of the value is
+    /// read, so an oversized length fails immediately, without allocating or scanning the
+    /// buffer.
+    #[inline]
+    pub fn with_length_limit(self, max_len: usize) -> DecodeContext {
+        DecodeContext { max_len, ..self }
+    }
+
+    /// Shares the values of `string = "arc"` fields decoded with this context, including in
+    /// nested messages, through `interner`, see `Interner`.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn with_interner(self, interner: &crate::Interner) -> DecodeContext {
+        DecodeContext {
+            interner: Some(interner.clone()),
+            ..self
+        }
+    }
+
     /// Call this function before recursively decoding.
     ///
     /// There is no `exit` function since this function creates a new `DecodeContext`
@@ -223,13 +313,14 @@ impl DecodeContext {
     pub(crate) fn enter_recursion(&self) -> DecodeContext {
         DecodeContext {
             recurse_count: self.recurse_count - 1,
+            ..self.clone()
         }
     }
 
     #[cfg(feature = "no-recursion-limit")]
     #[inline]
     pub(crate) fn enter_recursion(&self) -> DecodeContext {
-        DecodeContext {}
+        self.clone()
     }
 
     /// Checks whether the recursion limit has been reached in the stack of
@@ -253,6 +344,66 @@ impl DecodeContext {
     pub(crate) fn limit_reached(&self) -> Result<(), DecodeError> {
         Ok(())
     }
+
+    /// Returns a context for decoding the values nested in a length-delimited value, which ends
+    /// when `end` bytes remain in the buffer.
+    #[inline]
+    fn enclose(self, end: usize) -> DecodeContext {
+        DecodeContext {
+            enclosing_end: end,
+            ..self
+        }
+    }
+
+    /// Returns a context for decoding another buffer, which is not enclosed in a length-delimited
+    /// value.
+    #[inline]
+    pub(crate) fn detach(self) -> DecodeContext {
+        self.enclose(0)
+    }
+
+    /// Decodes the length prefix of a length-delimited value, checking it against the length
+    /// limit, against the bytes remaining in `buf`, and against the end of the enclosing
+    /// length-delimited value, if any.
+    #[inline]
+    pub(crate) fn decode_len<B>(&self, buf: &mut B) -> Result<usize, DecodeError>
+    where
+        B: Buf,
+    {
+        let len = decode_varint(buf)?;
+        if len > self.max_len as u64 {
+            return Err(DecodeError::new(format!(
+                "length-delimited value of {} bytes exceeds the limit of {} bytes",
+                len, self.max_len
+            )));
+        }
+        let remaining = buf.remaining();
+        if len > remaining as u64 {
+            return Err(DecodeError::new("buffer underflow"));
+        }
+        // Fewer bytes remaining than at the end of the enclosing value means that a preceding
+        // fixed width or varint value overran the enclosing value, which `merge_loop` reports
+        // once the value is merged.
+        if remaining >= self.enclosing_end && len > (remaining - self.enclosing_end) as u64 {
+            return Err(DecodeError::new(format!(
+                "length-delimited value of {} bytes overruns the enclosing value, which has {} \
+                 bytes left",
+                len,
+                remaining - self.enclosing_end
+            )));
+        }
+        Ok(len as usize)
+    }
+
+    /// Returns an `Arc<str>` holding `value`, shared through the interner, if any.
+    #[inline]
+    pub(crate) fn arc_str(&self, value: &str) -> Arc<str> {
+        #[cfg(feature = "std")]
+        if let Some(ref interner) = self.interner {
+            return interner.intern(value);
+        }
+        Arc::from(value)
+    }
 }
 
 /// Returns the encoded length of the value in LEB128 variable length format.
@@ -264,7 +415,9 @@ pub fn encoded_len_varint(value: u64) -> usize {
     ((((value | 1).leading_zeros() ^ 63) * 9 + 73) / 64) as usize
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+/// The wire type of an encoded field, stored in the low three bits of its key, which determines
+/// how the end of its value is found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(u8)]
 pub enum WireType {
     Varint = 0,
@@ -307,11 +460,21 @@ where
 {
     debug_assert!((MIN_TAG..=MAX_TAG).contains(&tag));
     let key = (tag << 3) | wire_type as u32;
-    encode_varint(u64::from(key), buf);
+    if tag < 16 {
+        // The key of a field with a tag below 16 always fits in a single byte.
+        buf.put_u8(key as u8);
+    } else {
+        encode_varint(u64::from(key), buf);
+    }
 }
 
 /// Decodes a Protobuf field key, which consists of a wire type designator and
 /// the field tag.
+///
+/// Returns an error if the wire type is not one of the six defined by Protobuf,
+/// or if the tag is not in the range `MIN_TAG..=MAX_TAG`. Tags in the range
+/// 19000 to 19999, which are reserved for the Protobuf implementation and can not
+/// be declared in a `.proto` file, are accepted, as by other implementations.
 #[inline(always)]
 pub fn decode_key<B>(buf: &mut B) -> Result<(u32, WireType), DecodeError>
 where
@@ -338,6 +501,28 @@ pub fn key_len(tag: u32) -> usize {
     encoded_len_varint(u64::from(tag << 3))
 }
 
+/// Returns the encoded length of a length-delimited field with the given tag, whose value is
+/// `len` bytes long.
+#[inline]
+pub fn delimited_len(tag: u32, len: usize) -> usize {
+    len.saturating_add(key_len(tag) + encoded_len_varint(len as u64))
+}
+
+/// Sums encoded lengths, saturating at `usize::MAX`.
+///
+/// The encoded length of a message is not bounded by the memory it occupies, e.g. a repeated
+/// `bytes` field may hold many clones of a `Bytes` sharing the same storage, so on 32-bit targets
+/// the length may not fit in a `usize`. All encoded length computations saturate rather than
+/// wrap: no buffer has room for `usize::MAX` bytes, so a message whose length overflows fails to
+/// encode instead of being encoded with corrupt length delimiters.
+#[inline]
+pub fn sum_len<I>(lens: I) -> usize
+where
+    I: IntoIterator<Item = usize>,
+{
+    lens.into_iter().fold(0, usize::saturating_add)
+}
+
 /// Checks that the expected wire type matches the actual wire type,
 /// or returns an error result.
 #[inline]
@@ -351,6 +536,60 @@ pub fn check_wire_type(expected: WireType, actual: WireType) -> Result<(), Decod
     Ok(())
 }
 
+/// Checks that the wire type of a field is one which the field's type may be encoded with, or
+/// returns an error naming the field's tag and the expected and actual wire types.
+///
+/// Meant to be used only by `Message` implementations, before decoding a field whose merge
+/// function does not take the wire type, such as a map field. The fields merged with a wire type
+/// are checked by their merge functions instead, see `field_merge_error`.
+#[inline]
+pub fn check_field_wire_type(
+    tag: u32,
+    expected: &[WireType],
+    actual: WireType,
+) -> Result<(), DecodeError> {
+    if expected.contains(&actual) {
+        Ok(())
+    } else {
+        Err(field_wire_type_error(tag, expected, actual))
+    }
+}
+
+/// Returns the error to report for a field which failed to merge: an error naming the field's
+/// tag and the expected and actual wire types if the wire type is not one which the field's type
+/// may be encoded with, and `error` otherwise.
+///
+/// Meant to be used only by `Message` implementations. The merge functions in this module check
+/// the wire type before decoding the value, but can not name the tag, so their errors are passed
+/// through this function rather than checking the wire type a second time up front. Repeated
+/// numeric fields may be encoded either packed or unpacked, so they expect two wire types.
+#[cold]
+pub fn field_merge_error(
+    tag: u32,
+    expected: &[WireType],
+    actual: WireType,
+    error: DecodeError,
+) -> DecodeError {
+    if expected.contains(&actual) {
+        error
+    } else {
+        field_wire_type_error(tag, expected, actual)
+    }
+}
+
+#[cold]
+fn field_wire_type_error(tag: u32, expected: &[WireType], actual: WireType) -> DecodeError {
+    let mut description = format!("invalid wire type for tag {}: {:?} (expected ", tag, actual);
+    for (i, wire_type) in expected.iter().enumerate() {
+        if i > 0 {
+            description.push_str(" or ");
+        }
+        description.push_str(&format!("{:?}", wire_type));
+    }
+    description.push(')');
+    DecodeError::new(description)
+}
+
 /// Helper function which abstracts reading a length delimiter prefix followed
 /// by decoding values until the length of bytes is exhausted.
 pub fn merge_loop<T, M, B>(
@@ -363,13 +602,9 @@ where
     M: FnMut(&mut T, &mut B, DecodeContext) -> Result<(), DecodeError>,
     B: Buf,
 {
-    let len = decode_varint(buf)?;
-    let remaining = buf.remaining();
-    if len > remaining as u64 {
-        return Err(DecodeError::new("buffer underflow"));
-    }
-
-    let limit = remaining - len as usize;
+    let len = ctx.decode_len(buf)?;
+    let limit = buf.remaining() - len;
+    let ctx = ctx.enclose(limit);
     while buf.remaining() > limit {
         merge(value, buf, ctx.clone())?;
     }
@@ -394,7 +629,7 @@ where
         WireType::Varint => decode_varint(buf).map(|_| 0)?,
         WireType::ThirtyTwoBit => 4,
         WireType::SixtyFourBit => 8,
-        WireType::LengthDelimited => decode_varint(buf)?,
+        WireType::LengthDelimited => ctx.decode_len(buf)? as u64,
         WireType::StartGroup => loop {
             let (inner_tag, inner_wire_type) = decode_key(buf)?;
             match inner_wire_type {
@@ -418,6 +653,85 @@ where
     Ok(())
 }
 
+/// A single field of an encoded message, as yielded by `RawFieldIter`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RawField<'a> {
+    /// The field's tag.
+    pub tag: u32,
+    /// The field's wire type. Groups are reported as `StartGroup`.
+    pub wire_type: WireType,
+    /// The complete encoding of the field, from the first byte of its key to the last byte of
+    /// its value, or of the end group key for a group.
+    pub raw: &'a [u8],
+}
+
+impl<'a> RawField<'a> {
+    /// Writes the field's original encoding to the buffer.
+    pub fn encode<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        buf.put_slice(self.raw);
+    }
+}
+
+/// An iterator over the fields of an encoded message, which does not require the message's
+/// schema.
+///
+/// Each field is yielded with its complete original encoding, so writing the yielded fields back
+/// out with `RawField::encode` reproduces the input exactly, and writing a subset of them
+/// produces a valid message without the omitted fields. This is useful for generic transformers,
+/// e.g. stripping fields by tag.
+///
+/// If a field fails to decode, the error is yielded and iteration ends.
+#[derive(Clone, Debug)]
+pub struct RawFieldIter<'a> {
+    buf: &'a [u8],
+    failed: bool,
+}
+
+impl<'a> RawFieldIter<'a> {
+    /// Creates an iterator over the fields encoded in `buf`.
+    pub fn new(buf: &'a [u8]) -> RawFieldIter<'a> {
+        RawFieldIter { buf, failed: false }
+    }
+
+    /// Returns the bytes which have not yet been iterated over.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.buf
+    }
+}
+
+impl<'a> Iterator for RawFieldIter<'a> {
+    type Item = Result<RawField<'a>, DecodeError>;
+
+    fn next(&mut self) -> Option<Result<RawField<'a>, DecodeError>> {
+        if self.failed || self.buf.is_empty() {
+            return None;
+        }
+        let start = self.buf;
+        let mut buf = self.buf;
+        let result = decode_key(&mut buf).and_then(|(tag, wire_type)| {
+            skip_field(wire_type, tag, &mut buf, DecodeContext::default())?;
+            Ok((tag, wire_type))
+        });
+        match result {
+            Ok((tag, wire_type)) => {
+                self.buf = buf;
+                Some(Ok(RawField {
+                    tag,
+                    wire_type,
+                    raw: &start[..start.len() - buf.len()],
+                }))
+            }
+            Err(error) => {
+                self.failed = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
 /// Helper macro which emits an `encode_repeated` function for the type.
 macro_rules! encode_repeated {
     ($ty:ty) => {
@@ -486,13 +800,16 @@ macro_rules! varint {
          pub mod $proto_ty {
             use crate::encoding::*;
 
+            /// The wire type of the field's values, unless they are packed.
+            pub const WIRE_TYPE: WireType = WireType::Varint;
+
             pub fn encode<B>(tag: u32, $to_uint64_value: &$ty, buf: &mut B) where B: BufMut {
-                encode_key(tag, WireType::Varint, buf);
+                encode_key(tag, WIRE_TYPE, buf);
                 encode_varint($to_uint64, buf);
             }
 
             pub fn merge<B>(wire_type: WireType, value: &mut $ty, buf: &mut B, _ctx: DecodeContext) -> Result<(), DecodeError> where B: Buf {
-                check_wire_type(WireType::Varint, wire_type)?;
+                check_wire_type(WIRE_TYPE, wire_type)?;
                 let $from_uint64_value = decode_varint(buf)?;
                 *value = $from_uint64;
                 Ok(())
@@ -523,9 +840,9 @@ macro_rules! varint {
 
             #[inline]
             pub fn encoded_len_repeated(tag: u32, values: &[$ty]) -> usize {
-                key_len(tag) * values.len() + values.iter().map(|$to_uint64_value| {
-                    encoded_len_varint($to_uint64)
-                }).sum::<usize>()
+                key_len(tag).saturating_mul(values.len()).saturating_add(
+                    sum_len(values.iter().map(|$to_uint64_value| encoded_len_varint($to_uint64)))
+                )
             }
 
             #[inline]
@@ -533,10 +850,9 @@ macro_rules! varint {
                 if values.is_empty() {
                     0
                 } else {
-                    let len = values.iter()
-                                    .map(|$to_uint64_value| encoded_len_varint($to_uint64))
-                                    .sum::<usize>();
-                    key_len(tag) + encoded_len_varint(len as u64) + len
+                    let len = sum_len(values.iter()
+                                    .map(|$to_uint64_value| encoded_len_varint($to_uint64)));
+                    delimited_len(tag, len)
                 }
             }
 
@@ -597,24 +913,34 @@ from_uint64(value) {
     ((value >> 1) as i64) ^ (-((value & 1) as i64))
 });
 
-/// Macro which emits a module containing a set of encoding functions for a
-/// fixed width numeric type.
-macro_rules! fixed_width {
-    ($ty:ty,
-     $width:expr,
-     $wire_type:expr,
-     $proto_ty:ident,
-     $put:ident,
-     $get:ident) => {
+/// Macro which emits a module containing a set of encoding functions for a non-zero integer type,
+/// such as `NonZeroU32`.
+///
+/// Values are encoded exactly like their integer counterparts, so a field may switch between the
+/// two representations without affecting the wire format. Decoding a zero value fails, as it can
+/// not be represented.
+macro_rules! non_zero {
+    ($ty:ident, $int_ty:ty, $proto_ty:ident, $int_module:ident) => {
         pub mod $proto_ty {
+            use core::num::$ty;
+
             use crate::encoding::*;
 
+            /// The wire type of the field's values, unless they are packed.
+            pub const WIRE_TYPE: WireType = WireType::Varint;
+
+            /// Returns a placeholder for the value of a field which is being decoded, since the
+            /// type has no default. It is overwritten by a successful `merge`.
+            pub fn default() -> $ty {
+                $ty::new(1).unwrap()
+            }
+
             pub fn encode<B>(tag: u32, value: &$ty, buf: &mut B)
             where
                 B: BufMut,
             {
-                encode_key(tag, $wire_type, buf);
-                buf.$put(*value);
+                encode_key(tag, WIRE_TYPE, buf);
+                encode_varint(value.get() as u64, buf);
             }
 
             pub fn merge<B>(
@@ -626,12 +952,18 @@ macro_rules! fixed_width {
             where
                 B: Buf,
             {
-                check_wire_type($wire_type, wire_type)?;
-                if buf.remaining() < $width {
-                    return Err(DecodeError::new("buffer underflow"));
+                check_wire_type(WIRE_TYPE, wire_type)?;
+                match $ty::new(decode_varint(buf)? as $int_ty) {
+                    Some(decoded) => {
+                        *value = decoded;
+                        Ok(())
+                    }
+                    None => Err(DecodeError::new(concat!(
+                        "invalid ",
+                        stringify!($ty),
+                        " value: 0"
+                    ))),
                 }
-                *value = buf.$get();
-                Ok(())
             }
 
             encode_repeated!($ty);
@@ -645,24 +977,57 @@ macro_rules! fixed_width {
                 }
 
                 encode_key(tag, WireType::LengthDelimited, buf);
-                let len = values.len() as u64 * $width;
+                let len: usize = values
+                    .iter()
+                    .map(|value| encoded_len_varint(value.get() as u64))
+                    .sum();
                 encode_varint(len as u64, buf);
 
                 for value in values {
-                    buf.$put(*value);
+                    encode_varint(value.get() as u64, buf);
                 }
             }
 
-            merge_repeated_numeric!($ty, $wire_type, merge, merge_repeated);
+            pub fn merge_repeated<B>(
+                wire_type: WireType,
+                values: &mut Vec<$ty>,
+                buf: &mut B,
+                ctx: DecodeContext,
+            ) -> Result<(), DecodeError>
+            where
+                B: Buf,
+            {
+                if wire_type == WireType::LengthDelimited {
+                    // Packed.
+                    merge_loop(values, buf, ctx, |values, buf, ctx| {
+                        let mut value = default();
+                        merge(WireType::Varint, &mut value, buf, ctx)?;
+                        values.push(value);
+                        Ok(())
+                    })
+                } else {
+                    // Unpacked.
+                    let mut value = default();
+                    merge(wire_type, &mut value, buf, ctx)?;
+                    values.push(value);
+                    Ok(())
+                }
+            }
 
             #[inline]
-            pub fn encoded_len(tag: u32, _: &$ty) -> usize {
-                key_len(tag) + $width
+            pub fn encoded_len(tag: u32, value: &$ty) -> usize {
+                key_len(tag) + encoded_len_varint(value.get() as u64)
             }
 
             #[inline]
             pub fn encoded_len_repeated(tag: u32, values: &[$ty]) -> usize {
-                (key_len(tag) + $width) * values.len()
+                key_len(tag)
+                    .saturating_mul(values.len())
+                    .saturating_add(sum_len(
+                        values
+                            .iter()
+                            .map(|value| encoded_len_varint(value.get() as u64)),
+                    ))
             }
 
             #[inline]
@@ -670,27 +1035,29 @@ macro_rules! fixed_width {
                 if values.is_empty() {
                     0
                 } else {
-                    let len = $width * values.len();
-                    key_len(tag) + encoded_len_varint(len as u64) + len
+                    let len = sum_len(
+                        values
+                            .iter()
+                            .map(|value| encoded_len_varint(value.get() as u64)),
+                    );
+                    delimited_len(tag, len)
                 }
             }
 
             #[cfg(test)]
             mod test {
+                use alloc::string::ToString;
+
                 use proptest::prelude::*;
 
-                use super::super::test::{check_collection_type, check_type};
+                use crate::encoding::test::{check_collection_type, check_type};
+
                 use super::*;
 
                 proptest! {
-                    #[test]
-                    fn check(value: $ty, tag in MIN_TAG..=MAX_TAG) {
-                        check_type(value, tag, $wire_type,
-                                   encode, merge, encoded_len)?;
-                    }
                     #[test]
                     fn check_repeated(value: Vec<$ty>, tag in MIN_TAG..=MAX_TAG) {
-                        check_collection_type(value, tag, $wire_type,
+                        check_collection_type(value, tag, WireType::Varint,
                                               encode_repeated, merge_repeated,
                                               encoded_len_repeated)?;
                     }
@@ -700,93 +1067,399 @@ macro_rules! fixed_width {
                                    encode_packed, merge_repeated,
                                    encoded_len_packed)?;
                     }
+                    #[test]
+                    fn same_encoding(value: $ty, tag in MIN_TAG..=MAX_TAG) {
+                        let mut buf = Vec::new();
+                        encode(tag, &value, &mut buf);
+                        let mut int_buf = Vec::new();
+                        $int_module::encode(tag, &value.get(), &mut int_buf);
+                        prop_assert_eq!(buf, int_buf);
+                    }
+                }
+
+                #[test]
+                fn zero() {
+                    let mut value = default();
+                    let error = merge(
+                        WireType::Varint,
+                        &mut value,
+                        &mut &[0u8][..],
+                        DecodeContext::default(),
+                    )
+                    .expect_err("zero value");
+                    assert_eq!(
+                        error.to_string(),
+                        concat!(
+                            "failed to decode Protobuf message: invalid ",
+                            stringify!($ty),
+                            " value: 0"
+                        )
+                    );
+                    assert_eq!(value, default());
                 }
             }
         }
     };
 }
-fixed_width!(
-    f32,
-    4,
-    WireType::ThirtyTwoBit,
-    float,
-    put_f32_le,
-    get_f32_le
-);
-fixed_width!(
-    f64,
-    8,
-    WireType::SixtyFourBit,
-    double,
-    put_f64_le,
-    get_f64_le
-);
-fixed_width!(
-    u32,
-    4,
-    WireType::ThirtyTwoBit,
-    fixed32,
-    put_u32_le,
-    get_u32_le
-);
-fixed_width!(
-    u64,
-    8,
-    WireType::SixtyFourBit,
-    fixed64,
-    put_u64_le,
-    get_u64_le
-);
-fixed_width!(
-    i32,
-    4,
-    WireType::ThirtyTwoBit,
-    sfixed32,
-    put_i32_le,
-    get_i32_le
-);
-fixed_width!(
-    i64,
-    8,
-    WireType::SixtyFourBit,
-    sfixed64,
-    put_i64_le,
-    get_i64_le
-);
+non_zero!(NonZeroI32, i32, non_zero_int32, int32);
+non_zero!(NonZeroI64, i64, non_zero_int64, int64);
+non_zero!(NonZeroU32, u32, non_zero_uint32, uint32);
+non_zero!(NonZeroU64, u64, non_zero_uint64, uint64);
 
-/// Macro which emits encoding functions for a length-delimited type.
-macro_rules! length_delimited {
-    ($ty:ty) => {
-        encode_repeated!($ty);
+/// Encoding functions for `char` values, which are encoded as `uint32` fields holding their
+/// Unicode code point.
+///
+/// Decoding fails unless the value is a Unicode scalar value, i.e. a code point which is at most
+/// `0x10FFFF` and is not a surrogate.
+pub mod char_uint32 {
+    use crate::encoding::*;
 
-        pub fn merge_repeated<B>(
-            wire_type: WireType,
-            values: &mut Vec<$ty>,
-            buf: &mut B,
-            ctx: DecodeContext,
-        ) -> Result<(), DecodeError>
-        where
-            B: Buf,
-        {
-            check_wire_type(WireType::LengthDelimited, wire_type)?;
-            let mut value = Default::default();
-            merge(wire_type, &mut value, buf, ctx)?;
-            values.push(value);
-            Ok(())
-        }
+    /// The wire type of the field's values, unless they are packed.
+    pub const WIRE_TYPE: WireType = WireType::Varint;
 
-        #[inline]
-        pub fn encoded_len(tag: u32, value: &$ty) -> usize {
-            key_len(tag) + encoded_len_varint(value.len() as u64) + value.len()
-        }
+    pub fn encode<B>(tag: u32, value: &char, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        encode_key(tag, WIRE_TYPE, buf);
+        encode_varint(u64::from(u32::from(*value)), buf);
+    }
 
-        #[inline]
-        pub fn encoded_len_repeated(tag: u32, values: &[$ty]) -> usize {
-            key_len(tag) * values.len()
-                + values
-                    .iter()
-                    .map(|value| encoded_len_varint(value.len() as u64) + value.len())
-                    .sum::<usize>()
+    pub fn merge<B>(
+        wire_type: WireType,
+        value: &mut char,
+        buf: &mut B,
+        _ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        B: Buf,
+    {
+        check_wire_type(WIRE_TYPE, wire_type)?;
+        let decoded = decode_varint(buf)?;
+        match u32::try_from(decoded).ok().and_then(core::char::from_u32) {
+            Some(decoded) => {
+                *value = decoded;
+                Ok(())
+            }
+            None => Err(DecodeError::new(format!(
+                "invalid char value: {:#x}",
+                decoded
+            ))),
+        }
+    }
+
+    encode_repeated!(char);
+
+    pub fn encode_packed<B>(tag: u32, values: &[char], buf: &mut B)
+    where
+        B: BufMut,
+    {
+        if values.is_empty() {
+            return;
+        }
+
+        encode_key(tag, WireType::LengthDelimited, buf);
+        encode_varint(packed_len(values) as u64, buf);
+        for value in values {
+            encode_varint(u64::from(u32::from(*value)), buf);
+        }
+    }
+
+    merge_repeated_numeric!(char, WireType::Varint, merge, merge_repeated);
+
+    #[inline]
+    pub fn encoded_len(tag: u32, value: &char) -> usize {
+        key_len(tag) + encoded_len_varint(u64::from(u32::from(*value)))
+    }
+
+    #[inline]
+    pub fn encoded_len_repeated(tag: u32, values: &[char]) -> usize {
+        key_len(tag)
+            .saturating_mul(values.len())
+            .saturating_add(packed_len(values))
+    }
+
+    #[inline]
+    pub fn encoded_len_packed(tag: u32, values: &[char]) -> usize {
+        if values.is_empty() {
+            0
+        } else {
+            delimited_len(tag, packed_len(values))
+        }
+    }
+
+    fn packed_len(values: &[char]) -> usize {
+        sum_len(
+            values
+                .iter()
+                .map(|value| encoded_len_varint(u64::from(u32::from(*value)))),
+        )
+    }
+
+    #[cfg(test)]
+    mod test {
+        use alloc::string::ToString;
+
+        use proptest::prelude::*;
+
+        use crate::encoding::test::{check_collection_type, check_type};
+
+        use super::*;
+
+        proptest! {
+            #[test]
+            fn check(value: char, tag in MIN_TAG..=MAX_TAG) {
+                check_type(value, tag, WireType::Varint,
+                           encode, merge, encoded_len)?;
+            }
+            #[test]
+            fn check_repeated(value: Vec<char>, tag in MIN_TAG..=MAX_TAG) {
+                check_collection_type(value, tag, WireType::Varint,
+                                      encode_repeated, merge_repeated,
+                                      encoded_len_repeated)?;
+            }
+            #[test]
+            fn check_packed(value: Vec<char>, tag in MIN_TAG..=MAX_TAG) {
+                check_type(value, tag, WireType::LengthDelimited,
+                           encode_packed, merge_repeated,
+                           encoded_len_packed)?;
+            }
+            #[test]
+            fn same_encoding(value: char, tag in MIN_TAG..=MAX_TAG) {
+                let mut buf = Vec::new();
+                encode(tag, &value, &mut buf);
+                let mut int_buf = Vec::new();
+                uint32::encode(tag, &u32::from(value), &mut int_buf);
+                prop_assert_eq!(buf, int_buf);
+            }
+        }
+
+        #[test]
+        fn invalid_code_points() {
+            for (code_point, expected) in [
+                (0xd800, "invalid char value: 0xd800"),
+                (0xdfff, "invalid char value: 0xdfff"),
+                (0x11_0000, "invalid char value: 0x110000"),
+                (0x1_0000_0041, "invalid char value: 0x100000041"),
+            ] {
+                let mut buf = Vec::new();
+                encode_varint(code_point, &mut buf);
+                let mut value = 'a';
+                let error = merge(
+                    WireType::Varint,
+                    &mut value,
+                    &mut &buf[..],
+                    DecodeContext::default(),
+                )
+                .expect_err("invalid code point");
+                assert_eq!(
+                    error.to_string(),
+                    format!("failed to decode Protobuf message: {}", expected)
+                );
+                assert_eq!(value, 'a');
+            }
+        }
+    }
+}
+
+/// Macro which emits a module containing a set of encoding functions for a
+/// fixed width numeric type.
+macro_rules! fixed_width {
+    ($ty:ty,
+     $width:expr,
+     $wire_type:expr,
+     $proto_ty:ident,
+     $put:ident,
+     $get:ident) => {
+        pub mod $proto_ty {
+            use crate::encoding::*;
+
+            /// The wire type of the field's values, unless they are packed.
+            pub const WIRE_TYPE: WireType = $wire_type;
+
+            pub fn encode<B>(tag: u32, value: &$ty, buf: &mut B)
+            where
+                B: BufMut,
+            {
+                encode_key(tag, WIRE_TYPE, buf);
+                buf.$put(*value);
+            }
+
+            pub fn merge<B>(
+                wire_type: WireType,
+                value: &mut $ty,
+                buf: &mut B,
+                _ctx: DecodeContext,
+            ) -> Result<(), DecodeError>
+            where
+                B: Buf,
+            {
+                check_wire_type(WIRE_TYPE, wire_type)?;
+                if buf.remaining() < $width {
+                    return Err(DecodeError::new("buffer underflow"));
+                }
+                *value = buf.$get();
+                Ok(())
+            }
+
+            encode_repeated!($ty);
+
+            pub fn encode_packed<B>(tag: u32, values: &[$ty], buf: &mut B)
+            where
+                B: BufMut,
+            {
+                if values.is_empty() {
+                    return;
+                }
+
+                encode_key(tag, WireType::LengthDelimited, buf);
+                let len = values.len() as u64 * $width;
+                encode_varint(len as u64, buf);
+
+                for value in values {
+                    buf.$put(*value);
+                }
+            }
+
+            merge_repeated_numeric!($ty, $wire_type, merge, merge_repeated);
+
+            #[inline]
+            pub fn encoded_len(tag: u32, _: &$ty) -> usize {
+                key_len(tag) + $width
+            }
+
+            #[inline]
+            pub fn encoded_len_repeated(tag: u32, values: &[$ty]) -> usize {
+                (key_len(tag) + $width).saturating_mul(values.len())
+            }
+
+            #[inline]
+            pub fn encoded_len_packed(tag: u32, values: &[$ty]) -> usize {
+                if values.is_empty() {
+                    0
+                } else {
+                    let len = values.len().saturating_mul($width);
+                    delimited_len(tag, len)
+                }
+            }
+
+            #[cfg(test)]
+            mod test {
+                use proptest::prelude::*;
+
+                use super::super::test::{check_collection_type, check_type};
+                use super::*;
+
+                proptest! {
+                    #[test]
+                    fn check(value: $ty, tag in MIN_TAG..=MAX_TAG) {
+                        check_type(value, tag, $wire_type,
+                                   encode, merge, encoded_len)?;
+                    }
+                    #[test]
+                    fn check_repeated(value: Vec<$ty>, tag in MIN_TAG..=MAX_TAG) {
+                        check_collection_type(value, tag, $wire_type,
+                                              encode_repeated, merge_repeated,
+                                              encoded_len_repeated)?;
+                    }
+                    #[test]
+                    fn check_packed(value: Vec<$ty>, tag in MIN_TAG..=MAX_TAG) {
+                        check_type(value, tag, WireType::LengthDelimited,
+                                   encode_packed, merge_repeated,
+                                   encoded_len_packed)?;
+                    }
+                }
+            }
+        }
+    };
+}
+fixed_width!(
+    f32,
+    4,
+    WireType::ThirtyTwoBit,
+    float,
+    put_f32_le,
+    get_f32_le
+);
+fixed_width!(
+    f64,
+    8,
+    WireType::SixtyFourBit,
+    double,
+    put_f64_le,
+    get_f64_le
+);
+fixed_width!(
+    u32,
+    4,
+    WireType::ThirtyTwoBit,
+    fixed32,
+    put_u32_le,
+    get_u32_le
+);
+fixed_width!(
+    u64,
+    8,
+    WireType::SixtyFourBit,
+    fixed64,
+    put_u64_le,
+    get_u64_le
+);
+fixed_width!(
+    i32,
+    4,
+    WireType::ThirtyTwoBit,
+    sfixed32,
+    put_i32_le,
+    get_i32_le
+);
+fixed_width!(
+    i64,
+    8,
+    WireType::SixtyFourBit,
+    sfixed64,
+    put_i64_le,
+    get_i64_le
+);
+
+/// Macro which emits encoding functions for a length-delimited type.
+macro_rules! length_delimited {
+    ($ty:ty) => {
+        length_delimited!($ty, Default::default());
+    };
+    ($ty:ty, $default:expr) => {
+        encode_repeated!($ty);
+
+        pub fn merge_repeated<B>(
+            wire_type: WireType,
+            values: &mut Vec<$ty>,
+            buf: &mut B,
+            ctx: DecodeContext,
+        ) -> Result<(), DecodeError>
+        where
+            B: Buf,
+        {
+            check_wire_type(WIRE_TYPE, wire_type)?;
+            let mut value = $default;
+            merge(wire_type, &mut value, buf, ctx)?;
+            values.push(value);
+            Ok(())
+        }
+
+        #[inline]
+        pub fn encoded_len(tag: u32, value: &$ty) -> usize {
+            key_len(tag) + encoded_len_varint(value.len() as u64) + value.len()
+        }
+
+        #[inline]
+        pub fn encoded_len_repeated(tag: u32, values: &[$ty]) -> usize {
+            key_len(tag)
+                .saturating_mul(values.len())
+                .saturating_add(sum_len(
+                    values
+                        .iter()
+                        .map(|value| encoded_len_varint(value.len() as u64) + value.len()),
+                ))
         }
     };
 }
@@ -794,11 +1467,14 @@ macro_rules! length_delimited {
 pub mod string {
     use super::*;
 
+    /// The wire type of the field's values.
+    pub const WIRE_TYPE: WireType = WireType::LengthDelimited;
+
     pub fn encode<B>(tag: u32, value: &String, buf: &mut B)
     where
         B: BufMut,
     {
-        encode_key(tag, WireType::LengthDelimited, buf);
+        encode_key(tag, WIRE_TYPE, buf);
         encode_varint(value.len() as u64, buf);
         buf.put_slice(value.as_bytes());
     }
@@ -841,13 +1517,21 @@ pub mod string {
                     mem::forget(drop_guard);
                     Ok(())
                 }
-                Err(_) => Err(DecodeError::new(
-                    "invalid string value: data is not UTF-8 encoded",
-                )),
+                Err(error) => Err(invalid_utf8(error)),
             }
         }
     }
 
+    /// Returns the error for a string value which is not valid UTF-8.
+    #[cold]
+    pub(super) fn invalid_utf8(error: str::Utf8Error) -> DecodeError {
+        DecodeError::new(format!(
+            "invalid string value: data is not UTF-8 encoded \
+             (invalid byte sequence at offset {})",
+            error.valid_up_to()
+        ))
+    }
+
     length_delimited!(String);
 
     #[cfg(test)]
@@ -873,15 +1557,227 @@ pub mod string {
     }
 }
 
-pub trait BytesAdapter: sealed::BytesAdapter {}
-
-mod sealed {
-    use super::{Buf, BufMut};
+/// Encoding functions for string fields represented as `Box<str>`, which unlike `String` never
+/// holds spare capacity.
+pub mod boxed_str {
+    use super::*;
 
-    pub trait BytesAdapter: Default + Sized + 'static {
-        fn len(&self) -> usize;
+    /// The wire type of the field's values.
+    pub const WIRE_TYPE: WireType = WireType::LengthDelimited;
 
-        /// Replace contents of this buffer with the contents of another buffer.
+    pub fn encode<B>(tag: u32, value: &Box<str>, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        encode_key(tag, WIRE_TYPE, buf);
+        encode_varint(value.len() as u64, buf);
+        buf.put_slice(value.as_bytes());
+    }
+
+    pub fn merge<B>(
+        wire_type: WireType,
+        value: &mut Box<str>,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        B: Buf,
+    {
+        check_wire_type(WIRE_TYPE, wire_type)?;
+        let len = ctx.decode_len(buf)?;
+
+        // Allocate exactly the decoded length, so that no reallocation is needed to box it.
+        let mut bytes = Vec::with_capacity(len);
+        bytes.put(buf.take(len));
+        match String::from_utf8(bytes) {
+            Ok(string) => {
+                *value = string.into_boxed_str();
+                Ok(())
+            }
+            Err(error) => {
+                *value = Box::default();
+                Err(string::invalid_utf8(error.utf8_error()))
+            }
+        }
+    }
+
+    length_delimited!(Box<str>);
+
+    #[cfg(test)]
+    mod test {
+        use proptest::prelude::*;
+
+        use super::super::test::{check_collection_type, check_type};
+        use super::*;
+
+        proptest! {
+            #[test]
+            fn check(value: String, tag in MIN_TAG..=MAX_TAG) {
+                check_type(value.into_boxed_str(), tag, WireType::LengthDelimited,
+                           encode, merge, encoded_len)?;
+            }
+            #[test]
+            fn check_repeated(value: Vec<String>, tag in MIN_TAG..=MAX_TAG) {
+                let value = value.into_iter().map(String::into_boxed_str).collect();
+                check_collection_type(value, tag, WireType::LengthDelimited,
+                                      encode_repeated, merge_repeated, encoded_len_repeated)?;
+            }
+        }
+
+        #[test]
+        fn merge_invalid_utf8() {
+            let mut value = Box::from("previous");
+            let buf = b"\x02\x80\x80";
+            merge(
+                WireType::LengthDelimited,
+                &mut value,
+                &mut &buf[..],
+                DecodeContext::default(),
+            )
+            .expect_err("must be an error");
+            assert!(value.is_empty());
+        }
+    }
+}
+
+/// Encoding functions for string fields represented as `Arc<str>`, whose decoded values may share
+/// their storage through an `Interner`.
+pub mod arc_str {
+    use super::*;
+
+    /// The wire type of the field's values.
+    pub const WIRE_TYPE: WireType = WireType::LengthDelimited;
+
+    /// Returns an empty string, the default value of the field.
+    pub fn default() -> Arc<str> {
+        Arc::from("")
+    }
+
+    pub fn encode<B>(tag: u32, value: &Arc<str>, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        encode_key(tag, WIRE_TYPE, buf);
+        encode_varint(value.len() as u64, buf);
+        buf.put_slice(value.as_bytes());
+    }
+
+    pub fn merge<B>(
+        wire_type: WireType,
+        value: &mut Arc<str>,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        B: Buf,
+    {
+        check_wire_type(WIRE_TYPE, wire_type)?;
+        let len = ctx.decode_len(buf)?;
+
+        // A value which is contiguous in the buffer is read in place, so that an interned value
+        // is not copied at all.
+        let result = if buf.chunk().len() >= len {
+            let result = str::from_utf8(&buf.chunk()[..len]).map(|string| ctx.arc_str(string));
+            buf.advance(len);
+            result
+        } else {
+            let mut bytes = Vec::with_capacity(len);
+            bytes.put(buf.take(len));
+            str::from_utf8(&bytes).map(|string| ctx.arc_str(string))
+        };
+        match result {
+            Ok(string) => {
+                *value = string;
+                Ok(())
+            }
+            Err(error) => {
+                *value = default();
+                Err(string::invalid_utf8(error))
+            }
+        }
+    }
+
+    length_delimited!(Arc<str>, default());
+
+    #[cfg(test)]
+    mod test {
+        use proptest::prelude::*;
+
+        use super::super::test::{check_collection_type, check_type};
+        use super::*;
+
+        proptest! {
+            #[test]
+            fn check(value: String, tag in MIN_TAG..=MAX_TAG) {
+                check_type(Arc::from(value), tag, WireType::LengthDelimited,
+                           encode, merge, encoded_len)?;
+            }
+            #[test]
+            fn check_repeated(value: Vec<String>, tag in MIN_TAG..=MAX_TAG) {
+                let value = value.into_iter().map(Arc::from).collect();
+                check_collection_type(value, tag, WireType::LengthDelimited,
+                                      encode_repeated, merge_repeated, encoded_len_repeated)?;
+            }
+        }
+
+        #[test]
+        fn merge_invalid_utf8() {
+            let mut value = Arc::from("previous");
+            let buf = b"\x02\x80\x80";
+            merge(
+                WireType::LengthDelimited,
+                &mut value,
+                &mut &buf[..],
+                DecodeContext::default(),
+            )
+            .expect_err("must be an error");
+            assert!(value.is_empty());
+        }
+
+        #[cfg(feature = "std")]
+        #[test]
+        fn merge_interned() {
+            let interner = crate::Interner::new();
+            let ctx = DecodeContext::default().with_interner(&interner);
+            let mut buf = Vec::new();
+            encode(1, &Arc::from("key"), &mut buf);
+
+            let mut first = default();
+            let mut second = default();
+            for value in [&mut first, &mut second] {
+                let mut buf = &buf[..];
+                let (tag, wire_type) = decode_key(&mut buf).unwrap();
+                assert_eq!(tag, 1);
+                merge(wire_type, value, &mut buf, ctx.clone()).unwrap();
+            }
+            assert_eq!(&*first, "key");
+            assert!(Arc::ptr_eq(&first, &second));
+            assert_eq!(interner.len(), 1);
+
+            // Values split across chunks of the buffer are interned too.
+            let mut third = default();
+            let mut chunks = (&b"\x03ke"[..]).chain(&b"y"[..]);
+            merge(WireType::LengthDelimited, &mut third, &mut chunks, ctx).unwrap();
+            assert!(Arc::ptr_eq(&first, &third));
+        }
+    }
+}
+
+/// A type which holds the value of a `bytes` field, i.e. `Vec<u8>`, `Bytes`, or `Box<[u8]>`.
+///
+/// A value of one of these types is always encoded as a single length-delimited field. The trait
+/// is sealed, and is the only way a `Vec<u8>` is accepted by an encoding function: `u8` is not a
+/// `Message`, nor the Rust type of any scalar Protobuf type, so a `Vec<u8>` can not be passed
+/// to the functions encoding repeated fields.
+pub trait BytesAdapter: sealed::BytesAdapter {}
+
+mod sealed {
+    use super::{Buf, BufMut};
+
+    pub trait BytesAdapter: Default + Sized + 'static {
+        fn len(&self) -> usize;
+
+        /// Replace contents of this buffer with the contents of another buffer.
         fn replace_with<B>(&mut self, buf: B)
         where
             B: Buf;
@@ -895,176 +1791,767 @@ mod sealed {
             self.len() == 0
         }
     }
+
+    pub trait RepeatedMessages<M> {
+        fn push(&mut self, msg: M);
+    }
+}
+
+impl BytesAdapter for Bytes {}
+
+impl sealed::BytesAdapter for Bytes {
+    fn len(&self) -> usize {
+        Buf::remaining(self)
+    }
+
+    fn replace_with<B>(&mut self, mut buf: B)
+    where
+        B: Buf,
+    {
+        *self = buf.copy_to_bytes(buf.remaining());
+    }
+
+    fn append_to<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        buf.put(self.clone())
+    }
+}
+
+impl BytesAdapter for Vec<u8> {}
+
+impl sealed::BytesAdapter for Vec<u8> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn replace_with<B>(&mut self, buf: B)
+    where
+        B: Buf,
+    {
+        self.clear();
+        self.reserve(buf.remaining());
+        self.put(buf);
+    }
+
+    fn append_to<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        buf.put(self.as_slice())
+    }
+}
+
+impl BytesAdapter for Box<[u8]> {}
+
+impl sealed::BytesAdapter for Box<[u8]> {
+    fn len(&self) -> usize {
+        <[u8]>::len(self)
+    }
+
+    fn replace_with<B>(&mut self, buf: B)
+    where
+        B: Buf,
+    {
+        // Allocate exactly the decoded length, so that no reallocation is needed to box it.
+        let mut vec = Vec::with_capacity(buf.remaining());
+        vec.put(buf);
+        *self = vec.into_boxed_slice();
+    }
+
+    fn append_to<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        buf.put_slice(self)
+    }
+}
+
+pub mod bytes {
+    use super::*;
+
+    /// The wire type of the field's values.
+    pub const WIRE_TYPE: WireType = WireType::LengthDelimited;
+
+    pub fn encode<A, B>(tag: u32, value: &A, buf: &mut B)
+    where
+        A: BytesAdapter,
+        B: BufMut,
+    {
+        encode_key(tag, WIRE_TYPE, buf);
+        encode_varint(value.len() as u64, buf);
+        value.append_to(buf);
+    }
+
+    pub fn merge<A, B>(
+        wire_type: WireType,
+        value: &mut A,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        A: BytesAdapter,
+        B: Buf,
+    {
+        check_wire_type(WIRE_TYPE, wire_type)?;
+        let len = ctx.decode_len(buf)?;
+
+        // Clear the existing value. This follows from the following rule in the encoding guide[1]:
+        //
+        // > Normally, an encoded message would never have more than one instance of a non-repeated
+        // > field. However, parsers are expected to handle the case in which they do. For numeric
+        // > types and strings, if the same field appears multiple times, the parser accepts the
+        // > last value it sees.
+        //
+        // [1]: https://developers.google.com/protocol-buffers/docs/encoding#optional
+        //
+        // This is intended for A and B both being Bytes so it is zero-copy.
+        // Some combinations of A and B types may cause a double-copy,
+        // in which case merge_one_copy() should be used instead.
+        value.replace_with(buf.copy_to_bytes(len));
+        Ok(())
+    }
+
+    pub(super) fn merge_one_copy<A, B>(
+        wire_type: WireType,
+        value: &mut A,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        A: BytesAdapter,
+        B: Buf,
+    {
+        check_wire_type(WIRE_TYPE, wire_type)?;
+        let len = ctx.decode_len(buf)?;
+
+        // If we must copy, make sure to copy only once.
+        value.replace_with(buf.take(len));
+        Ok(())
+    }
+
+    length_delimited!(impl BytesAdapter);
+
+    #[cfg(test)]
+    mod test {
+        use proptest::prelude::*;
+
+        use super::super::test::{check_collection_type, check_type};
+        use super::*;
+
+        proptest! {
+            #[test]
+            fn check_vec(value: Vec<u8>, tag in MIN_TAG..=MAX_TAG) {
+                super::test::check_type::<Vec<u8>, Vec<u8>>(value, tag, WireType::LengthDelimited,
+                                                            encode, merge, encoded_len)?;
+            }
+
+            #[test]
+            fn check_bytes(value: Vec<u8>, tag in MIN_TAG..=MAX_TAG) {
+                let value = Bytes::from(value);
+                super::test::check_type::<Bytes, Bytes>(value, tag, WireType::LengthDelimited,
+                                                        encode, merge, encoded_len)?;
+            }
+
+            #[test]
+            fn check_repeated_vec(value: Vec<Vec<u8>>, tag in MIN_TAG..=MAX_TAG) {
+                super::test::check_collection_type(value, tag, WireType::LengthDelimited,
+                                                   encode_repeated, merge_repeated,
+                                                   encoded_len_repeated)?;
+            }
+
+            #[test]
+            fn check_boxed(value: Vec<u8>, tag in MIN_TAG..=MAX_TAG) {
+                let value = value.into_boxed_slice();
+                super::test::check_type::<Box<[u8]>, Box<[u8]>>(value, tag,
+                                                                WireType::LengthDelimited,
+                                                                encode, merge, encoded_len)?;
+            }
+
+            #[test]
+            fn check_repeated_bytes(value: Vec<Vec<u8>>, tag in MIN_TAG..=MAX_TAG) {
+                let value = value.into_iter().map(Bytes::from).collect();
+                super::test::check_collection_type(value, tag, WireType::LengthDelimited,
+                                                   encode_repeated, merge_repeated,
+                                                   encoded_len_repeated)?;
+            }
+        }
+    }
+}
+
+/// Encoding functions for fixed-size bytes fields, represented as `[u8; N]`.
+///
+/// On the wire these are ordinary `bytes` fields, but decoding fails unless the field contains
+/// exactly `N` bytes.
+pub mod bytes_array {
+    use super::*;
+
+    /// The wire type of the field's values.
+    pub const WIRE_TYPE: WireType = WireType::LengthDelimited;
+
+    /// Returns the default value of a fixed-size bytes field, which is all zeros.
+    pub fn default<const N: usize>() -> [u8; N] {
+        [0; N]
+    }
+
+    pub fn encode<B, const N: usize>(tag: u32, value: &[u8; N], buf: &mut B)
+    where
+        B: BufMut,
+    {
+        encode_key(tag, WIRE_TYPE, buf);
+        encode_varint(N as u64, buf);
+        buf.put_slice(value);
+    }
+
+    pub fn merge<B, const N: usize>(
+        wire_type: WireType,
+        value: &mut [u8; N],
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        B: Buf,
+    {
+        check_wire_type(WIRE_TYPE, wire_type)?;
+        let len = ctx.decode_len(buf)?;
+        if len != N {
+            return Err(DecodeError::new(format!(
+                "invalid bytes length: expected {} bytes, found {}",
+                N, len
+            )));
+        }
+        buf.copy_to_slice(value);
+        Ok(())
+    }
+
+    pub fn encode_repeated<B, const N: usize>(tag: u32, values: &[[u8; N]], buf: &mut B)
+    where
+        B: BufMut,
+    {
+        for value in values {
+            encode(tag, value, buf);
+        }
+    }
+
+    pub fn merge_repeated<B, const N: usize>(
+        wire_type: WireType,
+        values: &mut Vec<[u8; N]>,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        B: Buf,
+    {
+        let mut value = default();
+        merge(wire_type, &mut value, buf, ctx)?;
+        values.push(value);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn encoded_len<const N: usize>(tag: u32, _value: &[u8; N]) -> usize {
+        key_len(tag) + encoded_len_varint(N as u64) + N
+    }
+
+    #[inline]
+    pub fn encoded_len_repeated<const N: usize>(tag: u32, values: &[[u8; N]]) -> usize {
+        (key_len(tag) + encoded_len_varint(N as u64) + N).saturating_mul(values.len())
+    }
+
+    #[cfg(test)]
+    mod test {
+        use alloc::string::ToString;
+        use alloc::vec;
+
+        use proptest::prelude::*;
+
+        use super::super::test::{check_collection_type, check_type};
+        use super::*;
+
+        proptest! {
+            #[test]
+            fn check(value: [u8; 32], tag in MIN_TAG..=MAX_TAG) {
+                check_type(value, tag, WireType::LengthDelimited,
+                           encode, merge, encoded_len)?;
+            }
+
+            #[test]
+            fn check_repeated(value: Vec<[u8; 20]>, tag in MIN_TAG..=MAX_TAG) {
+                check_collection_type(value, tag, WireType::LengthDelimited,
+                                      encode_repeated, merge_repeated, encoded_len_repeated)?;
+            }
+        }
+
+        #[test]
+        fn invalid_length() {
+            let mut buf = Vec::new();
+            bytes::encode(1, &vec![0xAAu8; 3], &mut buf);
+
+            let mut buf = &buf[1..];
+            let mut value = [0u8; 4];
+            let error = merge(
+                WireType::LengthDelimited,
+                &mut value,
+                &mut buf,
+                DecodeContext::default(),
+            )
+            .expect_err("length mismatch");
+            assert_eq!(
+                error.to_string(),
+                "failed to decode Protobuf message: invalid bytes length: expected 4 bytes, found 3"
+            );
+        }
+    }
+}
+
+/// Encoding functions for IP addresses, represented as `std::net::IpAddr`.
+///
+/// On the wire these are ordinary `bytes` fields, holding the 4 octets of an IPv4 address or the
+/// 16 octets of an IPv6 address. Decoding tells the two apart by length alone, so an IPv4-mapped
+/// IPv6 address such as `::ffff:192.0.2.1` decodes to the same IPv6 address, and fails for any
+/// other length, including 0.
+#[cfg(feature = "std")]
+pub mod ip_addr {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+
+    /// The wire type of the field's values.
+    pub const WIRE_TYPE: WireType = WireType::LengthDelimited;
+
+    /// Returns the default value of an IP address field, which is the unspecified IPv4 address,
+    /// `0.0.0.0`.
+    pub fn default() -> IpAddr {
+        IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+    }
+
+    /// Returns the octets of `value`, as they are encoded.
+    pub fn to_bytes(value: &IpAddr) -> Vec<u8> {
+        match value {
+            IpAddr::V4(addr) => addr.octets().to_vec(),
+            IpAddr::V6(addr) => addr.octets().to_vec(),
+        }
+    }
+
+    pub(super) fn octets_len(value: &IpAddr) -> usize {
+        match value {
+            IpAddr::V4(_) => 4,
+            IpAddr::V6(_) => 16,
+        }
+    }
+
+    pub(super) fn put<B>(value: &IpAddr, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        match value {
+            IpAddr::V4(addr) => buf.put_slice(&addr.octets()),
+            IpAddr::V6(addr) => buf.put_slice(&addr.octets()),
+        }
+    }
+
+    /// Reads an address of `len` octets.
+    pub(super) fn get<B>(len: usize, buf: &mut B) -> Result<IpAddr, DecodeError>
+    where
+        B: Buf,
+    {
+        match len {
+            4 => {
+                let mut octets = [0; 4];
+                buf.copy_to_slice(&mut octets);
+                Ok(IpAddr::from(octets))
+            }
+            16 => {
+                let mut octets = [0; 16];
+                buf.copy_to_slice(&mut octets);
+                Ok(IpAddr::from(octets))
+            }
+            _ => Err(DecodeError::new(format!(
+                "invalid IP address length: expected 4 or 16 bytes, found {}",
+                len
+            ))),
+        }
+    }
+
+    pub fn encode<B>(tag: u32, value: &IpAddr, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        encode_key(tag, WIRE_TYPE, buf);
+        encode_varint(octets_len(value) as u64, buf);
+        put(value, buf);
+    }
+
+    pub fn merge<B>(
+        wire_type: WireType,
+        value: &mut IpAddr,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        B: Buf,
+    {
+        check_wire_type(WIRE_TYPE, wire_type)?;
+        let len = ctx.decode_len(buf)?;
+        *value = get(len, buf)?;
+        Ok(())
+    }
+
+    pub fn encode_repeated<B>(tag: u32, values: &[IpAddr], buf: &mut B)
+    where
+        B: BufMut,
+    {
+        for value in values {
+            encode(tag, value, buf);
+        }
+    }
+
+    pub fn merge_repeated<B>(
+        wire_type: WireType,
+        values: &mut Vec<IpAddr>,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        B: Buf,
+    {
+        let mut value = default();
+        merge(wire_type, &mut value, buf, ctx)?;
+        values.push(value);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn encoded_len(tag: u32, value: &IpAddr) -> usize {
+        key_len(tag) + 1 + octets_len(value)
+    }
+
+    #[inline]
+    pub fn encoded_len_repeated(tag: u32, values: &[IpAddr]) -> usize {
+        sum_len(values.iter().map(|value| encoded_len(tag, value)))
+    }
+
+    #[cfg(test)]
+    mod test {
+        use std::net::Ipv6Addr;
+
+        use proptest::prelude::*;
+
+        use super::super::test::check_collection_type;
+        use super::*;
+
+        proptest! {
+            #[test]
+            fn check_repeated(value: Vec<IpAddr>, tag in MIN_TAG..=MAX_TAG) {
+                check_collection_type(value, tag, WireType::LengthDelimited,
+                                      encode_repeated, merge_repeated, encoded_len_repeated)?;
+            }
+        }
+
+        #[test]
+        fn discriminated_by_length() {
+            let mut buf = Vec::new();
+            encode(1, &IpAddr::from([192, 0, 2, 1]), &mut buf);
+            assert_eq!(buf, [0x0a, 4, 192, 0, 2, 1]);
+
+            let mapped = IpAddr::V6(Ipv4Addr::new(192, 0, 2, 1).to_ipv6_mapped());
+            let mut buf = Vec::new();
+            encode(1, &mapped, &mut buf);
+            assert_eq!(buf.len(), 18);
+
+            let mut value = default();
+            merge(
+                WireType::LengthDelimited,
+                &mut value,
+                &mut &buf[1..],
+                DecodeContext::default(),
+            )
+            .unwrap();
+            assert_eq!(value, mapped);
+            assert_eq!(to_bytes(&value), buf[2..]);
+            assert_eq!(to_bytes(&IpAddr::V6(Ipv6Addr::LOCALHOST)).len(), 16);
+        }
+
+        #[test]
+        fn invalid_length() {
+            for &len in &[0, 3, 5, 15, 17] {
+                let mut buf = Vec::new();
+                bytes::encode(1, &vec![0xAAu8; len], &mut buf);
+
+                let mut value = default();
+                let error = merge(
+                    WireType::LengthDelimited,
+                    &mut value,
+                    &mut &buf[1..],
+                    DecodeContext::default(),
+                )
+                .expect_err("length mismatch");
+                assert_eq!(
+                    error.to_string(),
+                    format!(
+                        "failed to decode Protobuf message: \
+                         invalid IP address length: expected 4 or 16 bytes, found {}",
+                        len
+                    )
+                );
+                assert_eq!(value, default());
+            }
+        }
+    }
 }
 
-impl BytesAdapter for Bytes {}
-
-impl sealed::BytesAdapter for Bytes {
-    fn len(&self) -> usize {
-        Buf::remaining(self)
-    }
+/// Encoding functions for socket addresses, represented as `std::net::SocketAddr`.
+///
+/// On the wire these are ordinary `bytes` fields, holding the octets of the IP address as encoded
+/// by `ip_addr`, followed by the port as 2 bytes in network byte order, for 6 bytes in total for
+/// IPv4 and 18 for IPv6. The flow information and scope ID of IPv6 socket addresses are not
+/// encoded, and decode as 0.
+#[cfg(feature = "std")]
+pub mod socket_addr {
+    use std::net::{IpAddr, SocketAddr};
 
-    fn replace_with<B>(&mut self, mut buf: B)
-    where
-        B: Buf,
-    {
-        *self = buf.copy_to_bytes(buf.remaining());
-    }
+    use super::*;
 
-    fn append_to<B>(&self, buf: &mut B)
-    where
-        B: BufMut,
-    {
-        buf.put(self.clone())
-    }
-}
+    /// The wire type of the field's values.
+    pub const WIRE_TYPE: WireType = WireType::LengthDelimited;
 
-impl BytesAdapter for Vec<u8> {}
+    /// The length of an encoded port.
+    const PORT_LEN: usize = 2;
 
-impl sealed::BytesAdapter for Vec<u8> {
-    fn len(&self) -> usize {
-        Vec::len(self)
+    /// Returns the default value of a socket address field, which is `0.0.0.0:0`.
+    pub fn default() -> SocketAddr {
+        SocketAddr::new(ip_addr::default(), 0)
     }
 
-    fn replace_with<B>(&mut self, buf: B)
-    where
-        B: Buf,
-    {
-        self.clear();
-        self.reserve(buf.remaining());
-        self.put(buf);
+    /// Returns the encoded bytes of `value`.
+    pub fn to_bytes(value: &SocketAddr) -> Vec<u8> {
+        let mut bytes = ip_addr::to_bytes(&value.ip());
+        bytes.extend_from_slice(&value.port().to_be_bytes());
+        bytes
     }
 
-    fn append_to<B>(&self, buf: &mut B)
-    where
-        B: BufMut,
-    {
-        buf.put(self.as_slice())
+    fn bytes_len(value: &SocketAddr) -> usize {
+        ip_addr::octets_len(&value.ip()) + PORT_LEN
     }
-}
-
-pub mod bytes {
-    use super::*;
 
-    pub fn encode<A, B>(tag: u32, value: &A, buf: &mut B)
+    pub fn encode<B>(tag: u32, value: &SocketAddr, buf: &mut B)
     where
-        A: BytesAdapter,
         B: BufMut,
     {
-        encode_key(tag, WireType::LengthDelimited, buf);
-        encode_varint(value.len() as u64, buf);
-        value.append_to(buf);
+        encode_key(tag, WIRE_TYPE, buf);
+        encode_varint(bytes_len(value) as u64, buf);
+        ip_addr::put(&value.ip(), buf);
+        buf.put_u16(value.port());
     }
 
-    pub fn merge<A, B>(
+    pub fn merge<B>(
         wire_type: WireType,
-        value: &mut A,
+        value: &mut SocketAddr,
         buf: &mut B,
-        _ctx: DecodeContext,
+        ctx: DecodeContext,
     ) -> Result<(), DecodeError>
     where
-        A: BytesAdapter,
         B: Buf,
     {
-        check_wire_type(WireType::LengthDelimited, wire_type)?;
-        let len = decode_varint(buf)?;
-        if len > buf.remaining() as u64 {
-            return Err(DecodeError::new("buffer underflow"));
-        }
-        let len = len as usize;
-
-        // Clear the existing value. This follows from the following rule in the encoding guide[1]:
-        //
-        // > Normally, an encoded message would never have more than one instance of a non-repeated
-        // > field. However, parsers are expected to handle the case in which they do. For numeric
-        // > types and strings, if the same field appears multiple times, the parser accepts the
-        // > last value it sees.
-        //
-        // [1]: https://developers.google.com/protocol-buffers/docs/encoding#optional
-        //
-        // This is intended for A and B both being Bytes so it is zero-copy.
-        // Some combinations of A and B types may cause a double-copy,
-        // in which case merge_one_copy() should be used instead.
-        value.replace_with(buf.copy_to_bytes(len));
+        check_wire_type(WIRE_TYPE, wire_type)?;
+        let len = ctx.decode_len(buf)?;
+        let ip: IpAddr = match len.checked_sub(PORT_LEN) {
+            Some(ip_len @ 4) | Some(ip_len @ 16) => ip_addr::get(ip_len, buf)?,
+            _ => {
+                return Err(DecodeError::new(format!(
+                    "invalid socket address length: expected 6 or 18 bytes, found {}",
+                    len
+                )))
+            }
+        };
+        *value = SocketAddr::new(ip, buf.get_u16());
         Ok(())
     }
 
-    pub(super) fn merge_one_copy<A, B>(
+    pub fn encode_repeated<B>(tag: u32, values: &[SocketAddr], buf: &mut B)
+    where
+        B: BufMut,
+    {
+        for value in values {
+            encode(tag, value, buf);
+        }
+    }
+
+    pub fn merge_repeated<B>(
         wire_type: WireType,
-        value: &mut A,
+        values: &mut Vec<SocketAddr>,
         buf: &mut B,
-        _ctx: DecodeContext,
+        ctx: DecodeContext,
     ) -> Result<(), DecodeError>
     where
-        A: BytesAdapter,
         B: Buf,
     {
-        check_wire_type(WireType::LengthDelimited, wire_type)?;
-        let len = decode_varint(buf)?;
-        if len > buf.remaining() as u64 {
-            return Err(DecodeError::new("buffer underflow"));
-        }
-        let len = len as usize;
-
-        // If we must copy, make sure to copy only once.
-        value.replace_with(buf.take(len));
+        let mut value = default();
+        merge(wire_type, &mut value, buf, ctx)?;
+        values.push(value);
         Ok(())
     }
 
-    length_delimited!(impl BytesAdapter);
+    #[inline]
+    pub fn encoded_len(tag: u32, value: &SocketAddr) -> usize {
+        key_len(tag) + 1 + bytes_len(value)
+    }
+
+    #[inline]
+    pub fn encoded_len_repeated(tag: u32, values: &[SocketAddr]) -> usize {
+        sum_len(values.iter().map(|value| encoded_len(tag, value)))
+    }
 
     #[cfg(test)]
     mod test {
+        use std::net::{Ipv6Addr, SocketAddrV6};
+
         use proptest::prelude::*;
 
-        use super::super::test::{check_collection_type, check_type};
+        use super::super::test::check_collection_type;
         use super::*;
 
+        fn socket_addr() -> impl Strategy<Value = SocketAddr> {
+            // Flow information and scope IDs are not encoded.
+            (any::<IpAddr>(), any::<u16>()).prop_map(|(ip, port)| SocketAddr::new(ip, port))
+        }
+
         proptest! {
             #[test]
-            fn check_vec(value: Vec<u8>, tag in MIN_TAG..=MAX_TAG) {
-                super::test::check_type::<Vec<u8>, Vec<u8>>(value, tag, WireType::LengthDelimited,
-                                                            encode, merge, encoded_len)?;
+            fn check_repeated(
+                value in proptest::collection::vec(socket_addr(), 0..8),
+                tag in MIN_TAG..=MAX_TAG,
+            ) {
+                check_collection_type(value, tag, WireType::LengthDelimited,
+                                      encode_repeated, merge_repeated, encoded_len_repeated)?;
             }
+        }
 
-            #[test]
-            fn check_bytes(value: Vec<u8>, tag in MIN_TAG..=MAX_TAG) {
-                let value = Bytes::from(value);
-                super::test::check_type::<Bytes, Bytes>(value, tag, WireType::LengthDelimited,
-                                                        encode, merge, encoded_len)?;
-            }
+        #[test]
+        fn port_in_network_byte_order() {
+            let mut buf = Vec::new();
+            encode(1, &SocketAddr::from(([192, 0, 2, 1], 8080)), &mut buf);
+            assert_eq!(buf, [0x0a, 6, 192, 0, 2, 1, 0x1f, 0x90]);
 
-            #[test]
-            fn check_repeated_vec(value: Vec<Vec<u8>>, tag in MIN_TAG..=MAX_TAG) {
-                super::test::check_collection_type(value, tag, WireType::LengthDelimited,
-                                                   encode_repeated, merge_repeated,
-                                                   encoded_len_repeated)?;
-            }
+            let addr = SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::LOCALHOST, 443, 7, 3));
+            let mut buf = Vec::new();
+            encode(1, &addr, &mut buf);
+            assert_eq!(buf.len(), 20);
+            assert_eq!(to_bytes(&addr), buf[2..]);
 
-            #[test]
-            fn check_repeated_bytes(value: Vec<Vec<u8>>, tag in MIN_TAG..=MAX_TAG) {
-                let value = value.into_iter().map(Bytes::from).collect();
-                super::test::check_collection_type(value, tag, WireType::LengthDelimited,
-                                                   encode_repeated, merge_repeated,
-                                                   encoded_len_repeated)?;
+            let mut value = default();
+            merge(
+                WireType::LengthDelimited,
+                &mut value,
+                &mut &buf[1..],
+                DecodeContext::default(),
+            )
+            .unwrap();
+            assert_eq!(value, SocketAddr::from((Ipv6Addr::LOCALHOST, 443)));
+        }
+
+        #[test]
+        fn invalid_length() {
+            for &len in &[0, 1, 4, 5, 7, 16, 17, 19] {
+                let mut buf = Vec::new();
+                bytes::encode(1, &vec![0xAAu8; len], &mut buf);
+
+                let mut value = default();
+                let error = merge(
+                    WireType::LengthDelimited,
+                    &mut value,
+                    &mut &buf[1..],
+                    DecodeContext::default(),
+                )
+                .expect_err("length mismatch");
+                assert_eq!(
+                    error.to_string(),
+                    format!(
+                        "failed to decode Protobuf message: \
+                         invalid socket address length: expected 6 or 18 bytes, found {}",
+                        len
+                    )
+                );
             }
         }
     }
 }
 
+/// A type which holds the elements of a repeated message field, i.e. `Vec<M>`, or
+/// `SmallVec<[M; N]>` with the `smallvec` feature.
+///
+/// A `SmallVec` keeps up to `N` messages inline, so decoding a field which usually has few
+/// elements does not allocate. Fields of either type are encoded and decoded alike. The trait is
+/// sealed.
+pub trait RepeatedMessages<M>: sealed::RepeatedMessages<M> {}
+
+impl<M> RepeatedMessages<M> for Vec<M> {}
+
+impl<M> sealed::RepeatedMessages<M> for Vec<M> {
+    fn push(&mut self, msg: M) {
+        Vec::push(self, msg)
+    }
+}
+
+#[cfg(feature = "smallvec")]
+impl<A> RepeatedMessages<A::Item> for smallvec::SmallVec<A> where A: smallvec::Array {}
+
+#[cfg(feature = "smallvec")]
+impl<A> sealed::RepeatedMessages<A::Item> for smallvec::SmallVec<A>
+where
+    A: smallvec::Array,
+{
+    fn push(&mut self, msg: A::Item) {
+        smallvec::SmallVec::push(self, msg)
+    }
+}
+
 pub mod message {
     use super::*;
 
+    /// The wire type of the field's values.
+    pub const WIRE_TYPE: WireType = WireType::LengthDelimited;
+
     pub fn encode<M, B>(tag: u32, msg: &M, buf: &mut B)
     where
         M: Message,
         B: BufMut,
     {
-        encode_key(tag, WireType::LengthDelimited, buf);
+        encode_key(tag, WIRE_TYPE, buf);
         encode_varint(msg.encoded_len() as u64, buf);
         msg.encode_raw(buf);
     }
 
+    /// Encodes a message field like `encode`, reusing the length cached by the message and by the
+    /// messages nested in it.
+    ///
+    /// Meant to be used only by `Message::encode_cached_raw`, whose caller has just refreshed the
+    /// cached lengths by calling `encoded_len`.
+    pub fn encode_cached<M, B>(tag: u32, msg: &M, buf: &mut B)
+    where
+        M: Message,
+        B: BufMut,
+    {
+        encode_key(tag, WIRE_TYPE, buf);
+        encode_varint(msg.cached_encoded_len() as u64, buf);
+        msg.encode_cached_raw(buf);
+    }
+
+    /// Encodes a message field with the message in canonical form.
+    ///
+    /// The length cached by the message is reused, since `Message::encode_canonical_raw` is only
+    /// called after `encoded_len` has refreshed it.
+    pub fn encode_canonical<M, B>(tag: u32, msg: &M, buf: &mut B)
+    where
+        M: Message,
+        B: BufMut,
+    {
+        encode_key(tag, WIRE_TYPE, buf);
+        encode_varint(msg.cached_encoded_len() as u64, buf);
+        msg.encode_canonical_raw(buf);
+    }
+
     pub fn merge<M, B>(
         wire_type: WireType,
         msg: &mut M,
@@ -1075,17 +2562,9 @@ pub mod message {
         M: Message,
         B: Buf,
     {
-        check_wire_type(WireType::LengthDelimited, wire_type)?;
+        check_wire_type(WIRE_TYPE, wire_type)?;
         ctx.limit_reached()?;
-        merge_loop(
-            msg,
-            buf,
-            ctx.enter_recursion(),
-            |msg: &mut M, buf: &mut B, ctx| {
-                let (tag, wire_type) = decode_key(buf)?;
-                msg.merge_field(tag, wire_type, buf, ctx)
-            },
-        )
+        msg.merge_delimited(buf, ctx.enter_recursion())
     }
 
     pub fn encode_repeated<M, B>(tag: u32, messages: &[M], buf: &mut B)
@@ -1098,19 +2577,61 @@ pub mod message {
         }
     }
 
-    pub fn merge_repeated<M, B>(
+    pub fn encode_repeated_canonical<M, B>(tag: u32, messages: &[M], buf: &mut B)
+    where
+        M: Message,
+        B: BufMut,
+    {
+        for msg in messages {
+            encode_canonical(tag, msg, buf);
+        }
+    }
+
+    pub fn merge_repeated<M, R, B>(
         wire_type: WireType,
-        messages: &mut Vec<M>,
+        messages: &mut R,
         buf: &mut B,
         ctx: DecodeContext,
     ) -> Result<(), DecodeError>
     where
         M: Message + Default,
+        R: RepeatedMessages<M>,
         B: Buf,
     {
-        check_wire_type(WireType::LengthDelimited, wire_type)?;
+        check_wire_type(WIRE_TYPE, wire_type)?;
         let mut msg = M::default();
         merge(WireType::LengthDelimited, &mut msg, buf, ctx)?;
+        sealed::RepeatedMessages::push(messages, msg);
+        Ok(())
+    }
+
+    /// Merges an element of a repeated message field, like `merge_repeated`, but reuses a message
+    /// from `pool` for the element if the pool is not empty.
+    ///
+    /// Messages taken from the pool are cleared before the element is merged into them, which
+    /// keeps the capacity of their repeated, `string` and `bytes` fields. Refilling the pool with
+    /// the elements of a field once they have been processed, e.g. with
+    /// `pool.append(&mut messages)`, lets the next decoded field reuse their allocations.
+    pub fn merge_repeated_reuse<M, B>(
+        wire_type: WireType,
+        messages: &mut Vec<M>,
+        pool: &mut Vec<M>,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        M: Message + Default,
+        B: Buf,
+    {
+        check_wire_type(WIRE_TYPE, wire_type)?;
+        let mut msg = match pool.pop() {
+            Some(mut msg) => {
+                msg.clear();
+                msg
+            }
+            None => M::default(),
+        };
+        merge(WireType::LengthDelimited, &mut msg, buf, ctx)?;
         messages.push(msg);
         Ok(())
     }
@@ -1120,8 +2641,7 @@ pub mod message {
     where
         M: Message,
     {
-        let len = msg.encoded_len();
-        key_len(tag) + encoded_len_varint(len as u64) + len
+        delimited_len(tag, msg.encoded_len())
     }
 
     #[inline]
@@ -1129,28 +2649,56 @@ pub mod message {
     where
         M: Message,
     {
-        key_len(tag) * messages.len()
-            + messages
-                .iter()
-                .map(Message::encoded_len)
-                .map(|len| len + encoded_len_varint(len as u64))
-                .sum::<usize>()
+        key_len(tag)
+            .saturating_mul(messages.len())
+            .saturating_add(sum_len(
+                messages
+                    .iter()
+                    .map(Message::encoded_len)
+                    .map(|len| len.saturating_add(encoded_len_varint(len as u64))),
+            ))
     }
 }
 
 pub mod group {
     use super::*;
 
+    /// The wire type of the key which starts each of the field's values.
+    pub const WIRE_TYPE: WireType = WireType::StartGroup;
+
     pub fn encode<M, B>(tag: u32, msg: &M, buf: &mut B)
     where
         M: Message,
         B: BufMut,
     {
-        encode_key(tag, WireType::StartGroup, buf);
+        encode_key(tag, WIRE_TYPE, buf);
         msg.encode_raw(buf);
         encode_key(tag, WireType::EndGroup, buf);
     }
 
+    /// Encodes a group field like `encode`, reusing the lengths cached by the messages nested in
+    /// the group, see `message::encode_cached`.
+    pub fn encode_cached<M, B>(tag: u32, msg: &M, buf: &mut B)
+    where
+        M: Message,
+        B: BufMut,
+    {
+        encode_key(tag, WIRE_TYPE, buf);
+        msg.encode_cached_raw(buf);
+        encode_key(tag, WireType::EndGroup, buf);
+    }
+
+    /// Encodes a group field with the group's fields in canonical form.
+    pub fn encode_canonical<M, B>(tag: u32, msg: &M, buf: &mut B)
+    where
+        M: Message,
+        B: BufMut,
+    {
+        encode_key(tag, WIRE_TYPE, buf);
+        msg.encode_canonical_raw(buf);
+        encode_key(tag, WireType::EndGroup, buf);
+    }
+
     pub fn merge<M, B>(
         tag: u32,
         wire_type: WireType,
@@ -1162,7 +2710,7 @@ pub mod group {
         M: Message,
         B: Buf,
     {
-        check_wire_type(WireType::StartGroup, wire_type)?;
+        check_wire_type(WIRE_TYPE, wire_type)?;
 
         ctx.limit_reached()?;
         loop {
@@ -1178,48 +2726,223 @@ pub mod group {
         }
     }
 
-    pub fn encode_repeated<M, B>(tag: u32, messages: &[M], buf: &mut B)
-    where
-        M: Message,
+    pub fn encode_repeated<M, B>(tag: u32, messages: &[M], buf: &mut B)
+    where
+        M: Message,
+        B: BufMut,
+    {
+        for msg in messages {
+            encode(tag, msg, buf);
+        }
+    }
+
+    pub fn encode_repeated_canonical<M, B>(tag: u32, messages: &[M], buf: &mut B)
+    where
+        M: Message,
+        B: BufMut,
+    {
+        for msg in messages {
+            encode_canonical(tag, msg, buf);
+        }
+    }
+
+    pub fn merge_repeated<M, B>(
+        tag: u32,
+        wire_type: WireType,
+        messages: &mut Vec<M>,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        M: Message + Default,
+        B: Buf,
+    {
+        check_wire_type(WIRE_TYPE, wire_type)?;
+        let mut msg = M::default();
+        merge(tag, WireType::StartGroup, &mut msg, buf, ctx)?;
+        messages.push(msg);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn encoded_len<M>(tag: u32, msg: &M) -> usize
+    where
+        M: Message,
+    {
+        msg.encoded_len().saturating_add(2 * key_len(tag))
+    }
+
+    #[inline]
+    pub fn encoded_len_repeated<M>(tag: u32, messages: &[M]) -> usize
+    where
+        M: Message,
+    {
+        (2 * key_len(tag))
+            .saturating_mul(messages.len())
+            .saturating_add(sum_len(messages.iter().map(Message::encoded_len)))
+    }
+}
+
+/// Encoding of `(K, V)` pairs as a message with two fields: the key with tag 1, and the value
+/// with tag 2.
+///
+/// This is the layout of a map entry, so a repeated pair field is wire compatible with a map
+/// field. As in a map entry, the key and the value are omitted when they hold their default
+/// value. The key and value are encoded with the functions of the modules matching their
+/// Protobuf types, passed as closures, e.g. `string::encode` and `string::encoded_len` for a
+/// `string` key.
+pub mod pair {
+    use super::*;
+
+    /// Encodes a pair as a length-delimited field.
+    pub fn encode<K, V, B, KE, KL, VE, VL>(
+        key_encode: KE,
+        key_encoded_len: KL,
+        val_encode: VE,
+        val_encoded_len: VL,
+        tag: u32,
+        value: &(K, V),
+        buf: &mut B,
+    ) where
+        K: Default + PartialEq,
+        V: Default + PartialEq,
         B: BufMut,
+        KE: Fn(u32, &K, &mut B),
+        KL: Fn(u32, &K) -> usize,
+        VE: Fn(u32, &V, &mut B),
+        VL: Fn(u32, &V) -> usize,
     {
-        for msg in messages {
-            encode(tag, msg, buf);
-        }
+        encode_with_default(
+            key_encode,
+            key_encoded_len,
+            val_encode,
+            val_encoded_len,
+            &V::default(),
+            tag,
+            (&value.0, &value.1),
+            buf,
+        )
     }
 
-    pub fn merge_repeated<M, B>(
-        tag: u32,
-        wire_type: WireType,
-        messages: &mut Vec<M>,
+    /// Merges a length-delimited pair into `value`.
+    ///
+    /// As with the map merge functions, the field's wire type must be checked by the caller.
+    pub fn merge<K, V, B, KM, VM>(
+        key_merge: KM,
+        val_merge: VM,
+        value: &mut (K, V),
         buf: &mut B,
         ctx: DecodeContext,
     ) -> Result<(), DecodeError>
     where
-        M: Message + Default,
         B: Buf,
+        KM: Fn(WireType, &mut K, &mut B, DecodeContext) -> Result<(), DecodeError>,
+        VM: Fn(WireType, &mut V, &mut B, DecodeContext) -> Result<(), DecodeError>,
     {
-        check_wire_type(WireType::StartGroup, wire_type)?;
-        let mut msg = M::default();
-        merge(tag, WireType::StartGroup, &mut msg, buf, ctx)?;
-        messages.push(msg);
-        Ok(())
+        ctx.limit_reached()?;
+        merge_loop(
+            value,
+            buf,
+            ctx.enter_recursion(),
+            |&mut (ref mut key, ref mut val), buf, ctx| {
+                let (tag, wire_type) = decode_key(buf)?;
+                match tag {
+                    1 => key_merge(wire_type, key, buf, ctx),
+                    2 => val_merge(wire_type, val, buf, ctx),
+                    _ => skip_field(wire_type, tag, buf, ctx),
+                }
+            },
+        )
     }
 
-    #[inline]
-    pub fn encoded_len<M>(tag: u32, msg: &M) -> usize
+    /// Returns the encoded length of a pair, including its key and length.
+    pub fn encoded_len<K, V, KL, VL>(
+        key_encoded_len: KL,
+        val_encoded_len: VL,
+        tag: u32,
+        value: &(K, V),
+    ) -> usize
     where
-        M: Message,
+        K: Default + PartialEq,
+        V: Default + PartialEq,
+        KL: Fn(u32, &K) -> usize,
+        VL: Fn(u32, &V) -> usize,
     {
-        2 * key_len(tag) + msg.encoded_len()
+        encoded_len_with_default(
+            key_encoded_len,
+            val_encoded_len,
+            &V::default(),
+            tag,
+            (&value.0, &value.1),
+        )
     }
 
-    #[inline]
-    pub fn encoded_len_repeated<M>(tag: u32, messages: &[M]) -> usize
+    /// Encodes a pair as a length-delimited field, with an overridden value default.
+    ///
+    /// This is necessary because enumeration values can have a default value other
+    /// than 0 in proto2.
+    pub fn encode_with_default<K, V, B, KE, KL, VE, VL>(
+        key_encode: KE,
+        key_encoded_len: KL,
+        val_encode: VE,
+        val_encoded_len: VL,
+        val_default: &V,
+        tag: u32,
+        (key, val): (&K, &V),
+        buf: &mut B,
+    ) where
+        K: Default + PartialEq,
+        V: PartialEq,
+        B: BufMut,
+        KE: Fn(u32, &K, &mut B),
+        KL: Fn(u32, &K) -> usize,
+        VE: Fn(u32, &V, &mut B),
+        VL: Fn(u32, &V) -> usize,
+    {
+        let skip_key = key == &K::default();
+        let skip_val = val == val_default;
+
+        let len = (if skip_key { 0 } else { key_encoded_len(1, key) })
+            + (if skip_val { 0 } else { val_encoded_len(2, val) });
+
+        encode_key(tag, WireType::LengthDelimited, buf);
+        encode_varint(len as u64, buf);
+        if !skip_key {
+            key_encode(1, key, buf);
+        }
+        if !skip_val {
+            val_encode(2, val, buf);
+        }
+    }
+
+    /// Returns the encoded length of a pair, with an overridden value default.
+    ///
+    /// This is necessary because enumeration values can have a default value other
+    /// than 0 in proto2.
+    pub fn encoded_len_with_default<K, V, KL, VL>(
+        key_encoded_len: KL,
+        val_encoded_len: VL,
+        val_default: &V,
+        tag: u32,
+        (key, val): (&K, &V),
+    ) -> usize
     where
-        M: Message,
+        K: Default + PartialEq,
+        V: PartialEq,
+        KL: Fn(u32, &K) -> usize,
+        VL: Fn(u32, &V) -> usize,
     {
-        2 * key_len(tag) * messages.len() + messages.iter().map(Message::encoded_len).sum::<usize>()
+        let len = (if key == &K::default() {
+            0
+        } else {
+            key_encoded_len(1, key)
+        })
+        .saturating_add(if val == val_default {
+            0
+        } else {
+            val_encoded_len(2, val)
+        });
+        delimited_len(tag, len)
     }
 }
 
@@ -1230,6 +2953,9 @@ macro_rules! map {
         use crate::encoding::*;
         use core::hash::Hash;
 
+        /// The wire type of the field's entries.
+        pub const WIRE_TYPE: WireType = WireType::LengthDelimited;
+
         /// Generic protobuf map encode function.
         pub fn encode<K, V, B, KE, KL, VE, VL>(
             key_encode: KE,
@@ -1316,21 +3042,82 @@ macro_rules! map {
             VE: Fn(u32, &V, &mut B),
             VL: Fn(u32, &V) -> usize,
         {
-            for (key, val) in values.iter() {
-                let skip_key = key == &K::default();
-                let skip_val = val == val_default;
+            encode_entries(
+                key_encode,
+                key_encoded_len,
+                val_encode,
+                val_encoded_len,
+                val_default,
+                tag,
+                values.iter(),
+                buf,
+            )
+        }
 
-                let len = (if skip_key { 0 } else { key_encoded_len(1, key) })
-                    + (if skip_val { 0 } else { val_encoded_len(2, val) });
+        /// Generic protobuf map encode function which writes the entries in ascending key
+        /// order, with an overridden value default.
+        pub fn encode_sorted_with_default<K, V, B, KE, KL, VE, VL>(
+            key_encode: KE,
+            key_encoded_len: KL,
+            val_encode: VE,
+            val_encoded_len: VL,
+            val_default: &V,
+            tag: u32,
+            values: &$map_ty<K, V>,
+            buf: &mut B,
+        ) where
+            K: Default + Eq + Hash + Ord,
+            V: PartialEq,
+            B: BufMut,
+            KE: Fn(u32, &K, &mut B),
+            KL: Fn(u32, &K) -> usize,
+            VE: Fn(u32, &V, &mut B),
+            VL: Fn(u32, &V) -> usize,
+        {
+            let mut entries = values.iter().collect::<Vec<_>>();
+            entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+            encode_entries(
+                key_encode,
+                key_encoded_len,
+                val_encode,
+                val_encoded_len,
+                val_default,
+                tag,
+                entries.into_iter(),
+                buf,
+            )
+        }
 
-                encode_key(tag, WireType::LengthDelimited, buf);
-                encode_varint(len as u64, buf);
-                if !skip_key {
-                    key_encode(1, key, buf);
-                }
-                if !skip_val {
-                    val_encode(2, val, buf);
-                }
+        fn encode_entries<'a, K, V, B, KE, KL, VE, VL, I>(
+            key_encode: KE,
+            key_encoded_len: KL,
+            val_encode: VE,
+            val_encoded_len: VL,
+            val_default: &V,
+            tag: u32,
+            entries: I,
+            buf: &mut B,
+        ) where
+            K: Default + Eq + 'a,
+            V: PartialEq + 'a,
+            B: BufMut,
+            KE: Fn(u32, &K, &mut B),
+            KL: Fn(u32, &K) -> usize,
+            VE: Fn(u32, &V, &mut B),
+            VL: Fn(u32, &V) -> usize,
+            I: Iterator<Item = (&'a K, &'a V)>,
+        {
+            for entry in entries {
+                pair::encode_with_default(
+                    &key_encode,
+                    &key_encoded_len,
+                    &val_encode,
+                    &val_encoded_len,
+                    val_default,
+                    tag,
+                    entry,
+                    buf,
+                );
             }
         }
 
@@ -1352,23 +3139,9 @@ macro_rules! map {
             KM: Fn(WireType, &mut K, &mut B, DecodeContext) -> Result<(), DecodeError>,
             VM: Fn(WireType, &mut V, &mut B, DecodeContext) -> Result<(), DecodeError>,
         {
-            let mut key = Default::default();
-            let mut val = val_default;
-            ctx.limit_reached()?;
-            merge_loop(
-                &mut (&mut key, &mut val),
-                buf,
-                ctx.enter_recursion(),
-                |&mut (ref mut key, ref mut val), buf, ctx| {
-                    let (tag, wire_type) = decode_key(buf)?;
-                    match tag {
-                        1 => key_merge(wire_type, key, buf, ctx),
-                        2 => val_merge(wire_type, val, buf, ctx),
-                        _ => skip_field(wire_type, tag, buf, ctx),
-                    }
-                },
-            )?;
-            values.insert(key, val);
+            let mut entry = (K::default(), val_default);
+            pair::merge(key_merge, val_merge, &mut entry, buf, ctx)?;
+            values.insert(entry.0, entry.1);
 
             Ok(())
         }
@@ -1390,22 +3163,15 @@ macro_rules! map {
             KL: Fn(u32, &K) -> usize,
             VL: Fn(u32, &V) -> usize,
         {
-            key_len(tag) * values.len()
-                + values
-                    .iter()
-                    .map(|(key, val)| {
-                        let len = (if key == &K::default() {
-                            0
-                        } else {
-                            key_encoded_len(1, key)
-                        }) + (if val == val_default {
-                            0
-                        } else {
-                            val_encoded_len(2, val)
-                        });
-                        encoded_len_varint(len as u64) + len
-                    })
-                    .sum::<usize>()
+            sum_len(values.iter().map(|entry| {
+                pair::encoded_len_with_default(
+                    &key_encoded_len,
+                    &val_encoded_len,
+                    val_default,
+                    tag,
+                    entry,
+                )
+            }))
         }
     };
 }
@@ -1423,6 +3189,7 @@ pub mod btree_map {
 #[cfg(test)]
 mod test {
     use alloc::string::ToString;
+    use alloc::vec;
     use core::borrow::Borrow;
     use core::fmt::Debug;
     use core::u64;
@@ -1600,6 +3367,27 @@ mod test {
         assert!(s.is_empty());
     }
 
+    #[test]
+    fn string_merge_invalid_utf8_offset() {
+        let mut s = String::from("previous");
+        // "abc" followed by a Latin-1 encoded 'é'.
+        let buf = b"\x05abc\xe9d";
+
+        let error = string::merge(
+            WireType::LengthDelimited,
+            &mut s,
+            &mut &buf[..],
+            DecodeContext::default(),
+        )
+        .expect_err("must be an error");
+        assert_eq!(
+            error.to_string(),
+            "failed to decode Protobuf message: invalid string value: data is not UTF-8 encoded \
+             (invalid byte sequence at offset 3)"
+        );
+        assert!(s.is_empty());
+    }
+
     #[test]
     fn varint() {
         fn check(value: u64, mut encoded: &[u8]) {
@@ -1618,6 +3406,15 @@ mod test {
             let roundtrip_value = decode_varint(&mut encoded.clone()).expect("decoding failed");
             assert_eq!(value, roundtrip_value);
 
+            // Split across two chunks, at every position, and followed by another byte.
+            for split in 0..=encoded.len() {
+                let (head, tail) = encoded.split_at(split);
+                let mut buf = head.chain(tail).chain(&[0x2a][..]);
+                let roundtrip_value = decode_varint(&mut buf).expect("chained decoding failed");
+                assert_eq!(value, roundtrip_value);
+                assert_eq!(buf.remaining(), 1);
+            }
+
             let roundtrip_value = decode_varint_slow(&mut encoded).expect("slow decoding failed");
             assert_eq!(value, roundtrip_value);
         }
@@ -1677,6 +3474,168 @@ mod test {
         );
     }
 
+    proptest! {
+        #[test]
+        fn check_encoded_len_varint(value: u64, shift in 0u32..64) {
+            // Shift the value to cover every encoded length evenly.
+            let value = value >> shift;
+            let mut expected = 1;
+            let mut rest = value >> 7;
+            while rest != 0 {
+                expected += 1;
+                rest >>= 7;
+            }
+            prop_assert_eq!(encoded_len_varint(value), expected);
+        }
+    }
+
+    #[test]
+    fn key() {
+        for &(tag, wire_type) in &[
+            (MIN_TAG, WireType::Varint),
+            (15, WireType::EndGroup),
+            (16, WireType::LengthDelimited),
+            (MAX_TAG, WireType::ThirtyTwoBit),
+        ] {
+            let mut buf = Vec::new();
+            encode_key(tag, wire_type, &mut buf);
+            assert_eq!(buf.len(), key_len(tag));
+            assert_eq!(decode_key(&mut &buf[..]).unwrap(), (tag, wire_type));
+        }
+
+        for &(key, message) in &[
+            (0x0e, "invalid wire type value: 6"),
+            (0x0f, "invalid wire type value: 7"),
+            (0x00, "invalid tag value: 0"),
+        ] {
+            let error = decode_key(&mut &[key][..]).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("failed to decode Protobuf message: {}", message)
+            );
+        }
+
+        // A key with a tag above `MAX_TAG` does not fit in 32 bits.
+        let mut buf = Vec::new();
+        encode_varint(u64::from(MAX_TAG + 1) << 3, &mut buf);
+        let error = decode_key(&mut &buf[..]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "failed to decode Protobuf message: invalid key value: 4294967296"
+        );
+
+        let mut buf = Vec::new();
+        encode_key(19000, WireType::Varint, &mut buf);
+        assert_eq!(
+            decode_key(&mut &buf[..]).unwrap(),
+            (19000, WireType::Varint)
+        );
+    }
+
+    #[test]
+    fn raw_field_iter() {
+        // A varint with a non-canonical key, a string, a group containing a fixed32, and a
+        // fixed64.
+        let buf: &[u8] = &[
+            0x88, 0x00, 0x96, 0x01, // field 1, varint 150, key padded to two bytes
+            0x12, 0x02, b'h', b'i', // field 2, "hi"
+            0x1b, 0x25, 0x01, 0x00, 0x00, 0x00, 0x1c, // field 3, group with field 4
+            0x29, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // field 5
+        ];
+
+        let fields = RawFieldIter::new(buf)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            fields
+                .iter()
+                .map(|field| (field.tag, field.wire_type, field.raw.len()))
+                .collect::<Vec<_>>(),
+            [
+                (1, WireType::Varint, 4),
+                (2, WireType::LengthDelimited, 4),
+                (3, WireType::StartGroup, 7),
+                (5, WireType::SixtyFourBit, 9),
+            ]
+        );
+
+        let mut reencoded = Vec::new();
+        for field in &fields {
+            field.encode(&mut reencoded);
+        }
+        assert_eq!(reencoded, buf);
+
+        // Malformed input yields a single error.
+        let mut iter = RawFieldIter::new(&buf[..buf.len() - 1]);
+        assert_eq!(iter.by_ref().take(3).filter(Result::is_ok).count(), 3);
+        iter.next().unwrap().expect_err("truncated field");
+        assert!(iter.next().is_none());
+        assert_eq!(iter.remaining().len(), 8);
+    }
+
+    #[test]
+    fn wire_type_consts() {
+        // Each module's values are encoded with a key of its wire type.
+        fn check(wire_type: WireType, buf: &[u8]) {
+            assert_eq!(decode_key(&mut &buf[..]).unwrap(), (1, wire_type));
+        }
+
+        let mut buf = Vec::new();
+        int64::encode(1, &-1, &mut buf);
+        check(int64::WIRE_TYPE, &buf);
+        buf.clear();
+        non_zero_uint32::encode(1, &core::num::NonZeroU32::new(7).unwrap(), &mut buf);
+        check(non_zero_uint32::WIRE_TYPE, &buf);
+        buf.clear();
+        fixed32::encode(1, &7, &mut buf);
+        check(fixed32::WIRE_TYPE, &buf);
+        buf.clear();
+        double::encode(1, &0.5, &mut buf);
+        check(double::WIRE_TYPE, &buf);
+        buf.clear();
+        string::encode(1, &"value".to_string(), &mut buf);
+        check(string::WIRE_TYPE, &buf);
+        buf.clear();
+        bytes_array::encode(1, &[1, 2], &mut buf);
+        check(bytes_array::WIRE_TYPE, &buf);
+        buf.clear();
+        message::encode(1, &0u32, &mut buf);
+        check(message::WIRE_TYPE, &buf);
+        buf.clear();
+        group::encode(1, &0u32, &mut buf);
+        check(group::WIRE_TYPE, &buf);
+
+        // Packed repeated values are length-delimited.
+        buf.clear();
+        int64::encode_packed(1, &[1, 2], &mut buf);
+        check(WireType::LengthDelimited, &buf);
+        assert_eq!(int64::WIRE_TYPE, WireType::Varint);
+    }
+
+    #[test]
+    fn encode_key_small_tags() {
+        let wire_types = [
+            WireType::Varint,
+            WireType::SixtyFourBit,
+            WireType::LengthDelimited,
+            WireType::StartGroup,
+            WireType::EndGroup,
+            WireType::ThirtyTwoBit,
+        ];
+        for tag in MIN_TAG..=2047 {
+            for &wire_type in &wire_types {
+                let mut buf = Vec::new();
+                encode_key(tag, wire_type, &mut buf);
+
+                let mut expected = Vec::new();
+                encode_varint(u64::from(tag << 3 | wire_type as u32), &mut expected);
+                assert_eq!(buf, expected, "tag {}, wire type {:?}", tag, wire_type);
+                assert_eq!(buf.len(), key_len(tag));
+                assert_eq!(decode_key(&mut &buf[..]).unwrap(), (tag, wire_type));
+            }
+        }
+    }
+
     #[test]
     fn varint_overflow() {
         let mut u64_max_plus_one: &[u8] =
@@ -1687,6 +3646,451 @@ mod test {
             .expect_err("slow decoding u64::MAX + 1 succeeded");
     }
 
+    #[test]
+    fn varint_too_long() {
+        // Ten bytes with the continuation bit set, followed by more bytes, by the end of the
+        // buffer, and split across chunks.
+        let ones = [0xFF; 10];
+        let mut followed = ones.to_vec();
+        followed.extend_from_slice(&[0x01, 0x08, 0x01]);
+        for buf in &[&followed[..], &ones[..]] {
+            let error = decode_varint(&mut &buf[..]).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "failed to decode Protobuf message: varint too long"
+            );
+            let error = decode_varint_slow(&mut &buf[..]).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "failed to decode Protobuf message: varint too long"
+            );
+            let error = decode_varint(&mut (&buf[..3]).chain(&buf[3..])).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "failed to decode Protobuf message: varint too long"
+            );
+        }
+
+        // A buffer which ends within the varint.
+        let error = decode_varint(&mut &ones[..9]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "failed to decode Protobuf message: invalid varint"
+        );
+
+        // The value 1, padded to the maximum of ten bytes, is accepted like any other ten byte
+        // varint, while padding it to eleven bytes is not.
+        let mut padded = vec![0x81];
+        padded.extend_from_slice(&[0x80; 8]);
+        padded.push(0x00);
+        assert_eq!(decode_varint(&mut &padded[..]).unwrap(), 1);
+        assert_eq!(decode_varint_slow(&mut &padded[..]).unwrap(), 1);
+        padded.insert(1, 0x80);
+        let error = decode_varint(&mut &padded[..]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "failed to decode Protobuf message: varint too long"
+        );
+    }
+
+    #[test]
+    fn zigzag() {
+        fn check_sint32(value: i32, encoded: &[u8]) {
+            let mut buf = Vec::new();
+            sint32::encode(1, &value, &mut buf);
+            assert_eq!(&buf[1..], encoded);
+            assert_eq!(sint32::encoded_len(1, &value), buf.len());
+
+            let mut decoded = 0;
+            sint32::merge(
+                WireType::Varint,
+                &mut decoded,
+                &mut &buf[1..],
+                DecodeContext::default(),
+            )
+            .unwrap();
+            assert_eq!(decoded, value);
+        }
+
+        fn check_sint64(value: i64, encoded: &[u8]) {
+            let mut buf = Vec::new();
+            sint64::encode(1, &value, &mut buf);
+            assert_eq!(&buf[1..], encoded);
+            assert_eq!(sint64::encoded_len(1, &value), buf.len());
+
+            let mut decoded = 0;
+            sint64::merge(
+                WireType::Varint,
+                &mut decoded,
+                &mut &buf[1..],
+                DecodeContext::default(),
+            )
+            .unwrap();
+            assert_eq!(decoded, value);
+        }
+
+        check_sint32(0, &[0x00]);
+        check_sint32(-1, &[0x01]);
+        check_sint32(1, &[0x02]);
+        check_sint32(-2, &[0x03]);
+        check_sint32(i32::MAX, &[0xFE, 0xFF, 0xFF, 0xFF, 0x0F]);
+        check_sint32(i32::MIN, &[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]);
+
+        check_sint64(0, &[0x00]);
+        check_sint64(-1, &[0x01]);
+        check_sint64(1, &[0x02]);
+        check_sint64(
+            i64::MAX,
+            &[0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01],
+        );
+        check_sint64(
+            i64::MIN,
+            &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01],
+        );
+
+        // Plain varint integers sign-extend negative values to ten bytes.
+        assert_eq!(int32::encoded_len(1, &-1), 11);
+        assert_eq!(sint32::encoded_len(1, &-1), 2);
+    }
+
+    #[test]
+    fn plain_varints() {
+        macro_rules! check {
+            ($proto_ty:ident, $value:expr, $encoded:expr) => {{
+                let value = $value;
+                let mut buf = Vec::new();
+                $proto_ty::encode(1, &value, &mut buf);
+                assert_eq!(&buf[1..], &$encoded[..]);
+                assert_eq!($proto_ty::encoded_len(1, &value), buf.len());
+
+                let mut decoded = Default::default();
+                $proto_ty::merge(
+                    WireType::Varint,
+                    &mut decoded,
+                    &mut &buf[1..],
+                    DecodeContext::default(),
+                )
+                .unwrap();
+                assert_eq!(decoded, value);
+
+                let error = $proto_ty::merge(
+                    WireType::SixtyFourBit,
+                    &mut decoded,
+                    &mut &buf[1..],
+                    DecodeContext::default(),
+                )
+                .unwrap_err();
+                assert_eq!(
+                    error.to_string(),
+                    "failed to decode Protobuf message: \
+                     invalid wire type: SixtyFourBit (expected Varint)"
+                );
+            }};
+        }
+
+        let ten_ones = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01];
+        check!(uint64, u64::MAX, ten_ones);
+        check!(int64, -1i64, ten_ones);
+        check!(
+            int64,
+            i64::MIN,
+            [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01]
+        );
+        check!(int32, -1i32, ten_ones);
+        check!(
+            int32,
+            i32::MIN,
+            [0x80, 0x80, 0x80, 0x80, 0xF8, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]
+        );
+        check!(uint32, u32::MAX, [0xFF, 0xFF, 0xFF, 0xFF, 0x0F]);
+        check!(bool, false, [0x00]);
+        check!(bool, true, [0x01]);
+
+        // Any nonzero varint decodes as `true`.
+        let mut value = false;
+        bool::merge(
+            WireType::Varint,
+            &mut value,
+            &mut &[0x80, 0x01][..],
+            DecodeContext::default(),
+        )
+        .unwrap();
+        assert!(value);
+    }
+
+    #[test]
+    fn fixed_width_special_values() {
+        for &value in &[
+            0.0f32,
+            -0.0,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::NAN,
+            f32::MIN_POSITIVE / 2.0,
+            f32::MAX,
+        ] {
+            let mut buf = Vec::new();
+            float::encode(1, &value, &mut buf);
+            assert_eq!(buf, [&[0x0D][..], &value.to_le_bytes()].concat());
+
+            let mut decoded = 0.0;
+            float::merge(
+                WireType::ThirtyTwoBit,
+                &mut decoded,
+                &mut &buf[1..],
+                DecodeContext::default(),
+            )
+            .unwrap();
+            assert_eq!(decoded.to_bits(), value.to_bits());
+        }
+
+        for &value in &[
+            0.0f64,
+            -0.0,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NAN,
+            f64::MIN_POSITIVE / 2.0,
+            f64::MAX,
+        ] {
+            let mut buf = Vec::new();
+            double::encode(1, &value, &mut buf);
+            assert_eq!(buf, [&[0x09][..], &value.to_le_bytes()].concat());
+
+            let mut decoded = 0.0;
+            double::merge(
+                WireType::SixtyFourBit,
+                &mut decoded,
+                &mut &buf[1..],
+                DecodeContext::default(),
+            )
+            .unwrap();
+            assert_eq!(decoded.to_bits(), value.to_bits());
+        }
+
+        // The wire type must match the field type.
+        let mut value = 0u32;
+        let error = fixed32::merge(
+            WireType::SixtyFourBit,
+            &mut value,
+            &mut &[0u8; 8][..],
+            DecodeContext::default(),
+        )
+        .expect_err("mismatched wire type");
+        assert_eq!(
+            error.to_string(),
+            "failed to decode Protobuf message: invalid wire type: SixtyFourBit (expected ThirtyTwoBit)"
+        );
+    }
+
+    #[test]
+    fn packed_and_unpacked_merge() {
+        let values: Vec<u32> = (0..10_000).collect();
+
+        let mut packed = Vec::new();
+        uint32::encode_packed(1, &values, &mut packed);
+        assert_eq!(packed.len(), uint32::encoded_len_packed(1, &values));
+
+        let mut unpacked = Vec::new();
+        uint32::encode_repeated(1, &values, &mut unpacked);
+        assert_eq!(unpacked.len(), uint32::encoded_len_repeated(1, &values));
+        // One key for the whole field instead of one key per element.
+        assert!(packed.len() < unpacked.len() - 9_000);
+
+        // Packed and unpacked occurrences of the same field are accepted and appended in order.
+        let mut buf = Vec::new();
+        fixed32::encode_packed(1, &[1, 2], &mut buf);
+        fixed32::encode_repeated(1, &[3], &mut buf);
+        fixed32::encode_packed(1, &[4], &mut buf);
+
+        let mut decoded = Vec::new();
+        let mut buf = &buf[..];
+        while buf.has_remaining() {
+            let (tag, wire_type) = decode_key(&mut buf).unwrap();
+            assert_eq!(tag, 1);
+            fixed32::merge_repeated(wire_type, &mut decoded, &mut buf, DecodeContext::default())
+                .unwrap();
+        }
+        assert_eq!(decoded, [1, 2, 3, 4]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn hash_map_duplicate_keys() {
+        use std::collections::HashMap;
+
+        let mut buf = Vec::new();
+        for value in ["first", "second"] {
+            let entry: HashMap<i32, String> = [(7, value.to_string())].into_iter().collect();
+            crate::encoding::hash_map::encode(
+                int32::encode,
+                int32::encoded_len,
+                string::encode,
+                string::encoded_len,
+                1,
+                &entry,
+                &mut buf,
+            );
+        }
+
+        let mut map = HashMap::new();
+        let mut buf = &buf[..];
+        while buf.has_remaining() {
+            let (tag, wire_type) = decode_key(&mut buf).unwrap();
+            assert_eq!(tag, 1);
+            check_wire_type(WireType::LengthDelimited, wire_type).unwrap();
+            crate::encoding::hash_map::merge(
+                int32::merge,
+                string::merge,
+                &mut map,
+                &mut buf,
+                DecodeContext::default(),
+            )
+            .unwrap();
+        }
+
+        // The last entry for a duplicated key wins.
+        assert_eq!(map.len(), 1);
+        assert_eq!(map[&7], "second");
+    }
+
+    #[test]
+    fn btree_map_deterministic() {
+        fn encode_map(map: &BTreeMap<String, u32>) -> Vec<u8> {
+            let mut buf = Vec::new();
+            crate::encoding::btree_map::encode(
+                string::encode,
+                string::encoded_len,
+                uint32::encode,
+                uint32::encoded_len,
+                1,
+                map,
+                &mut buf,
+            );
+            assert_eq!(
+                buf.len(),
+                crate::encoding::btree_map::encoded_len(
+                    string::encoded_len,
+                    uint32::encoded_len,
+                    1,
+                    map
+                )
+            );
+            buf
+        }
+
+        let keys = ["delta", "alpha", "charlie", "bravo"];
+        let forward: BTreeMap<String, u32> = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| (key.to_string(), i as u32))
+            .collect();
+        let reverse: BTreeMap<String, u32> = keys
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, key)| (key.to_string(), i as u32))
+            .collect();
+
+        let buf = encode_map(&forward);
+        assert_eq!(buf, encode_map(&forward));
+        assert_eq!(buf, encode_map(&reverse));
+
+        let mut decoded = BTreeMap::new();
+        let mut buf = &buf[..];
+        while buf.has_remaining() {
+            let (tag, wire_type) = decode_key(&mut buf).unwrap();
+            assert_eq!(tag, 1);
+            check_wire_type(WireType::LengthDelimited, wire_type).unwrap();
+            crate::encoding::btree_map::merge(
+                string::merge,
+                uint32::merge,
+                &mut decoded,
+                &mut buf,
+                DecodeContext::default(),
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            decoded.keys().map(String::as_str).collect::<Vec<_>>(),
+            ["alpha", "bravo", "charlie", "delta"]
+        );
+    }
+
+    #[test]
+    fn bytes_vec_is_one_field() {
+        let value = Vec::from(&[0u8, 1, 2][..]);
+        let mut buf = Vec::new();
+        bytes::encode(1, &value, &mut buf);
+        assert_eq!(buf, [0x0a, 0x03, 0x00, 0x01, 0x02]);
+        assert_eq!(bytes::encoded_len(1, &value), buf.len());
+
+        let mut repeated = Vec::new();
+        bytes::encode_repeated(1, &[value, Vec::new()], &mut repeated);
+        assert_eq!(repeated, [0x0a, 0x03, 0x00, 0x01, 0x02, 0x0a, 0x00]);
+    }
+
+    #[test]
+    fn pair_matches_map_entry() {
+        let entry = (7, "seven".to_string());
+        let mut buf = Vec::new();
+        pair::encode(
+            int32::encode,
+            int32::encoded_len,
+            string::encode,
+            string::encoded_len,
+            3,
+            &entry,
+            &mut buf,
+        );
+        assert_eq!(buf, b"\x1a\x09\x08\x07\x12\x05seven");
+        assert_eq!(
+            buf.len(),
+            pair::encoded_len(int32::encoded_len, string::encoded_len, 3, &entry)
+        );
+
+        let map: BTreeMap<i32, String> = [entry.clone()].into_iter().collect();
+        let mut map_buf = Vec::new();
+        crate::encoding::btree_map::encode(
+            int32::encode,
+            int32::encoded_len,
+            string::encode,
+            string::encoded_len,
+            3,
+            &map,
+            &mut map_buf,
+        );
+        assert_eq!(buf, map_buf);
+
+        let mut decoded = (0, String::new());
+        let mut buf = &buf[..];
+        let (tag, wire_type) = decode_key(&mut buf).unwrap();
+        assert_eq!((tag, wire_type), (3, WireType::LengthDelimited));
+        pair::merge(
+            int32::merge,
+            string::merge,
+            &mut decoded,
+            &mut buf,
+            DecodeContext::default(),
+        )
+        .unwrap();
+        assert_eq!(decoded, entry);
+        assert!(!buf.has_remaining());
+
+        // Default keys and values are omitted.
+        let mut buf = Vec::new();
+        pair::encode(
+            int32::encode,
+            int32::encoded_len,
+            string::encode,
+            string::encoded_len,
+            3,
+            &(0, String::new()),
+            &mut buf,
+        );
+        assert_eq!(buf, b"\x1a\x00");
+    }
+
     /// This big bowl o' macro soup generates an encoding property test for each combination of map
     /// type, scalar map key, and value type.
     /// TODO: these tests take a long time to compile, can this be improved?
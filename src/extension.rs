@@ -0,0 +1,342 @@
+//! Storage for proto2 extensions.
+
+use alloc::vec::Vec;
+
+use ::bytes::{Buf, BufMut};
+
+use crate::encoding::{decode_key, DecodeContext, WireType};
+use crate::text_format::TextFormatWriter;
+use crate::unknown::UnknownFieldList;
+use crate::DecodeError;
+
+/// A proto2 extension, i.e. a field declared in an `extend` block outside of the message it
+/// extends.
+///
+/// Extensions are implemented by a marker type, which is passed to the typed accessors of
+/// `ExtensionSet`. The encoding functions are usually those of the `prost::encoding` module
+/// matching the field's Protobuf type:
+///
+/// ```rust
+/// use prost::bytes::{Buf, BufMut};
+/// use prost::encoding::{int32, DecodeContext, WireType};
+/// use prost::{DecodeError, Extension};
+///
+/// /// `extend Request { optional int32 priority = 1000; }`
+/// struct Priority;
+///
+/// impl Extension for Priority {
+///     type Value = i32;
+///     const NUMBER: u32 = 1000;
+///
+///     fn encode<B: BufMut>(value: &i32, buf: &mut B) {
+///         int32::encode(Self::NUMBER, value, buf);
+///     }
+///
+///     fn merge<B: Buf>(
+///         wire_type: WireType,
+///         value: &mut i32,
+///         buf: &mut B,
+///         ctx: DecodeContext,
+///     ) -> Result<(), DecodeError> {
+///         int32::merge(wire_type, value, buf, ctx)
+///     }
+/// }
+/// ```
+pub trait Extension {
+    /// The Rust type of the extension's value.
+    type Value: Default;
+
+    /// The extension's field number.
+    const NUMBER: u32;
+
+    /// Encodes the value as a field with tag `NUMBER`, including its key.
+    fn encode<B>(value: &Self::Value, buf: &mut B)
+    where
+        B: BufMut;
+
+    /// Decodes a single occurrence of the field, whose key has already been read, and merges it
+    /// into the value.
+    fn merge<B>(
+        wire_type: WireType,
+        value: &mut Self::Value,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        B: Buf;
+}
+
+/// The proto2 extensions set on a message.
+///
+/// Messages opt in to extensions by including a field of this type annotated with the tag ranges
+/// the message reserves for extensions, e.g. `#[prost(extensions = "1000 to max")]`. Fields with
+/// tags in those ranges are stored in the set while decoding, in their encoded form, and are
+/// re-emitted after all other known fields when the message is encoded. Individual extensions
+/// are decoded on access, with `get_extension`.
+///
+/// Two sets are equal if they hold the same encoded values for each extension.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExtensionSet {
+    fields: UnknownFieldList,
+}
+
+impl ExtensionSet {
+    /// Creates an empty `ExtensionSet`.
+    pub fn new() -> ExtensionSet {
+        ExtensionSet::default()
+    }
+
+    /// Returns `true` if no extensions are set.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Returns `true` if the extension is set.
+    pub fn has_extension<E>(&self) -> bool
+    where
+        E: Extension,
+    {
+        self.fields.iter().any(|(tag, _)| tag == E::NUMBER)
+    }
+
+    /// Decodes the value of the extension, or returns `None` if it is not set.
+    ///
+    /// An error is returned if the stored value can not be decoded as the extension's type, e.g.
+    /// because it was written with a different wire type.
+    pub fn get_extension<E>(&self) -> Result<Option<E::Value>, DecodeError>
+    where
+        E: Extension,
+    {
+        let mut occurrences = UnknownFieldList::new();
+        for (tag, field) in self.fields.iter().filter(|&(tag, _)| tag == E::NUMBER) {
+            occurrences.push(tag, field.clone());
+        }
+        if occurrences.is_empty() {
+            return Ok(None);
+        }
+
+        let mut buf = Vec::with_capacity(occurrences.encoded_len());
+        occurrences.encode_raw(&mut buf);
+        let mut buf = &buf[..];
+        let mut value = E::Value::default();
+        while buf.has_remaining() {
+            let (_, wire_type) = decode_key(&mut buf)?;
+            E::merge(wire_type, &mut value, &mut buf, DecodeContext::default())?;
+        }
+        Ok(Some(value))
+    }
+
+    /// Sets the value of the extension, replacing any existing value.
+    pub fn set_extension<E>(&mut self, value: &E::Value)
+    where
+        E: Extension,
+    {
+        self.clear_extension::<E>();
+
+        let mut buf = Vec::new();
+        E::encode(value, &mut buf);
+        let mut buf = &buf[..];
+        while buf.has_remaining() {
+            let (tag, wire_type) = decode_key(&mut buf).expect("invalid extension encoding");
+            debug_assert_eq!(tag, E::NUMBER);
+            self.fields
+                .merge_field(tag, wire_type, &mut buf, DecodeContext::default())
+                .expect("invalid extension encoding");
+        }
+    }
+
+    /// Removes the extension's value, if it is set.
+    pub fn clear_extension<E>(&mut self)
+    where
+        E: Extension,
+    {
+        self.fields.retain(|tag| tag != E::NUMBER);
+    }
+
+    /// Decodes an extension field from a buffer, and adds it to the set.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn merge_field<B>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        B: Buf,
+    {
+        self.fields.merge_field(tag, wire_type, buf, ctx)
+    }
+
+    /// Encodes the extensions to a buffer.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn encode_raw<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        self.fields.encode_raw(buf)
+    }
+
+    /// Encodes the extensions to a buffer in ascending order of field number.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn encode_canonical_raw<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        self.fields.encode_canonical_raw(buf)
+    }
+
+    /// Returns the encoded length of the extensions.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn encoded_len(&self) -> usize {
+        self.fields.encoded_len()
+    }
+
+    /// Reports the encoded length of each extension field to `record`, under its own field
+    /// number, see `Message::encode_with_stats`.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn field_lens(&self, path: &mut Vec<u32>, record: &mut dyn FnMut(&[u32], usize)) {
+        self.fields.field_lens(path, record)
+    }
+
+    /// Writes the extensions to a text format writer, identified by their field numbers.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn write_text_format(&self, writer: &mut TextFormatWriter) {
+        self.fields.write_text_format(writer)
+    }
+
+    /// Removes all extensions.
+    pub fn clear(&mut self) {
+        self.fields.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::{String, ToString};
+    use alloc::vec;
+
+    use crate::encoding::{int32, string};
+
+    use super::*;
+
+    struct Priority;
+
+    impl Extension for Priority {
+        type Value = i32;
+        const NUMBER: u32 = 1000;
+
+        fn encode<B: BufMut>(value: &i32, buf: &mut B) {
+            int32::encode(Self::NUMBER, value, buf);
+        }
+
+        fn merge<B: Buf>(
+            wire_type: WireType,
+            value: &mut i32,
+            buf: &mut B,
+            ctx: DecodeContext,
+        ) -> Result<(), DecodeError> {
+            int32::merge(wire_type, value, buf, ctx)
+        }
+    }
+
+    struct Labels;
+
+    impl Extension for Labels {
+        type Value = Vec<String>;
+        const NUMBER: u32 = 1001;
+
+        fn encode<B: BufMut>(value: &Vec<String>, buf: &mut B) {
+            string::encode_repeated(Self::NUMBER, value, buf);
+        }
+
+        fn merge<B: Buf>(
+            wire_type: WireType,
+            value: &mut Vec<String>,
+            buf: &mut B,
+            ctx: DecodeContext,
+        ) -> Result<(), DecodeError> {
+            string::merge_repeated(wire_type, value, buf, ctx)
+        }
+    }
+
+    /// Shares a field number with `Priority`, but with a different type.
+    struct PriorityName;
+
+    impl Extension for PriorityName {
+        type Value = String;
+        const NUMBER: u32 = 1000;
+
+        fn encode<B: BufMut>(value: &String, buf: &mut B) {
+            string::encode(Self::NUMBER, value, buf);
+        }
+
+        fn merge<B: Buf>(
+            wire_type: WireType,
+            value: &mut String,
+            buf: &mut B,
+            ctx: DecodeContext,
+        ) -> Result<(), DecodeError> {
+            string::merge(wire_type, value, buf, ctx)
+        }
+    }
+
+    #[test]
+    fn typed_access() {
+        let mut extensions = ExtensionSet::new();
+        assert!(extensions.is_empty());
+        assert_eq!(extensions.get_extension::<Priority>().unwrap(), None);
+
+        // A set default value is distinct from an unset one.
+        extensions.set_extension::<Priority>(&0);
+        assert!(extensions.has_extension::<Priority>());
+        assert_eq!(extensions.get_extension::<Priority>().unwrap(), Some(0));
+
+        extensions.set_extension::<Priority>(&-7);
+        let labels = vec!["a".to_string(), "b".to_string()];
+        extensions.set_extension::<Labels>(&labels);
+        assert_eq!(extensions.get_extension::<Priority>().unwrap(), Some(-7));
+        assert_eq!(extensions.get_extension::<Labels>().unwrap(), Some(labels));
+
+        extensions
+            .get_extension::<PriorityName>()
+            .expect_err("wrong wire type");
+
+        extensions.clear_extension::<Priority>();
+        assert!(!extensions.has_extension::<Priority>());
+        assert!(extensions.has_extension::<Labels>());
+        extensions.clear();
+        assert!(extensions.is_empty());
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut extensions = ExtensionSet::new();
+        extensions.set_extension::<Priority>(&3);
+        let mut buf = Vec::new();
+        extensions.encode_raw(&mut buf);
+        assert_eq!(buf.len(), extensions.encoded_len());
+
+        let mut decoded = ExtensionSet::new();
+        let mut slice = &buf[..];
+        while slice.has_remaining() {
+            let (tag, wire_type) = decode_key(&mut slice).unwrap();
+            decoded
+                .merge_field(tag, wire_type, &mut slice, DecodeContext::default())
+                .unwrap();
+        }
+        assert_eq!(decoded, extensions);
+    }
+}
@@ -0,0 +1,250 @@
+//! Encoding and decoding single fields, outside of a message struct.
+//!
+//! `encode_field` writes one field, its key followed by its value, to a buffer. Concatenating
+//! encoded fields produces a valid message, so messages holding only some of their fields, such
+//! as sparse updates, can be built field by field without a struct declaring them. Since a Rust
+//! type may be encoded as several Protobuf types, e.g. an `i32` as an `int32`, `sint32` or
+//! `sfixed32`, the Protobuf type is named by one of the marker types of this module:
+//!
+//! ```rust
+//! use prost::field::{self, Sint32};
+//! use prost::Message;
+//!
+//! #[derive(Clone, PartialEq, Message)]
+//! struct Account {
+//!     #[prost(string, tag = "1")]
+//!     name: String,
+//!     #[prost(sint32, tag = "2")]
+//!     balance: i32,
+//!     #[prost(string, tag = "3")]
+//!     owner: String,
+//! }
+//!
+//! // An update which sets only the balance and the owner.
+//! let mut update = Vec::new();
+//! prost::encode_field::<Sint32, _>(2, &-10, &mut update);
+//! prost::encode_field::<field::String, _>(3, &"root".to_owned(), &mut update);
+//! assert_eq!(update.len(), prost::encoded_len_field::<Sint32>(2, &-10) + 6);
+//!
+//! let mut account = Account { name: "checking".into(), ..Account::default() };
+//! account.merge(&*update).unwrap();
+//! assert_eq!(account.balance, -10);
+//! assert_eq!(account.owner, "root");
+//! assert_eq!(account.name, "checking");
+//! ```
+//!
+//! Enumeration fields are encoded as `Int32` fields holding the `i32` value of the enumeration.
+//!
+//! `merge_field` decodes one field whose key has already been read with `decode_key`. The key's
+//! `WireType` and the `DecodeContext` it takes are re-exported here, since the `encoding` module
+//! they are defined in is not part of the public API.
+
+use core::marker::PhantomData;
+
+use ::bytes::{Buf, BufMut};
+
+use crate::encoding::{self, BytesAdapter};
+pub use crate::encoding::{decode_key, DecodeContext, WireType};
+use crate::DecodeError;
+
+/// A Protobuf field type, implemented by the marker types of this module.
+pub trait Field {
+    /// The Rust type of the field's values.
+    type Value;
+
+    /// The wire type of a single encoded value of the field.
+    const WIRE_TYPE: WireType;
+
+    /// Encodes the value as a field with the tag, including its key.
+    fn encode<B>(tag: u32, value: &Self::Value, buf: &mut B)
+    where
+        B: BufMut;
+
+    /// Decodes a single occurrence of the field, whose key has already been read, and merges it
+    /// into the value.
+    fn merge<B>(
+        wire_type: WireType,
+        value: &mut Self::Value,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        B: Buf;
+
+    /// Returns the encoded length of the value as a field with the tag, including its key.
+    fn encoded_len(tag: u32, value: &Self::Value) -> usize;
+}
+
+macro_rules! scalar_field {
+    ($name:ident, $module:ident, $ty:ty, $proto:literal) => {
+        scalar_field!(
+            #[doc = concat!("The `", $proto, "` field type, whose values are `", stringify!($ty), "`.")]
+            $name, $module, $ty
+        );
+    };
+
+    ($(#[$attr:meta])* $name:ident, $module:ident, $ty:ty) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug)]
+        pub struct $name;
+
+        impl Field for $name {
+            type Value = $ty;
+
+            const WIRE_TYPE: WireType = encoding::$module::WIRE_TYPE;
+
+            fn encode<B>(tag: u32, value: &$ty, buf: &mut B)
+            where
+                B: BufMut,
+            {
+                encoding::$module::encode(tag, value, buf)
+            }
+
+            fn merge<B>(
+                wire_type: WireType,
+                value: &mut $ty,
+                buf: &mut B,
+                ctx: DecodeContext,
+            ) -> Result<(), DecodeError>
+            where
+                B: Buf,
+            {
+                encoding::$module::merge(wire_type, value, buf, ctx)
+            }
+
+            fn encoded_len(tag: u32, value: &$ty) -> usize {
+                encoding::$module::encoded_len(tag, value)
+            }
+        }
+    };
+}
+
+scalar_field!(Int32, int32, i32, "int32");
+scalar_field!(Int64, int64, i64, "int64");
+scalar_field!(Uint32, uint32, u32, "uint32");
+scalar_field!(Uint64, uint64, u64, "uint64");
+scalar_field!(Sint32, sint32, i32, "sint32");
+scalar_field!(Sint64, sint64, i64, "sint64");
+scalar_field!(Fixed32, fixed32, u32, "fixed32");
+scalar_field!(Fixed64, fixed64, u64, "fixed64");
+scalar_field!(Sfixed32, sfixed32, i32, "sfixed32");
+scalar_field!(Sfixed64, sfixed64, i64, "sfixed64");
+scalar_field!(Float, float, f32, "float");
+scalar_field!(Double, double, f64, "double");
+scalar_field!(Bool, bool, bool, "bool");
+scalar_field!(String, string, alloc::string::String, "string");
+scalar_field!(
+    /// The `uint32` field type, whose values are `char`s, encoded as their code points. Decoding
+    /// fails unless the value is a Unicode scalar value.
+    Char,
+    char_uint32,
+    char
+);
+
+/// The `bytes` field type, whose values are `Vec<u8>`, or another `BytesAdapter` such as `Bytes`.
+#[derive(Clone, Copy, Debug)]
+pub struct Bytes<T = alloc::vec::Vec<u8>>(PhantomData<T>);
+
+impl<T> Field for Bytes<T>
+where
+    T: BytesAdapter,
+{
+    type Value = T;
+
+    const WIRE_TYPE: WireType = encoding::bytes::WIRE_TYPE;
+
+    fn encode<B>(tag: u32, value: &T, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        encoding::bytes::encode(tag, value, buf)
+    }
+
+    fn merge<B>(
+        wire_type: WireType,
+        value: &mut T,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        B: Buf,
+    {
+        encoding::bytes::merge(wire_type, value, buf, ctx)
+    }
+
+    fn encoded_len(tag: u32, value: &T) -> usize {
+        encoding::bytes::encoded_len(tag, value)
+    }
+}
+
+/// The field type of embedded messages of type `M`.
+#[derive(Clone, Copy, Debug)]
+pub struct Message<M>(PhantomData<M>);
+
+impl<M> Field for Message<M>
+where
+    M: crate::Message,
+{
+    type Value = M;
+
+    const WIRE_TYPE: WireType = encoding::message::WIRE_TYPE;
+
+    fn encode<B>(tag: u32, value: &M, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        encoding::message::encode(tag, value, buf)
+    }
+
+    fn merge<B>(
+        wire_type: WireType,
+        value: &mut M,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        B: Buf,
+    {
+        encoding::message::merge(wire_type, value, buf, ctx)
+    }
+
+    fn encoded_len(tag: u32, value: &M) -> usize {
+        encoding::message::encoded_len(tag, value)
+    }
+}
+
+/// Encodes the value as a field of type `F` with the tag, including its key.
+pub fn encode_field<F, B>(tag: u32, value: &F::Value, buf: &mut B)
+where
+    F: Field,
+    B: BufMut,
+{
+    F::encode(tag, value, buf)
+}
+
+/// Returns the encoded length of the value as a field of type `F` with the tag, including its
+/// key.
+pub fn encoded_len_field<F>(tag: u32, value: &F::Value) -> usize
+where
+    F: Field,
+{
+    F::encoded_len(tag, value)
+}
+
+/// Decodes a single occurrence of a field of type `F`, whose key has already been read, e.g.
+/// with `decode_key`, and merges it into the value.
+///
+/// As when decoding a message, a scalar value replaces the previous one, and a message is merged
+/// into it.
+pub fn merge_field<F, B>(
+    wire_type: WireType,
+    value: &mut F::Value,
+    buf: &mut B,
+    ctx: DecodeContext,
+) -> Result<(), DecodeError>
+where
+    F: Field,
+    B: Buf,
+{
+    F::merge(wire_type, value, buf, ctx)
+}
@@ -0,0 +1,155 @@
+//! Hashing of a message's encoding without buffering it.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::hash::Hasher;
+
+use bytes::buf::UninitSlice;
+use bytes::BufMut;
+
+/// The number of bytes buffered before they are written to the hasher.
+const CHUNK_LEN: usize = 256;
+
+/// A `BufMut` which feeds the bytes put into it to a `Hasher`, rather than storing them.
+///
+/// This computes the hash of a message's encoding in a single pass, e.g. for content-addressed
+/// storage, without encoding the message to a `Vec` first. Combined with
+/// `Message::encode_canonical`, equal messages always produce equal hashes:
+///
+/// ```rust
+/// use std::collections::hash_map::DefaultHasher;
+/// use std::hash::Hasher;
+///
+/// use prost::{HashingBuf, Message};
+///
+/// let message = "content".to_string();
+/// let mut buf = HashingBuf::new(DefaultHasher::new());
+/// message.encode_canonical(&mut buf).unwrap();
+/// let hash = buf.into_inner().finish();
+///
+/// let mut hasher = DefaultHasher::new();
+/// hasher.write(&message.encode_canonical_to_vec());
+/// assert_eq!(hash, hasher.finish());
+/// ```
+///
+/// The bytes are passed to `Hasher::write` in chunks of a fixed size, so the sequence of calls
+/// depends only on the bytes written, not on how the encoder wrote them. The hash is the same as
+/// that of the encoded bytes written to the hasher in one call, provided the hasher, like
+/// `DefaultHasher`, treats its input as a stream of bytes.
+///
+/// Digests which implement `std::io::Write` can instead be passed to
+/// `Message::encode_to_writer`.
+pub struct HashingBuf<H> {
+    hasher: H,
+    buf: Vec<u8>,
+}
+
+impl<H> HashingBuf<H>
+where
+    H: Hasher,
+{
+    /// Creates a `HashingBuf` which writes to `hasher`.
+    pub fn new(hasher: H) -> HashingBuf<H> {
+        HashingBuf {
+            hasher,
+            buf: Vec::with_capacity(CHUNK_LEN),
+        }
+    }
+
+    /// Writes any buffered bytes to the hasher, and returns it.
+    pub fn into_inner(mut self) -> H {
+        self.flush_buf();
+        self.hasher
+    }
+
+    fn flush_buf(&mut self) {
+        if !self.buf.is_empty() {
+            self.hasher.write(&self.buf);
+            self.buf.clear();
+        }
+    }
+}
+
+unsafe impl<H> BufMut for HashingBuf<H>
+where
+    H: Hasher,
+{
+    fn remaining_mut(&self) -> usize {
+        usize::MAX
+    }
+
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        assert!(
+            cnt <= CHUNK_LEN - self.buf.len(),
+            "cannot advance past the end of the chunk"
+        );
+        self.buf.advance_mut(cnt)
+    }
+
+    fn chunk_mut(&mut self) -> &mut UninitSlice {
+        if self.buf.len() == CHUNK_LEN {
+            self.flush_buf();
+        }
+        let len = CHUNK_LEN - self.buf.len();
+        &mut self.buf.chunk_mut()[..len]
+    }
+}
+
+impl<H> fmt::Debug for HashingBuf<H>
+where
+    H: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HashingBuf")
+            .field("hasher", &self.hasher)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Records every call to `write`.
+    #[derive(Default)]
+    struct Recorder {
+        writes: Vec<Vec<u8>>,
+    }
+
+    impl Hasher for Recorder {
+        fn write(&mut self, bytes: &[u8]) {
+            self.writes.push(bytes.to_vec());
+        }
+
+        fn finish(&self) -> u64 {
+            0
+        }
+    }
+
+    #[test]
+    fn chunks_are_independent_of_writes() {
+        let data: Vec<u8> = (0..CHUNK_LEN * 2 + 3).map(|i| i as u8).collect();
+
+        let mut whole = HashingBuf::new(Recorder::default());
+        whole.put_slice(&data);
+
+        let mut pieces = HashingBuf::new(Recorder::default());
+        pieces.put_u8(data[0]);
+        pieces.put_slice(&data[1..10]);
+        for &b in &data[10..CHUNK_LEN + 1] {
+            pieces.put_u8(b);
+        }
+        pieces.put_slice(&data[CHUNK_LEN + 1..]);
+
+        let whole = whole.into_inner().writes;
+        assert_eq!(whole, pieces.into_inner().writes);
+        assert_eq!(whole.len(), 3);
+        assert_eq!(whole.concat(), data);
+    }
+
+    #[test]
+    fn empty() {
+        let buf = HashingBuf::new(Recorder::default());
+        assert_eq!(buf.into_inner().writes, Vec::<Vec<u8>>::new());
+    }
+}
@@ -0,0 +1,92 @@
+//! Sharing the storage of identical decoded strings.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// A set of strings, shared by the `string = "arc"` fields decoded with a `DecodeContext` holding
+/// it, see `DecodeContext::with_interner`.
+///
+/// Each string decoded into an `Arc<str>` field is looked up in the set before it is allocated:
+/// a string which was decoded before is a clone of the same `Arc`, and a new string is added to
+/// the set. This saves memory when the same strings, such as label keys, occur again and again.
+///
+/// The set is never pruned, so it holds every distinct string decoded with it until it is cleared
+/// or dropped. Cloning an `Interner` returns a handle to the same set.
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use prost::encoding::DecodeContext;
+/// use prost::{Interner, Message};
+///
+/// #[derive(Clone, PartialEq, Message)]
+/// struct Labels {
+///     #[prost(string = "arc", repeated, tag = "1")]
+///     keys: Vec<Arc<str>>,
+/// }
+///
+/// let encoded = Labels { keys: vec!["host".into(), "host".into()] }.encode_to_vec();
+///
+/// let interner = Interner::new();
+/// let mut labels = Labels::default();
+/// labels
+///     .merge_with_context(&*encoded, DecodeContext::default().with_interner(&interner))
+///     .unwrap();
+/// assert!(Arc::ptr_eq(&labels.keys[0], &labels.keys[1]));
+/// assert_eq!(interner.len(), 1);
+/// ```
+#[derive(Clone, Default)]
+pub struct Interner {
+    strings: Arc<Mutex<HashSet<Arc<str>>>>,
+}
+
+impl Interner {
+    /// Creates an empty `Interner`.
+    pub fn new() -> Interner {
+        Interner::default()
+    }
+
+    /// Returns the string of the set equal to `value`, adding it to the set if there is none.
+    pub fn intern(&self, value: &str) -> Arc<str> {
+        let mut strings = self.strings();
+        match strings.get(value) {
+            Some(interned) => interned.clone(),
+            None => {
+                let interned = Arc::<str>::from(value);
+                strings.insert(interned.clone());
+                interned
+            }
+        }
+    }
+
+    /// Returns the number of distinct strings in the set.
+    pub fn len(&self) -> usize {
+        self.strings().len()
+    }
+
+    /// Returns `true` if the set holds no strings.
+    pub fn is_empty(&self) -> bool {
+        self.strings().is_empty()
+    }
+
+    /// Removes every string from the set. Decoded fields keep their strings.
+    pub fn clear(&self) {
+        self.strings().clear()
+    }
+
+    fn strings(&self) -> std::sync::MutexGuard<'_, HashSet<Arc<str>>> {
+        // The set is valid even if a thread panicked while holding the lock.
+        self.strings
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl fmt::Debug for Interner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interner")
+            .field("len", &self.len())
+            .finish()
+    }
+}
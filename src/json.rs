@@ -0,0 +1,373 @@
+//! Serialization of messages to the Protobuf JSON format.
+//!
+//! Messages are written following the [canonical JSON mapping] for proto3:
+//!
+//!  * Fields are named by the lowerCamelCase form of their Protobuf name, unless a name is set
+//!    with the `#[prost(json_name = "...")]` field attribute, and fields which hold their default
+//!    value are omitted.
+//!  * 64-bit integers are written as decimal strings, since they can not be represented exactly
+//!    by a JavaScript number.
+//!  * `float` and `double` values are written as numbers, except for `"NaN"`, `"Infinity"` and
+//!    `"-Infinity"`.
+//!  * `bytes` values are written as padded, standard base64 strings.
+//!  * Enumeration values are written as the name of the variant, or as a number if the value is
+//!    not a known variant.
+//!  * Maps are written as objects, with the keys converted to strings.
+//!  * The well-known wrapper types implemented by `prost`, e.g. `u32` for `UInt32Value`, are
+//!    written as their wrapped value.
+//!
+//! [canonical JSON mapping]: https://developers.google.com/protocol-buffers/docs/proto3#json
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::fmt::{self, Write};
+
+use crate::EnumName;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// A message which can be serialized to the proto3 JSON mapping.
+///
+/// This is implemented by `#[derive(Message)]`, and for the well-known wrapper types implemented
+/// by `prost`. Hand-written messages may implement it to be serialized, or to be embedded in
+/// derived messages which are.
+pub trait ToJson {
+    /// Writes the message to a JSON writer, as a single JSON value.
+    fn write_json(&self, writer: &mut JsonWriter);
+
+    /// Serializes the message to a string, following the proto3 JSON mapping.
+    fn to_json(&self) -> String {
+        let mut writer = JsonWriter::new();
+        self.write_json(&mut writer);
+        writer.into_string()
+    }
+}
+
+impl<M> ToJson for Box<M>
+where
+    M: ToJson,
+{
+    fn write_json(&self, writer: &mut JsonWriter) {
+        (**self).write_json(writer)
+    }
+}
+
+impl<M> ToJson for Arc<M>
+where
+    M: ToJson,
+{
+    fn write_json(&self, writer: &mut JsonWriter) {
+        (**self).write_json(writer)
+    }
+}
+
+/// A writer which serializes messages to a JSON string.
+///
+/// Messages drive the writer from `ToJson::write_json`, which most callers reach through
+/// `ToJson::to_json`. The writer inserts the separators between object members
+/// and array elements, so every value is written with a single method call.
+#[derive(Debug, Default)]
+pub struct JsonWriter {
+    buf: String,
+    /// Whether a value has been written to the current object or array, i.e. whether the next
+    /// member or element must be preceded by a comma.
+    needs_comma: bool,
+}
+
+impl JsonWriter {
+    /// Creates an empty `JsonWriter`.
+    pub fn new() -> JsonWriter {
+        JsonWriter::default()
+    }
+
+    /// Returns the JSON written so far.
+    pub fn as_str(&self) -> &str {
+        &self.buf
+    }
+
+    /// Consumes the writer, returning the JSON written to it.
+    pub fn into_string(self) -> String {
+        self.buf
+    }
+
+    /// Begins a JSON object.
+    pub fn begin_object(&mut self) {
+        self.separate();
+        self.buf.push('{');
+        self.needs_comma = false;
+    }
+
+    /// Ends the current JSON object.
+    pub fn end_object(&mut self) {
+        self.buf.push('}');
+        self.needs_comma = true;
+    }
+
+    /// Begins a JSON array.
+    pub fn begin_array(&mut self) {
+        self.separate();
+        self.buf.push('[');
+        self.needs_comma = false;
+    }
+
+    /// Ends the current JSON array.
+    pub fn end_array(&mut self) {
+        self.buf.push(']');
+        self.needs_comma = true;
+    }
+
+    /// Writes the name of an object member. The member's value must be written next.
+    pub fn key(&mut self, key: &str) {
+        self.separate();
+        write_escaped(&mut self.buf, key);
+        self.buf.push(':');
+        self.needs_comma = false;
+    }
+
+    /// Writes the name of an object member from its `Display` form, e.g. an integer map key.
+    pub fn key_display<T>(&mut self, key: T)
+    where
+        T: fmt::Display,
+    {
+        let mut name = String::new();
+        write!(name, "{}", key).unwrap();
+        self.key(&name);
+    }
+
+    /// Writes a `bool` value.
+    pub fn write_bool(&mut self, value: bool) {
+        self.raw(if value { "true" } else { "false" });
+    }
+
+    /// Writes an `int32`, `sint32` or `sfixed32` value.
+    pub fn write_i32(&mut self, value: i32) {
+        self.display(value);
+    }
+
+    /// Writes a `uint32` or `fixed32` value.
+    pub fn write_u32(&mut self, value: u32) {
+        self.display(value);
+    }
+
+    /// Writes an `int64`, `sint64` or `sfixed64` value, as a string.
+    pub fn write_i64(&mut self, value: i64) {
+        self.quoted(value);
+    }
+
+    /// Writes a `uint64` or `fixed64` value, as a string.
+    pub fn write_u64(&mut self, value: u64) {
+        self.quoted(value);
+    }
+
+    /// Writes a `float` value.
+    pub fn write_f32(&mut self, value: f32) {
+        if value.is_finite() {
+            self.display(value);
+        } else {
+            self.write_f64(f64::from(value));
+        }
+    }
+
+    /// Writes a `double` value.
+    pub fn write_f64(&mut self, value: f64) {
+        if value.is_nan() {
+            self.write_str("NaN");
+        } else if value == f64::INFINITY {
+            self.write_str("Infinity");
+        } else if value == f64::NEG_INFINITY {
+            self.write_str("-Infinity");
+        } else {
+            self.display(value);
+        }
+    }
+
+    /// Writes a `string` value.
+    pub fn write_str(&mut self, value: &str) {
+        self.separate();
+        write_escaped(&mut self.buf, value);
+        self.needs_comma = true;
+    }
+
+    /// Writes a `bytes` value, as a base64 string.
+    pub fn write_bytes(&mut self, value: &[u8]) {
+        self.separate();
+        self.buf.push('"');
+        for chunk in value.chunks(3) {
+            let b = [
+                chunk[0],
+                chunk.get(1).copied().unwrap_or(0),
+                chunk.get(2).copied().unwrap_or(0),
+            ];
+            let indices = [
+                b[0] >> 2,
+                (b[0] & 0x03) << 4 | b[1] >> 4,
+                (b[1] & 0x0f) << 2 | b[2] >> 6,
+                b[2] & 0x3f,
+            ];
+            for (i, &index) in indices.iter().enumerate() {
+                if i <= chunk.len() {
+                    self.buf
+                        .push(char::from(BASE64_ALPHABET[usize::from(index)]));
+                } else {
+                    self.buf.push('=');
+                }
+            }
+        }
+        self.buf.push('"');
+        self.needs_comma = true;
+    }
+
+    /// Writes the value of an enumeration field, as the name of the variant if it is known.
+    pub fn write_enum<E>(&mut self, value: i32)
+    where
+        E: EnumName,
+    {
+        match E::proto_name(value) {
+            Some(name) => self.write_str(name),
+            None => self.write_i32(value),
+        }
+    }
+
+    /// Writes a message value.
+    pub fn write_message<M>(&mut self, message: &M)
+    where
+        M: ToJson,
+    {
+        message.write_json(self);
+    }
+
+    /// Writes a JSON `null`.
+    pub fn write_null(&mut self) {
+        self.raw("null");
+    }
+
+    fn separate(&mut self) {
+        if self.needs_comma {
+            self.buf.push(',');
+        }
+    }
+
+    fn raw(&mut self, value: &str) {
+        self.separate();
+        self.buf.push_str(value);
+        self.needs_comma = true;
+    }
+
+    fn display<T>(&mut self, value: T)
+    where
+        T: fmt::Display,
+    {
+        self.separate();
+        write!(self.buf, "{}", value).unwrap();
+        self.needs_comma = true;
+    }
+
+    fn quoted<T>(&mut self, value: T)
+    where
+        T: fmt::Display,
+    {
+        self.separate();
+        write!(self.buf, "\"{}\"", value).unwrap();
+        self.needs_comma = true;
+    }
+}
+
+/// Writes `value` as a quoted JSON string.
+fn write_escaped(buf: &mut String, value: &str) {
+    buf.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            '\u{08}' => buf.push_str("\\b"),
+            '\u{0c}' => buf.push_str("\\f"),
+            c if c < ' ' => write!(buf, "\\u{:04x}", c as u32).unwrap(),
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write<F>(f: F) -> String
+    where
+        F: FnOnce(&mut JsonWriter),
+    {
+        let mut writer = JsonWriter::new();
+        f(&mut writer);
+        writer.into_string()
+    }
+
+    #[test]
+    fn separators() {
+        let json = write(|w| {
+            w.begin_object();
+            w.key("a");
+            w.write_i32(1);
+            w.key("b");
+            w.begin_array();
+            w.write_bool(true);
+            w.begin_object();
+            w.end_object();
+            w.write_null();
+            w.end_array();
+            w.key("c");
+            w.begin_object();
+            w.key_display(-7);
+            w.write_str("x");
+            w.end_object();
+            w.end_object();
+        });
+        assert_eq!(json, r#"{"a":1,"b":[true,{},null],"c":{"-7":"x"}}"#);
+    }
+
+    #[test]
+    fn numbers() {
+        let json = write(|w| {
+            w.begin_array();
+            w.write_i32(i32::MIN);
+            w.write_u32(u32::MAX);
+            w.write_i64(i64::MIN);
+            w.write_u64(u64::MAX);
+            w.write_f32(1.5);
+            w.write_f64(-0.25);
+            w.write_f64(f64::NAN);
+            w.write_f32(f32::INFINITY);
+            w.write_f64(f64::NEG_INFINITY);
+            w.end_array();
+        });
+        assert_eq!(
+            json,
+            r#"[-2147483648,4294967295,"-9223372036854775808","18446744073709551615",1.5,-0.25,"NaN","Infinity","-Infinity"]"#
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let json = write(|w| w.write_str("a\"b\\c\n\u{1}\u{7f}é"));
+        assert_eq!(json, "\"a\\\"b\\\\c\\n\\u0001\u{7f}é\"");
+    }
+
+    #[test]
+    fn bytes() {
+        let cases: &[(&[u8], &str)] = &[
+            (b"", r#""""#),
+            (b"f", r#""Zg==""#),
+            (b"fo", r#""Zm8=""#),
+            (b"foo", r#""Zm9v""#),
+            (b"foob", r#""Zm9vYg==""#),
+            (&[0xfb, 0xff, 0xbf], r#""+/+/""#),
+        ];
+        for &(value, expected) in cases {
+            assert_eq!(write(|w| w.write_bytes(value)), expected);
+        }
+    }
+}
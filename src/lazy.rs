@@ -0,0 +1,386 @@
+//! Embedded messages which are decoded on demand.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::encoding::{DecodeContext, WireType};
+use crate::json::{JsonWriter, ToJson};
+use crate::reflect::{FieldVisitor, Reflect};
+use crate::text_format::{TextFormatWriter, ToTextFormat};
+use crate::{DebugBytes, DecodeError, Message};
+
+/// An embedded message which is kept in its encoded form until it is accessed.
+///
+/// Services which forward messages often never inspect some of their embedded messages. Using
+/// `LazyField<M>` in place of `M` as the type of a message field, e.g.
+/// `#[prost(message, optional, tag = "2")] pub payload: Option<LazyField<Payload>>`, defers
+/// decoding of the embedded message: merging the field only stores its encoded bytes, without
+/// parsing them, and encoding the field writes the stored bytes verbatim. When the outer message
+/// is decoded from a `Bytes` buffer, the stored bytes share its allocation.
+///
+/// The message is decoded by the first call to `get_mut` or `into_inner`, after which the field
+/// behaves like a field of type `M`. `decode` returns a decoded copy, leaving the field untouched.
+/// Since an untouched field is not parsed, malformed content is only detected once it is
+/// decoded, and it is written out unchanged otherwise, including by `Message::encode_canonical`.
+///
+/// Two fields are equal if they hold equal bytes, or else decode to equal messages.
+#[derive(Clone)]
+pub struct LazyField<M> {
+    state: State<M>,
+}
+
+#[derive(Clone)]
+enum State<M> {
+    Encoded(Bytes),
+    Decoded(M),
+}
+
+impl<M> LazyField<M>
+where
+    M: Message + Default,
+{
+    /// Creates a `LazyField` holding a decoded message.
+    pub fn new(message: M) -> LazyField<M> {
+        LazyField {
+            state: State::Decoded(message),
+        }
+    }
+
+    /// Creates a `LazyField` holding the encoding of a message, without a length delimiter.
+    pub fn from_encoded(bytes: Bytes) -> LazyField<M> {
+        LazyField {
+            state: State::Encoded(bytes),
+        }
+    }
+
+    /// Returns `true` if the message has been decoded.
+    pub fn is_decoded(&self) -> bool {
+        matches!(self.state, State::Decoded(_))
+    }
+
+    /// Returns the encoding of the message, if it has not been decoded.
+    pub fn encoded(&self) -> Option<&Bytes> {
+        match self.state {
+            State::Encoded(ref bytes) => Some(bytes),
+            State::Decoded(_) => None,
+        }
+    }
+
+    /// Returns a decoded copy of the message.
+    pub fn decode(&self) -> Result<M, DecodeError>
+    where
+        M: Clone,
+    {
+        match self.state {
+            State::Encoded(ref bytes) => M::decode(bytes.clone()),
+            State::Decoded(ref message) => Ok(message.clone()),
+        }
+    }
+
+    /// Returns a mutable reference to the message, decoding it first if necessary.
+    ///
+    /// If decoding fails, the error is returned and the field keeps its encoded form.
+    pub fn get_mut(&mut self) -> Result<&mut M, DecodeError> {
+        if let State::Encoded(ref bytes) = self.state {
+            self.state = State::Decoded(M::decode(bytes.clone())?);
+        }
+        match self.state {
+            State::Decoded(ref mut message) => Ok(message),
+            State::Encoded(_) => unreachable!(),
+        }
+    }
+
+    /// Consumes the field, returning the decoded message.
+    pub fn into_inner(self) -> Result<M, DecodeError> {
+        match self.state {
+            State::Encoded(bytes) => M::decode(bytes),
+            State::Decoded(message) => Ok(message),
+        }
+    }
+}
+
+impl<M> Default for LazyField<M> {
+    fn default() -> LazyField<M> {
+        LazyField {
+            state: State::Encoded(Bytes::new()),
+        }
+    }
+}
+
+impl<M> From<M> for LazyField<M>
+where
+    M: Message + Default,
+{
+    fn from(message: M) -> LazyField<M> {
+        LazyField::new(message)
+    }
+}
+
+impl<M> Message for LazyField<M>
+where
+    M: Message + Default + Clone,
+{
+    fn encode_raw<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        match self.state {
+            State::Encoded(ref bytes) => buf.put_slice(bytes),
+            State::Decoded(ref message) => message.encode_raw(buf),
+        }
+    }
+
+    fn encode_cached_raw<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        match self.state {
+            State::Encoded(ref bytes) => buf.put_slice(bytes),
+            State::Decoded(ref message) => message.encode_cached_raw(buf),
+        }
+    }
+
+    fn encode_canonical_raw<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        match self.state {
+            State::Encoded(ref bytes) => buf.put_slice(bytes),
+            State::Decoded(ref message) => message.encode_canonical_raw(buf),
+        }
+    }
+
+    fn merge_field<B>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        B: Buf,
+    {
+        self.get_mut()?.merge_field(tag, wire_type, buf, ctx)
+    }
+
+    fn field_tag(tag: u32) -> u32 {
+        M::field_tag(tag)
+    }
+
+    fn merge_delimited<B>(&mut self, buf: &mut B, ctx: DecodeContext) -> Result<(), DecodeError>
+    where
+        B: Buf,
+    {
+        match self.state {
+            State::Encoded(ref mut bytes) => {
+                let len = ctx.decode_len(buf)?;
+                let tail = buf.copy_to_bytes(len);
+                if bytes.is_empty() {
+                    *bytes = tail;
+                } else if !tail.is_empty() {
+                    // Concatenating the encodings of a message merges them.
+                    let mut joined = BytesMut::with_capacity(bytes.len() + tail.len());
+                    joined.extend_from_slice(bytes);
+                    joined.extend_from_slice(&tail);
+                    *bytes = joined.freeze();
+                }
+                Ok(())
+            }
+            State::Decoded(ref mut message) => message.merge_delimited(buf, ctx),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        match self.state {
+            State::Encoded(ref bytes) => bytes.len(),
+            State::Decoded(ref message) => message.encoded_len(),
+        }
+    }
+
+    fn cached_encoded_len(&self) -> usize {
+        match self.state {
+            State::Encoded(ref bytes) => bytes.len(),
+            State::Decoded(ref message) => message.cached_encoded_len(),
+        }
+    }
+
+    fn field_lens(&self, path: &mut Vec<u32>, record: &mut dyn FnMut(&[u32], usize)) {
+        match self.state {
+            State::Encoded(ref bytes) => {
+                if let Ok(message) = M::decode(bytes.clone()) {
+                    message.field_lens(path, record);
+                }
+            }
+            State::Decoded(ref message) => message.field_lens(path, record),
+        }
+    }
+
+    fn check_initialized(&self) -> Result<(), DecodeError> {
+        match self.state {
+            State::Encoded(ref bytes) => M::decode(bytes.clone())?.check_initialized(),
+            State::Decoded(ref message) => message.check_initialized(),
+        }
+    }
+
+    fn is_default(&self) -> bool {
+        match self.state {
+            State::Encoded(ref bytes) => {
+                bytes.is_empty() || M::decode(bytes.clone()).map_or(false, |m| m.is_default())
+            }
+            State::Decoded(ref message) => message.is_default(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.state = State::Encoded(Bytes::new());
+    }
+}
+
+impl<M> ToJson for LazyField<M>
+where
+    M: Message + Default + Clone + ToJson,
+{
+    fn write_json(&self, writer: &mut JsonWriter) {
+        match self.decode() {
+            Ok(message) => message.write_json(writer),
+            Err(_) => writer.write_null(),
+        }
+    }
+}
+
+impl<M> ToTextFormat for LazyField<M>
+where
+    M: Message + Default + Clone + ToTextFormat,
+{
+    fn write_text_format(&self, writer: &mut TextFormatWriter) {
+        if let Ok(message) = self.decode() {
+            message.write_text_format(writer);
+        }
+    }
+}
+
+impl<M> Reflect for LazyField<M>
+where
+    M: Message + Default + Clone + Reflect,
+{
+    fn reflect(&self, visitor: &mut dyn FieldVisitor) {
+        if let Ok(message) = self.decode() {
+            message.reflect(visitor);
+        }
+    }
+}
+
+impl<M> PartialEq for LazyField<M>
+where
+    M: Message + Default + Clone + PartialEq,
+{
+    fn eq(&self, other: &LazyField<M>) -> bool {
+        if let (State::Encoded(a), State::Encoded(b)) = (&self.state, &other.state) {
+            if a == b {
+                return true;
+            }
+        }
+        match (self.decode(), other.decode()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl<M> fmt::Debug for LazyField<M>
+where
+    M: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.state {
+            State::Encoded(ref bytes) => f
+                .debug_tuple("LazyField")
+                .field(&DebugBytes(bytes))
+                .finish(),
+            State::Decoded(ref message) => f.debug_tuple("LazyField").field(message).finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+
+    use crate::encoding::{message, string};
+
+    use super::*;
+
+    /// Encodes `value` as the string field 1 of an embedded message, as field 2.
+    fn embedded(value: &str) -> Vec<u8> {
+        let mut inner = Vec::new();
+        string::encode(1, &value.to_string(), &mut inner);
+        let mut buf = Vec::new();
+        crate::encoding::bytes::encode(2, &inner, &mut buf);
+        buf
+    }
+
+    fn merge(field: &mut LazyField<String>, buf: &[u8]) -> Result<(), DecodeError> {
+        let mut buf = Bytes::copy_from_slice(buf);
+        while buf.has_remaining() {
+            let (_, wire_type) = crate::encoding::decode_key(&mut buf)?;
+            message::merge(wire_type, field, &mut buf, DecodeContext::default())?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn deferred_decoding() {
+        let mut field = LazyField::<String>::default();
+        merge(&mut field, &embedded("hello")).unwrap();
+        assert!(!field.is_decoded());
+        assert_eq!(
+            field.encoded().unwrap()[..],
+            [0x0a, 0x05, b'h', b'e', b'l', b'l', b'o']
+        );
+        assert_eq!(field.encoded_len(), 7);
+
+        // The stored bytes are written out verbatim.
+        let mut buf = Vec::new();
+        message::encode(2, &field, &mut buf);
+        assert_eq!(buf, embedded("hello"));
+
+        assert_eq!(field.decode().unwrap(), "hello");
+        assert!(!field.is_decoded());
+        assert_eq!(field, LazyField::new("hello".to_string()));
+
+        field.get_mut().unwrap().push('!');
+        assert!(field.is_decoded());
+        let mut buf = Vec::new();
+        message::encode(2, &field, &mut buf);
+        assert_eq!(buf, embedded("hello!"));
+    }
+
+    #[test]
+    fn merge_occurrences() {
+        // Occurrences merged before the field is decoded are concatenated, and those merged
+        // afterwards are decoded into the message.
+        let mut field = LazyField::<String>::default();
+        let mut buf = embedded("a");
+        buf.extend_from_slice(&embedded("b"));
+        merge(&mut field, &buf).unwrap();
+        assert_eq!(field.encoded().unwrap().len(), 6);
+        assert_eq!(field.decode().unwrap(), "b");
+
+        field.get_mut().unwrap();
+        merge(&mut field, &embedded("c")).unwrap();
+        assert_eq!(field.into_inner().unwrap(), "c");
+    }
+
+    #[test]
+    fn malformed() {
+        let mut field = LazyField::<String>::default();
+        merge(&mut field, &[0x12, 0x02, 0x0a, 0x05]).unwrap();
+        field.get_mut().expect_err("truncated string");
+        assert!(!field.is_decoded());
+
+        merge(&mut field, &[0x12, 0x05, 0x0a]).expect_err("truncated message");
+    }
+}
@@ -9,15 +9,52 @@ pub extern crate alloc;
 // Re-export the bytes crate for use within derived code.
 pub use bytes;
 
+mod cached_size;
+mod debug;
+mod delimited;
 mod error;
+mod extension;
+mod hashing;
+#[cfg(feature = "std")]
+mod interner;
+mod lazy;
 mod message;
+mod name;
+mod newtype;
+mod presence;
 mod types;
+mod unknown;
+mod vectored;
+#[cfg(feature = "std")]
+mod writer;
 
 #[doc(hidden)]
 pub mod encoding;
+pub mod field;
+pub mod json;
+pub mod reflect;
+pub mod text_format;
 
+pub use crate::cached_size::CachedSize;
+pub use crate::debug::DebugBytes;
+#[cfg(all(feature = "std", feature = "tokio"))]
+pub use crate::delimited::decode_length_delimited_from;
+pub use crate::delimited::{LengthDelimitedDecoder, LengthDelimitedEncoder};
+#[cfg(feature = "std")]
+pub use crate::delimited::{LengthDelimitedReader, LengthDelimitedWriter};
+pub use crate::encoding::{RawField, RawFieldIter};
 pub use crate::error::{DecodeError, EncodeError};
-pub use crate::message::Message;
+pub use crate::extension::{Extension, ExtensionSet};
+pub use crate::field::{encode_field, encoded_len_field, merge_field};
+pub use crate::hashing::HashingBuf;
+#[cfg(feature = "std")]
+pub use crate::interner::Interner;
+pub use crate::lazy::LazyField;
+pub use crate::message::{DynMessage, Message};
+pub use crate::name::{EnumName, Name};
+pub use crate::presence::Presence;
+pub use crate::unknown::{UnknownField, UnknownFieldIter, UnknownFieldList};
+pub use crate::vectored::VectoredBuf;
 
 use bytes::{Buf, BufMut};
 
@@ -56,6 +93,22 @@ pub fn length_delimiter_len(length: usize) -> usize {
     encoded_len_varint(length as u64)
 }
 
+/// Returns the total length of a sequence of messages encoded with
+/// [Message.encode_length_delimited], including each message's length delimiter.
+///
+/// Applications may use this to size a single buffer for a batch of length-delimited messages.
+/// Like `Message::encoded_len`, the length saturates at `usize::MAX`.
+pub fn total_encoded_len_delimited<'a, M, I>(messages: I) -> usize
+where
+    M: Message + 'a,
+    I: IntoIterator<Item = &'a M>,
+{
+    encoding::sum_len(messages.into_iter().map(|message| {
+        let len = message.encoded_len();
+        len.saturating_add(length_delimiter_len(len))
+    }))
+}
+
 /// Decodes a length delimiter from the buffer.
 ///
 /// This method allows the length delimiter to be decoded independently of the message, when the
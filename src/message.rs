@@ -1,30 +1,63 @@
 use alloc::boxed::Box;
+use alloc::format;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 use core::fmt::Debug;
 use core::usize;
 
-use bytes::{Buf, BufMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 
 use crate::encoding::{
-    decode_key, encode_varint, encoded_len_varint, message, DecodeContext, WireType,
+    decode_key, encode_varint, encoded_len_varint, merge_loop, message, skip_field, DecodeContext,
+    RawFieldIter, WireType,
 };
+#[cfg(feature = "std")]
+use crate::writer::WriterBuf;
 use crate::DecodeError;
 use crate::EncodeError;
 
 /// A Protocol Buffers message.
 pub trait Message: Debug + Send + Sync {
-    /// Encodes the message to a buffer.
-    ///
-    /// This method will panic if the buffer has insufficient capacity.
+    /// Encodes the message to a buffer, without checking its capacity.
     ///
-    /// Meant to be used only by `Message` implementations.
-    #[doc(hidden)]
+    /// This method will panic if the buffer has insufficient capacity. Callers which have already
+    /// reserved at least `encoded_len()` bytes may use it to avoid the capacity check performed by
+    /// `encode`.
     fn encode_raw<B>(&self, buf: &mut B)
     where
         B: BufMut,
         Self: Sized;
 
+    /// Encodes the message to a buffer in the canonical form described by `encode_canonical`,
+    /// without checking its capacity.
+    ///
+    /// Meant to be used only by `Message` implementations. The default implementation calls
+    /// `encode_raw`.
+    #[doc(hidden)]
+    fn encode_canonical_raw<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+        Self: Sized,
+    {
+        self.encode_raw(buf)
+    }
+
+    /// Encodes the message like `encode_raw`, reusing the lengths cached by the messages nested in
+    /// it, see `CachedSize`.
+    ///
+    /// Meant to be used only by `Message` implementations, and called only right after
+    /// `encoded_len`, which refreshes the cached lengths. The default implementation calls
+    /// `encode_raw`.
+    #[doc(hidden)]
+    fn encode_cached_raw<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+        Self: Sized,
+    {
+        self.encode_raw(buf)
+    }
+
     /// Decodes a field from a buffer, and merges it into `self`.
     ///
     /// Meant to be used only by `Message` implementations.
@@ -40,12 +73,80 @@ pub trait Message: Debug + Send + Sync {
         B: Buf,
         Self: Sized;
 
+    /// Returns the tag of the field which is decoded from fields with the tag: the field's own tag
+    /// if the tag is one of its alias tags, and otherwise the tag itself.
+    ///
+    /// Meant to be used only by `Message` implementations. The default implementation returns the
+    /// tag, as for a message without alias tags.
+    #[doc(hidden)]
+    fn field_tag(tag: u32) -> u32
+    where
+        Self: Sized,
+    {
+        tag
+    }
+
+    /// Decodes the length delimiter and body of an embedded message from a buffer, and merges
+    /// the message into `self`.
+    ///
+    /// Meant to be used only by `Message` implementations. The default implementation merges each
+    /// field in turn with `merge_field`.
+    #[doc(hidden)]
+    fn merge_delimited<B>(&mut self, buf: &mut B, ctx: DecodeContext) -> Result<(), DecodeError>
+    where
+        B: Buf,
+        Self: Sized,
+    {
+        merge_loop(self, buf, ctx, |msg: &mut Self, buf: &mut B, ctx| {
+            let (tag, wire_type) = decode_key(buf)?;
+            msg.merge_field(tag, wire_type, buf, ctx)
+        })
+    }
+
     /// Returns the encoded length of the message without a length delimiter.
+    ///
+    /// The length saturates at `usize::MAX` if it does not fit in a `usize`, which is possible on
+    /// 32-bit targets. No buffer can hold a message of that length, so encoding it returns an
+    /// error, or panics in the methods which allocate their own buffer.
     fn encoded_len(&self) -> usize;
 
+    /// Returns the encoded length of the message without a length delimiter, reusing the length
+    /// stored by the last call to `encoded_len` if the message caches it.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    fn cached_encoded_len(&self) -> usize {
+        self.encoded_len()
+    }
+
+    /// Reports the encoded length of each field of the message to `record`, with the tags of the
+    /// enclosing fields in `path`, see `encode_with_stats`.
+    ///
+    /// Meant to be used only by `Message` implementations. The default implementation scans the
+    /// encoding of the message, so it does not break down the lengths of embedded messages.
+    #[doc(hidden)]
+    fn field_lens(&self, path: &mut Vec<u32>, record: &mut dyn FnMut(&[u32], usize))
+    where
+        Self: Sized,
+    {
+        let encoded = self.encode_to_vec();
+        // A message's own encoding is well-formed, unless `encode_raw` disagrees with
+        // `encoded_len`, in which case the fields which can be scanned are reported.
+        for field in RawFieldIter::new(&encoded) {
+            let field = match field {
+                Ok(field) => field,
+                Err(_) => break,
+            };
+            path.push(field.tag);
+            record(path, field.raw.len());
+            path.pop();
+        }
+    }
+
     /// Encodes the message to a buffer.
     ///
-    /// An error will be returned if the buffer does not have sufficient capacity.
+    /// An error will be returned if the buffer does not have sufficient capacity. The capacity is
+    /// checked once, up front, after which the message is written with `encode_raw`.
     fn encode<B>(&self, buf: &mut B) -> Result<(), EncodeError>
     where
         B: BufMut,
@@ -57,7 +158,7 @@ pub trait Message: Debug + Send + Sync {
             return Err(EncodeError::new(required, remaining));
         }
 
-        self.encode_raw(buf);
+        self.encode_cached_raw(buf);
         Ok(())
     }
 
@@ -68,7 +169,92 @@ pub trait Message: Debug + Send + Sync {
     {
         let mut buf = Vec::with_capacity(self.encoded_len());
 
-        self.encode_raw(&mut buf);
+        self.encode_cached_raw(&mut buf);
+        buf
+    }
+
+    /// Encodes the message to a newly allocated `Bytes`, which may be cloned and shared, e.g.
+    /// between several async sinks, without copying the encoding.
+    fn encode_to_bytes(&self) -> Bytes
+    where
+        Self: Sized,
+    {
+        let mut buf = BytesMut::with_capacity(self.encoded_len());
+
+        self.encode_cached_raw(&mut buf);
+        buf.freeze()
+    }
+
+    /// Encodes the message to an array on the stack, returning the array and the number of bytes
+    /// written to the front of it.
+    ///
+    /// This avoids allocating for small messages whose maximum length is known. An error is
+    /// returned if the message is longer than `N` bytes.
+    fn encode_to_array<const N: usize>(&self) -> Result<([u8; N], usize), EncodeError>
+    where
+        Self: Sized,
+    {
+        let len = self.encoded_len();
+        if len > N {
+            return Err(EncodeError::new(len, N));
+        }
+
+        let mut array = [0; N];
+        self.encode_cached_raw(&mut &mut array[..]);
+        Ok((array, len))
+    }
+
+    /// Encodes the message to a buffer in a canonical form, which depends only on the message's
+    /// value.
+    ///
+    /// `encode` may write fields in different orders for equal messages, e.g. `HashMap` entries
+    /// in iteration order. The canonical form follows fixed rules, so that equal messages are
+    /// always encoded to the same bytes, e.g. for signing:
+    ///
+    ///  1. Known singular fields, including oneofs and embedded messages, are written first, in
+    ///     ascending order of field number. They are followed by the repeated and map fields, also
+    ///     in ascending order of field number. A oneof is written at the position of the field
+    ///     number of its current variant.
+    ///  2. Fields with implicit presence which hold their default value, i.e. proto3 scalar,
+    ///     string, bytes and enumeration fields without `optional`, and empty repeated and map
+    ///     fields, are omitted. Fields with explicit presence are written whenever they are set.
+    ///  3. Repeated fields are written in order, packed if the field is packed.
+    ///  4. Map entries are written in ascending key order. Within an entry, the key is written
+    ///     before the value, and a key or value which holds its default is omitted.
+    ///  5. Nested messages and groups are written in canonical form.
+    ///  6. Extensions, followed by unknown fields, are written after all known fields, each in
+    ///     ascending order of field number, with the relative order of fields sharing a number
+    ///     preserved. Their contents are written as they were decoded.
+    ///
+    /// Integers are written as minimal varints. The canonical form is a valid encoding of the
+    /// message, and has the same length as the output of `encode`.
+    ///
+    /// An error will be returned if the buffer does not have sufficient capacity.
+    fn encode_canonical<B>(&self, buf: &mut B) -> Result<(), EncodeError>
+    where
+        B: BufMut,
+        Self: Sized,
+    {
+        let required = self.encoded_len();
+        let remaining = buf.remaining_mut();
+        if required > remaining {
+            return Err(EncodeError::new(required, remaining));
+        }
+
+        self.encode_canonical_raw(buf);
+        Ok(())
+    }
+
+    /// Encodes the message in canonical form to a newly allocated buffer.
+    ///
+    /// See `encode_canonical` for the rules of the canonical form.
+    fn encode_canonical_to_vec(&self) -> Vec<u8>
+    where
+        Self: Sized,
+    {
+        let mut buf = Vec::with_capacity(self.encoded_len());
+
+        self.encode_canonical_raw(&mut buf);
         buf
     }
 
@@ -81,13 +267,13 @@ pub trait Message: Debug + Send + Sync {
         Self: Sized,
     {
         let len = self.encoded_len();
-        let required = len + encoded_len_varint(len as u64);
+        let required = len.saturating_add(encoded_len_varint(len as u64));
         let remaining = buf.remaining_mut();
         if required > remaining {
             return Err(EncodeError::new(required, remaining));
         }
         encode_varint(len as u64, buf);
-        self.encode_raw(buf);
+        self.encode_cached_raw(buf);
         Ok(())
     }
 
@@ -97,16 +283,103 @@ pub trait Message: Debug + Send + Sync {
         Self: Sized,
     {
         let len = self.encoded_len();
-        let mut buf = Vec::with_capacity(len + encoded_len_varint(len as u64));
+        let mut buf = Vec::with_capacity(len.saturating_add(encoded_len_varint(len as u64)));
 
         encode_varint(len as u64, &mut buf);
-        self.encode_raw(&mut buf);
+        self.encode_cached_raw(&mut buf);
         buf
     }
 
+    /// Encodes the message to an `io::Write`, such as a file or a hasher.
+    ///
+    /// The message is streamed to the writer through a small fixed-size buffer, so it is never
+    /// held in memory in its entirety. Large `bytes` and `string` values are written directly.
+    /// Any error returned by the writer is returned, in which case the message may have been
+    /// partially written.
+    #[cfg(feature = "std")]
+    fn encode_to_writer<W>(&self, writer: &mut W) -> std::io::Result<()>
+    where
+        W: std::io::Write,
+        Self: Sized,
+    {
+        // Computing the length refreshes the cached lengths of nested messages.
+        self.encoded_len();
+        let mut buf = WriterBuf::new(writer);
+        self.encode_cached_raw(&mut buf);
+        buf.finish()
+    }
+
+    /// Encodes the message with a length-delimiter to an `io::Write`.
+    ///
+    /// See `encode_to_writer` for how the message is written.
+    #[cfg(feature = "std")]
+    fn encode_length_delimited_to_writer<W>(&self, writer: &mut W) -> std::io::Result<()>
+    where
+        W: std::io::Write,
+        Self: Sized,
+    {
+        let len = self.encoded_len();
+        let mut buf = WriterBuf::new(writer);
+        encode_varint(len as u64, &mut buf);
+        self.encode_cached_raw(&mut buf);
+        buf.finish()
+    }
+
+    /// Encodes the message to a buffer, adding the number of bytes written for each field to
+    /// `stats`, keyed by the path of tags leading to the field.
+    ///
+    /// This is a diagnostic, e.g. for finding the fields which dominate the encoded size of
+    /// typical messages. The count of a field includes its key and length delimiter, and is summed
+    /// over all elements of a repeated field or map. A field of the message itself is keyed by
+    /// its tag alone, e.g. `[2]`, and a field of an embedded message or group held by it by both
+    /// tags, e.g. `[2, 1]`, and so on for deeper nesting, so the counts at each level break down
+    /// the count of the enclosing field. Map entries are counted as a whole, and extensions and
+    /// unknown fields are counted under their own tags. Counts are added to the existing entries
+    /// of `stats`, so that they can be aggregated across a sample of messages.
+    ///
+    /// The counts are computed from the lengths of the fields, at no cost to other methods. An
+    /// error will be returned if the buffer does not have sufficient capacity, in which case
+    /// `stats` is left unchanged.
+    #[cfg(feature = "std")]
+    fn encode_with_stats<B>(
+        &self,
+        buf: &mut B,
+        stats: &mut std::collections::HashMap<Vec<u32>, usize>,
+    ) -> Result<(), EncodeError>
+    where
+        B: BufMut,
+        Self: Sized,
+    {
+        let required = self.encoded_len();
+        let remaining = buf.remaining_mut();
+        if required > remaining {
+            return Err(EncodeError::new(required, remaining));
+        }
+
+        self.encode_cached_raw(buf);
+        self.field_lens(
+            &mut Vec::new(),
+            &mut |path, len| match stats.get_mut(path) {
+                Some(count) => *count += len,
+                None => {
+                    stats.insert(path.to_vec(), len);
+                }
+            },
+        );
+        Ok(())
+    }
+
     /// Decodes an instance of the message from a buffer.
     ///
-    /// The entire buffer will be consumed.
+    /// The entire buffer will be consumed. Since `&[u8]` implements `Buf`, a byte slice may be
+    /// passed directly, e.g. `MyMessage::decode(&bytes[..])`.
+    ///
+    /// Fields may appear in any order, and a singular field may appear more than once, as when
+    /// several encodings of the message are concatenated: the last occurrence of a scalar, string
+    /// or bytes field wins, while the occurrences of a message field are merged, as in `merge`.
+    ///
+    /// Messages deriving `Message` with the `#[prost(try_from_bytes)]` attribute also implement
+    /// `TryFrom<&[u8]>` by calling this method.
     fn decode<B>(mut buf: B) -> Result<Self, DecodeError>
     where
         B: Buf,
@@ -117,6 +390,12 @@ pub trait Message: Debug + Send + Sync {
     }
 
     /// Decodes a length-delimited instance of the message from the buffer.
+    ///
+    /// Only the length delimiter and the message body are consumed. Passing the buffer by
+    /// mutable reference, e.g. `MyMessage::decode_length_delimited(&mut buf)`, leaves it
+    /// positioned immediately after the message, so a sequence of length-delimited messages may
+    /// be decoded one at a time. If the buffer holds fewer bytes than the delimiter specifies, a
+    /// buffer underflow error is returned.
     fn decode_length_delimited<B>(buf: B) -> Result<Self, DecodeError>
     where
         B: Buf,
@@ -127,15 +406,145 @@ pub trait Message: Debug + Send + Sync {
         Ok(message)
     }
 
+    /// Decodes an instance of the message from a buffer, merging it into `base`, and returns the
+    /// result.
+    ///
+    /// This is `decode` for messages which do not implement `Default`, or whose decoding should
+    /// start from a base constructed by the caller: fields absent from the buffer keep their
+    /// values from `base`, as described in `merge`. The entire buffer will be consumed.
+    fn decode_into<B>(mut base: Self, buf: B) -> Result<Self, DecodeError>
+    where
+        B: Buf,
+        Self: Sized,
+    {
+        base.merge(buf)?;
+        Ok(base)
+    }
+
+    /// Decodes a length-delimited instance of the message from the buffer, merging it into
+    /// `base`, and returns the result.
+    ///
+    /// This is `decode_length_delimited` starting from `base` rather than from the default
+    /// message, and consumes the buffer in the same way.
+    fn decode_length_delimited_into<B>(mut base: Self, buf: B) -> Result<Self, DecodeError>
+    where
+        B: Buf,
+        Self: Sized,
+    {
+        base.merge_length_delimited(buf)?;
+        Ok(base)
+    }
+
+    /// Decodes as much of an instance of the message from a buffer as possible, returning the
+    /// fields decoded before the first error, along with the error, if any.
+    ///
+    /// This is lossy, and meant for forensics, such as logging which fields of a corrupted
+    /// message made it through before the corruption: a message returned along with an error must
+    /// not be used as if it had been decoded. It holds every field which precedes the error in the
+    /// buffer, merged as in `merge`, while the fields which follow it are lost. The field being
+    /// decoded when the error occurred may be left partially decoded, such as a packed repeated
+    /// field holding only its first values, or an embedded message holding only its first fields.
+    ///
+    /// Without an error, this is equivalent to `decode`, and consumes the entire buffer.
+    fn decode_best_effort<B>(buf: B) -> (Self, Option<DecodeError>)
+    where
+        B: Buf,
+        Self: Default,
+    {
+        let mut message = Self::default();
+        let error = message.merge(buf).err();
+        (message, error)
+    }
+
+    /// Decodes a length-delimited instance of the message from the buffer, rejecting any value
+    /// longer than `max_len` bytes.
+    ///
+    /// The limit applies to the message itself and to every length-delimited value nested within
+    /// it: embedded messages, strings, bytes and packed repeated fields. Each length prefix is
+    /// checked before the value is read, so input claiming an oversized length fails immediately,
+    /// without allocating or scanning the buffer. Otherwise this behaves like
+    /// `decode_length_delimited`.
+    fn decode_with_limit<B>(mut buf: B, max_len: usize) -> Result<Self, DecodeError>
+    where
+        B: Buf,
+        Self: Default,
+    {
+        let mut message = Self::default();
+        message::merge(
+            WireType::LengthDelimited,
+            &mut message,
+            &mut buf,
+            DecodeContext::default().with_length_limit(max_len),
+        )?;
+        Ok(message)
+    }
+
+    /// Decodes an instance of the message from a buffer, keeping only the fields whose tags are in
+    /// `allowed`.
+    ///
+    /// Every other field is skipped with `skip_field`, which only reads as far as the end of the
+    /// field: its value is neither parsed nor allocated, and it is not retained as an unknown
+    /// field, even by messages which retain unknown fields. The returned message holds the
+    /// default value for each field which is not allowed. A oneof field is allowed by the tags of
+    /// its variants, and a map field by its own tag. A field with alias tags is allowed by its own
+    /// tag, and is then also decoded from its alias tags. The fields of embedded messages are not
+    /// filtered, so an allowed message field is decoded entirely.
+    ///
+    /// This is useful to cheaply extract a few fields, such as routing keys, from large messages.
+    /// A skipped field must still be well-formed, and the entire buffer will be consumed.
+    fn decode_fields<B>(mut buf: B, allowed: &[u32]) -> Result<Self, DecodeError>
+    where
+        B: Buf,
+        Self: Default,
+    {
+        let mut message = Self::default();
+        let ctx = DecodeContext::default();
+        while buf.has_remaining() {
+            let (tag, wire_type) = decode_key(&mut buf)?;
+            if allowed.contains(&Self::field_tag(tag)) {
+                message.merge_field(tag, wire_type, &mut buf, ctx.clone())?;
+            } else {
+                skip_field(wire_type, tag, &mut buf, ctx.clone())?;
+            }
+        }
+        Ok(message)
+    }
+
     /// Decodes an instance of the message from a buffer, and merges it into `self`.
     ///
+    /// Merging follows the Protobuf rules for a message which is encoded in several parts:
+    /// singular scalar, string and bytes fields present in the buffer replace the values in
+    /// `self`, repeated fields and maps are extended, and singular message fields are merged
+    /// recursively. Fields absent from the buffer, including proto3 fields holding their default
+    /// value, are left unchanged.
+    ///
+    /// `self` is never reset before merging, so a message may be built up from several fragments
+    /// by merging each of them in turn; use `decode` or `clear` to start from the default.
+    ///
     /// The entire buffer will be consumed.
-    fn merge<B>(&mut self, mut buf: B) -> Result<(), DecodeError>
+    fn merge<B>(&mut self, buf: B) -> Result<(), DecodeError>
     where
         B: Buf,
         Self: Sized,
     {
-        let ctx = DecodeContext::default();
+        self.merge_with_context(buf, DecodeContext::default())
+    }
+
+    /// Decodes an instance of the message from a buffer, and merges it into `self`, using the
+    /// provided decode context.
+    ///
+    /// This allows the recursion limit to be raised for trusted inputs which nest more deeply than
+    /// the default allows, e.g. with `DecodeContext::with_recursion_limit(1000)`.
+    ///
+    /// The entire buffer will be consumed. The context may be one passed to `merge_field`, e.g. to
+    /// decode a message held in a bytes field: the bounds of the value enclosing the field only
+    /// apply to the buffer it was decoded from.
+    fn merge_with_context<B>(&mut self, mut buf: B, ctx: DecodeContext) -> Result<(), DecodeError>
+    where
+        B: Buf,
+        Self: Sized,
+    {
+        let ctx = ctx.detach();
         while buf.has_remaining() {
             let (tag, wire_type) = decode_key(&mut buf)?;
             self.merge_field(tag, wire_type, &mut buf, ctx.clone())?;
@@ -143,8 +552,32 @@ pub trait Message: Debug + Send + Sync {
         Ok(())
     }
 
+    /// Merges the fields of `other` into `self`.
+    ///
+    /// This is equivalent to merging the encoding of `other` into `self`, and follows the same
+    /// rules as `merge`: it can be used to apply a partial update to a base message. Unlike
+    /// `merge`, it is not subject to the recursion limit, since `other` is already held in memory
+    /// however deeply it nests.
+    ///
+    /// An error is returned if the encoding of `other` can not be decoded, e.g. if a hand-written
+    /// implementation does not decode its own encoding; `self` may then have been partially
+    /// merged.
+    fn merge_from(&mut self, other: &Self) -> Result<(), DecodeError>
+    where
+        Self: Sized,
+    {
+        let buf = other.encode_to_vec();
+        self.merge_with_context(&*buf, DecodeContext::with_recursion_limit(u32::MAX))
+    }
+
     /// Decodes a length-delimited instance of the message from buffer, and
     /// merges it into `self`.
+    ///
+    /// The message body is decoded in place, without an intermediate `Take` adapter. On success,
+    /// exactly the length delimiter and the number of bytes it specifies have been consumed,
+    /// leaving the buffer positioned at the first byte after the message. An error is returned if
+    /// the body does not consist of whole fields, e.g. if it ends with padding, or if its last
+    /// field extends past the end of the body; the position of the buffer is then unspecified.
     fn merge_length_delimited<B>(&mut self, mut buf: B) -> Result<(), DecodeError>
     where
         B: Buf,
@@ -158,6 +591,53 @@ pub trait Message: Debug + Send + Sync {
         )
     }
 
+    /// Decodes an instance of the message from a slice, and merges it into `self`, like `merge`.
+    ///
+    /// The slice must hold exactly the message: after merging, the slice is checked to have been
+    /// consumed entirely, and an error naming the number of unexpected trailing bytes is returned
+    /// otherwise, since they usually indicate a framing bug.
+    fn merge_from_slice(&mut self, mut buf: &[u8]) -> Result<(), DecodeError>
+    where
+        Self: Sized,
+    {
+        self.merge(&mut buf)?;
+        if !buf.is_empty() {
+            return Err(DecodeError::new(format!(
+                "{} unexpected trailing bytes",
+                buf.len()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks that every proto2 `required` field of the message, and of the messages nested in
+    /// it, has been set.
+    ///
+    /// Decoding does not check required fields, so this is meant to be called on decoded
+    /// messages. Required fields are only checked in messages which track them with a
+    /// `#[prost(presence)]` field, see `Presence`. The error names the first missing field, and
+    /// the fields of the enclosing messages leading to it.
+    fn check_initialized(&self) -> Result<(), DecodeError> {
+        Ok(())
+    }
+
+    /// Returns `true` if every field of the message holds its default value, without
+    /// constructing a default message to compare it to.
+    ///
+    /// This follows proto3 semantics. A singular scalar, string or bytes field holds its default
+    /// if it compares equal to it, so a `float` or `double` field holding `-0.0` does, and one
+    /// holding `NaN` does not. Optional fields and oneofs only hold their default when they are
+    /// unset, even if they are set to a default value, since they are encoded whenever they are
+    /// set. Repeated fields and maps hold their default when they are empty, as the encoding does
+    /// not distinguish an empty field from an absent one. A required message field holds its
+    /// default if all of its own fields do. Retained unknown fields and extensions must be empty.
+    ///
+    /// Messages implemented with `#[derive(Message)]` compare each field to its default; the
+    /// default implementation returns whether the encoded length is zero.
+    fn is_default(&self) -> bool {
+        self.encoded_len() == 0
+    }
+
     /// Clears the message, resetting all fields to their default.
     fn clear(&mut self);
 }
@@ -172,6 +652,18 @@ where
     {
         (**self).encode_raw(buf)
     }
+    fn encode_canonical_raw<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        (**self).encode_canonical_raw(buf)
+    }
+    fn encode_cached_raw<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        (**self).encode_cached_raw(buf)
+    }
     fn merge_field<B>(
         &mut self,
         tag: u32,
@@ -184,17 +676,315 @@ where
     {
         (**self).merge_field(tag, wire_type, buf, ctx)
     }
+    fn field_tag(tag: u32) -> u32 {
+        M::field_tag(tag)
+    }
+    fn merge_delimited<B>(&mut self, buf: &mut B, ctx: DecodeContext) -> Result<(), DecodeError>
+    where
+        B: Buf,
+    {
+        (**self).merge_delimited(buf, ctx)
+    }
     fn encoded_len(&self) -> usize {
         (**self).encoded_len()
     }
+    fn cached_encoded_len(&self) -> usize {
+        (**self).cached_encoded_len()
+    }
+    fn field_lens(&self, path: &mut Vec<u32>, record: &mut dyn FnMut(&[u32], usize)) {
+        (**self).field_lens(path, record)
+    }
+    fn check_initialized(&self) -> Result<(), DecodeError> {
+        (**self).check_initialized()
+    }
+    fn is_default(&self) -> bool {
+        (**self).is_default()
+    }
     fn clear(&mut self) {
         (**self).clear()
     }
 }
 
+// Shared messages are encoded in place, and copied on write when merged into while shared.
+impl<M> Message for Arc<M>
+where
+    M: Message + Clone,
+{
+    fn encode_raw<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        (**self).encode_raw(buf)
+    }
+    fn encode_canonical_raw<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        (**self).encode_canonical_raw(buf)
+    }
+    fn encode_cached_raw<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        (**self).encode_cached_raw(buf)
+    }
+    fn merge_field<B>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        B: Buf,
+    {
+        Arc::make_mut(self).merge_field(tag, wire_type, buf, ctx)
+    }
+    fn field_tag(tag: u32) -> u32 {
+        M::field_tag(tag)
+    }
+    fn merge_delimited<B>(&mut self, buf: &mut B, ctx: DecodeContext) -> Result<(), DecodeError>
+    where
+        B: Buf,
+    {
+        Arc::make_mut(self).merge_delimited(buf, ctx)
+    }
+    fn encoded_len(&self) -> usize {
+        (**self).encoded_len()
+    }
+    fn cached_encoded_len(&self) -> usize {
+        (**self).cached_encoded_len()
+    }
+    fn field_lens(&self, path: &mut Vec<u32>, record: &mut dyn FnMut(&[u32], usize)) {
+        (**self).field_lens(path, record)
+    }
+    fn check_initialized(&self) -> Result<(), DecodeError> {
+        (**self).check_initialized()
+    }
+    fn is_default(&self) -> bool {
+        (**self).is_default()
+    }
+    fn clear(&mut self) {
+        Arc::make_mut(self).clear()
+    }
+}
+
+/// An object-safe extension of [Message], which erases the buffer type.
+///
+/// `Message::encode` and `Message::merge` are generic over the buffer, so they can not be called
+/// on a `dyn Message`. This trait is implemented for every `Message`, and allows messages of
+/// different types to be encoded and decoded through a `Box<dyn DynMessage>`, at the cost of
+/// dynamic dispatch for every buffer access.
+pub trait DynMessage: Message {
+    /// Encodes the message to a buffer.
+    ///
+    /// An error will be returned if the buffer does not have sufficient capacity.
+    fn encode_dyn(&self, buf: &mut dyn BufMut) -> Result<(), EncodeError>;
+
+    /// Encodes the message to a newly allocated buffer.
+    fn encode_to_vec_dyn(&self) -> Vec<u8>;
+
+    /// Decodes an instance of the message from a buffer, and merges it into `self`.
+    ///
+    /// The entire buffer will be consumed.
+    fn merge_dyn(&mut self, buf: &mut dyn Buf) -> Result<(), DecodeError>;
+}
+
+impl<M> DynMessage for M
+where
+    M: Message,
+{
+    fn encode_dyn(&self, mut buf: &mut dyn BufMut) -> Result<(), EncodeError> {
+        self.encode(&mut buf)
+    }
+
+    fn encode_to_vec_dyn(&self) -> Vec<u8> {
+        self.encode_to_vec()
+    }
+
+    fn merge_dyn(&mut self, buf: &mut dyn Buf) -> Result<(), DecodeError> {
+        self.merge(buf)
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use alloc::string::{String, ToString};
+    use alloc::vec;
+
     use super::*;
 
     const _MESSAGE_IS_OBJECT_SAFE: Option<&dyn Message> = None;
+    const _DYN_MESSAGE_IS_OBJECT_SAFE: Option<&dyn DynMessage> = None;
+
+    #[test]
+    fn dyn_message() {
+        let messages: Vec<Box<dyn DynMessage>> =
+            vec![Box::new(42u32), Box::new("hello".to_string())];
+
+        let mut decoded: Vec<Box<dyn DynMessage>> = vec![Box::new(0u32), Box::new(String::new())];
+        for (message, decoded) in messages.iter().zip(decoded.iter_mut()) {
+            let mut buf = Vec::new();
+            message.encode_dyn(&mut buf).unwrap();
+            assert_eq!(buf.len(), message.encoded_len());
+            assert_eq!(buf, message.encode_to_vec_dyn());
+
+            decoded.merge_dyn(&mut &buf[..]).unwrap();
+            assert_eq!(decoded.encode_to_vec_dyn(), buf);
+        }
+        assert_eq!(alloc::format!("{:?}", decoded), "[42, \"hello\"]");
+
+        let mut buf = [0u8; 1];
+        let error = messages[1].encode_dyn(&mut &mut buf[..]).unwrap_err();
+        assert_eq!(error.required_capacity(), messages[1].encoded_len());
+    }
+
+    #[test]
+    fn encode_to_bytes() {
+        let message = "hello".to_string();
+        let bytes = message.encode_to_bytes();
+        assert_eq!(bytes, message.encode_to_vec());
+
+        // Clones share the encoding.
+        let clone = bytes.clone();
+        assert_eq!(clone.as_ptr(), bytes.as_ptr());
+
+        assert!(String::new().encode_to_bytes().is_empty());
+    }
+
+    #[test]
+    fn encode_to_array() {
+        let message = "hello".to_string();
+        let (array, len) = message.encode_to_array::<16>().unwrap();
+        assert_eq!(array[..len], message.encode_to_vec()[..]);
+        assert!(array[len..].iter().all(|&b| b == 0));
+
+        let (array, len) = message.encode_to_array::<7>().unwrap();
+        assert_eq!(array[..len], message.encode_to_vec()[..]);
+
+        let error = message.encode_to_array::<6>().unwrap_err();
+        assert_eq!(error.required_capacity(), 7);
+        assert_eq!(error.remaining(), 6);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn encode_to_writer() {
+        let message = "hello".repeat(10_000);
+
+        let mut buf = Vec::new();
+        message.encode_to_writer(&mut buf).unwrap();
+        assert_eq!(buf, message.encode_to_vec());
+
+        let mut buf = Vec::new();
+        message.encode_length_delimited_to_writer(&mut buf).unwrap();
+        assert_eq!(buf, message.encode_length_delimited_to_vec());
+
+        let mut array = [0u8; 16];
+        let error = message.encode_to_writer(&mut &mut array[..]).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::WriteZero);
+    }
+
+    #[test]
+    fn merge_from_slice() {
+        let buf = 42u32.encode_to_vec();
+
+        let mut value = 0u32;
+        value.merge_from_slice(&buf).unwrap();
+        assert_eq!(value, 42);
+
+        // The slice is merged like any other buffer, field by field.
+        let mut value = String::from("a");
+        value
+            .merge_from_slice(&[0x0a, 0x01, b'b', 0x0a, 0x01, b'c'])
+            .unwrap();
+        assert_eq!(value, "c");
+
+        0u32.merge_from_slice(&buf[..buf.len() - 1])
+            .expect_err("truncated message");
+    }
+
+    #[test]
+    fn merge_length_delimited() {
+        // The buffer is advanced past the message, and no further.
+        let mut buf = &[0x02, 0x08, 0x2a, 0x08, 0x07][..];
+        let mut value = 0u32;
+        value.merge_length_delimited(&mut buf).unwrap();
+        assert_eq!(value, 42);
+        assert_eq!(buf, [0x08, 0x07]);
+
+        // Zero padding after the value's field is decoded as a field with tag 0.
+        let padded = [0x04, 0x08, 0x2a, 0x00, 0x00, 0x08, 0x07];
+        let error = 0u32.merge_length_delimited(&padded[..]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "failed to decode Protobuf message: invalid tag value: 0"
+        );
+
+        // A field which extends past the end of the body.
+        let overrun = [0x02, 0x08, 0x96, 0x01];
+        let error = 0u32.merge_length_delimited(&overrun[..]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "failed to decode Protobuf message: delimited length exceeded"
+        );
+    }
+
+    #[test]
+    fn decode_into() {
+        // Fields absent from the buffer keep the value of the base.
+        assert_eq!(u32::decode_into(7, &[][..]).unwrap(), 7);
+        assert_eq!(u32::decode_into(7, &[0x08, 0x2a][..]).unwrap(), 42);
+
+        let mut buf = &[0x00, 0x02, 0x08, 0x2a, 0x08][..];
+        assert_eq!(u32::decode_length_delimited_into(7, &mut buf).unwrap(), 7);
+        assert_eq!(u32::decode_length_delimited_into(7, &mut buf).unwrap(), 42);
+        assert_eq!(buf, [0x08]);
+
+        u32::decode_into(7, &[0x08][..]).expect_err("truncated message");
+    }
+
+    #[test]
+    fn decode_length_delimited_chained() {
+        // A length delimiter split across the chunks of a `Buf`, as in scatter-gather buffers.
+        let message = "x".repeat(200);
+        let buf = message.encode_length_delimited_to_vec();
+        assert_eq!(buf[..2], [0xcb, 0x01]);
+
+        let mut chained = (&buf[..1]).chain(&buf[1..100]).chain(&buf[100..]);
+        assert_eq!(
+            String::decode_length_delimited(&mut chained).unwrap(),
+            message
+        );
+        assert!(!chained.has_remaining());
+    }
+
+    #[test]
+    fn decode_with_limit() {
+        let buf = "hello".to_string().encode_length_delimited_to_vec();
+        assert_eq!(String::decode_with_limit(&buf[..], 7).unwrap(), "hello");
+
+        let error = String::decode_with_limit(&buf[..], 6).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "failed to decode Protobuf message: \
+             length-delimited value of 7 bytes exceeds the limit of 6 bytes"
+        );
+
+        // The nested string is checked against the limit too: a 3 byte message claiming to hold
+        // a 1000 byte string is rejected before the buffer underflow is detected.
+        let nested = [0x03, 0x0a, 0xe8, 0x07];
+        let error = String::decode_with_limit(&nested[..], 16).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "failed to decode Protobuf message: \
+             length-delimited value of 1000 bytes exceeds the limit of 16 bytes"
+        );
+
+        // A huge length prefix is rejected without reading any further.
+        let mut huge = Vec::new();
+        encode_varint(u64::MAX >> 1, &mut huge);
+        let error = String::decode_with_limit(&huge[..], 1 << 20).unwrap_err();
+        assert!(error.to_string().contains("exceeds the limit"));
+    }
 }
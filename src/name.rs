@@ -0,0 +1,62 @@
+//! Names of Protobuf definitions.
+
+use alloc::format;
+use alloc::string::String;
+
+use crate::Message;
+
+/// The Protobuf names of the values of an enumeration.
+///
+/// This is implemented by `#[derive(Enumeration)]`. The name of each variant is taken from its
+/// `#[prost(name = "...")]` attribute, which `prost-build` emits with the value's name as written
+/// in the `.proto` file, and otherwise from the variant's Rust identifier.
+pub trait EnumName {
+    /// Returns the name of the variant with the given value, or `None` if `value` is not a
+    /// variant of the enumeration.
+    fn proto_name(value: i32) -> Option<&'static str>;
+}
+
+/// The fully-qualified Protobuf name of a message type.
+///
+/// The name identifies the message's type in the `type_url` of a `google.protobuf.Any`, and is
+/// useful wherever messages are handled without knowing their Rust type, e.g. in logs.
+/// `prost-build` implements it for generated messages selected with `Config::type_names`.
+///
+/// ```rust
+/// use prost::Name;
+///
+/// #[derive(Clone, PartialEq, prost::Message)]
+/// struct Widget {}
+///
+/// impl Name for Widget {
+///     const NAME: &'static str = "Widget";
+///     const PACKAGE: &'static str = "my.package";
+/// }
+///
+/// assert_eq!(Widget::full_name(), "my.package.Widget");
+/// assert_eq!(Widget::type_url(), "type.googleapis.com/my.package.Widget");
+/// ```
+pub trait Name: Message {
+    /// The name of the message, without its package. The names of nested messages include the
+    /// names of the messages they are nested in, e.g. `Outer.Inner`.
+    const NAME: &'static str;
+
+    /// The package the message is defined in, e.g. `google.protobuf`, or the empty string if
+    /// the message's file has no package.
+    const PACKAGE: &'static str;
+
+    /// Returns the fully-qualified name of the message, e.g. `google.protobuf.Duration`.
+    fn full_name() -> String {
+        if Self::PACKAGE.is_empty() {
+            Self::NAME.into()
+        } else {
+            format!("{}.{}", Self::PACKAGE, Self::NAME)
+        }
+    }
+
+    /// Returns the type URL identifying the message in a `google.protobuf.Any`, e.g.
+    /// `type.googleapis.com/google.protobuf.Duration`.
+    fn type_url() -> String {
+        format!("type.googleapis.com/{}", Self::full_name())
+    }
+}
@@ -0,0 +1,294 @@
+//! Encoding of newtypes wrapping a scalar value exactly like the value itself.
+
+/// Generates a module of encoding functions for a tuple struct wrapping a single scalar value, so
+/// that fields holding the newtype are encoded exactly like fields holding the value.
+///
+/// The module is used by annotating a scalar field with `newtype`, naming the module by its path
+/// from the message. The Protobuf type of the field is the one after `as`. The macro must be
+/// invoked outside of any function, in a module where the newtype is in scope:
+///
+/// ```rust
+/// use prost::Message;
+///
+/// #[derive(Clone, Copy, Debug, PartialEq)]
+/// pub struct Port(u16);
+///
+/// #[derive(Clone, Debug, PartialEq)]
+/// pub struct UserName(String);
+///
+/// prost::newtype_field! {
+///     /// Encodes a `Port` as a `uint32`.
+///     pub mod port: Port(u16) as uint32;
+/// }
+/// prost::newtype_field!(pub mod user_name: UserName(String) as string;);
+///
+/// #[derive(Clone, PartialEq, Message)]
+/// struct Endpoint {
+///     #[prost(uint32, newtype = "port", tag = "1")]
+///     port: Port,
+///     #[prost(string, newtype = "user_name", optional, tag = "2")]
+///     owner: Option<UserName>,
+/// }
+///
+/// # fn main() {
+/// let endpoint = Endpoint { port: Port(8080), owner: Some(UserName("root".into())) };
+/// assert_eq!(endpoint.encode_to_vec(), b"\x08\x90\x3f\x12\x04root");
+///
+/// // Values which do not fit in the newtype fail to decode.
+/// assert!(Endpoint::decode(&[0x08, 0x80, 0x80, 0x04][..]).is_err());
+/// # }
+/// ```
+///
+/// A newtype may wrap any type that converts from and to the Rust type of a numeric Protobuf
+/// type: decoding a value which does not fit in the newtype fails. The wrapped value must be
+/// `Copy`. A newtype of `string` must wrap a `String`, and a newtype of `bytes` must wrap a
+/// `Vec<u8>` or `Bytes`.
+///
+/// The value of the default newtype is the default of the wrapped value, and is the one omitted
+/// from encodings. Unlike fields of the wrapped types, newtype fields may not have a custom
+/// default, and have no getters.
+#[macro_export]
+macro_rules! newtype_field {
+    ($(#[$attr:meta])* $vis:vis mod $module:ident: $newtype:ident($inner:ty) as string;) => {
+        $crate::newtype_field!(
+            @length_delimited $(#[$attr])* $vis mod $module: $newtype($inner) as string;
+        );
+    };
+
+    ($(#[$attr:meta])* $vis:vis mod $module:ident: $newtype:ident($inner:ty) as bytes;) => {
+        $crate::newtype_field!(
+            @length_delimited $(#[$attr])* $vis mod $module: $newtype($inner) as bytes;
+        );
+    };
+
+    ($(#[$attr:meta])* $vis:vis mod $module:ident: $newtype:ident($inner:ty) as $proto:ident;) => {
+        $(#[$attr])*
+        $vis mod $module {
+            #![allow(dead_code)]
+
+            use super::*;
+
+            use $crate::alloc::vec::Vec;
+            use $crate::bytes::{Buf, BufMut};
+            use $crate::encoding::{encode_key, encode_varint, encoded_len_varint, key_len};
+            use $crate::encoding::{DecodeContext, WireType};
+            use $crate::DecodeError;
+
+            /// The wire type of the field's values, unless they are packed.
+            pub const WIRE_TYPE: WireType = $crate::encoding::$proto::WIRE_TYPE;
+
+            /// Returns the newtype wrapping the default value.
+            pub fn default() -> $newtype {
+                $newtype(::core::default::Default::default())
+            }
+
+            /// Returns the value of the newtype, as the Rust type `T` of its Protobuf type.
+            pub fn to_proto<T>(value: &$newtype) -> T
+            where
+                T: ::core::convert::From<$inner>,
+            {
+                T::from(value.0)
+            }
+
+            fn from_proto<T>(value: T) -> Result<$newtype, DecodeError>
+            where
+                $inner: ::core::convert::TryFrom<T>,
+            {
+                <$inner as ::core::convert::TryFrom<T>>::try_from(value)
+                    .map($newtype)
+                    .map_err(|_| {
+                        DecodeError::new(concat!(
+                            "invalid ",
+                            stringify!($newtype),
+                            " value: out of range"
+                        ))
+                    })
+            }
+
+            pub fn encode<B>(tag: u32, value: &$newtype, buf: &mut B)
+            where
+                B: BufMut,
+            {
+                $crate::encoding::$proto::encode(tag, &to_proto(value), buf)
+            }
+
+            pub fn merge<B>(
+                wire_type: WireType,
+                value: &mut $newtype,
+                buf: &mut B,
+                ctx: DecodeContext,
+            ) -> Result<(), DecodeError>
+            where
+                B: Buf,
+            {
+                let mut proto = ::core::default::Default::default();
+                $crate::encoding::$proto::merge(wire_type, &mut proto, buf, ctx)?;
+                *value = from_proto(proto)?;
+                Ok(())
+            }
+
+            pub fn encode_repeated<B>(tag: u32, values: &[$newtype], buf: &mut B)
+            where
+                B: BufMut,
+            {
+                for value in values {
+                    encode(tag, value, buf);
+                }
+            }
+
+            pub fn encode_packed<B>(tag: u32, values: &[$newtype], buf: &mut B)
+            where
+                B: BufMut,
+            {
+                if values.is_empty() {
+                    return;
+                }
+
+                encode_key(tag, WireType::LengthDelimited, buf);
+                encode_varint(packed_len(values) as u64, buf);
+                for value in values {
+                    // Each value is encoded as a field with tag 1, whose key is a single byte,
+                    // and written without its key.
+                    let mut field = [0u8; 11];
+                    let len = {
+                        let mut slice = &mut field[..];
+                        encode(1, value, &mut slice);
+                        11 - slice.len()
+                    };
+                    buf.put_slice(&field[1..len]);
+                }
+            }
+
+            pub fn merge_repeated<B>(
+                wire_type: WireType,
+                values: &mut Vec<$newtype>,
+                buf: &mut B,
+                ctx: DecodeContext,
+            ) -> Result<(), DecodeError>
+            where
+                B: Buf,
+            {
+                if wire_type == WireType::LengthDelimited {
+                    // Packed.
+                    let mut protos = Vec::new();
+                    $crate::encoding::$proto::merge_repeated(wire_type, &mut protos, buf, ctx)?;
+                    values.reserve(protos.len());
+                    for proto in protos {
+                        values.push(from_proto(proto)?);
+                    }
+                } else {
+                    // Unpacked.
+                    let mut value = default();
+                    merge(wire_type, &mut value, buf, ctx)?;
+                    values.push(value);
+                }
+                Ok(())
+            }
+
+            #[inline]
+            pub fn encoded_len(tag: u32, value: &$newtype) -> usize {
+                $crate::encoding::$proto::encoded_len(tag, &to_proto(value))
+            }
+
+            #[inline]
+            pub fn encoded_len_repeated(tag: u32, values: &[$newtype]) -> usize {
+                values.iter().map(|value| encoded_len(tag, value)).sum()
+            }
+
+            #[inline]
+            pub fn encoded_len_packed(tag: u32, values: &[$newtype]) -> usize {
+                if values.is_empty() {
+                    0
+                } else {
+                    let len = packed_len(values);
+                    key_len(tag) + encoded_len_varint(len as u64) + len
+                }
+            }
+
+            fn packed_len(values: &[$newtype]) -> usize {
+                values.iter().map(|value| encoded_len(1, value) - 1).sum()
+            }
+        }
+    };
+
+    (@length_delimited
+     $(#[$attr:meta])* $vis:vis mod $module:ident: $newtype:ident($inner:ty) as $proto:ident;) => {
+        $(#[$attr])*
+        $vis mod $module {
+            #![allow(dead_code)]
+
+            use super::*;
+
+            use $crate::alloc::vec::Vec;
+            use $crate::bytes::{Buf, BufMut};
+            use $crate::encoding::{DecodeContext, WireType};
+            use $crate::DecodeError;
+
+            /// The wire type of the field's values.
+            pub const WIRE_TYPE: WireType = $crate::encoding::$proto::WIRE_TYPE;
+
+            /// Returns the newtype wrapping the default value.
+            pub fn default() -> $newtype {
+                $newtype(::core::default::Default::default())
+            }
+
+            /// Returns a reference to the value of the newtype.
+            pub fn to_proto(value: &$newtype) -> &$inner {
+                &value.0
+            }
+
+            pub fn encode<B>(tag: u32, value: &$newtype, buf: &mut B)
+            where
+                B: BufMut,
+            {
+                $crate::encoding::$proto::encode(tag, &value.0, buf)
+            }
+
+            pub fn merge<B>(
+                wire_type: WireType,
+                value: &mut $newtype,
+                buf: &mut B,
+                ctx: DecodeContext,
+            ) -> Result<(), DecodeError>
+            where
+                B: Buf,
+            {
+                $crate::encoding::$proto::merge(wire_type, &mut value.0, buf, ctx)
+            }
+
+            pub fn encode_repeated<B>(tag: u32, values: &[$newtype], buf: &mut B)
+            where
+                B: BufMut,
+            {
+                for value in values {
+                    encode(tag, value, buf);
+                }
+            }
+
+            pub fn merge_repeated<B>(
+                wire_type: WireType,
+                values: &mut Vec<$newtype>,
+                buf: &mut B,
+                ctx: DecodeContext,
+            ) -> Result<(), DecodeError>
+            where
+                B: Buf,
+            {
+                let mut value = default();
+                merge(wire_type, &mut value, buf, ctx)?;
+                values.push(value);
+                Ok(())
+            }
+
+            #[inline]
+            pub fn encoded_len(tag: u32, value: &$newtype) -> usize {
+                $crate::encoding::$proto::encoded_len(tag, &value.0)
+            }
+
+            #[inline]
+            pub fn encoded_len_repeated(tag: u32, values: &[$newtype]) -> usize {
+                values.iter().map(|value| encoded_len(tag, value)).sum()
+            }
+        }
+    };
+}
@@ -0,0 +1,64 @@
+//! Presence tracking for proto2 `required` fields.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+
+/// The set of `required` fields which have been set on a message, identified by their tags.
+///
+/// Required fields are stored as plain values, so a message decoded without one of them holds
+/// the field's default value, just as if it had been sent. Messages opt in to tracking their
+/// required fields by including a field of this type annotated with `#[prost(presence)]`: the
+/// tag of every required field is recorded when the field is decoded, and
+/// `Message::check_initialized` reports the required fields whose tags were not recorded.
+///
+/// Fields set directly, rather than decoded, must be recorded with `insert`. Clearing the message
+/// clears the set.
+///
+/// The set is not part of the message's value: all `Presence`s compare equal.
+#[derive(Clone, Default)]
+pub struct Presence {
+    tags: Vec<u32>,
+}
+
+impl Presence {
+    /// Creates an empty `Presence`.
+    pub const fn new() -> Presence {
+        Presence { tags: Vec::new() }
+    }
+
+    /// Returns `true` if the field with the tag has been set.
+    pub fn contains(&self, tag: u32) -> bool {
+        self.tags.contains(&tag)
+    }
+
+    /// Records that the field with the tag has been set.
+    pub fn insert(&mut self, tag: u32) {
+        if !self.contains(tag) {
+            self.tags.push(tag);
+        }
+    }
+
+    /// Forgets all fields which have been set.
+    pub fn clear(&mut self) {
+        self.tags.clear();
+    }
+}
+
+impl fmt::Debug for Presence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(&self.tags).finish()
+    }
+}
+
+impl PartialEq for Presence {
+    fn eq(&self, _other: &Presence) -> bool {
+        true
+    }
+}
+
+impl Eq for Presence {}
+
+impl Hash for Presence {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
@@ -0,0 +1,165 @@
+//! Visiting the fields of messages without knowing their types.
+//!
+//! `Reflect::reflect` calls a `FieldVisitor` method for each field of a message, in tag order,
+//! passing the field's tag, its Protobuf name and its value. This allows tools such as converters
+//! to other formats to be written once, for every message type:
+//!
+//!  * Fields are visited as they would be encoded: fields which hold their default value, unset
+//!    optional fields, empty repeated fields and empty maps are skipped, and a oneof is visited as
+//!    its active field, if any.
+//!  * Each element of a repeated field is visited with the field's tag and name, between calls to
+//!    `begin_repeated` and `end_repeated`.
+//!  * Embedded messages and groups are passed to `visit_message` as a `&dyn Reflect`, which the
+//!    visitor may reflect in turn to recurse into them.
+//!  * Maps are visited as repeated fields of entry messages, each of which has its key as field 1,
+//!    named `key`, and its value as field 2, named `value`. Both are always visited.
+//!  * Extensions and unknown fields retained with `#[prost(unknown_fields)]` are not visited.
+//!
+//! Integer fields are visited with the Rust type of the field, so e.g. `int32`, `sint32` and
+//! `sfixed32` fields are all visited with `visit_i32`.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+
+/// A message whose fields can be visited by a `FieldVisitor`.
+///
+/// This is implemented by `#[derive(Message)]`, and for the well-known wrapper types implemented
+/// by `prost`. It is object safe, so that embedded messages can be passed to visitors without
+/// knowing their types. Hand-written messages must implement it to be embedded in derived
+/// messages.
+///
+/// ```rust
+/// use prost::reflect::{FieldVisitor, Reflect};
+///
+/// #[derive(Clone, PartialEq, prost::Message)]
+/// struct Point {
+///     #[prost(sint32, tag = "1")]
+///     x: i32,
+///     #[prost(sint32, tag = "2")]
+///     y: i32,
+///     #[prost(string, repeated, tag = "3")]
+///     labels: Vec<String>,
+/// }
+///
+/// /// Collects the names of the visited fields.
+/// #[derive(Default)]
+/// struct Names(Vec<String>);
+///
+/// impl FieldVisitor for Names {
+///     fn visit_bool(&mut self, _: u32, name: &str, _: bool) { self.0.push(name.into()) }
+///     fn visit_i32(&mut self, _: u32, name: &str, _: i32) { self.0.push(name.into()) }
+///     fn visit_i64(&mut self, _: u32, name: &str, _: i64) { self.0.push(name.into()) }
+///     fn visit_u32(&mut self, _: u32, name: &str, _: u32) { self.0.push(name.into()) }
+///     fn visit_u64(&mut self, _: u32, name: &str, _: u64) { self.0.push(name.into()) }
+///     fn visit_f32(&mut self, _: u32, name: &str, _: f32) { self.0.push(name.into()) }
+///     fn visit_f64(&mut self, _: u32, name: &str, _: f64) { self.0.push(name.into()) }
+///     fn visit_str(&mut self, _: u32, name: &str, _: &str) { self.0.push(name.into()) }
+///     fn visit_bytes(&mut self, _: u32, name: &str, _: &[u8]) { self.0.push(name.into()) }
+///     fn visit_message(&mut self, _: u32, name: &str, value: &dyn Reflect) {
+///         self.0.push(name.into());
+///         value.reflect(self);
+///     }
+/// }
+///
+/// let point = Point { x: 3, y: 0, labels: vec!["a".into(), "b".into()] };
+/// let mut names = Names::default();
+/// point.reflect(&mut names);
+/// assert_eq!(names.0, ["x", "labels", "labels"]);
+/// ```
+pub trait Reflect {
+    /// Calls the visitor method for each field of the message, in tag order.
+    fn reflect(&self, visitor: &mut dyn FieldVisitor);
+}
+
+impl<M> Reflect for Box<M>
+where
+    M: Reflect,
+{
+    fn reflect(&self, visitor: &mut dyn FieldVisitor) {
+        (**self).reflect(visitor)
+    }
+}
+
+impl<M> Reflect for Arc<M>
+where
+    M: Reflect,
+{
+    fn reflect(&self, visitor: &mut dyn FieldVisitor) {
+        (**self).reflect(visitor)
+    }
+}
+
+/// A visitor of the fields of a message, see `Reflect`.
+///
+/// Each method is passed the tag and the Protobuf name of the field, followed by its value.
+pub trait FieldVisitor {
+    /// Visits a `bool` field.
+    fn visit_bool(&mut self, tag: u32, name: &str, value: bool);
+
+    /// Visits an `int32`, `sint32` or `sfixed32` field.
+    fn visit_i32(&mut self, tag: u32, name: &str, value: i32);
+
+    /// Visits an `int64`, `sint64` or `sfixed64` field.
+    fn visit_i64(&mut self, tag: u32, name: &str, value: i64);
+
+    /// Visits a `uint32` or `fixed32` field.
+    fn visit_u32(&mut self, tag: u32, name: &str, value: u32);
+
+    /// Visits a `uint64` or `fixed64` field.
+    fn visit_u64(&mut self, tag: u32, name: &str, value: u64);
+
+    /// Visits a `float` field.
+    fn visit_f32(&mut self, tag: u32, name: &str, value: f32);
+
+    /// Visits a `double` field.
+    fn visit_f64(&mut self, tag: u32, name: &str, value: f64);
+
+    /// Visits a `string` field.
+    fn visit_str(&mut self, tag: u32, name: &str, value: &str);
+
+    /// Visits a `bytes` field.
+    fn visit_bytes(&mut self, tag: u32, name: &str, value: &[u8]);
+
+    /// Visits an enumeration field, with the name of its variant, or `None` if the value is not a
+    /// known variant.
+    ///
+    /// The default implementation visits the value with `visit_i32`.
+    fn visit_enum(&mut self, tag: u32, name: &str, value: i32, variant: Option<&'static str>) {
+        let _ = variant;
+        self.visit_i32(tag, name, value)
+    }
+
+    /// Visits an embedded message or group field, or a map entry.
+    ///
+    /// The fields of the message are only visited if the visitor calls `value.reflect(self)`.
+    fn visit_message(&mut self, tag: u32, name: &str, value: &dyn Reflect);
+
+    /// Begins a repeated field or map with `len` elements, each of which is visited next.
+    ///
+    /// The default implementation does nothing.
+    fn begin_repeated(&mut self, tag: u32, name: &str, len: usize) {
+        let _ = (tag, name, len);
+    }
+
+    /// Ends a repeated field or map.
+    ///
+    /// The default implementation does nothing.
+    fn end_repeated(&mut self, tag: u32, name: &str) {
+        let _ = (tag, name);
+    }
+}
+
+/// A map entry, whose fields are visited by the wrapped function.
+///
+/// Meant to be used only by `Reflect` implementations.
+#[doc(hidden)]
+pub struct MapEntry<F>(pub F);
+
+impl<F> Reflect for MapEntry<F>
+where
+    F: Fn(&mut dyn FieldVisitor),
+{
+    fn reflect(&self, visitor: &mut dyn FieldVisitor) {
+        (self.0)(visitor)
+    }
+}
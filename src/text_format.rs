@@ -0,0 +1,296 @@
+//! Serialization of messages to the Protobuf text format.
+//!
+//! The output follows `protoc --decode`: each field is written on its own line as
+//! `name: value`, using the field's Protobuf name, and nested messages are written as
+//! `name { ... }` with their fields indented by two spaces. Repeated fields are written as one
+//! line per element, and map entries as nested messages with `key` and `value` fields. Fields
+//! which hold their default value are omitted.
+//!
+//! `string` and `bytes` values are quoted, with non-printable and non-ASCII bytes written as octal
+//! escape sequences. Enumeration values are written by name, or as a number if the value is not a
+//! known variant. Unknown fields retained with `#[prost(unknown_fields)]` are written by tag
+//! number.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::fmt::{self, Write};
+
+use crate::EnumName;
+
+/// A message which can be serialized to the Protobuf text format.
+///
+/// This is implemented by `#[derive(Message)]`, and for the well-known wrapper types implemented
+/// by `prost`. Hand-written messages may implement it to be serialized, or to be embedded in
+/// derived messages which are.
+pub trait ToTextFormat {
+    /// Writes the fields of the message to a text format writer.
+    fn write_text_format(&self, writer: &mut TextFormatWriter);
+
+    /// Serializes the message to a string in the Protobuf text format, as printed by
+    /// `protoc --decode`.
+    fn to_text_format(&self) -> String {
+        let mut writer = TextFormatWriter::new();
+        self.write_text_format(&mut writer);
+        writer.into_string()
+    }
+}
+
+impl<M> ToTextFormat for Box<M>
+where
+    M: ToTextFormat,
+{
+    fn write_text_format(&self, writer: &mut TextFormatWriter) {
+        (**self).write_text_format(writer)
+    }
+}
+
+impl<M> ToTextFormat for Arc<M>
+where
+    M: ToTextFormat,
+{
+    fn write_text_format(&self, writer: &mut TextFormatWriter) {
+        (**self).write_text_format(writer)
+    }
+}
+
+/// A writer which serializes messages to the Protobuf text format.
+///
+/// Messages drive the writer from `ToTextFormat::write_text_format`, which most callers reach
+/// through `ToTextFormat::to_text_format`. Each field is written by a call to `key`,
+/// followed by a single call writing its value.
+#[derive(Debug, Default)]
+pub struct TextFormatWriter {
+    buf: String,
+    indent: usize,
+}
+
+impl TextFormatWriter {
+    /// Creates an empty `TextFormatWriter`.
+    pub fn new() -> TextFormatWriter {
+        TextFormatWriter::default()
+    }
+
+    /// Returns the text written so far.
+    pub fn as_str(&self) -> &str {
+        &self.buf
+    }
+
+    /// Consumes the writer, returning the text written to it.
+    pub fn into_string(self) -> String {
+        self.buf
+    }
+
+    /// Begins a field. The field's value must be written next.
+    pub fn key(&mut self, name: &str) {
+        self.write_indent();
+        self.buf.push_str(name);
+    }
+
+    /// Begins a field identified by its tag number, e.g. an unknown field.
+    pub fn key_tag(&mut self, tag: u32) {
+        self.write_indent();
+        write!(self.buf, "{}", tag).unwrap();
+    }
+
+    /// Writes a `bool` value.
+    pub fn write_bool(&mut self, value: bool) {
+        self.display(value);
+    }
+
+    /// Writes an `int32`, `sint32` or `sfixed32` value.
+    pub fn write_i32(&mut self, value: i32) {
+        self.display(value);
+    }
+
+    /// Writes a `uint32` or `fixed32` value.
+    pub fn write_u32(&mut self, value: u32) {
+        self.display(value);
+    }
+
+    /// Writes an `int64`, `sint64` or `sfixed64` value.
+    pub fn write_i64(&mut self, value: i64) {
+        self.display(value);
+    }
+
+    /// Writes a `uint64` or `fixed64` value.
+    pub fn write_u64(&mut self, value: u64) {
+        self.display(value);
+    }
+
+    /// Writes a `float` value.
+    pub fn write_f32(&mut self, value: f32) {
+        if value.is_finite() {
+            self.display(value);
+        } else {
+            self.write_f64(f64::from(value));
+        }
+    }
+
+    /// Writes a `double` value.
+    pub fn write_f64(&mut self, value: f64) {
+        if value.is_nan() {
+            self.display("nan");
+        } else if value == f64::INFINITY {
+            self.display("inf");
+        } else if value == f64::NEG_INFINITY {
+            self.display("-inf");
+        } else {
+            self.display(value);
+        }
+    }
+
+    /// Writes a `string` value.
+    pub fn write_str(&mut self, value: &str) {
+        self.write_bytes(value.as_bytes());
+    }
+
+    /// Writes a `bytes` value, as a quoted string with octal escape sequences.
+    pub fn write_bytes(&mut self, value: &[u8]) {
+        self.buf.push_str(": ");
+        write_quoted_bytes(&mut self.buf, value).unwrap();
+        self.buf.push('\n');
+    }
+
+    /// Writes a `fixed32` value as a hexadecimal number, as is done for unknown fields.
+    pub fn write_hex_u32(&mut self, value: u32) {
+        self.display(format_args!("0x{:08x}", value));
+    }
+
+    /// Writes a `fixed64` value as a hexadecimal number, as is done for unknown fields.
+    pub fn write_hex_u64(&mut self, value: u64) {
+        self.display(format_args!("0x{:016x}", value));
+    }
+
+    /// Writes the value of an enumeration field, as the name of the variant if it is known.
+    pub fn write_enum<E>(&mut self, value: i32)
+    where
+        E: EnumName,
+    {
+        match E::proto_name(value) {
+            Some(name) => self.display(name),
+            None => self.display(value),
+        }
+    }
+
+    /// Writes a message value, enclosed in braces.
+    pub fn write_message<M>(&mut self, message: &M)
+    where
+        M: ToTextFormat,
+    {
+        self.begin_message();
+        message.write_text_format(self);
+        self.end_message();
+    }
+
+    /// Begins a message value. Its fields must be written next, followed by `end_message`.
+    pub fn begin_message(&mut self) {
+        self.buf.push_str(" {\n");
+        self.indent += 1;
+    }
+
+    /// Ends a message value.
+    pub fn end_message(&mut self) {
+        self.indent -= 1;
+        self.write_indent();
+        self.buf.push_str("}\n");
+    }
+
+    fn write_indent(&mut self) {
+        for _ in 0..self.indent {
+            self.buf.push_str("  ");
+        }
+    }
+
+    fn display<T>(&mut self, value: T)
+    where
+        T: fmt::Display,
+    {
+        writeln!(self.buf, ": {}", value).unwrap();
+    }
+}
+
+/// Writes `value` as a quoted string, with non-printable and non-ASCII bytes written as octal
+/// escape sequences.
+pub(crate) fn write_quoted_bytes<W>(w: &mut W, value: &[u8]) -> fmt::Result
+where
+    W: Write,
+{
+    w.write_char('"')?;
+    for &b in value {
+        match b {
+            b'\n' => w.write_str("\\n")?,
+            b'\r' => w.write_str("\\r")?,
+            b'\t' => w.write_str("\\t")?,
+            b'"' => w.write_str("\\\"")?,
+            b'\'' => w.write_str("\\'")?,
+            b'\\' => w.write_str("\\\\")?,
+            b' '..=b'~' => w.write_char(char::from(b))?,
+            _ => write!(w, "\\{:03o}", b)?,
+        }
+    }
+    w.write_char('"')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write<F>(f: F) -> String
+    where
+        F: FnOnce(&mut TextFormatWriter),
+    {
+        let mut writer = TextFormatWriter::new();
+        f(&mut writer);
+        writer.into_string()
+    }
+
+    #[test]
+    fn nesting() {
+        let text = write(|w| {
+            w.key("a");
+            w.write_i32(1);
+            w.key("b");
+            w.begin_message();
+            w.key("c");
+            w.begin_message();
+            w.key("d");
+            w.write_bool(true);
+            w.end_message();
+            w.key_tag(7);
+            w.write_hex_u32(7);
+            w.end_message();
+            w.key("e");
+            w.write_u64(u64::MAX);
+        });
+        assert_eq!(
+            text,
+            "a: 1\nb {\n  c {\n    d: true\n  }\n  7: 0x00000007\n}\ne: 18446744073709551615\n"
+        );
+    }
+
+    #[test]
+    fn floats() {
+        let text = write(|w| {
+            for &value in &[1.5, -0.25, f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+                w.key("f");
+                w.write_f64(value);
+            }
+        });
+        assert_eq!(text, "f: 1.5\nf: -0.25\nf: nan\nf: inf\nf: -inf\n");
+    }
+
+    #[test]
+    fn escapes() {
+        let text = write(|w| {
+            w.key("s");
+            w.write_str("a\"b'c\\\n\té");
+            w.key("b");
+            w.write_bytes(&[0, 0x7f, b'z', 0xff]);
+        });
+        assert_eq!(
+            text,
+            "s: \"a\\\"b\\'c\\\\\\n\\t\\303\\251\"\nb: \"\\000\\177z\\377\"\n"
+        );
+    }
+}
@@ -15,6 +15,9 @@ use crate::{
         bool, bytes, double, float, int32, int64, skip_field, string, uint32, uint64,
         DecodeContext, WireType,
     },
+    json::{JsonWriter, ToJson},
+    reflect::{FieldVisitor, Reflect},
+    text_format::{TextFormatWriter, ToTextFormat},
     DecodeError, Message,
 };
 
@@ -56,6 +59,29 @@ impl Message for bool {
     }
 }
 
+impl ToJson for bool {
+    fn write_json(&self, writer: &mut JsonWriter) {
+        writer.write_bool(*self);
+    }
+}
+
+impl ToTextFormat for bool {
+    fn write_text_format(&self, writer: &mut TextFormatWriter) {
+        if *self {
+            writer.key("value");
+            writer.write_bool(*self);
+        }
+    }
+}
+
+impl Reflect for bool {
+    fn reflect(&self, visitor: &mut dyn FieldVisitor) {
+        if *self {
+            visitor.visit_bool(1, "value", *self);
+        }
+    }
+}
+
 /// `google.protobuf.UInt32Value`
 impl Message for u32 {
     fn encode_raw<B>(&self, buf: &mut B)
@@ -94,6 +120,29 @@ impl Message for u32 {
     }
 }
 
+impl ToJson for u32 {
+    fn write_json(&self, writer: &mut JsonWriter) {
+        writer.write_u32(*self);
+    }
+}
+
+impl ToTextFormat for u32 {
+    fn write_text_format(&self, writer: &mut TextFormatWriter) {
+        if *self != 0 {
+            writer.key("value");
+            writer.write_u32(*self);
+        }
+    }
+}
+
+impl Reflect for u32 {
+    fn reflect(&self, visitor: &mut dyn FieldVisitor) {
+        if *self != 0 {
+            visitor.visit_u32(1, "value", *self);
+        }
+    }
+}
+
 /// `google.protobuf.UInt64Value`
 impl Message for u64 {
     fn encode_raw<B>(&self, buf: &mut B)
@@ -132,6 +181,29 @@ impl Message for u64 {
     }
 }
 
+impl ToJson for u64 {
+    fn write_json(&self, writer: &mut JsonWriter) {
+        writer.write_u64(*self);
+    }
+}
+
+impl ToTextFormat for u64 {
+    fn write_text_format(&self, writer: &mut TextFormatWriter) {
+        if *self != 0 {
+            writer.key("value");
+            writer.write_u64(*self);
+        }
+    }
+}
+
+impl Reflect for u64 {
+    fn reflect(&self, visitor: &mut dyn FieldVisitor) {
+        if *self != 0 {
+            visitor.visit_u64(1, "value", *self);
+        }
+    }
+}
+
 /// `google.protobuf.Int32Value`
 impl Message for i32 {
     fn encode_raw<B>(&self, buf: &mut B)
@@ -170,6 +242,29 @@ impl Message for i32 {
     }
 }
 
+impl ToJson for i32 {
+    fn write_json(&self, writer: &mut JsonWriter) {
+        writer.write_i32(*self);
+    }
+}
+
+impl ToTextFormat for i32 {
+    fn write_text_format(&self, writer: &mut TextFormatWriter) {
+        if *self != 0 {
+            writer.key("value");
+            writer.write_i32(*self);
+        }
+    }
+}
+
+impl Reflect for i32 {
+    fn reflect(&self, visitor: &mut dyn FieldVisitor) {
+        if *self != 0 {
+            visitor.visit_i32(1, "value", *self);
+        }
+    }
+}
+
 /// `google.protobuf.Int64Value`
 impl Message for i64 {
     fn encode_raw<B>(&self, buf: &mut B)
@@ -208,6 +303,29 @@ impl Message for i64 {
     }
 }
 
+impl ToJson for i64 {
+    fn write_json(&self, writer: &mut JsonWriter) {
+        writer.write_i64(*self);
+    }
+}
+
+impl ToTextFormat for i64 {
+    fn write_text_format(&self, writer: &mut TextFormatWriter) {
+        if *self != 0 {
+            writer.key("value");
+            writer.write_i64(*self);
+        }
+    }
+}
+
+impl Reflect for i64 {
+    fn reflect(&self, visitor: &mut dyn FieldVisitor) {
+        if *self != 0 {
+            visitor.visit_i64(1, "value", *self);
+        }
+    }
+}
+
 /// `google.protobuf.FloatValue`
 impl Message for f32 {
     fn encode_raw<B>(&self, buf: &mut B)
@@ -246,6 +364,29 @@ impl Message for f32 {
     }
 }
 
+impl ToJson for f32 {
+    fn write_json(&self, writer: &mut JsonWriter) {
+        writer.write_f32(*self);
+    }
+}
+
+impl ToTextFormat for f32 {
+    fn write_text_format(&self, writer: &mut TextFormatWriter) {
+        if *self != 0.0 {
+            writer.key("value");
+            writer.write_f32(*self);
+        }
+    }
+}
+
+impl Reflect for f32 {
+    fn reflect(&self, visitor: &mut dyn FieldVisitor) {
+        if *self != 0.0 {
+            visitor.visit_f32(1, "value", *self);
+        }
+    }
+}
+
 /// `google.protobuf.DoubleValue`
 impl Message for f64 {
     fn encode_raw<B>(&self, buf: &mut B)
@@ -284,6 +425,29 @@ impl Message for f64 {
     }
 }
 
+impl ToJson for f64 {
+    fn write_json(&self, writer: &mut JsonWriter) {
+        writer.write_f64(*self);
+    }
+}
+
+impl ToTextFormat for f64 {
+    fn write_text_format(&self, writer: &mut TextFormatWriter) {
+        if *self != 0.0 {
+            writer.key("value");
+            writer.write_f64(*self);
+        }
+    }
+}
+
+impl Reflect for f64 {
+    fn reflect(&self, visitor: &mut dyn FieldVisitor) {
+        if *self != 0.0 {
+            visitor.visit_f64(1, "value", *self);
+        }
+    }
+}
+
 /// `google.protobuf.StringValue`
 impl Message for String {
     fn encode_raw<B>(&self, buf: &mut B)
@@ -322,6 +486,29 @@ impl Message for String {
     }
 }
 
+impl ToJson for String {
+    fn write_json(&self, writer: &mut JsonWriter) {
+        writer.write_str(self);
+    }
+}
+
+impl ToTextFormat for String {
+    fn write_text_format(&self, writer: &mut TextFormatWriter) {
+        if !self.is_empty() {
+            writer.key("value");
+            writer.write_str(self);
+        }
+    }
+}
+
+impl Reflect for String {
+    fn reflect(&self, visitor: &mut dyn FieldVisitor) {
+        if !self.is_empty() {
+            visitor.visit_str(1, "value", self);
+        }
+    }
+}
+
 /// `google.protobuf.BytesValue`
 impl Message for Vec<u8> {
     fn encode_raw<B>(&self, buf: &mut B)
@@ -360,6 +547,29 @@ impl Message for Vec<u8> {
     }
 }
 
+impl ToJson for Vec<u8> {
+    fn write_json(&self, writer: &mut JsonWriter) {
+        writer.write_bytes(self);
+    }
+}
+
+impl ToTextFormat for Vec<u8> {
+    fn write_text_format(&self, writer: &mut TextFormatWriter) {
+        if !self.is_empty() {
+            writer.key("value");
+            writer.write_bytes(self);
+        }
+    }
+}
+
+impl Reflect for Vec<u8> {
+    fn reflect(&self, visitor: &mut dyn FieldVisitor) {
+        if !self.is_empty() {
+            visitor.visit_bytes(1, "value", self);
+        }
+    }
+}
+
 /// `google.protobuf.BytesValue`
 impl Message for Bytes {
     fn encode_raw<B>(&self, buf: &mut B)
@@ -398,6 +608,29 @@ impl Message for Bytes {
     }
 }
 
+impl ToJson for Bytes {
+    fn write_json(&self, writer: &mut JsonWriter) {
+        writer.write_bytes(self);
+    }
+}
+
+impl ToTextFormat for Bytes {
+    fn write_text_format(&self, writer: &mut TextFormatWriter) {
+        if !self.is_empty() {
+            writer.key("value");
+            writer.write_bytes(self);
+        }
+    }
+}
+
+impl Reflect for Bytes {
+    fn reflect(&self, visitor: &mut dyn FieldVisitor) {
+        if !self.is_empty() {
+            visitor.visit_bytes(1, "value", self);
+        }
+    }
+}
+
 /// `google.protobuf.Empty`
 impl Message for () {
     fn encode_raw<B>(&self, _buf: &mut B)
@@ -422,3 +655,18 @@ impl Message for () {
     }
     fn clear(&mut self) {}
 }
+
+impl ToJson for () {
+    fn write_json(&self, writer: &mut JsonWriter) {
+        writer.begin_object();
+        writer.end_object();
+    }
+}
+
+impl ToTextFormat for () {
+    fn write_text_format(&self, _writer: &mut TextFormatWriter) {}
+}
+
+impl Reflect for () {
+    fn reflect(&self, _visitor: &mut dyn FieldVisitor) {}
+}
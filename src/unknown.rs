@@ -0,0 +1,306 @@
+//! Storage for fields which are not recognized by a message.
+
+use alloc::vec::Vec;
+use core::slice;
+
+use ::bytes::{Buf, BufMut, Bytes};
+
+use crate::encoding::{
+    bytes, decode_key, decode_varint, encode_key, encode_varint, encoded_len_varint, key_len,
+    sum_len, DecodeContext, WireType,
+};
+use crate::text_format::TextFormatWriter;
+use crate::DecodeError;
+
+/// A set of Protobuf fields which were not recognized while decoding a message.
+///
+/// Messages opt in to retaining unknown fields by including a field of this type annotated with
+/// `#[prost(unknown_fields)]`. Unknown fields are stored in the order in which they were decoded,
+/// and are re-emitted after all known fields when the message is encoded, so that data added by
+/// newer versions of a schema survives a decode/encode round trip.
+///
+/// Two lists are equal if they hold the same values for each tag, in the same order. The relative
+/// order of fields with different tags is ignored, since it does not change the meaning of the
+/// encoded message, so a message compares equal to a copy decoded from reordered bytes.
+#[derive(Clone, Debug, Default)]
+pub struct UnknownFieldList {
+    fields: Vec<(u32, UnknownField)>,
+}
+
+/// The value of a single unknown field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UnknownField {
+    /// A field with the `Varint` wire type.
+    Varint(u64),
+    /// A field with the `SixtyFourBit` wire type.
+    SixtyFourBit(u64),
+    /// A field with the `LengthDelimited` wire type.
+    LengthDelimited(Bytes),
+    /// A group, containing the unknown fields nested within it.
+    Group(UnknownFieldList),
+    /// A field with the `ThirtyTwoBit` wire type.
+    ThirtyTwoBit(u32),
+}
+
+impl UnknownFieldList {
+    /// Creates an empty `UnknownFieldList`.
+    pub fn new() -> UnknownFieldList {
+        UnknownFieldList::default()
+    }
+
+    /// Returns `true` if no unknown fields have been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Returns the number of unknown fields.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Returns an iterator over the `(tag, value)` pairs of the unknown fields, in decode order.
+    pub fn iter(&self) -> UnknownFieldIter<'_> {
+        UnknownFieldIter {
+            inner: self.fields.iter(),
+        }
+    }
+
+    /// Appends an unknown field.
+    pub fn push(&mut self, tag: u32, field: UnknownField) {
+        self.fields.push((tag, field));
+    }
+
+    /// Removes all unknown fields.
+    pub fn clear(&mut self) {
+        self.fields.clear();
+    }
+
+    /// Removes the fields whose tags do not satisfy the predicate.
+    pub(crate) fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(u32) -> bool,
+    {
+        self.fields.retain(|&(tag, _)| f(tag));
+    }
+
+    /// Decodes an unknown field from a buffer, and appends it to the list.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn merge_field<B>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        B: Buf,
+    {
+        let field = match wire_type {
+            WireType::Varint => UnknownField::Varint(decode_varint(buf)?),
+            WireType::SixtyFourBit => {
+                if buf.remaining() < 8 {
+                    return Err(DecodeError::new("buffer underflow"));
+                }
+                UnknownField::SixtyFourBit(buf.get_u64_le())
+            }
+            WireType::LengthDelimited => {
+                let mut value = Bytes::new();
+                bytes::merge(wire_type, &mut value, buf, ctx)?;
+                UnknownField::LengthDelimited(value)
+            }
+            WireType::StartGroup => {
+                ctx.limit_reached()?;
+                let mut group = UnknownFieldList::new();
+                loop {
+                    let (field_tag, field_wire_type) = decode_key(buf)?;
+                    if field_wire_type == WireType::EndGroup {
+                        if field_tag != tag {
+                            return Err(DecodeError::new("unexpected end group tag"));
+                        }
+                        break;
+                    }
+                    group.merge_field(field_tag, field_wire_type, buf, ctx.enter_recursion())?;
+                }
+                UnknownField::Group(group)
+            }
+            WireType::EndGroup => return Err(DecodeError::new("unexpected end group tag")),
+            WireType::ThirtyTwoBit => {
+                if buf.remaining() < 4 {
+                    return Err(DecodeError::new("buffer underflow"));
+                }
+                UnknownField::ThirtyTwoBit(buf.get_u32_le())
+            }
+        };
+        self.push(tag, field);
+        Ok(())
+    }
+
+    /// Encodes the unknown fields to a buffer.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn encode_raw<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        for &(tag, ref field) in &self.fields {
+            field.encode(tag, buf, false);
+        }
+    }
+
+    /// Encodes the unknown fields to a buffer in ascending tag order, preserving the order of
+    /// fields which share a tag. The fields of unknown groups are sorted in the same way.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn encode_canonical_raw<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        let mut fields: Vec<_> = self.fields.iter().collect();
+        fields.sort_by_key(|&&(tag, _)| tag);
+        for &&(tag, ref field) in &fields {
+            field.encode(tag, buf, true);
+        }
+    }
+
+    /// Returns the encoded length of the unknown fields.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn encoded_len(&self) -> usize {
+        sum_len(
+            self.fields
+                .iter()
+                .map(|&(tag, ref field)| field.encoded_len(tag)),
+        )
+    }
+
+    /// Reports the encoded length of each unknown field to `record`, under its own tag, see
+    /// `Message::encode_with_stats`.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn field_lens(&self, path: &mut Vec<u32>, record: &mut dyn FnMut(&[u32], usize)) {
+        for &(tag, ref field) in &self.fields {
+            path.push(tag);
+            record(path, field.encoded_len(tag));
+            path.pop();
+        }
+    }
+
+    /// Writes the unknown fields to a text format writer, identified by their tags.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn write_text_format(&self, writer: &mut TextFormatWriter) {
+        for &(tag, ref field) in &self.fields {
+            writer.key_tag(tag);
+            match *field {
+                UnknownField::Varint(value) => writer.write_u64(value),
+                UnknownField::SixtyFourBit(value) => writer.write_hex_u64(value),
+                UnknownField::LengthDelimited(ref value) => writer.write_bytes(value),
+                UnknownField::Group(ref group) => {
+                    writer.begin_message();
+                    group.write_text_format(writer);
+                    writer.end_message();
+                }
+                UnknownField::ThirtyTwoBit(value) => writer.write_hex_u32(value),
+            }
+        }
+    }
+}
+
+impl UnknownField {
+    fn encode<B>(&self, tag: u32, buf: &mut B, canonical: bool)
+    where
+        B: BufMut,
+    {
+        match *self {
+            UnknownField::Varint(value) => {
+                encode_key(tag, WireType::Varint, buf);
+                encode_varint(value, buf);
+            }
+            UnknownField::SixtyFourBit(value) => {
+                encode_key(tag, WireType::SixtyFourBit, buf);
+                buf.put_u64_le(value);
+            }
+            UnknownField::LengthDelimited(ref value) => {
+                bytes::encode(tag, value, buf);
+            }
+            UnknownField::Group(ref group) => {
+                encode_key(tag, WireType::StartGroup, buf);
+                if canonical {
+                    group.encode_canonical_raw(buf);
+                } else {
+                    group.encode_raw(buf);
+                }
+                encode_key(tag, WireType::EndGroup, buf);
+            }
+            UnknownField::ThirtyTwoBit(value) => {
+                encode_key(tag, WireType::ThirtyTwoBit, buf);
+                buf.put_u32_le(value);
+            }
+        }
+    }
+
+    fn encoded_len(&self, tag: u32) -> usize {
+        match *self {
+            UnknownField::Varint(value) => key_len(tag) + encoded_len_varint(value),
+            UnknownField::SixtyFourBit(_) => key_len(tag) + 8,
+            UnknownField::LengthDelimited(ref value) => bytes::encoded_len(tag, value),
+            UnknownField::Group(ref group) => group.encoded_len().saturating_add(2 * key_len(tag)),
+            UnknownField::ThirtyTwoBit(_) => key_len(tag) + 4,
+        }
+    }
+}
+
+impl PartialEq for UnknownFieldList {
+    fn eq(&self, other: &UnknownFieldList) -> bool {
+        if self.fields.len() != other.fields.len() {
+            return false;
+        }
+        if self.fields == other.fields {
+            return true;
+        }
+        // The sort is stable, so fields sharing a tag keep their relative order.
+        let mut fields: Vec<_> = self.fields.iter().collect();
+        let mut other_fields: Vec<_> = other.fields.iter().collect();
+        fields.sort_by_key(|&&(tag, _)| tag);
+        other_fields.sort_by_key(|&&(tag, _)| tag);
+        fields == other_fields
+    }
+}
+
+impl Eq for UnknownFieldList {}
+
+impl<'a> IntoIterator for &'a UnknownFieldList {
+    type Item = (u32, &'a UnknownField);
+    type IntoIter = UnknownFieldIter<'a>;
+
+    fn into_iter(self) -> UnknownFieldIter<'a> {
+        self.iter()
+    }
+}
+
+/// An iterator over the fields of an `UnknownFieldList`.
+#[derive(Clone, Debug)]
+pub struct UnknownFieldIter<'a> {
+    inner: slice::Iter<'a, (u32, UnknownField)>,
+}
+
+impl<'a> Iterator for UnknownFieldIter<'a> {
+    type Item = (u32, &'a UnknownField);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|&(tag, ref field)| (tag, field))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for UnknownFieldIter<'a> {}
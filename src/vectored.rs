@@ -0,0 +1,189 @@
+//! Encoding of messages to a list of chunks, for vectored writes.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use bytes::buf::UninitSlice;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// The default for the length from which values are stored as separate chunks.
+const DEFAULT_MIN_SHARED_LEN: usize = 512;
+
+/// A `BufMut` which collects an encoding as a list of chunks, rather than in a single contiguous
+/// buffer, for vectored writes.
+///
+/// Each value of a `bytes` field which is at least `min_shared_len` bytes long becomes a chunk of
+/// its own. Values represented as `Bytes` are not copied: their chunk shares the field's storage.
+/// The rest of the encoding, such as keys, length delimiters and smaller values, is copied into
+/// the chunks between them.
+///
+/// ```rust
+/// use std::io::IoSlice;
+///
+/// use prost::bytes::Bytes;
+/// use prost::{Message, VectoredBuf};
+///
+/// #[derive(Clone, PartialEq, Message)]
+/// struct Upload {
+///     #[prost(string, tag = "1")]
+///     name: String,
+///     #[prost(bytes = "bytes", tag = "2")]
+///     data: Bytes,
+/// }
+///
+/// let upload = Upload {
+///     name: "blob".to_string(),
+///     data: Bytes::from(vec![7; 4096]),
+/// };
+/// let mut buf = VectoredBuf::new();
+/// upload.encode_length_delimited(&mut buf).unwrap();
+/// let chunks = buf.into_chunks();
+///
+/// // The delimiter, the name and the key of the data field, followed by the data itself.
+/// assert_eq!(chunks.len(), 2);
+/// assert_eq!(chunks[1].as_ptr(), upload.data.as_ptr());
+///
+/// let slices = chunks.iter().map(|chunk| IoSlice::new(chunk)).collect::<Vec<_>>();
+/// # let _ = slices;
+/// ```
+pub struct VectoredBuf {
+    chunks: Vec<Bytes>,
+    current: BytesMut,
+    min_shared_len: usize,
+}
+
+impl VectoredBuf {
+    /// Creates an empty `VectoredBuf`, which shares the storage of values of at least 512 bytes.
+    pub fn new() -> VectoredBuf {
+        VectoredBuf::with_min_shared_len(DEFAULT_MIN_SHARED_LEN)
+    }
+
+    /// Creates an empty `VectoredBuf`, which shares the storage of values of at least
+    /// `min_shared_len` bytes.
+    ///
+    /// Each shared value ends the current chunk, so a small `min_shared_len` yields many small
+    /// chunks.
+    pub fn with_min_shared_len(min_shared_len: usize) -> VectoredBuf {
+        VectoredBuf {
+            chunks: Vec::new(),
+            current: BytesMut::new(),
+            min_shared_len,
+        }
+    }
+
+    /// Returns the total length of the chunks written so far.
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(Bytes::len).sum::<usize>() + self.current.len()
+    }
+
+    /// Returns `true` if nothing has been written.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Consumes the buffer, returning the chunks written to it, in order. Chunks are never empty.
+    pub fn into_chunks(mut self) -> Vec<Bytes> {
+        self.end_chunk();
+        self.chunks
+    }
+
+    fn end_chunk(&mut self) {
+        if !self.current.is_empty() {
+            self.chunks.push(self.current.split().freeze());
+        }
+    }
+}
+
+impl Default for VectoredBuf {
+    fn default() -> VectoredBuf {
+        VectoredBuf::new()
+    }
+}
+
+unsafe impl BufMut for VectoredBuf {
+    fn remaining_mut(&self) -> usize {
+        self.current.remaining_mut()
+    }
+
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        self.current.advance_mut(cnt)
+    }
+
+    fn chunk_mut(&mut self) -> &mut UninitSlice {
+        self.current.chunk_mut()
+    }
+
+    fn put<T>(&mut self, mut src: T)
+    where
+        T: Buf,
+        Self: Sized,
+    {
+        let len = src.remaining();
+        if len == 0 || len < self.min_shared_len {
+            self.current.put(src);
+        } else {
+            // `Bytes` returns a handle to its own storage, rather than a copy.
+            self.end_chunk();
+            self.chunks.push(src.copy_to_bytes(len));
+        }
+    }
+
+    fn put_slice(&mut self, src: &[u8]) {
+        self.current.extend_from_slice(src);
+    }
+}
+
+impl fmt::Debug for VectoredBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VectoredBuf")
+            .field("chunks", &self.chunks.len())
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec;
+
+    use crate::encoding::{bytes, int32};
+
+    use super::*;
+
+    #[test]
+    fn shares_large_values() {
+        let large = Bytes::from(vec![1u8; 16]);
+        let small = Bytes::from(vec![2u8; 4]);
+
+        let mut buf = VectoredBuf::with_min_shared_len(16);
+        int32::encode(1, &5, &mut buf);
+        bytes::encode(2, &large, &mut buf);
+        bytes::encode(3, &small, &mut buf);
+        bytes::encode(4, &large, &mut buf);
+        bytes::encode(5, &vec![3u8; 16], &mut buf);
+        assert_eq!(buf.len(), 2 + 18 + 6 + 18 + 18);
+
+        let mut expected = Vec::new();
+        int32::encode(1, &5, &mut expected);
+        bytes::encode(2, &large, &mut expected);
+        bytes::encode(3, &small, &mut expected);
+        bytes::encode(4, &large, &mut expected);
+        bytes::encode(5, &vec![3u8; 16], &mut expected);
+
+        let chunks = buf.into_chunks();
+        assert_eq!(chunks.concat(), expected);
+        assert_eq!(
+            chunks.iter().map(Bytes::len).collect::<Vec<_>>(),
+            [4, 16, 8, 16, 2, 16]
+        );
+        assert_eq!(chunks[1].as_ptr(), large.as_ptr());
+        assert_eq!(chunks[3].as_ptr(), large.as_ptr());
+    }
+
+    #[test]
+    fn empty() {
+        let buf = VectoredBuf::new();
+        assert!(buf.is_empty());
+        assert!(buf.into_chunks().is_empty());
+    }
+}
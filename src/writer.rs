@@ -0,0 +1,119 @@
+//! Encoding of messages to an `io::Write`.
+
+use alloc::vec::Vec;
+use std::io::{self, Write};
+
+use bytes::buf::UninitSlice;
+use bytes::BufMut;
+
+/// The number of bytes buffered before they are written to the writer.
+const CHUNK_LEN: usize = 8 * 1024;
+
+/// A `BufMut` which streams the bytes put into it to an `io::Write`, through a fixed-size buffer.
+///
+/// `BufMut` methods can not fail, so the first error returned by the writer is stored, and
+/// returned by `finish`. Bytes put after an error are discarded.
+pub(crate) struct WriterBuf<'a, W> {
+    writer: &'a mut W,
+    buf: Vec<u8>,
+    error: Option<io::Error>,
+}
+
+impl<'a, W> WriterBuf<'a, W>
+where
+    W: Write,
+{
+    pub(crate) fn new(writer: &'a mut W) -> WriterBuf<'a, W> {
+        WriterBuf {
+            writer,
+            buf: Vec::with_capacity(CHUNK_LEN),
+            error: None,
+        }
+    }
+
+    /// Writes the buffered bytes to the writer, returning the first error encountered.
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        self.flush_buf();
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    fn flush_buf(&mut self) {
+        if self.error.is_none() {
+            if let Err(error) = self.writer.write_all(&self.buf) {
+                self.error = Some(error);
+            }
+        }
+        self.buf.clear();
+    }
+}
+
+unsafe impl<'a, W> BufMut for WriterBuf<'a, W>
+where
+    W: Write,
+{
+    fn remaining_mut(&self) -> usize {
+        usize::MAX
+    }
+
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        self.buf.advance_mut(cnt)
+    }
+
+    fn chunk_mut(&mut self) -> &mut UninitSlice {
+        if self.buf.len() == self.buf.capacity() {
+            self.flush_buf();
+        }
+        // The buffer has spare capacity, so the chunk is never reallocated.
+        self.buf.chunk_mut()
+    }
+
+    fn put_slice(&mut self, src: &[u8]) {
+        if src.len() > self.buf.capacity() - self.buf.len() {
+            self.flush_buf();
+        }
+        if src.len() >= self.buf.capacity() {
+            // Large slices, e.g. `bytes` fields, are written without copying them to the buffer.
+            if self.error.is_none() {
+                if let Err(error) = self.writer.write_all(src) {
+                    self.error = Some(error);
+                }
+            }
+        } else {
+            self.buf.extend_from_slice(src);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn chunks() {
+        let data: Vec<u8> = (0..3 * CHUNK_LEN + 17).map(|i| i as u8).collect();
+
+        let mut out = Vec::new();
+        let mut buf = WriterBuf::new(&mut out);
+        for &b in &data[..CHUNK_LEN + 1] {
+            buf.put_u8(b);
+        }
+        buf.put_slice(&data[CHUNK_LEN + 1..CHUNK_LEN + 10]);
+        buf.put_slice(&data[CHUNK_LEN + 10..]);
+        buf.finish().unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn error() {
+        let mut array = [0u8; 4];
+        let mut out = &mut array[..];
+        let mut buf = WriterBuf::new(&mut out);
+        buf.put_slice(&[1; CHUNK_LEN]);
+        buf.put_u8(2);
+        let error = buf.finish().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WriteZero);
+    }
+}
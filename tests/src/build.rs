@@ -70,6 +70,10 @@ fn main() {
         .compile_protos(&[src.join("oneof_attributes.proto")], includes)
         .unwrap();
 
+    config
+        .compile_protos(&[src.join("oneof_case.proto")], includes)
+        .unwrap();
+
     config
         .compile_protos(&[src.join("no_unused_results.proto")], includes)
         .unwrap();
@@ -90,6 +94,11 @@ fn main() {
         .compile_protos(&[src.join("default_string_escape.proto")], includes)
         .unwrap();
 
+    prost_build::Config::new()
+        .type_names(&["."])
+        .compile_protos(&[src.join("type_names.proto")], includes)
+        .unwrap();
+
     prost_build::Config::new()
         .protoc_arg("--experimental_allow_proto3_optional")
         .compile_protos(&[src.join("proto3_presence.proto")], includes)
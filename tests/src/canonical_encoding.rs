@@ -0,0 +1,198 @@
+//! Tests for `Message::encode_canonical`.
+
+#![cfg(feature = "std")]
+
+use std::collections::HashMap;
+
+use prost::alloc::{borrow::ToOwned, string::String, vec::Vec};
+use prost::{Message, Oneof, RawFieldIter, UnknownFieldList};
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Entry {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(hash_map = "uint32, string", tag = "2")]
+    pub attributes: HashMap<u32, String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Document {
+    #[prost(int32, tag = "1")]
+    pub id: i32,
+    #[prost(oneof = "Body", tags = "2, 5")]
+    pub body: Option<Body>,
+    #[prost(string, tag = "3")]
+    pub title: String,
+    #[prost(hash_map = "string, message", tag = "4")]
+    pub entries: HashMap<String, Entry>,
+    #[prost(unknown_fields)]
+    pub unknown_fields: UnknownFieldList,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+pub enum Body {
+    #[prost(string, tag = "2")]
+    Text(String),
+    #[prost(message, tag = "5")]
+    Entry(Entry),
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Record {
+    #[prost(uint32, repeated, tag = "1")]
+    pub ids: Vec<u32>,
+    #[prost(message, optional, tag = "2")]
+    pub owner: Option<Entry>,
+    #[prost(string, repeated, tag = "3")]
+    pub aliases: Vec<String>,
+    #[prost(bool, tag = "4")]
+    pub active: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Signed {
+    #[prost(hash_map = "string, string", deterministic, tag = "1")]
+    pub labels: HashMap<String, String>,
+    #[prost(hash_map = "int32, string", deterministic, tag = "2")]
+    pub codes: HashMap<i32, String>,
+}
+
+fn tags(buf: &[u8]) -> Vec<u32> {
+    RawFieldIter::new(buf)
+        .map(|field| field.unwrap().tag)
+        .collect()
+}
+
+fn entry(name: &str, keys: impl Iterator<Item = u32>) -> Entry {
+    Entry {
+        name: name.to_owned(),
+        attributes: keys.map(|key| (key, key.to_string())).collect(),
+    }
+}
+
+#[test]
+fn canonical_maps_are_sorted() {
+    let forward = entry("e", 1..=32);
+    let mut reverse = entry("e", (1..=32).rev());
+    reverse.attributes.reserve(64);
+    assert_eq!(forward, reverse);
+
+    let buf = forward.encode_canonical_to_vec();
+    assert_eq!(buf, reverse.encode_canonical_to_vec());
+    assert_eq!(buf.len(), forward.encoded_len());
+    assert_eq!(Entry::decode(&*buf).unwrap(), forward);
+
+    // Each raw map entry is its tag, length and the key's tag, followed by the single-byte key.
+    let keys = RawFieldIter::new(&buf)
+        .skip(1)
+        .map(|field| u32::from(field.unwrap().raw[3]))
+        .collect::<Vec<_>>();
+    assert_eq!(keys, (1..=32).collect::<Vec<_>>());
+}
+
+#[test]
+fn canonical_fields_are_in_tag_order() {
+    let mut document = Document {
+        id: 1,
+        body: Some(Body::Entry(entry("body", 1..=8))),
+        title: "title".to_owned(),
+        ..Document::default()
+    };
+    for i in 0..8 {
+        document
+            .entries
+            .insert(i.to_string(), entry(&i.to_string(), 1..=8));
+    }
+
+    // The oneof is written at the position of its lowest tag by `encode`.
+    assert_eq!(tags(&document.encode_to_vec())[..3], [1, 5, 3]);
+
+    // The map is written after all singular fields, including the oneof.
+    let buf = document.encode_canonical_to_vec();
+    let mut expected = vec![1, 3, 5];
+    expected.extend(vec![4; 8]);
+    assert_eq!(tags(&buf), expected);
+    assert_eq!(Document::decode(&*buf).unwrap(), document);
+
+    // Nested maps are sorted too, so equal documents have equal encodings.
+    let mut copy = Document::decode(&*document.encode_to_vec()).unwrap();
+    copy.entries.shrink_to_fit();
+    assert_eq!(copy.encode_canonical_to_vec(), buf);
+
+    let mut capacity = [0u8; 8];
+    document
+        .encode_canonical(&mut &mut capacity[..])
+        .expect_err("insufficient capacity");
+}
+
+#[test]
+fn canonical_singular_fields_come_first() {
+    let record = Record {
+        ids: vec![3, 1, 2],
+        owner: Some(entry("owner", 1..=2)),
+        aliases: vec!["b".to_owned(), "a".to_owned()],
+        active: true,
+    };
+    assert_eq!(tags(&record.encode_to_vec()), [1, 2, 3, 3, 4]);
+
+    // Repeated fields keep the order of their values.
+    let buf = record.encode_canonical_to_vec();
+    assert_eq!(tags(&buf), [2, 4, 1, 3, 3]);
+    assert_eq!(buf.len(), record.encoded_len());
+    assert_eq!(Record::decode(&*buf).unwrap(), record);
+}
+
+#[test]
+fn canonical_unknown_fields_are_sorted() {
+    // Field 21, varint 1, field 20, varint 2, and field 21, varint 3.
+    let buf: &[u8] = &[0xa8, 0x01, 0x01, 0xa0, 0x01, 0x02, 0xa8, 0x01, 0x03];
+    let document = Document::decode(buf).unwrap();
+    assert_eq!(document.encode_to_vec(), buf);
+    assert_eq!(
+        document.encode_canonical_to_vec(),
+        [0xa0, 0x01, 0x02, 0xa8, 0x01, 0x01, 0xa8, 0x01, 0x03]
+    );
+}
+
+#[test]
+fn deterministic_maps_are_sorted_by_key() {
+    let mut signed = Signed::default();
+    for label in ["aa", "b", "ccc", "a"] {
+        signed.labels.insert(label.to_owned(), String::new());
+    }
+    for code in [-1, 200, 300, 1] {
+        signed.codes.insert(code, String::new());
+    }
+
+    let buf = signed.encode_to_vec();
+    assert_eq!(buf.len(), signed.encoded_len());
+    assert_eq!(Signed::decode(&*buf).unwrap(), signed);
+
+    // Each raw map entry is its tag and length, followed by the encoded key, as the values are
+    // empty.
+    let keys = RawFieldIter::new(&buf)
+        .map(|field| {
+            let field = field.unwrap();
+            (field.tag, field.raw[2..].to_vec())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        keys,
+        [
+            (1, b"\x0a\x01a".to_vec()),
+            (1, b"\x0a\x02aa".to_vec()),
+            (1, b"\x0a\x01b".to_vec()),
+            (1, b"\x0a\x03ccc".to_vec()),
+            (
+                2,
+                vec![0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]
+            ),
+            (2, vec![0x08, 0x01]),
+            (2, vec![0x08, 0xc8, 0x01]),
+            (2, vec![0x08, 0xac, 0x02]),
+        ]
+    );
+
+    // Deterministic and canonical encoding agree on the order.
+    assert_eq!(signed.encode_canonical_to_vec(), buf);
+}
@@ -51,7 +51,7 @@ fn basic() {
          enumeration_btree_map: {}, \
          string_btree_map: {}, \
          oneof: None, \
-         bytes_map: {\"hello\": [119, 111, 114, 108, 100]} \
+         bytes_map: {\"hello\": \"world\"} \
          }"
     );
 }
@@ -95,3 +95,37 @@ fn oneof_with_enum() {
         "MessageWithOneof { of: Some(Enumeration(TWO)) }"
     );
 }
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct Blobs {
+    #[prost(bytes = "vec", tag = "1")]
+    vec: prost::alloc::vec::Vec<u8>,
+    #[prost(bytes = "bytes", optional, tag = "2")]
+    bytes: Option<prost::bytes::Bytes>,
+    #[prost(bytes = "array", repeated, tag = "3")]
+    arrays: prost::alloc::vec::Vec<[u8; 2]>,
+    #[prost(btree_map = "string, bytes", tag = "4")]
+    map: prost::alloc::collections::BTreeMap<String, prost::alloc::vec::Vec<u8>>,
+}
+
+/// Bytes fields are written as escaped strings, truncated if they are long
+#[test]
+fn bytes() {
+    let mut msg = Blobs {
+        vec: prost::alloc::vec![0xab; 1 << 20],
+        bytes: Some(prost::bytes::Bytes::from_static(b"\"quoted\"\n")),
+        arrays: prost::alloc::vec![[0, 1], [b'o', b'k']],
+        ..Blobs::default()
+    };
+    msg.map.insert("key".into(), b"\x7f".to_vec());
+    assert_eq!(
+        format!("{:?}", msg),
+        "Blobs { \
+         vec: \"\\253\\253\\253\\253\\253\\253\\253\\253\\253\\253\\253\\253\\253\\253\\253\\253\
+         \\253\\253\\253\\253\\253\\253\\253\\253\\253\\253\\253\\253\\253\\253\\253\\253\"... (1048576 bytes), \
+         bytes: Some(\"\\\"quoted\\\"\\n\"), \
+         arrays: [\"\\000\\001\", \"ok\"], \
+         map: {\"key\": \"\\177\"} \
+         }"
+    );
+}
@@ -0,0 +1,138 @@
+//! Tests for proto2 extensions stored with `#[prost(extensions = "...")]`.
+
+use prost::alloc::{borrow::ToOwned, string::String, vec::Vec};
+use prost::bytes::{Buf, BufMut};
+use prost::encoding::{int32, message, DecodeContext, WireType};
+use prost::{DecodeError, Extension, ExtensionSet, Message, UnknownFieldList};
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Request {
+    #[prost(string, tag = "1")]
+    pub path: String,
+    #[prost(extensions = "100 to 199, 1000 to max")]
+    pub extensions: ExtensionSet,
+    #[prost(unknown_fields)]
+    pub unknown_fields: UnknownFieldList,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Origin {
+    #[prost(string, tag = "1")]
+    pub host: String,
+}
+
+/// `extend Request { optional int32 priority = 100; }`
+struct Priority;
+
+impl Extension for Priority {
+    type Value = i32;
+    const NUMBER: u32 = 100;
+
+    fn encode<B: BufMut>(value: &i32, buf: &mut B) {
+        int32::encode(Self::NUMBER, value, buf);
+    }
+
+    fn merge<B: Buf>(
+        wire_type: WireType,
+        value: &mut i32,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        int32::merge(wire_type, value, buf, ctx)
+    }
+}
+
+/// `extend Request { optional Origin origin = 5000; }`
+struct OriginExtension;
+
+impl Extension for OriginExtension {
+    type Value = Origin;
+    const NUMBER: u32 = 5000;
+
+    fn encode<B: BufMut>(value: &Origin, buf: &mut B) {
+        message::encode(Self::NUMBER, value, buf);
+    }
+
+    fn merge<B: Buf>(
+        wire_type: WireType,
+        value: &mut Origin,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        message::merge(wire_type, value, buf, ctx)
+    }
+}
+
+#[test]
+fn extensions_round_trip() {
+    let mut request = Request {
+        path: "/".to_owned(),
+        ..Request::default()
+    };
+    request.extensions.set_extension::<Priority>(&3);
+    request
+        .extensions
+        .set_extension::<OriginExtension>(&Origin {
+            host: "example.com".to_owned(),
+        });
+
+    let buf = request.encode_to_vec();
+    assert_eq!(buf.len(), request.encoded_len());
+
+    let decoded = Request::decode(&*buf).unwrap();
+    assert_eq!(decoded, request);
+    assert!(decoded.unknown_fields.is_empty());
+    assert_eq!(
+        decoded.extensions.get_extension::<Priority>().unwrap(),
+        Some(3)
+    );
+    assert_eq!(
+        decoded
+            .extensions
+            .get_extension::<OriginExtension>()
+            .unwrap()
+            .unwrap()
+            .host,
+        "example.com"
+    );
+}
+
+#[test]
+fn fields_outside_extension_ranges_are_unknown() {
+    // Field 50, varint 1, and field 150, varint 2.
+    let buf: &[u8] = &[0x90, 0x03, 0x01, 0xb0, 0x09, 0x02];
+    let request = Request::decode(buf).unwrap();
+    assert_eq!(request.unknown_fields.len(), 1);
+    assert_eq!(
+        request.extensions.get_extension::<Priority>().unwrap(),
+        None
+    );
+    assert!(!request.extensions.is_empty());
+
+    let mut request = request;
+    request.clear();
+    assert!(request.extensions.is_empty());
+    assert!(request.unknown_fields.is_empty());
+}
+
+#[test]
+fn repeated_message_extension_occurrences_are_merged() {
+    let mut buf = Vec::new();
+    OriginExtension::encode(
+        &Origin {
+            host: "first".to_owned(),
+        },
+        &mut buf,
+    );
+    OriginExtension::encode(&Origin::default(), &mut buf);
+    let request = Request::decode(&*buf).unwrap();
+    assert_eq!(
+        request
+            .extensions
+            .get_extension::<OriginExtension>()
+            .unwrap(),
+        Some(Origin {
+            host: "first".to_owned(),
+        })
+    );
+}
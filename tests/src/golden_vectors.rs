@@ -0,0 +1,258 @@
+//! Encodings produced by other Protobuf implementations.
+//!
+//! The `Test1` to `Test4` vectors are the examples of the Protobuf encoding guide
+//! (https://developers.google.com/protocol-buffers/docs/encoding), as written by the reference
+//! C++ implementation. The remaining vectors, for the values at the boundaries of each varint
+//! length and for negative numbers, are fully determined by the encoding rules, which leave
+//! conforming implementations no choice in how they are written.
+
+use prost::alloc::{string::String, vec, vec::Vec};
+use prost::Message;
+
+#[derive(Clone, PartialEq, Message)]
+struct Test1 {
+    #[prost(int32, tag = "1")]
+    a: i32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Test2 {
+    #[prost(string, tag = "2")]
+    b: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Test3 {
+    #[prost(message, optional, tag = "3")]
+    c: Option<Test1>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Test4 {
+    #[prost(int32, repeated, tag = "4")]
+    d: Vec<i32>,
+}
+
+/// One field of each integer type, for single-field vectors.
+#[derive(Clone, PartialEq, Message)]
+struct Integers {
+    #[prost(uint64, tag = "1")]
+    uint64: u64,
+    #[prost(int32, tag = "2")]
+    int32: i32,
+    #[prost(int64, tag = "3")]
+    int64: i64,
+    #[prost(sint32, tag = "4")]
+    sint32: i32,
+    #[prost(sint64, tag = "5")]
+    sint64: i64,
+    #[prost(sfixed32, tag = "6")]
+    sfixed32: i32,
+    #[prost(sfixed64, tag = "7")]
+    sfixed64: i64,
+}
+
+/// Checks that `encoded` decodes to `expected`, and that `expected` encodes to `encoded`.
+fn check_golden<M>(expected: M, encoded: &[u8])
+where
+    M: Message + Default + PartialEq,
+{
+    assert_eq!(M::decode(encoded).unwrap(), expected, "{:02x?}", encoded);
+    assert_eq!(expected.encode_to_vec(), encoded, "{:?}", expected);
+    assert_eq!(expected.encoded_len(), encoded.len());
+}
+
+#[test]
+fn encoding_guide() {
+    check_golden(Test1 { a: 150 }, &[0x08, 0x96, 0x01]);
+    check_golden(
+        Test2 {
+            b: "testing".into(),
+        },
+        &[0x12, 0x07, 0x74, 0x65, 0x73, 0x74, 0x69, 0x6e, 0x67],
+    );
+    check_golden(
+        Test3 {
+            c: Some(Test1 { a: 150 }),
+        },
+        &[0x1a, 0x03, 0x08, 0x96, 0x01],
+    );
+    check_golden(
+        Test4 {
+            d: vec![3, 270, 86942],
+        },
+        &[0x22, 0x06, 0x03, 0x8e, 0x02, 0x9e, 0xa7, 0x05],
+    );
+}
+
+#[test]
+fn varint_boundaries() {
+    let vectors: &[(u64, &[u8])] = &[
+        (1, &[0x01]),
+        (127, &[0x7f]),
+        (128, &[0x80, 0x01]),
+        (16_383, &[0xff, 0x7f]),
+        (16_384, &[0x80, 0x80, 0x01]),
+        (2_097_151, &[0xff, 0xff, 0x7f]),
+        (2_097_152, &[0x80, 0x80, 0x80, 0x01]),
+        (268_435_455, &[0xff, 0xff, 0xff, 0x7f]),
+        (268_435_456, &[0x80, 0x80, 0x80, 0x80, 0x01]),
+        (34_359_738_367, &[0xff, 0xff, 0xff, 0xff, 0x7f]),
+        (34_359_738_368, &[0x80, 0x80, 0x80, 0x80, 0x80, 0x01]),
+        (4_398_046_511_103, &[0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]),
+        (
+            4_398_046_511_104,
+            &[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01],
+        ),
+        (
+            562_949_953_421_311,
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f],
+        ),
+        (
+            562_949_953_421_312,
+            &[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01],
+        ),
+        (
+            72_057_594_037_927_935,
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f],
+        ),
+        (
+            72_057_594_037_927_936,
+            &[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01],
+        ),
+        (
+            9_223_372_036_854_775_807,
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f],
+        ),
+        (
+            9_223_372_036_854_775_808,
+            &[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01],
+        ),
+        (
+            u64::MAX,
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+        ),
+    ];
+    for &(value, varint) in vectors {
+        let mut encoded = vec![0x08];
+        encoded.extend_from_slice(varint);
+        check_golden(
+            Integers {
+                uint64: value,
+                ..Integers::default()
+            },
+            &encoded,
+        );
+    }
+}
+
+#[test]
+fn negative_numbers() {
+    // Negative `int32` and `int64` values are sign-extended to ten byte varints.
+    check_golden(
+        Integers {
+            int32: -1,
+            ..Integers::default()
+        },
+        &[
+            0x10, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01,
+        ],
+    );
+    check_golden(
+        Integers {
+            int32: i32::MIN,
+            ..Integers::default()
+        },
+        &[
+            0x10, 0x80, 0x80, 0x80, 0x80, 0xf8, 0xff, 0xff, 0xff, 0xff, 0x01,
+        ],
+    );
+    check_golden(
+        Integers {
+            int64: -2,
+            ..Integers::default()
+        },
+        &[
+            0x18, 0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01,
+        ],
+    );
+    check_golden(
+        Integers {
+            int64: i64::MIN,
+            ..Integers::default()
+        },
+        &[
+            0x18, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01,
+        ],
+    );
+
+    // ZigZag encoding maps 0, -1, 1, -2, ... to 0, 1, 2, 3, ...
+    let sint32: &[(i32, &[u8])] = &[
+        (-1, &[0x01]),
+        (1, &[0x02]),
+        (-2, &[0x03]),
+        (-64, &[0x7f]),
+        (64, &[0x80, 0x01]),
+        (i32::MAX, &[0xfe, 0xff, 0xff, 0xff, 0x0f]),
+        (i32::MIN, &[0xff, 0xff, 0xff, 0xff, 0x0f]),
+    ];
+    for &(value, varint) in sint32 {
+        let mut encoded = vec![0x20];
+        encoded.extend_from_slice(varint);
+        check_golden(
+            Integers {
+                sint32: value,
+                ..Integers::default()
+            },
+            &encoded,
+        );
+    }
+    check_golden(
+        Integers {
+            sint64: i64::MIN,
+            ..Integers::default()
+        },
+        &[
+            0x28, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01,
+        ],
+    );
+
+    // Fixed-width values are written in little-endian two's complement.
+    check_golden(
+        Integers {
+            sfixed32: -2,
+            ..Integers::default()
+        },
+        &[0x35, 0xfe, 0xff, 0xff, 0xff],
+    );
+    check_golden(
+        Integers {
+            sfixed64: i64::MIN,
+            ..Integers::default()
+        },
+        &[0x39, 0, 0, 0, 0, 0, 0, 0, 0x80],
+    );
+}
+
+#[test]
+fn non_canonical_inputs() {
+    // Other implementations may write a packed field unpacked, or in several packed runs;
+    // decoding accepts both, and re-encoding writes the canonical, packed form.
+    let expected = Test4 {
+        d: vec![3, 270, 86942],
+    };
+    let unpacked = [0x20, 0x03, 0x20, 0x8e, 0x02, 0x20, 0x9e, 0xa7, 0x05];
+    let split = [0x22, 0x01, 0x03, 0x22, 0x05, 0x8e, 0x02, 0x9e, 0xa7, 0x05];
+    for encoded in &[&unpacked[..], &split[..]] {
+        let decoded = Test4::decode(*encoded).unwrap();
+        assert_eq!(decoded, expected);
+        assert_eq!(
+            decoded.encode_to_vec(),
+            [0x22, 0x06, 0x03, 0x8e, 0x02, 0x9e, 0xa7, 0x05]
+        );
+    }
+
+    // Varints padded with continuation bytes, as some encoders write fixed-width lengths.
+    let padded = [0x08, 0x96, 0x81, 0x80, 0x00];
+    assert_eq!(Test1::decode(&padded[..]).unwrap(), Test1 { a: 150 });
+}
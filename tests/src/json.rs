@@ -0,0 +1,233 @@
+//! Tests for serializing messages to the proto3 JSON mapping with `ToJson::to_json`.
+
+#![cfg(feature = "std")]
+
+use std::collections::{BTreeMap, HashMap};
+
+use prost::alloc::{borrow::ToOwned, string::String, vec, vec::Vec};
+use prost::json::ToJson;
+use prost::{EnumName, Enumeration, Message, Oneof};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enumeration)]
+pub enum Color {
+    #[prost(name = "COLOR_UNSPECIFIED")]
+    Unspecified = 0,
+    #[prost(name = "COLOR_RED")]
+    Red = 1,
+    // Without a name attribute, the identifier is used.
+    Green = 2,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Inner {
+    #[prost(string, tag = "1")]
+    pub label: String,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+pub enum Choice {
+    #[prost(int32, tag = "20")]
+    ChoiceInt(i32),
+    #[prost(message, tag = "21")]
+    ChoiceMessage(Inner),
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct JsonMessage {
+    #[prost(int32, tag = "1")]
+    pub int32_field: i32,
+    #[prost(int64, tag = "2")]
+    pub int64_field: i64,
+    #[prost(uint64, tag = "3")]
+    pub uint64_field: u64,
+    #[prost(sint32, tag = "4")]
+    pub sint32_field: i32,
+    #[prost(fixed32, tag = "5")]
+    pub fixed32_field: u32,
+    #[prost(double, tag = "6")]
+    pub double_field: f64,
+    #[prost(float, tag = "7")]
+    pub float_field: f32,
+    #[prost(bool, tag = "8")]
+    pub bool_field: bool,
+    #[prost(string, tag = "9")]
+    pub string_field: String,
+    #[prost(bytes = "vec", tag = "10")]
+    pub bytes_field: Vec<u8>,
+    #[prost(enumeration = "Color", tag = "11")]
+    pub color: i32,
+    #[prost(enumeration = "Color", repeated, tag = "12")]
+    pub colors: Vec<i32>,
+    #[prost(message, optional, tag = "13")]
+    pub inner: Option<Inner>,
+    #[prost(message, repeated, tag = "14")]
+    pub inners: Vec<Inner>,
+    #[prost(int64, repeated, tag = "15")]
+    pub repeated_int64: Vec<i64>,
+    #[prost(btree_map = "int32, string", tag = "16")]
+    pub int_map: BTreeMap<i32, String>,
+    #[prost(map = "string, message", tag = "17")]
+    pub message_map: HashMap<String, Inner>,
+    #[prost(int32, optional, tag = "18")]
+    pub optional_int32: Option<i32>,
+    #[prost(message, optional, tag = "19")]
+    pub wrapped_uint64: Option<u64>,
+    #[prost(oneof = "Choice", tags = "20, 21")]
+    pub choice: Option<Choice>,
+}
+
+#[test]
+fn default_fields_are_omitted() {
+    assert_eq!(JsonMessage::default().to_json(), "{}");
+
+    // Fields with explicit presence are written even when they hold the default value.
+    let msg = JsonMessage {
+        optional_int32: Some(0),
+        wrapped_uint64: Some(0),
+        inner: Some(Inner::default()),
+        choice: Some(Choice::ChoiceInt(0)),
+        ..JsonMessage::default()
+    };
+    assert_eq!(
+        msg.to_json(),
+        r#"{"inner":{},"optionalInt32":0,"wrappedUint64":"0","choiceInt":0}"#
+    );
+}
+
+#[test]
+fn scalar_fields() {
+    let msg = JsonMessage {
+        int32_field: -1,
+        int64_field: -9_007_199_254_740_993,
+        uint64_field: u64::MAX,
+        sint32_field: 2,
+        fixed32_field: 3,
+        double_field: 0.5,
+        float_field: f32::NEG_INFINITY,
+        bool_field: true,
+        string_field: "quote \" and\nnewline".to_owned(),
+        bytes_field: b"hello".to_vec(),
+        repeated_int64: vec![1, -2],
+        ..JsonMessage::default()
+    };
+    assert_eq!(
+        msg.to_json(),
+        concat!(
+            r#"{"int32Field":-1,"int64Field":"-9007199254740993","#,
+            r#""uint64Field":"18446744073709551615","sint32Field":2,"fixed32Field":3,"#,
+            r#""doubleField":0.5,"floatField":"-Infinity","boolField":true,"#,
+            r#""stringField":"quote \" and\nnewline","bytesField":"aGVsbG8=","#,
+            r#""repeatedInt64":["1","-2"]}"#,
+        )
+    );
+}
+
+#[test]
+fn enum_fields() {
+    let msg = JsonMessage {
+        color: Color::Red as i32,
+        colors: vec![Color::Unspecified as i32, Color::Green as i32, 7],
+        ..JsonMessage::default()
+    };
+    assert_eq!(
+        msg.to_json(),
+        r#"{"color":"COLOR_RED","colors":["COLOR_UNSPECIFIED","Green",7]}"#
+    );
+
+    assert_eq!(Color::proto_name(1), Some("COLOR_RED"));
+    assert_eq!(Color::proto_name(3), None);
+    assert_eq!(
+        prost_types::field_descriptor_proto::Type::proto_name(1),
+        Some("TYPE_DOUBLE")
+    );
+}
+
+#[test]
+fn message_and_map_fields() {
+    let mut int_map = BTreeMap::new();
+    int_map.insert(-1, "minus one".to_owned());
+    int_map.insert(2, "two".to_owned());
+    let mut message_map = HashMap::new();
+    message_map.insert(
+        "key".to_owned(),
+        Inner {
+            label: "value".to_owned(),
+        },
+    );
+    let msg = JsonMessage {
+        inners: vec![
+            Inner::default(),
+            Inner {
+                label: "second".to_owned(),
+            },
+        ],
+        int_map,
+        message_map,
+        choice: Some(Choice::ChoiceMessage(Inner {
+            label: "chosen".to_owned(),
+        })),
+        ..JsonMessage::default()
+    };
+    assert_eq!(
+        msg.to_json(),
+        concat!(
+            r#"{"inners":[{},{"label":"second"}],"intMap":{"-1":"minus one","2":"two"},"#,
+            r#""messageMap":{"key":{"label":"value"}},"choiceMessage":{"label":"chosen"}}"#,
+        )
+    );
+}
+
+#[test]
+fn wrapper_types() {
+    assert_eq!(42u32.to_json(), "42");
+    assert_eq!(42i64.to_json(), r#""42""#);
+    assert_eq!(String::from("s").to_json(), r#""s""#);
+    assert_eq!(vec![0xffu8].to_json(), r#""/w==""#);
+    assert_eq!(().to_json(), "{}");
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+pub enum RenamedChoice {
+    #[prost(string, json_name = "legacyText", tag = "4")]
+    Text(String),
+    #[prost(int32, tag = "5")]
+    NumberValue(i32),
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RenamedFields {
+    #[prost(string, json_name = "user-id", tag = "1")]
+    pub user_id: String,
+    #[prost(int32, repeated, json_name = "Scores", tag = "2")]
+    pub scores: Vec<i32>,
+    #[prost(map = "string, int32", json_name = "legacy_counts", tag = "3")]
+    pub counts: HashMap<String, i32>,
+    #[prost(oneof = "RenamedChoice", tags = "4, 5")]
+    pub choice: Option<RenamedChoice>,
+    #[prost(bool, tag = "6")]
+    pub default_named: bool,
+}
+
+#[test]
+fn json_name_fields() {
+    let mut counts = HashMap::new();
+    counts.insert("a".to_owned(), 1);
+    let msg = RenamedFields {
+        user_id: "u".to_owned(),
+        scores: vec![1, 2],
+        counts,
+        choice: Some(RenamedChoice::Text("t".to_owned())),
+        default_named: true,
+    };
+    assert_eq!(
+        msg.to_json(),
+        r#"{"user-id":"u","Scores":[1,2],"legacy_counts":{"a":1},"legacyText":"t","defaultNamed":true}"#
+    );
+
+    // The other variants keep their default names.
+    let msg = RenamedFields {
+        choice: Some(RenamedChoice::NumberValue(3)),
+        ..RenamedFields::default()
+    };
+    assert_eq!(msg.to_json(), r#"{"numberValue":3}"#);
+}
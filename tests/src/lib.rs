@@ -18,6 +18,9 @@ cfg_if! {
         extern crate prost;
         extern crate prost_types;
         extern crate protobuf;
+        extern crate smallvec;
+        #[cfg(test)]
+        extern crate proptest;
         #[cfg(test)]
         extern crate prost_build;
         #[cfg(test)]
@@ -33,16 +36,34 @@ pub mod unittest;
 #[cfg(test)]
 mod bootstrap;
 #[cfg(test)]
+mod canonical_encoding;
+#[cfg(test)]
 mod debug;
 #[cfg(test)]
 mod deprecated_field;
 #[cfg(test)]
+mod extensions;
+#[cfg(test)]
 mod generic_derive;
 #[cfg(test)]
+mod golden_vectors;
+#[cfg(test)]
+mod json;
+#[cfg(test)]
 mod message_encoding;
 #[cfg(test)]
 mod no_unused_results;
 #[cfg(test)]
+mod proptests;
+#[cfg(test)]
+mod reflect;
+#[cfg(test)]
+mod text_format;
+#[cfg(test)]
+mod type_names;
+#[cfg(test)]
+mod unknown_fields;
+#[cfg(test)]
 mod well_known_types;
 
 pub mod foo {
@@ -78,6 +99,10 @@ pub mod oneof_attributes {
     include!(concat!(env!("OUT_DIR"), "/foo.custom.one_of_attrs.rs"));
 }
 
+pub mod oneof_case {
+    include!(concat!(env!("OUT_DIR"), "/oneof_case.rs"));
+}
+
 /// Issue https://github.com/tokio-rs/prost/issues/118
 ///
 /// When a message contains an enum field with a default value, we
@@ -505,6 +530,21 @@ mod tests {
         };
     }
 
+    #[test]
+    fn test_oneof_case() {
+        use crate::oneof_case::{drawing, widget, Drawing, Widget};
+        let mut msg = Widget::default();
+        assert_eq!(msg.which_kind(), None);
+        msg.kind = Some(widget::Kind::Id(42));
+        assert_eq!(msg.which_kind(), Some(widget::KindCase::Id));
+        assert_eq!(widget::KindCase::Name.tag(), 1);
+
+        let _ = Drawing {
+            shape: Some(drawing::Shape::Circle("c".to_string())),
+        };
+        let _ = drawing::ShapeCase {};
+    }
+
     #[test]
     fn test_267_regression() {
         // Checks that skip_field will error appropriately when given a big stack of StartGroup
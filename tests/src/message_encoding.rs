@@ -1,5 +1,9 @@
-use prost::alloc::{borrow::ToOwned, string::String, vec, vec::Vec};
+use prost::alloc::{
+    borrow::ToOwned, boxed::Box, format, string::String, string::ToString, sync::Arc, vec, vec::Vec,
+};
 use prost::bytes::Bytes;
+use prost::json::ToJson;
+use prost::text_format::ToTextFormat;
 use prost::{Enumeration, Message, Oneof};
 
 use crate::check_message;
@@ -25,6 +29,114 @@ fn check_repeated_floats() {
     });
 }
 
+#[derive(Clone, PartialEq, Message)]
+pub struct Floats {
+    #[prost(float, tag = "1")]
+    pub float: f32,
+    #[prost(double, tag = "2")]
+    pub double: f64,
+}
+
+#[test]
+fn check_float_default_omission() {
+    fn encode(float: f32, double: f64) -> Vec<u8> {
+        let msg = Floats { float, double };
+        let buf = msg.encode_to_vec();
+        assert_eq!(buf.len(), msg.encoded_len());
+        buf
+    }
+
+    // Fields are omitted if they compare equal to the default, so `-0.0` is omitted along with
+    // `0.0`, while `NaN` never compares equal and is always written.
+    assert_eq!(encode(0.0, 0.0), []);
+    assert_eq!(encode(-0.0, -0.0), []);
+    assert_eq!(
+        &encode(f32::NAN, f64::NAN)[..],
+        &[
+            &[0x0d][..],
+            &f32::NAN.to_le_bytes(),
+            &[0x11],
+            &f64::NAN.to_le_bytes(),
+        ]
+        .concat()[..]
+    );
+    assert_eq!(
+        encode(f32::INFINITY, f64::NEG_INFINITY),
+        [0x0d, 0, 0, 0x80, 0x7f, 0x11, 0, 0, 0, 0, 0, 0, 0xf0, 0xff]
+    );
+
+    // An explicit `-0.0` written by another encoder keeps its sign when decoded.
+    let decoded = Floats::decode(&[0x0d, 0, 0, 0, 0x80][..]).unwrap();
+    assert!(decoded.float == 0.0 && decoded.float.is_sign_negative());
+
+    let decoded = Floats::decode(&encode(f32::NAN, f64::NAN)[..]).unwrap();
+    assert!(decoded.float.is_nan() && decoded.double.is_nan());
+}
+
+#[test]
+fn check_is_default() {
+    assert!(Basic::default().is_default());
+    assert!(Compound::default().is_default());
+
+    // Scalars hold their default if they compare equal to it.
+    assert!(Floats {
+        float: -0.0,
+        double: -0.0
+    }
+    .is_default());
+    assert!(!Floats {
+        float: f32::NAN,
+        double: 0.0
+    }
+    .is_default());
+
+    // Fields with explicit presence only hold their default when unset.
+    assert!(!Basic {
+        optional_string: Some(String::new()),
+        ..Basic::default()
+    }
+    .is_default());
+    assert!(!Basic {
+        oneof: Some(BasicOneof::Int(0)),
+        ..Basic::default()
+    }
+    .is_default());
+    assert!(!Compound {
+        optional_message: Some(Basic::default()),
+        ..Compound::default()
+    }
+    .is_default());
+
+    // Collections hold their default when empty, even if their elements do.
+    assert!(Basic {
+        bools: Vec::new(),
+        ..Basic::default()
+    }
+    .is_default());
+    assert!(!Basic {
+        bools: vec![false],
+        ..Basic::default()
+    }
+    .is_default());
+    let mut map = Basic::default();
+    map.string_btree_map.insert(String::new(), String::new());
+    assert!(!map.is_default());
+
+    // Required messages hold their default if their fields do.
+    assert!(!Compound {
+        required_message: Basic {
+            int32: 1,
+            ..Basic::default()
+        },
+        ..Compound::default()
+    }
+    .is_default());
+
+    // Wrapper types fall back to checking for an empty encoding.
+    assert!(0u32.is_default());
+    assert!(!Box::new("x".to_owned()).is_default());
+}
+
 #[test]
 fn check_scalar_types() {
     check_message(&ScalarTypes::default());
@@ -317,78 +429,2426 @@ fn check_default_values() {
     assert_eq!(0, default.encoded_len());
 }
 
-/// A protobuf enum.
-#[allow(clippy::upper_case_acronyms)]
-#[derive(Clone, Copy, Debug, PartialEq, Enumeration)]
-pub enum BasicEnumeration {
-    ZERO = 0,
-    ONE = 1,
-    TWO = 2,
-    THREE = 3,
+#[test]
+fn check_default_value_presence() {
+    // Absent fields decode to their declared defaults.
+    let decoded = DefaultValues::decode(&[][..]).unwrap();
+    assert_eq!(decoded.int32, 42);
+    assert_eq!(decoded.optional_int32(), 88);
+    assert_eq!(decoded.optional_enumeration(), BasicEnumeration::TWO);
+
+    // A field without a label is omitted when it holds its default value, while an optional
+    // field is encoded whenever it is set, even to its default value.
+    let msg = DefaultValues {
+        int32: 42,
+        optional_int32: Some(88),
+        ..DefaultValues::default()
+    };
+    assert_eq!(msg.encode_to_vec(), [0x10, 88]);
+    let decoded = DefaultValues::decode(&*msg.encode_to_vec()).unwrap();
+    assert_eq!(decoded.optional_int32, Some(88));
+
+    let msg = DefaultValues {
+        int32: 0,
+        ..DefaultValues::default()
+    };
+    assert_eq!(msg.encode_to_vec(), [0x08, 0x00]);
+    assert_eq!(DefaultValues::decode(&[0x08, 0x00][..]).unwrap(), msg);
+}
+
+#[test]
+fn check_nested_decode_error_location() {
+    // Compound.required_message (tag 2) containing Basic.string (tag 3) with invalid UTF-8.
+    let buf: &[u8] = &[0x12, 0x03, 0x1a, 0x01, 0x80];
+    let error = Compound::decode(buf).expect_err("invalid UTF-8 must fail to decode");
+    assert_eq!(
+        error.to_string(),
+        "failed to decode Protobuf message: \
+         Basic.string: Compound.required_message: \
+         invalid string value: data is not UTF-8 encoded (invalid byte sequence at offset 0)"
+    );
+}
+
+#[test]
+fn check_decode_best_effort() {
+    let msg = Compound {
+        repeated_message: vec![Basic {
+            int32: 7,
+            ..Basic::default()
+        }],
+        ..Compound::default()
+    };
+    assert_eq!(
+        Compound::decode_best_effort(&*msg.encode_to_vec()),
+        (msg, None)
+    );
+
+    // Compound.repeated_message (tag 3), then Compound.optional_message (tag 1) holding
+    // Basic.int32 (tag 1) and Basic.string (tag 3) with invalid UTF-8, then another
+    // Compound.repeated_message which is never reached.
+    let buf: &[u8] = &[
+        0x1a, 0x02, 0x08, 0x07, 0x0a, 0x05, 0x08, 0x01, 0x1a, 0x01, 0x80, 0x1a, 0x02, 0x08, 0x08,
+    ];
+    let (partial, error) = Compound::decode_best_effort(buf);
+    assert_eq!(
+        error
+            .expect("invalid UTF-8 must fail to decode")
+            .to_string(),
+        "failed to decode Protobuf message: \
+         Basic.string: Compound.optional_message: \
+         invalid string value: data is not UTF-8 encoded (invalid byte sequence at offset 0)"
+    );
+    assert_eq!(
+        partial.repeated_message,
+        [Basic {
+            int32: 7,
+            ..Basic::default()
+        }]
+    );
+    assert_eq!(partial.optional_message.unwrap().int32, 1);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn check_encode_with_stats() {
+    let msg = Compound {
+        optional_message: Some(Basic {
+            int32: 1,
+            string: "hello".to_owned(),
+            ..Basic::default()
+        }),
+        repeated_message: vec![
+            Basic::default(),
+            Basic {
+                bools: vec![true, false],
+                ..Basic::default()
+            },
+        ],
+        ..Compound::default()
+    };
+
+    let mut stats = std::collections::HashMap::new();
+    let mut buf = Vec::new();
+    msg.encode_with_stats(&mut buf, &mut stats).unwrap();
+    assert_eq!(buf, msg.encode_to_vec());
+    // The embedded message is counted as a whole: key, length, and 2 + 7 bytes of fields, which
+    // are broken down under the paths of their tags.
+    assert_eq!(stats[&vec![1]], 11);
+    assert_eq!(stats[&vec![1, 1]], 2);
+    assert_eq!(stats[&vec![1, 3]], 7);
+    assert_eq!(stats[&vec![2]], 2);
+    // The fields of the elements of a repeated field are summed.
+    assert_eq!(stats[&vec![3]], 8);
+    assert_eq!(stats[&vec![3, 2]], 4);
+    assert_eq!(stats.len(), 6);
+    let top_level = stats.iter().filter(|(path, _)| path.len() == 1);
+    assert_eq!(
+        top_level.map(|(_, len)| len).sum::<usize>(),
+        msg.encoded_len()
+    );
+
+    // Counts accumulate across messages, and are unchanged if the buffer is too small.
+    msg.encode_with_stats(&mut Vec::new(), &mut stats).unwrap();
+    assert_eq!(stats[&vec![1, 3]], 14);
+    msg.encode_with_stats(&mut &mut [0u8; 4][..], &mut stats)
+        .expect_err("buffer is too small");
+    assert_eq!(stats[&vec![1, 3]], 14);
+
+    // A message implemented by hand reports the fields found in its encoding.
+    let mut stats = std::collections::HashMap::new();
+    7u32.encode_with_stats(&mut Vec::new(), &mut stats).unwrap();
+    assert_eq!(stats[&vec![1]], 2);
+    assert_eq!(stats.len(), 1);
 }
 
 #[derive(Clone, PartialEq, Message)]
-pub struct Basic {
-    #[prost(int32, tag = "1")]
-    pub int32: i32,
+pub struct Packedness {
+    #[prost(int32, repeated, tag = "1")]
+    pub packed: Vec<i32>,
+    #[prost(int32, repeated, packed = "false", tag = "2")]
+    pub unpacked: Vec<i32>,
+}
 
-    #[prost(bool, repeated, packed = "false", tag = "2")]
-    pub bools: Vec<bool>,
+#[test]
+fn check_packed_false() {
+    let message = Packedness {
+        packed: vec![1, 2],
+        unpacked: vec![1, 2],
+    };
+    let buf = message.encode_to_vec();
+    assert_eq!(buf, [0x0a, 0x02, 0x01, 0x02, 0x10, 0x01, 0x10, 0x02]);
 
-    #[prost(string, tag = "3")]
-    pub string: String,
+    // Both fields accept both encodings, regardless of how they are encoded.
+    let swapped: &[u8] = &[0x08, 0x01, 0x08, 0x02, 0x12, 0x02, 0x01, 0x02];
+    assert_eq!(Packedness::decode(swapped).unwrap(), message);
+}
 
-    #[prost(string, optional, tag = "4")]
-    pub optional_string: Option<String>,
+#[derive(Clone, PartialEq, Message)]
+pub struct WireTypes {
+    #[prost(fixed64, tag = "1")]
+    pub fixed: u64,
+    #[prost(int32, repeated, tag = "2")]
+    pub numbers: Vec<i32>,
+    #[prost(btree_map = "string, string", tag = "3")]
+    pub map: prost::alloc::collections::BTreeMap<String, String>,
+    #[prost(oneof = "WireTypesOneof", tags = "4")]
+    pub choice: Option<WireTypesOneof>,
+}
 
-    #[prost(enumeration = "BasicEnumeration", tag = "5")]
-    pub enumeration: i32,
+#[derive(Clone, PartialEq, Oneof)]
+pub enum WireTypesOneof {
+    #[prost(string, tag = "4")]
+    Text(String),
+}
 
-    #[prost(map = "int32, enumeration(BasicEnumeration)", tag = "6")]
-    #[cfg(feature = "std")]
-    pub enumeration_map: ::std::collections::HashMap<i32, i32>,
+#[test]
+fn check_wire_type_errors() {
+    let cases: &[(&[u8], &str)] = &[
+        (
+            &[0x08, 0x01],
+            "WireTypes.fixed: invalid wire type for tag 1: Varint (expected SixtyFourBit)",
+        ),
+        (
+            &[0x15, 0x01, 0x00, 0x00, 0x00],
+            "WireTypes.numbers: invalid wire type for tag 2: ThirtyTwoBit \
+             (expected Varint or LengthDelimited)",
+        ),
+        // A varint is not mistaken for the length of a map entry.
+        (
+            &[0x18, 0x02, 0x0a, 0x00],
+            "WireTypes.map: invalid wire type for tag 3: Varint (expected LengthDelimited)",
+        ),
+        (
+            &[0x20, 0x01],
+            "WireTypes.choice: invalid wire type for tag 4: Varint (expected LengthDelimited)",
+        ),
+    ];
+    for &(buf, expected) in cases {
+        let error = WireTypes::decode(buf).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("failed to decode Protobuf message: {}", expected)
+        );
+    }
 
-    #[prost(hash_map = "string, string", tag = "7")]
-    #[cfg(feature = "std")]
-    pub string_map: ::std::collections::HashMap<String, String>,
+    // Repeated numeric fields accept both the packed and unpacked encodings.
+    let decoded = WireTypes::decode(&[0x10, 0x01, 0x12, 0x02, 0x02, 0x03][..]).unwrap();
+    assert_eq!(decoded.numbers, [1, 2, 3]);
+}
 
-    #[prost(btree_map = "int32, enumeration(BasicEnumeration)", tag = "10")]
-    pub enumeration_btree_map: prost::alloc::collections::BTreeMap<i32, i32>,
+#[test]
+fn check_encode_length_delimited_capacity() {
+    let msg = Basic {
+        int32: 42,
+        string: "forty two".to_owned(),
+        ..Basic::default()
+    };
+    let required = msg.encoded_len() + prost::length_delimiter_len(msg.encoded_len());
 
-    #[prost(btree_map = "string, string", tag = "11")]
-    pub string_btree_map: prost::alloc::collections::BTreeMap<String, String>,
+    let mut buf = vec![0u8; required - 1];
+    let error = msg
+        .encode_length_delimited(&mut buf.as_mut_slice())
+        .expect_err("encoding into a too-small buffer must fail");
+    assert_eq!(error.required_capacity(), required);
+    assert_eq!(error.remaining(), required - 1);
 
-    #[prost(oneof = "BasicOneof", tags = "8, 9")]
-    pub oneof: Option<BasicOneof>,
+    let mut buf = vec![0u8; required];
+    msg.encode_length_delimited(&mut buf.as_mut_slice())
+        .expect("encoding into an exactly-sized buffer must succeed");
+    assert_eq!(buf, msg.encode_length_delimited_to_vec());
+}
 
-    #[prost(map = "string, bytes", tag = "12")]
-    #[cfg(feature = "std")]
-    pub bytes_map: ::std::collections::HashMap<String, Vec<u8>>,
+#[test]
+fn check_encode_to_vec() {
+    let empty = Basic::default();
+    assert!(empty.encode_to_vec().is_empty());
+    assert_eq!(empty.encode_length_delimited_to_vec(), vec![0]);
+
+    let large = Compound {
+        repeated_message: vec![
+            Basic {
+                string: "x".repeat(1024),
+                ..Basic::default()
+            };
+            64
+        ],
+        ..Compound::default()
+    };
+    let mut buf = Vec::new();
+    large.encode(&mut buf).unwrap();
+    let vec = large.encode_to_vec();
+    assert_eq!(vec.len(), large.encoded_len());
+    assert_eq!(vec, buf);
+
+    let delimited = large.encode_length_delimited_to_vec();
+    assert_eq!(
+        delimited.len(),
+        large.encoded_len() + prost::length_delimiter_len(large.encoded_len())
+    );
+    assert_eq!(
+        prost::total_encoded_len_delimited(&[large.clone(), large.clone()]),
+        2 * delimited.len()
+    );
+    assert_eq!(
+        Compound::decode_length_delimited(&*delimited).unwrap(),
+        large
+    );
+}
+
+#[test]
+fn check_decode_length_delimited_sequence() {
+    let messages = [
+        Basic {
+            int32: 1,
+            ..Basic::default()
+        },
+        Basic::default(),
+        Basic {
+            string: "three".to_owned(),
+            ..Basic::default()
+        },
+    ];
+    let mut encoded = Vec::new();
+    for message in &messages {
+        message.encode_length_delimited(&mut encoded).unwrap();
+    }
+    assert_eq!(prost::total_encoded_len_delimited(&messages), encoded.len());
+
+    let mut buf = prost::bytes::Bytes::from(encoded.clone());
+    for message in &messages {
+        let before = buf.len();
+        assert_eq!(&Basic::decode_length_delimited(&mut buf).unwrap(), message);
+        assert_eq!(
+            before - buf.len(),
+            message.encoded_len() + prost::length_delimiter_len(message.encoded_len())
+        );
+    }
+    assert!(buf.is_empty());
+
+    // A truncated final frame is an error rather than a partially decoded message.
+    let mut buf = &encoded[..encoded.len() - 1];
+    Basic::decode_length_delimited(&mut buf).unwrap();
+    Basic::decode_length_delimited(&mut buf).unwrap();
+    let error = Basic::decode_length_delimited(&mut buf).expect_err("truncated frame");
+    assert!(error.to_string().contains("buffer underflow"));
+}
+
+#[test]
+fn check_clear() {
+    let mut msg = Compound {
+        optional_message: Some(Basic {
+            int32: 7,
+            ..Basic::default()
+        }),
+        required_message: Basic {
+            string: "required".to_owned(),
+            bools: vec![true, false],
+            oneof: Some(BasicOneof::Int(3)),
+            ..Basic::default()
+        },
+        repeated_message: vec![Basic::default(); 3],
+        ..Compound::default()
+    };
+    msg.clear();
+    assert_eq!(msg, Compound::default());
+    assert_eq!(msg.encoded_len(), Compound::default().encoded_len());
+
+    // A cleared message can be reused as the target of another decode.
+    let other = Compound {
+        repeated_message: vec![Basic {
+            int32: 1,
+            ..Basic::default()
+        }],
+        ..Compound::default()
+    };
+    msg.merge(&*other.encode_to_vec()).unwrap();
+    assert_eq!(msg, other);
+}
+
+#[test]
+fn check_bytes_zero_copy() {
+    let msg = DefaultValues {
+        bytes_buf: Bytes::from(vec![7u8; 1024]),
+        ..DefaultValues::default()
+    };
+    let encoded = Bytes::from(msg.encode_to_vec());
+
+    // Decoding from a `Bytes` buffer slices the payload out of the source allocation.
+    let decoded = DefaultValues::decode(encoded.clone()).unwrap();
+    assert_eq!(decoded.bytes_buf, msg.bytes_buf);
+    let source = encoded.as_ptr_range();
+    let payload = decoded.bytes_buf.as_ptr_range();
+    assert!(source.start <= payload.start && payload.end <= source.end);
+
+    // Other buffers fall back to copying.
+    let decoded = DefaultValues::decode(&encoded[..]).unwrap();
+    assert_eq!(decoded.bytes_buf, msg.bytes_buf);
+    assert!(!source.contains(&decoded.bytes_buf.as_ptr()));
+}
+
+#[test]
+fn check_skip_unknown_fields() {
+    let mut buf = Vec::new();
+    prost::encoding::int32::encode(1, &5, &mut buf);
+    prost::encoding::bytes::encode(20, &vec![0xFF; 300], &mut buf);
+    prost::encoding::fixed64::encode(21, &6, &mut buf);
+    prost::encoding::group::encode(22, &Basic::default(), &mut buf);
+    prost::encoding::string::encode(3, &"after".to_owned(), &mut buf);
+
+    let msg = Basic::decode(&*buf).unwrap();
+    assert_eq!(
+        msg,
+        Basic {
+            int32: 5,
+            string: "after".to_owned(),
+            ..Basic::default()
+        }
+    );
+}
+
+#[test]
+fn check_unknown_enumeration_value() {
+    let known = Basic {
+        enumeration: BasicEnumeration::TWO as i32,
+        ..Basic::default()
+    };
+    check_message(&known);
+    assert_eq!(known.enumeration(), BasicEnumeration::TWO);
+
+    // Proto3 enums are open: values without a matching variant are retained as the raw integer.
+    let unknown = Basic {
+        enumeration: 42,
+        ..Basic::default()
+    };
+    let decoded = Basic::decode(&*unknown.encode_to_vec()).unwrap();
+    assert_eq!(decoded.enumeration, 42);
+    assert_eq!(decoded.enumeration(), BasicEnumeration::default());
+    assert_eq!(BasicEnumeration::from_i32(decoded.enumeration), None);
+    assert_eq!(decoded.encode_to_vec(), unknown.encode_to_vec());
 }
 
 #[derive(Clone, PartialEq, Message)]
-pub struct Compound {
+pub struct ClampedEnumerations {
+    #[prost(enumeration = "BasicEnumeration", unknown = "clamp", tag = "1")]
+    pub plain: i32,
+    #[prost(
+        enumeration = "BasicEnumeration",
+        optional,
+        unknown = "clamp",
+        tag = "2"
+    )]
+    pub optional: Option<i32>,
+    #[prost(
+        enumeration = "BasicEnumeration",
+        repeated,
+        unknown = "clamp",
+        tag = "3"
+    )]
+    pub repeated: Vec<i32>,
+    #[prost(
+        enumeration = "BasicEnumeration",
+        repeated,
+        unknown = "preserve",
+        tag = "4"
+    )]
+    pub preserved: Vec<i32>,
+    #[prost(oneof = "ClampedEnumerationsOneof", tags = "5")]
+    pub oneof: Option<ClampedEnumerationsOneof>,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+pub enum ClampedEnumerationsOneof {
+    #[prost(enumeration = "BasicEnumeration", unknown = "clamp", tag = "5")]
+    Enumeration(i32),
+}
+
+#[test]
+fn check_clamped_enumeration_values() {
+    let two = BasicEnumeration::TWO as i32;
+    let zero = BasicEnumeration::ZERO as i32;
+
+    // Values without a matching variant are replaced by the default variant when decoded, unless
+    // the field preserves them.
+    let unknown = ClampedEnumerations {
+        plain: 42,
+        optional: Some(-1),
+        repeated: vec![two, 42, 3],
+        preserved: vec![two, 42],
+        oneof: Some(ClampedEnumerationsOneof::Enumeration(7)),
+    };
+    let decoded = ClampedEnumerations::decode(&*unknown.encode_to_vec()).unwrap();
+    assert_eq!(
+        decoded,
+        ClampedEnumerations {
+            plain: zero,
+            optional: Some(zero),
+            repeated: vec![two, zero, 3],
+            preserved: vec![two, 42],
+            oneof: Some(ClampedEnumerationsOneof::Enumeration(zero)),
+        }
+    );
+
+    // Known values are decoded unchanged, and merging only clamps the values it decodes.
+    let mut merged = ClampedEnumerations {
+        repeated: vec![42],
+        ..ClampedEnumerations::default()
+    };
+    merged.merge(&*decoded.encode_to_vec()).unwrap();
+    assert_eq!(merged.repeated, [42, two, zero, 3]);
+    assert_eq!(merged.optional, Some(zero));
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Recursive {
+    #[prost(message, optional, boxed, tag = "1")]
+    pub child: Option<Box<Recursive>>,
+}
+
+#[test]
+fn check_custom_recursion_limit() {
+    use prost::encoding::DecodeContext;
+
+    let mut msg = Recursive::default();
+    for _ in 0..150 {
+        msg = Recursive {
+            child: Some(Box::new(msg)),
+        };
+    }
+    let buf = msg.encode_to_vec();
+
+    let error = Recursive::decode(&*buf).expect_err("default recursion limit must be exceeded");
+    assert!(error.to_string().contains("recursion limit reached"));
+
+    let mut decoded = Recursive::default();
+    decoded
+        .merge_with_context(&*buf, DecodeContext::with_recursion_limit(150))
+        .unwrap();
+    assert_eq!(decoded, msg);
+
+    let mut decoded = Recursive::default();
+    decoded
+        .merge_with_context(&*buf, DecodeContext::with_recursion_limit(149))
+        .expect_err("recursion limit must be exceeded");
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct LengthChild {
+    #[prost(string, tag = "1")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct LengthParent {
     #[prost(message, optional, tag = "1")]
-    pub optional_message: Option<Basic>,
+    pub child: Option<LengthChild>,
+    #[prost(string, tag = "2")]
+    pub sibling: String,
+}
 
-    #[prost(message, required, tag = "2")]
-    pub required_message: Basic,
+#[test]
+fn check_nested_length_overrun() {
+    let parent = LengthParent {
+        child: Some(LengthChild {
+            name: "a".to_owned(),
+        }),
+        sibling: "bc".to_owned(),
+    };
+    let buf = parent.encode_to_vec();
+    assert_eq!(buf, [0x0a, 0x03, 0x0a, 0x01, b'a', 0x12, 0x02, b'b', b'c']);
+    assert_eq!(LengthParent::decode(&*buf).unwrap(), parent);
 
-    #[prost(message, repeated, tag = "3")]
-    pub repeated_message: Vec<Basic>,
+    // The child's name claims five bytes, which fit in the buffer but extend past the end of the
+    // child into the sibling field.
+    let mut overrun = buf.clone();
+    overrun[3] = 0x05;
+    let error = LengthParent::decode(&*overrun).expect_err("name must not overrun the child");
+    assert_eq!(
+        error.to_string(),
+        "failed to decode Protobuf message: LengthChild.name: LengthParent.child: \
+         length-delimited value of 5 bytes overruns the enclosing value, which has 1 bytes left"
+    );
 
-    #[prost(map = "sint32, message", tag = "4")]
-    #[cfg(feature = "std")]
-    pub message_map: ::std::collections::HashMap<i32, Basic>,
+    // Unknown fields of the child are checked in the same way.
+    overrun[2] = 0x1a;
+    let error = LengthParent::decode(&*overrun).expect_err("unknown field must not overrun");
+    assert!(error
+        .to_string()
+        .contains("length-delimited value of 5 bytes overruns the enclosing value"));
+}
 
-    #[prost(btree_map = "sint32, message", tag = "5")]
-    pub message_btree_map: prost::alloc::collections::BTreeMap<i32, Basic>,
+/// A message holding a `LengthChild` encoded in a bytes field, which it decodes from a buffer of
+/// its own.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BytesEnvelope {
+    child: LengthChild,
+}
+
+impl Message for BytesEnvelope {
+    fn encode_raw<B>(&self, buf: &mut B)
+    where
+        B: prost::bytes::BufMut,
+    {
+        prost::encoding::bytes::encode(1, &self.child.encode_to_vec(), buf);
+    }
+
+    fn merge_field<B>(
+        &mut self,
+        tag: u32,
+        wire_type: prost::encoding::WireType,
+        buf: &mut B,
+        ctx: prost::encoding::DecodeContext,
+    ) -> Result<(), prost::DecodeError>
+    where
+        B: prost::bytes::Buf,
+    {
+        if tag == 1 {
+            let mut payload = Vec::new();
+            prost::encoding::bytes::merge(wire_type, &mut payload, buf, ctx.clone())?;
+            self.child.merge_with_context(&*payload, ctx)
+        } else {
+            prost::encoding::skip_field(wire_type, tag, buf, ctx)
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        prost::encoding::bytes::encoded_len(1, &self.child.encode_to_vec())
+    }
+
+    fn clear(&mut self) {
+        self.child.clear();
+    }
+}
+
+impl ToJson for BytesEnvelope {
+    fn write_json(&self, writer: &mut prost::json::JsonWriter) {
+        writer.begin_object();
+        writer.end_object();
+    }
+}
+
+impl ToTextFormat for BytesEnvelope {
+    fn write_text_format(&self, _writer: &mut prost::text_format::TextFormatWriter) {}
+}
+
+impl prost::reflect::Reflect for BytesEnvelope {
+    fn reflect(&self, _visitor: &mut dyn prost::reflect::FieldVisitor) {}
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct BytesEnvelopeParent {
+    #[prost(message, optional, tag = "1")]
+    pub envelope: Option<BytesEnvelope>,
+    #[prost(string, tag = "2")]
+    pub sibling: String,
+}
+
+#[test]
+fn check_nested_length_in_another_buffer() {
+    // The child's name is longer than the bytes left after the envelope in the parent, which does
+    // not matter as it is decoded from the envelope's own buffer.
+    let parent = BytesEnvelopeParent {
+        envelope: Some(BytesEnvelope {
+            child: LengthChild {
+                name: "abcdefghij".to_owned(),
+            },
+        }),
+        sibling: "klmnopqr".to_owned(),
+    };
+    let buf = parent.encode_to_vec();
+    assert_eq!(BytesEnvelopeParent::decode(&*buf).unwrap(), parent);
+}
+
+#[test]
+fn check_optional_message_merge() {
+    // An unset optional message field is not written at all, leaving only the empty required
+    // message.
+    let unset = Compound::default();
+    assert_eq!(unset.optional_message, None);
+    assert_eq!(unset.encode_to_vec(), [0x12, 0x00]);
+
+    // Repeated occurrences of a singular message field are merged into the existing value rather
+    // than replacing it.
+    let mut buf = Vec::new();
+    let first = Basic {
+        int32: 5,
+        bools: vec![true],
+        ..Basic::default()
+    };
+    let second = Basic {
+        string: "second".to_owned(),
+        bools: vec![false],
+        ..Basic::default()
+    };
+    prost::encoding::message::encode(1, &first, &mut buf);
+    prost::encoding::message::encode(1, &second, &mut buf);
+    prost::encoding::message::encode(2, &Basic::default(), &mut buf);
+
+    let decoded = Compound::decode(&*buf).unwrap();
+    assert_eq!(
+        decoded.optional_message,
+        Some(Basic {
+            int32: 5,
+            bools: vec![true, false],
+            string: "second".to_owned(),
+            ..Basic::default()
+        })
+    );
+}
+
+#[test]
+fn check_encode_field() {
+    use prost::field::{self, decode_key, Bool, DecodeContext, Field, Int32, WireType};
+
+    // A sparse message, built field by field.
+    let mut child = Vec::new();
+    prost::encode_field::<Int32, _>(1, &-1, &mut child);
+    prost::encode_field::<Bool, _>(2, &true, &mut child);
+    prost::encode_field::<Bool, _>(2, &false, &mut child);
+    prost::encode_field::<field::String, _>(3, &"child".to_owned(), &mut child);
+    prost::encode_field::<Int32, _>(5, &(BasicEnumeration::TWO as i32), &mut child);
+    assert_eq!(
+        child.len(),
+        prost::encoded_len_field::<Int32>(1, &-1)
+            + 2 * prost::encoded_len_field::<Bool>(2, &true)
+            + prost::encoded_len_field::<field::String>(3, &"child".to_owned())
+            + prost::encoded_len_field::<Int32>(5, &2)
+    );
+    let expected = Basic {
+        int32: -1,
+        bools: vec![true, false],
+        string: "child".to_owned(),
+        enumeration: BasicEnumeration::TWO as i32,
+        ..Basic::default()
+    };
+    assert_eq!(Basic::decode(&*child).unwrap(), expected);
+
+    let mut buf = Vec::new();
+    prost::encode_field::<field::Message<Basic>, _>(1, &expected, &mut buf);
+    assert_eq!(
+        buf.len(),
+        prost::encoded_len_field::<field::Message<Basic>>(1, &expected)
+    );
+    let decoded = Compound::decode(&*buf).unwrap();
+    assert_eq!(decoded.optional_message, Some(expected.clone()));
+
+    // Merging a message field merges into the existing value.
+    let mut buf = &buf[..];
+    let (tag, wire_type) = decode_key(&mut buf).unwrap();
+    assert_eq!(tag, 1);
+    let mut merged = Basic {
+        optional_string: Some("kept".to_owned()),
+        ..Basic::default()
+    };
+    prost::merge_field::<field::Message<Basic>, _>(
+        wire_type,
+        &mut merged,
+        &mut buf,
+        DecodeContext::default(),
+    )
+    .unwrap();
+    assert!(buf.is_empty());
+    assert_eq!(merged.string, "child");
+    assert_eq!(merged.optional_string.as_deref(), Some("kept"));
+
+    // `bytes` fields may hold any `BytesAdapter`.
+    let mut buf = Vec::new();
+    let payload = Bytes::from_static(b"payload");
+    prost::encode_field::<field::Bytes<Bytes>, _>(12, &payload, &mut buf);
+    let mut buf = &buf[..];
+    let (_, wire_type) = decode_key(&mut buf).unwrap();
+    let mut decoded = Vec::new();
+    prost::merge_field::<field::Bytes, _>(
+        wire_type,
+        &mut decoded,
+        &mut buf,
+        DecodeContext::default(),
+    )
+    .unwrap();
+    assert_eq!(decoded, b"payload");
+    assert_eq!(wire_type, field::Bytes::<Bytes>::WIRE_TYPE);
+
+    assert_eq!(Int32::WIRE_TYPE, WireType::Varint);
+    assert_eq!(field::Double::WIRE_TYPE, WireType::SixtyFourBit);
+    assert_eq!(field::Fixed32::WIRE_TYPE, WireType::ThirtyTwoBit);
+    assert_eq!(field::Char::WIRE_TYPE, WireType::Varint);
+    assert_eq!(field::String::WIRE_TYPE, WireType::LengthDelimited);
+    assert_eq!(
+        field::Message::<Basic>::WIRE_TYPE,
+        WireType::LengthDelimited
+    );
+
+    // The wire type is checked.
+    let mut buf = &[0x01, 0x02][..];
+    let mut value = 0;
+    assert!(prost::merge_field::<Int32, _>(
+        wire_type,
+        &mut value,
+        &mut buf,
+        DecodeContext::default()
+    )
+    .is_err());
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RoutedEnvelope {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(string, tag = "2")]
+    pub route: String,
+    #[prost(bytes = "vec", tag = "3")]
+    pub payload: Vec<u8>,
+    #[prost(message, optional, tag = "4")]
+    pub header: Option<Basic>,
+    #[prost(unknown_fields)]
+    pub unknown_fields: prost::UnknownFieldList,
+}
+
+#[test]
+fn check_decode_fields() {
+    let envelope = RoutedEnvelope {
+        id: 7,
+        route: "eu-west".to_owned(),
+        payload: vec![0xff; 1024],
+        header: Some(Basic {
+            int32: 1,
+            string: "header".to_owned(),
+            ..Basic::default()
+        }),
+        ..RoutedEnvelope::default()
+    };
+    let mut buf = envelope.encode_to_vec();
+    // An unknown field, which is skipped too.
+    buf.extend_from_slice(&[0x28, 0x01]);
+
+    let decoded = RoutedEnvelope::decode_fields(&*buf, &[2]).unwrap();
+    assert_eq!(
+        decoded,
+        RoutedEnvelope {
+            route: "eu-west".to_owned(),
+            ..RoutedEnvelope::default()
+        }
+    );
+
+    // Allowed message fields are decoded entirely.
+    let decoded = RoutedEnvelope::decode_fields(&*buf, &[1, 4, 5]).unwrap();
+    assert_eq!(decoded.id, 7);
+    assert_eq!(decoded.header, envelope.header);
+    assert!(decoded.payload.is_empty());
+    assert_eq!(decoded.unknown_fields.len(), 1);
+
+    assert_eq!(
+        RoutedEnvelope::decode_fields(&*buf, &[]).unwrap(),
+        RoutedEnvelope::default()
+    );
+
+    // Skipped fields must be well-formed.
+    let truncated = &buf[..buf.len() - 3];
+    assert!(RoutedEnvelope::decode_fields(truncated, &[1, 2]).is_err());
+    assert!(RoutedEnvelope::decode_fields(&[0x1a, 0x05, 0x00][..], &[1, 2]).is_err());
+}
+
+#[test]
+fn check_merge_from() {
+    let mut base = Compound {
+        optional_message: Some(Basic {
+            int32: 1,
+            string: "base".to_owned(),
+            bools: vec![true],
+            ..Basic::default()
+        }),
+        repeated_message: vec![Basic::default()],
+        ..Compound::default()
+    };
+    base.message_btree_map.insert(1, Basic::default());
+
+    let mut update = Compound {
+        optional_message: Some(Basic {
+            int32: 2,
+            bools: vec![false],
+            ..Basic::default()
+        }),
+        repeated_message: vec![Basic {
+            int32: 3,
+            ..Basic::default()
+        }],
+        ..Compound::default()
+    };
+    update.message_btree_map.insert(2, Basic::default());
+
+    base.merge_from(&update).unwrap();
+
+    // The singular message is merged recursively: the scalar is overwritten, the string left
+    // unset by the update is kept, and the repeated field is extended.
+    assert_eq!(
+        base.optional_message,
+        Some(Basic {
+            int32: 2,
+            string: "base".to_owned(),
+            bools: vec![true, false],
+            ..Basic::default()
+        })
+    );
+    assert_eq!(
+        base.repeated_message,
+        vec![
+            Basic::default(),
+            Basic {
+                int32: 3,
+                ..Basic::default()
+            }
+        ]
+    );
+    assert_eq!(
+        base.message_btree_map.keys().copied().collect::<Vec<_>>(),
+        vec![1, 2]
+    );
+
+    // Messages nesting more deeply than the recursion limit allows are merged all the same.
+    let mut deep = Recursive::default();
+    for _ in 0..150 {
+        deep = Recursive {
+            child: Some(Box::new(deep)),
+        };
+    }
+    let mut merged = Recursive::default();
+    merged.merge_from(&deep).unwrap();
+    assert_eq!(merged, deep);
+}
+
+#[test]
+fn check_merge_accumulates() {
+    // Merging never resets the message: fragments merged one after another accumulate into it.
+    let mut msg = Basic {
+        int32: 1,
+        bools: vec![true],
+        string: "base".to_owned(),
+        optional_string: Some("kept".to_owned()),
+        oneof: Some(BasicOneof::Int(5)),
+        ..Basic::default()
+    };
+    msg.string_btree_map.insert("a".to_owned(), "1".to_owned());
+
+    // Repeated fields are appended to and maps extended, while the scalars absent from the
+    // fragment keep their values.
+    let mut first = Basic {
+        bools: vec![false, true],
+        ..Basic::default()
+    };
+    first
+        .string_btree_map
+        .insert("b".to_owned(), "2".to_owned());
+    msg.merge(&*first.encode_to_vec()).unwrap();
+    assert_eq!(msg.int32, 1);
+    assert_eq!(msg.bools, [true, false, true]);
+    assert_eq!(msg.string, "base");
+    assert_eq!(msg.optional_string.as_deref(), Some("kept"));
+    assert_eq!(msg.oneof, Some(BasicOneof::Int(5)));
+    assert_eq!(msg.string_btree_map.keys().collect::<Vec<_>>(), ["a", "b"]);
+
+    // Scalars present in a fragment are overwritten, even by an explicitly encoded zero.
+    let second = Basic {
+        string: "second".to_owned(),
+        ..Basic::default()
+    };
+    msg.merge(&*second.encode_to_vec()).unwrap();
+    msg.merge(&[0x08, 0x00][..]).unwrap();
+    assert_eq!(msg.int32, 0);
+    assert_eq!(msg.string, "second");
+    assert_eq!(msg.bools, [true, false, true]);
+
+    // An empty fragment changes nothing.
+    let before = msg.clone();
+    msg.merge(&[][..]).unwrap();
+    assert_eq!(msg, before);
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FixedBytes {
+    #[prost(bytes = "array", tag = "1")]
+    pub hash: [u8; 32],
+    #[prost(bytes = "array", optional, tag = "2")]
+    pub signature: Option<[u8; 64]>,
+    #[prost(bytes = "array", repeated, tag = "3")]
+    pub addresses: Vec<[u8; 20]>,
+    #[prost(oneof = "FixedBytesOneof", tags = "4, 5")]
+    pub oneof: Option<FixedBytesOneof>,
 }
 
 #[derive(Clone, PartialEq, Oneof)]
-pub enum BasicOneof {
-    #[prost(int32, tag = "8")]
-    Int(i32),
-    #[prost(string, tag = "9")]
-    String(String),
+pub enum FixedBytesOneof {
+    #[prost(bytes = "array", tag = "4")]
+    Short([u8; 4]),
+    #[prost(bytes = "array", tag = "5")]
+    Long([u8; 48]),
+}
+
+#[test]
+fn check_fixed_bytes() {
+    check_message(&FixedBytes::default());
+    assert_eq!(FixedBytes::default().encoded_len(), 0);
+
+    let msg = FixedBytes {
+        hash: [1; 32],
+        signature: Some([2; 64]),
+        addresses: vec![[3; 20], [4; 20]],
+        oneof: Some(FixedBytesOneof::Long([5; 48])),
+    };
+    check_message(&msg);
+
+    // Fixed-size bytes fields are ordinary bytes fields on the wire, but must have the exact length.
+    let mut buf = Vec::new();
+    prost::encoding::bytes::encode(1, &vec![1u8; 31], &mut buf);
+    let error = FixedBytes::decode(&*buf).expect_err("hash is too short");
+    assert_eq!(
+        error.to_string(),
+        "failed to decode Protobuf message: FixedBytes.hash: \
+         invalid bytes length: expected 32 bytes, found 31"
+    );
+}
+
+#[cfg(feature = "std")]
+#[derive(Clone, PartialEq, Message)]
+pub struct Endpoint {
+    #[prost(bytes = "ip_addr", tag = "1")]
+    pub ip: std::net::IpAddr,
+    #[prost(bytes = "socket_addr", optional, tag = "2")]
+    pub socket: Option<std::net::SocketAddr>,
+    #[prost(bytes = "ip_addr", repeated, tag = "3")]
+    pub peers: Vec<std::net::IpAddr>,
+    #[prost(oneof = "EndpointOneof", tags = "4, 5")]
+    pub oneof: Option<EndpointOneof>,
+}
+
+#[cfg(feature = "std")]
+#[derive(Clone, PartialEq, Oneof)]
+pub enum EndpointOneof {
+    #[prost(bytes = "ip_addr", tag = "4")]
+    Ip(std::net::IpAddr),
+    #[prost(bytes = "socket_addr", tag = "5")]
+    Socket(std::net::SocketAddr),
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn check_addresses() {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    check_message(&Endpoint::default());
+    assert_eq!(Endpoint::default().encoded_len(), 0);
+    assert_eq!(Endpoint::default().ip, IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    assert_eq!(
+        Endpoint::default().socket(),
+        SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+    );
+
+    let msg = Endpoint {
+        ip: IpAddr::V6(Ipv6Addr::LOCALHOST),
+        socket: Some(SocketAddr::from(([192, 0, 2, 1], 8080))),
+        peers: vec![
+            IpAddr::from([10, 0, 0, 1]),
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        ],
+        oneof: Some(EndpointOneof::Socket(SocketAddr::from((
+            Ipv6Addr::LOCALHOST,
+            443,
+        )))),
+    };
+    check_message(&msg);
+    assert_eq!(msg.socket(), SocketAddr::from(([192, 0, 2, 1], 8080)));
+
+    // The address family is given by the length of the field.
+    let mut buf = Vec::new();
+    prost::encoding::bytes::encode(1, &vec![127u8, 0, 0, 1], &mut buf);
+    prost::encoding::bytes::encode(2, &vec![0u8; 18], &mut buf);
+    let decoded = Endpoint::decode(&*buf).unwrap();
+    assert_eq!(decoded.ip, IpAddr::from([127, 0, 0, 1]));
+    assert_eq!(
+        decoded.socket,
+        Some(SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)))
+    );
+
+    let mut buf = Vec::new();
+    prost::encoding::bytes::encode(1, &vec![0u8; 6], &mut buf);
+    let error = Endpoint::decode(&*buf).expect_err("invalid address length");
+    assert_eq!(
+        error.to_string(),
+        "failed to decode Protobuf message: Endpoint.ip: \
+         invalid IP address length: expected 4 or 16 bytes, found 6"
+    );
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct NonZeroIds {
+    #[prost(uint32 = "non_zero", optional, tag = "1")]
+    pub id: Option<core::num::NonZeroU32>,
+    #[prost(int64 = "non_zero", repeated, tag = "2")]
+    pub deltas: Vec<core::num::NonZeroI64>,
+    #[prost(uint64 = "non_zero", repeated, packed = "false", tag = "3")]
+    pub parents: Vec<core::num::NonZeroU64>,
+    #[prost(oneof = "NonZeroIdsOneof", tags = "4")]
+    pub oneof: Option<NonZeroIdsOneof>,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+pub enum NonZeroIdsOneof {
+    #[prost(int32 = "non_zero", tag = "4")]
+    Owner(core::num::NonZeroI32),
+}
+
+/// The integer fields which `NonZeroIds` is encoded like.
+#[derive(Clone, PartialEq, Message)]
+pub struct Ids {
+    #[prost(uint32, optional, tag = "1")]
+    pub id: Option<u32>,
+    #[prost(int64, repeated, tag = "2")]
+    pub deltas: Vec<i64>,
+    #[prost(uint64, repeated, packed = "false", tag = "3")]
+    pub parents: Vec<u64>,
+    #[prost(int32, optional, tag = "4")]
+    pub owner: Option<i32>,
+}
+
+#[test]
+fn check_non_zero() {
+    use core::num::{NonZeroI32, NonZeroI64, NonZeroU32, NonZeroU64};
+
+    check_message(&NonZeroIds::default());
+
+    let msg = NonZeroIds {
+        id: NonZeroU32::new(7),
+        deltas: vec![
+            NonZeroI64::new(-1).unwrap(),
+            NonZeroI64::new(i64::MAX).unwrap(),
+        ],
+        parents: vec![NonZeroU64::new(u64::MAX).unwrap()],
+        oneof: Some(NonZeroIdsOneof::Owner(NonZeroI32::new(i32::MIN).unwrap())),
+    };
+    check_message(&msg);
+
+    // Non-zero fields are encoded exactly like their integer counterparts.
+    let ids = Ids {
+        id: Some(7),
+        deltas: vec![-1, i64::MAX],
+        parents: vec![u64::MAX],
+        owner: Some(i32::MIN),
+    };
+    assert_eq!(msg.encode_to_vec(), ids.encode_to_vec());
+
+    // An explicit zero value can not be decoded, and leaves the field unchanged when merged.
+    for (ids, field) in &[
+        (
+            Ids {
+                id: Some(0),
+                ..Ids::default()
+            },
+            "id: invalid NonZeroU32 value: 0",
+        ),
+        (
+            Ids {
+                deltas: vec![3, 0],
+                ..Ids::default()
+            },
+            "deltas: invalid NonZeroI64 value: 0",
+        ),
+        (
+            Ids {
+                parents: vec![0],
+                ..Ids::default()
+            },
+            "parents: invalid NonZeroU64 value: 0",
+        ),
+        (
+            Ids {
+                owner: Some(0),
+                ..Ids::default()
+            },
+            "oneof: invalid NonZeroI32 value: 0",
+        ),
+    ] {
+        let mut merged = msg.clone();
+        let error = merged.merge(&*ids.encode_to_vec()).expect_err("zero value");
+        assert_eq!(
+            error.to_string(),
+            format!("failed to decode Protobuf message: NonZeroIds.{}", field)
+        );
+        assert_eq!(merged.id, msg.id);
+        assert_eq!(merged.oneof, msg.oneof);
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Glyphs {
+    #[prost(uint32 = "char", tag = "1")]
+    pub initial: char,
+    #[prost(uint32 = "char", optional, tag = "2")]
+    pub separator: Option<char>,
+    #[prost(uint32 = "char", repeated, tag = "3")]
+    pub code_points: Vec<char>,
+    #[prost(uint32 = "char", default = "'?'", tag = "4")]
+    pub replacement: char,
+    #[prost(oneof = "GlyphsOneof", tags = "5")]
+    pub oneof: Option<GlyphsOneof>,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+pub enum GlyphsOneof {
+    #[prost(uint32 = "char", tag = "5")]
+    Marker(char),
+}
+
+/// The integer fields which `Glyphs` is encoded like.
+#[derive(Clone, PartialEq, Message)]
+pub struct CodePoints {
+    #[prost(uint32, tag = "1")]
+    pub initial: u32,
+    #[prost(uint32, optional, tag = "2")]
+    pub separator: Option<u32>,
+    #[prost(uint32, repeated, tag = "3")]
+    pub code_points: Vec<u32>,
+    #[prost(uint32, default = "63", tag = "4")]
+    pub replacement: u32,
+    #[prost(uint32, optional, tag = "5")]
+    pub marker: Option<u32>,
+}
+
+#[test]
+fn check_char() {
+    let default = Glyphs::default();
+    check_message(&default);
+    assert_eq!(default.initial, '\0');
+    assert_eq!(default.separator(), '\0');
+    assert_eq!(default.replacement, '?');
+
+    let msg = Glyphs {
+        initial: 'p',
+        separator: Some('\u{a0}'),
+        code_points: vec!['\u{10ffff}', 'é', '\0'],
+        replacement: '\u{fffd}',
+        oneof: Some(GlyphsOneof::Marker('\u{1f980}')),
+    };
+    check_message(&msg);
+
+    // Char fields are encoded exactly like `uint32` fields holding their code points.
+    let code_points = CodePoints {
+        initial: 0x70,
+        separator: Some(0xa0),
+        code_points: vec![0x10_ffff, 0xe9, 0],
+        replacement: 0xfffd,
+        marker: Some(0x1_f980),
+    };
+    assert_eq!(msg.encode_to_vec(), code_points.encode_to_vec());
+
+    // Surrogates and values beyond the last code point can not be decoded, and leave the field
+    // unchanged when merged.
+    for (code_points, field) in &[
+        (
+            CodePoints {
+                initial: 0xd800,
+                ..CodePoints::default()
+            },
+            "initial: invalid char value: 0xd800",
+        ),
+        (
+            CodePoints {
+                separator: Some(0x11_0000),
+                ..CodePoints::default()
+            },
+            "separator: invalid char value: 0x110000",
+        ),
+        (
+            CodePoints {
+                code_points: vec![0x61, 0xdfff],
+                ..CodePoints::default()
+            },
+            "code_points: invalid char value: 0xdfff",
+        ),
+        (
+            CodePoints {
+                marker: Some(u32::MAX),
+                ..CodePoints::default()
+            },
+            "oneof: invalid char value: 0xffffffff",
+        ),
+    ] {
+        let mut merged = msg.clone();
+        let error = merged
+            .merge(&*code_points.encode_to_vec())
+            .expect_err("invalid code point");
+        assert_eq!(
+            error.to_string(),
+            format!("failed to decode Protobuf message: Glyphs.{}", field)
+        );
+        assert_eq!(merged.separator, msg.separator);
+        assert_eq!(merged.oneof, msg.oneof);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Port(u16);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UserId(u64);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserName(String);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Digest(Bytes);
+
+prost::newtype_field!(pub mod port: Port(u16) as uint32;);
+prost::newtype_field!(pub mod user_id: UserId(u64) as uint64;);
+prost::newtype_field!(pub mod user_name: UserName(String) as string;);
+prost::newtype_field!(pub mod digest: Digest(Bytes) as bytes;);
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Newtypes {
+    #[prost(uint32, newtype = "port", tag = "1")]
+    pub port: Port,
+    #[prost(uint64, newtype = "user_id", optional, tag = "2")]
+    pub owner: Option<UserId>,
+    #[prost(uint64, newtype = "user_id", repeated, tag = "3")]
+    pub members: Vec<UserId>,
+    #[prost(string, newtype = "user_name", tag = "4")]
+    pub name: UserName,
+    #[prost(string, newtype = "user_name", repeated, tag = "5")]
+    pub aliases: Vec<UserName>,
+    #[prost(uint32, newtype = "port", repeated, packed = "false", tag = "6")]
+    pub fallback_ports: Vec<Port>,
+    #[prost(oneof = "NewtypesOneof", tags = "7")]
+    pub oneof: Option<NewtypesOneof>,
+    #[prost(bytes = "bytes", newtype = "digest", tag = "8")]
+    pub digest: Digest,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+pub enum NewtypesOneof {
+    #[prost(string, newtype = "user_name", tag = "7")]
+    Admin(UserName),
+}
+
+/// The fields which `Newtypes` is encoded like.
+#[derive(Clone, PartialEq, Message)]
+pub struct Unwrapped {
+    #[prost(uint32, tag = "1")]
+    pub port: u32,
+    #[prost(uint64, optional, tag = "2")]
+    pub owner: Option<u64>,
+    #[prost(uint64, repeated, tag = "3")]
+    pub members: Vec<u64>,
+    #[prost(string, tag = "4")]
+    pub name: String,
+    #[prost(string, repeated, tag = "5")]
+    pub aliases: Vec<String>,
+    #[prost(uint32, repeated, packed = "false", tag = "6")]
+    pub fallback_ports: Vec<u32>,
+    #[prost(string, optional, tag = "7")]
+    pub admin: Option<String>,
+    #[prost(bytes = "bytes", tag = "8")]
+    pub digest: Bytes,
+}
+
+#[test]
+fn check_newtypes() {
+    let default = Newtypes::default();
+    assert_eq!(default.port, Port(0));
+    assert_eq!(default.encoded_len(), 0);
+    check_message(&default);
+
+    let mut msg = Newtypes {
+        port: Port(u16::MAX),
+        owner: Some(UserId(0)),
+        members: vec![UserId(1), UserId(u64::MAX), UserId(0)],
+        name: UserName("name".to_owned()),
+        aliases: vec![UserName("a".to_owned()), UserName(String::new())],
+        fallback_ports: vec![Port(80), Port(0)],
+        oneof: Some(NewtypesOneof::Admin(UserName("root".to_owned()))),
+        digest: Digest(Bytes::from_static(b"\x01\x02")),
+    };
+    check_message(&msg);
+
+    // Newtypes are encoded exactly like the values they wrap.
+    let unwrapped = Unwrapped {
+        port: u32::from(u16::MAX),
+        owner: Some(0),
+        members: vec![1, u64::MAX, 0],
+        name: "name".to_owned(),
+        aliases: vec!["a".to_owned(), String::new()],
+        fallback_ports: vec![80, 0],
+        admin: Some("root".to_owned()),
+        digest: Bytes::from_static(b"\x01\x02"),
+    };
+    assert_eq!(msg.encode_to_vec(), unwrapped.encode_to_vec());
+    assert_eq!(Newtypes::decode(&*unwrapped.encode_to_vec()).unwrap(), msg);
+    assert_eq!(msg.to_json(), unwrapped.to_json());
+    assert_eq!(msg.to_text_format(), unwrapped.to_text_format());
+
+    // Values which do not fit in the newtype can not be decoded.
+    for (unwrapped, field) in &[
+        (
+            Unwrapped {
+                port: 65_536,
+                ..Unwrapped::default()
+            },
+            "port",
+        ),
+        (
+            Unwrapped {
+                fallback_ports: vec![1, u32::MAX],
+                ..Unwrapped::default()
+            },
+            "fallback_ports",
+        ),
+    ] {
+        let error = Newtypes::decode(&*unwrapped.encode_to_vec()).expect_err("out of range");
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "failed to decode Protobuf message: Newtypes.{}: invalid Port value: out of range",
+                field
+            )
+        );
+    }
+
+    msg.clear();
+    assert_eq!(msg, Newtypes::default());
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct OneofMessage {
+    #[prost(int32, tag = "1")]
+    pub before: i32,
+    #[prost(oneof = "ThreeVariants", tags = "2, 3, 4", case)]
+    pub choice: Option<ThreeVariants>,
+    #[prost(int32, tag = "5")]
+    pub after: i32,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+#[prost(case)]
+pub enum ThreeVariants {
+    #[prost(string, tag = "2")]
+    Name(String),
+    #[prost(int64, tag = "3")]
+    Id(i64),
+    #[prost(message, tag = "4")]
+    Nested(Basic),
+}
+
+#[test]
+fn check_oneof() {
+    check_message(&OneofMessage::default());
+    for choice in [
+        ThreeVariants::Name("name".to_owned()),
+        ThreeVariants::Id(-7),
+        ThreeVariants::Nested(Basic {
+            int32: 3,
+            ..Basic::default()
+        }),
+    ] {
+        let msg = OneofMessage {
+            before: 1,
+            choice: Some(choice),
+            after: 2,
+        };
+        check_message(&msg);
+        // Only the active variant is encoded.
+        assert_eq!(
+            msg.encoded_len(),
+            4 + msg.choice.as_ref().unwrap().encoded_len()
+        );
+    }
+
+    // The last variant on the wire wins, and unknown tags next to the oneof are skipped.
+    let mut buf = Vec::new();
+    prost::encoding::string::encode(2, &"name".to_owned(), &mut buf);
+    prost::encoding::uint32::encode(6, &99, &mut buf);
+    prost::encoding::int64::encode(3, &5, &mut buf);
+    let msg = OneofMessage::decode(&*buf).unwrap();
+    assert_eq!(msg.choice, Some(ThreeVariants::Id(5)));
+}
+
+/// A oneof without the `case` attribute, so its case enum does not clash with this type.
+#[derive(Clone, PartialEq, Oneof)]
+pub enum Shape {
+    #[prost(uint32, tag = "1")]
+    Circle(u32),
+    #[prost(uint32, tag = "2")]
+    Square(u32),
+}
+
+pub struct ShapeCase;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Drawing {
+    #[prost(oneof = "Shape", tags = "1, 2")]
+    pub shape: Option<Shape>,
+}
+
+#[test]
+fn check_oneof_case() {
+    let mut msg = OneofMessage::default();
+    assert_eq!(msg.which_choice(), None);
+
+    msg.choice = Some(ThreeVariants::Id(5));
+    assert_eq!(msg.which_choice(), Some(ThreeVariantsCase::Id));
+    assert_eq!(ThreeVariantsCase::Id.tag(), 3);
+    assert_eq!(msg.choice.as_ref().unwrap().case(), ThreeVariantsCase::Id);
+
+    msg.choice = Some(ThreeVariants::Nested(Basic::default()));
+    assert_eq!(msg.which_choice(), Some(ThreeVariantsCase::Nested));
+    assert_eq!(ThreeVariantsCase::Nested.tag(), 4);
+
+    // Clearing the oneof leaves the other fields alone.
+    msg.before = 1;
+    msg.clear_choice();
+    assert_eq!(msg.choice, None);
+    assert_eq!(msg.which_choice(), None);
+    assert_eq!(msg.before, 1);
+
+    let mut drawing = Drawing {
+        shape: Some(Shape::Square(2)),
+    };
+    drawing.clear_shape();
+    assert_eq!(drawing, Drawing::default());
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct LegacyGroups {
+    #[prost(group, optional, tag = "1")]
+    pub single: Option<Basic>,
+    #[prost(group, repeated, tag = "2")]
+    pub repeated: Vec<Basic>,
+}
+
+#[test]
+fn check_groups() {
+    let msg = LegacyGroups {
+        single: Some(Basic {
+            int32: 1,
+            ..Basic::default()
+        }),
+        repeated: vec![
+            Basic::default(),
+            Basic {
+                string: "two".to_owned(),
+                ..Basic::default()
+            },
+        ],
+    };
+    check_message(&msg);
+
+    // Group 1 containing Basic.int32 = 1.
+    let buf: &[u8] = &[0x0b, 0x08, 0x01, 0x0c];
+    assert_eq!(LegacyGroups::decode(buf).unwrap().single.unwrap().int32, 1);
+
+    // Group 1 closed by the end group key of group 2.
+    let buf: &[u8] = &[0x0b, 0x08, 0x01, 0x14];
+    let error = LegacyGroups::decode(buf).expect_err("mismatched end group");
+    assert!(error.to_string().contains("unexpected end group tag"));
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CachedRecursive {
+    #[prost(message, optional, boxed, tag = "1")]
+    pub child: Option<Box<CachedRecursive>>,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(cached_size)]
+    pub cached_size: prost::CachedSize,
+}
+
+#[test]
+fn check_cached_size() {
+    let mut msg = CachedRecursive::default();
+    for _ in 0..20 {
+        msg = CachedRecursive {
+            child: Some(Box::new(msg)),
+            name: "nested".to_owned(),
+            ..CachedRecursive::default()
+        };
+    }
+    assert_eq!(msg.cached_size.get(), None);
+    check_message(&msg);
+
+    let len = msg.encoded_len();
+    assert_eq!(msg.cached_size.get(), Some(len));
+    let child = msg.child.as_ref().unwrap();
+    assert_eq!(child.cached_size.get(), Some(child.encoded_len()));
+
+    // Modifications are picked up by the next encode, which recomputes the length.
+    msg.child.as_mut().unwrap().name = "a longer name".to_owned();
+    let buf = msg.encode_to_vec();
+    assert_eq!(buf.len(), msg.encoded_len());
+    assert_eq!(CachedRecursive::decode(&*buf).unwrap(), msg);
+
+    // `encode_raw` does not trust lengths cached before a modification.
+    msg.encoded_len();
+    msg.child.as_mut().unwrap().name.clear();
+    let mut buf = Vec::new();
+    msg.encode_raw(&mut buf);
+    assert_eq!(buf.len(), msg.encoded_len());
+    assert_eq!(CachedRecursive::decode(&*buf).unwrap(), msg);
+
+    // The cached size does not take part in comparisons.
+    let mut fresh = msg.clone();
+    fresh.cached_size = prost::CachedSize::new();
+    assert_eq!(fresh, msg);
+
+    // Saturated lengths are cached like any other.
+    let cached = prost::CachedSize::new();
+    cached.set(usize::MAX);
+    assert_eq!(cached.get(), Some(usize::MAX));
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct BoxedFields {
+    #[prost(string = "boxed", tag = "1")]
+    pub name: Box<str>,
+    #[prost(bytes = "boxed", tag = "2")]
+    pub payload: Box<[u8]>,
+    #[prost(string = "boxed", optional, tag = "3", default = "unnamed")]
+    pub alias: Option<Box<str>>,
+    #[prost(bytes = "boxed", repeated, tag = "4")]
+    pub chunks: Vec<Box<[u8]>>,
+    #[prost(string = "boxed", tag = "5", default = "fallback")]
+    pub with_default: Box<str>,
+}
+
+#[test]
+fn check_boxed_fields() {
+    let default = BoxedFields::default();
+    assert_eq!(&*default.with_default, "fallback");
+    assert_eq!(default.alias(), "unnamed");
+    check_message(&default);
+
+    let mut msg = BoxedFields {
+        name: "name".into(),
+        payload: vec![1, 2, 3].into_boxed_slice(),
+        alias: Some("alias".into()),
+        chunks: vec![Box::from(&b"chunk"[..]), Box::default()],
+        with_default: "".into(),
+    };
+    check_message(&msg);
+    let decoded = BoxedFields::decode(&*msg.encode_to_vec()).unwrap();
+    assert_eq!(decoded, msg);
+    assert_eq!(decoded.alias(), "alias");
+
+    msg.clear();
+    assert_eq!(msg, BoxedFields::default());
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SharedStrings {
+    #[prost(string = "arc", tag = "1")]
+    pub name: Arc<str>,
+    #[prost(string = "arc", optional, tag = "2", default = "unnamed")]
+    pub alias: Option<Arc<str>>,
+    #[prost(string = "arc", repeated, tag = "3")]
+    pub labels: Vec<Arc<str>>,
+    #[prost(string = "arc", tag = "4", default = "fallback")]
+    pub with_default: Arc<str>,
+    #[prost(message, repeated, tag = "5")]
+    pub children: Vec<SharedStrings>,
+}
+
+#[test]
+fn check_shared_strings() {
+    let default = SharedStrings::default();
+    assert_eq!(&*default.with_default, "fallback");
+    assert_eq!(default.alias(), "unnamed");
+    check_message(&default);
+
+    let mut msg = SharedStrings {
+        name: "name".into(),
+        alias: Some("alias".into()),
+        labels: vec!["a".into(), "".into(), "a".into()],
+        with_default: "".into(),
+        children: vec![SharedStrings {
+            labels: vec!["a".into()],
+            ..SharedStrings::default()
+        }],
+    };
+    check_message(&msg);
+    let decoded = SharedStrings::decode(&*msg.encode_to_vec()).unwrap();
+    assert_eq!(decoded, msg);
+    assert_eq!(decoded.alias(), "alias");
+    // Without an interner, each value has its own allocation.
+    assert!(!Arc::ptr_eq(&decoded.labels[0], &decoded.labels[2]));
+
+    msg.clear();
+    assert_eq!(msg, SharedStrings::default());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn check_interned_strings() {
+    use prost::encoding::DecodeContext;
+    use prost::Interner;
+
+    let msg = SharedStrings {
+        name: "a".into(),
+        labels: vec!["a".into(), "b".into(), "a".into()],
+        children: vec![SharedStrings {
+            labels: vec!["b".into()],
+            ..SharedStrings::default()
+        }],
+        ..SharedStrings::default()
+    };
+    let encoded = msg.encode_to_vec();
+
+    let interner = Interner::new();
+    let mut decoded = SharedStrings::default();
+    decoded
+        .merge_with_context(&*encoded, DecodeContext::default().with_interner(&interner))
+        .unwrap();
+    assert_eq!(decoded, msg);
+    assert_eq!(interner.len(), 2);
+    assert!(Arc::ptr_eq(&decoded.name, &decoded.labels[0]));
+    assert!(Arc::ptr_eq(&decoded.labels[0], &decoded.labels[2]));
+    assert!(Arc::ptr_eq(
+        &decoded.labels[1],
+        &decoded.children[0].labels[0]
+    ));
+
+    // The set is shared by every message decoded with it.
+    let mut again = SharedStrings::default();
+    again
+        .merge_with_context(&*encoded, DecodeContext::default().with_interner(&interner))
+        .unwrap();
+    assert!(Arc::ptr_eq(&again.name, &decoded.name));
+    assert_eq!(interner.len(), 2);
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SharedParent {
+    #[prost(message, optional, tag = "1")]
+    pub shared: Option<Arc<Basic>>,
+    #[prost(message, repeated, tag = "2")]
+    pub repeated: Vec<Arc<Basic>>,
+}
+
+#[test]
+fn check_arc_message() {
+    let child = Arc::new(Basic {
+        int32: 1,
+        ..Basic::default()
+    });
+    let parent = SharedParent {
+        shared: Some(child.clone()),
+        repeated: vec![child.clone(), child.clone()],
+    };
+    check_message(&parent);
+
+    // Merging into a shared message copies it rather than modifying the other references.
+    let mut merged = parent.clone();
+    let update = SharedParent {
+        shared: Some(Arc::new(Basic {
+            string: "merged".to_owned(),
+            ..Basic::default()
+        })),
+        repeated: Vec::new(),
+    };
+    merged.merge(&*update.encode_to_vec()).unwrap();
+    assert_eq!(merged.shared.as_ref().unwrap().int32, 1);
+    assert_eq!(merged.shared.as_ref().unwrap().string, "merged");
+    assert_eq!(child.string, "");
+    assert_eq!(parent.shared.as_ref().unwrap().string, "");
+    assert!(Arc::ptr_eq(&merged.repeated[0], &child));
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct InlineChildren {
+    #[prost(message, repeated, tag = "1")]
+    pub children: smallvec::SmallVec<[Basic; 2]>,
+    #[prost(message, repeated, tag = "2")]
+    pub shared: smallvec::SmallVec<[Arc<Basic>; 1]>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct HeapChildren {
+    #[prost(message, repeated, tag = "1")]
+    pub children: Vec<Basic>,
+    #[prost(message, repeated, tag = "2")]
+    pub shared: Vec<Arc<Basic>>,
+}
+
+#[test]
+fn check_small_vec_messages() {
+    let child = |int32| Basic {
+        int32,
+        ..Basic::default()
+    };
+    let mut msg = InlineChildren {
+        children: smallvec::smallvec![child(1), child(2)],
+        shared: smallvec::smallvec![Arc::new(child(3))],
+    };
+    check_message(&msg);
+
+    // A `SmallVec` field is encoded exactly like a `Vec` field.
+    let heap = HeapChildren {
+        children: vec![child(1), child(2)],
+        shared: vec![Arc::new(child(3))],
+    };
+    let encoded = msg.encode_to_vec();
+    assert_eq!(encoded, heap.encode_to_vec());
+    assert_eq!(msg.encoded_len(), heap.encoded_len());
+
+    let decoded = InlineChildren::decode(&*encoded).unwrap();
+    assert_eq!(decoded, msg);
+    assert!(!decoded.children.spilled());
+    assert!(!decoded.shared.spilled());
+
+    // Elements beyond the inline capacity are moved to the heap.
+    msg.merge(&*encoded).unwrap();
+    assert_eq!(msg.children.len(), 4);
+    assert!(msg.children.spilled());
+    assert_eq!(msg.shared.len(), 2);
+
+    msg.clear();
+    assert_eq!(msg, InlineChildren::default());
+}
+
+/// A protobuf enum.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, PartialEq, Enumeration)]
+pub enum BasicEnumeration {
+    ZERO = 0,
+    ONE = 1,
+    TWO = 2,
+    THREE = 3,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Basic {
+    #[prost(int32, tag = "1")]
+    pub int32: i32,
+
+    #[prost(bool, repeated, packed = "false", tag = "2")]
+    pub bools: Vec<bool>,
+
+    #[prost(string, tag = "3")]
+    pub string: String,
+
+    #[prost(string, optional, tag = "4")]
+    pub optional_string: Option<String>,
+
+    #[prost(enumeration = "BasicEnumeration", tag = "5")]
+    pub enumeration: i32,
+
+    #[prost(map = "int32, enumeration(BasicEnumeration)", tag = "6")]
+    #[cfg(feature = "std")]
+    pub enumeration_map: ::std::collections::HashMap<i32, i32>,
+
+    #[prost(hash_map = "string, string", tag = "7")]
+    #[cfg(feature = "std")]
+    pub string_map: ::std::collections::HashMap<String, String>,
+
+    #[prost(btree_map = "int32, enumeration(BasicEnumeration)", tag = "10")]
+    pub enumeration_btree_map: prost::alloc::collections::BTreeMap<i32, i32>,
+
+    #[prost(btree_map = "string, string", tag = "11")]
+    pub string_btree_map: prost::alloc::collections::BTreeMap<String, String>,
+
+    #[prost(oneof = "BasicOneof", tags = "8, 9")]
+    pub oneof: Option<BasicOneof>,
+
+    #[prost(map = "string, bytes", tag = "12")]
+    #[cfg(feature = "std")]
+    pub bytes_map: ::std::collections::HashMap<String, Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Compound {
+    #[prost(message, optional, tag = "1")]
+    pub optional_message: Option<Basic>,
+
+    #[prost(message, required, tag = "2")]
+    pub required_message: Basic,
+
+    #[prost(message, repeated, tag = "3")]
+    pub repeated_message: Vec<Basic>,
+
+    #[prost(map = "sint32, message", tag = "4")]
+    #[cfg(feature = "std")]
+    pub message_map: ::std::collections::HashMap<i32, Basic>,
+
+    #[prost(btree_map = "sint32, message", tag = "5")]
+    pub message_btree_map: prost::alloc::collections::BTreeMap<i32, Basic>,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+pub enum BasicOneof {
+    #[prost(int32, tag = "8")]
+    Int(i32),
+    #[prost(string, tag = "9")]
+    String(String),
+}
+
+/// A newer version of `ReservedTags`, which reuses a tag the older version retired.
+#[derive(Clone, PartialEq, Message)]
+pub struct ReusedTag {
+    #[prost(int32, tag = "1")]
+    pub id: i32,
+    #[prost(string, tag = "2")]
+    pub reused: String,
+    #[prost(int32, tag = "4")]
+    pub unknown: i32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+#[prost(reserved_tags = "2, 9 to 11")]
+pub struct ReservedTags {
+    #[prost(int32, tag = "1")]
+    pub id: i32,
+}
+
+#[test]
+fn check_reserved_tags() {
+    // Unknown tags which are not reserved are still skipped.
+    let msg = ReusedTag {
+        id: 1,
+        unknown: 4,
+        ..ReusedTag::default()
+    };
+    assert_eq!(
+        ReservedTags::decode(&*msg.encode_to_vec()).unwrap(),
+        ReservedTags { id: 1 }
+    );
+
+    let msg = ReusedTag {
+        id: 1,
+        reused: "reused".to_string(),
+        ..ReusedTag::default()
+    };
+    let error = ReservedTags::decode(&*msg.encode_to_vec()).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "failed to decode Protobuf message: field tag 2 is reserved"
+    );
+
+    // Tag 10, wire type varint.
+    ReservedTags::decode(&[0x50, 0x01][..]).expect_err("reserved tag within a range");
+}
+
+/// An older version of `AliasedTags`, before its fields were moved to other tags.
+#[derive(Clone, PartialEq, Message)]
+pub struct PreviousTags {
+    #[prost(string, tag = "5")]
+    pub name: String,
+    #[prost(int32, repeated, tag = "6")]
+    pub ids: Vec<i32>,
+    #[prost(int32, repeated, tag = "7")]
+    pub legacy_ids: Vec<i32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AliasedTags {
+    #[prost(string, alias_tag = "5", tag = "1")]
+    pub name: String,
+    #[prost(int32, repeated, alias_tag = "6, 7", tag = "2")]
+    pub ids: Vec<i32>,
+}
+
+#[test]
+fn check_alias_tags() {
+    let previous = PreviousTags {
+        name: "name".to_owned(),
+        ids: vec![1, 2],
+        legacy_ids: vec![3],
+    };
+    let msg = AliasedTags::decode(&*previous.encode_to_vec()).unwrap();
+    assert_eq!(
+        msg,
+        AliasedTags {
+            name: "name".to_owned(),
+            ids: vec![1, 2, 3],
+        }
+    );
+
+    // Fields are only encoded with their own tags.
+    let mut buf = Vec::new();
+    prost::encoding::string::encode(1, &msg.name, &mut buf);
+    prost::encoding::int32::encode_packed(2, &msg.ids, &mut buf);
+    assert_eq!(msg.encode_to_vec(), buf);
+    assert_eq!(
+        PreviousTags::decode(&*buf).unwrap(),
+        PreviousTags::default()
+    );
+
+    // The own and alias tags of a field are decoded like any repeated occurrence of the field.
+    prost::encoding::string::encode(5, &"alias".to_owned(), &mut buf);
+    prost::encoding::int32::encode(6, &4, &mut buf);
+    let msg = AliasedTags::decode(&*buf).unwrap();
+    assert_eq!(msg.name, "alias");
+    assert_eq!(msg.ids, [1, 2, 3, 4]);
+
+    // Alias tags are checked against the field's wire types. Tag 5, wire type varint.
+    let error = AliasedTags::decode(&[0x28, 0x01][..]).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "failed to decode Protobuf message: AliasedTags.name: \
+         invalid wire type for tag 5: Varint (expected LengthDelimited)"
+    );
+
+    // A field allowed by `decode_fields` is also decoded from its alias tags.
+    let buf = previous.encode_to_vec();
+    let msg = AliasedTags::decode_fields(&*buf, &[2]).unwrap();
+    assert_eq!(
+        msg,
+        AliasedTags {
+            name: String::new(),
+            ids: vec![1, 2, 3],
+        }
+    );
+    assert_eq!(
+        AliasedTags::decode_fields(&*buf, &[5, 6, 7]).unwrap(),
+        AliasedTags::default()
+    );
+}
+
+/// A message which reports an arbitrary encoded length, standing in for the very large messages
+/// whose lengths do not fit in a `usize` on 32-bit targets.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Huge {
+    len: usize,
+}
+
+const HUGE: Huge = Huge {
+    len: usize::MAX - 1,
+};
+
+impl Message for Huge {
+    fn encode_raw<B>(&self, _buf: &mut B)
+    where
+        B: prost::bytes::BufMut,
+    {
+        unreachable!("Huge can not be encoded");
+    }
+
+    fn merge_field<B>(
+        &mut self,
+        tag: u32,
+        wire_type: prost::encoding::WireType,
+        buf: &mut B,
+        ctx: prost::encoding::DecodeContext,
+    ) -> Result<(), prost::DecodeError>
+    where
+        B: prost::bytes::Buf,
+    {
+        prost::encoding::skip_field(wire_type, tag, buf, ctx)
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.len
+    }
+
+    fn clear(&mut self) {}
+}
+
+impl ToJson for Huge {
+    fn write_json(&self, writer: &mut prost::json::JsonWriter) {
+        writer.begin_object();
+        writer.end_object();
+    }
+}
+
+impl ToTextFormat for Huge {
+    fn write_text_format(&self, _writer: &mut prost::text_format::TextFormatWriter) {}
+}
+
+impl prost::reflect::Reflect for Huge {
+    fn reflect(&self, _visitor: &mut dyn prost::reflect::FieldVisitor) {}
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ContainsHuge {
+    #[prost(message, optional, tag = "1")]
+    pub single: Option<Huge>,
+    #[prost(message, repeated, tag = "2")]
+    pub repeated: Vec<Huge>,
+    #[prost(btree_map = "int32, message", tag = "3")]
+    pub map: prost::alloc::collections::BTreeMap<i32, Huge>,
+}
+
+#[test]
+fn check_encoded_len_saturates() {
+    let messages = [
+        ContainsHuge {
+            single: Some(HUGE),
+            ..ContainsHuge::default()
+        },
+        ContainsHuge {
+            repeated: vec![HUGE, HUGE],
+            ..ContainsHuge::default()
+        },
+        ContainsHuge {
+            map: (1..3).map(|key| (key, HUGE)).collect(),
+            ..ContainsHuge::default()
+        },
+    ];
+    for message in messages.iter() {
+        assert_eq!(message.encoded_len(), usize::MAX);
+
+        let error = message.encode(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.required_capacity(), usize::MAX);
+        message
+            .encode_length_delimited(&mut Vec::new())
+            .unwrap_err();
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Credentials {
+    #[prost(string, required, tag = "1")]
+    pub user: String,
+    #[prost(bytes = "vec", optional, tag = "2")]
+    pub token: Option<Vec<u8>>,
+    #[prost(presence)]
+    pub presence: prost::Presence,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Login {
+    #[prost(message, required, tag = "1")]
+    pub credentials: Credentials,
+    #[prost(message, repeated, tag = "2")]
+    pub fallbacks: Vec<Credentials>,
+    #[prost(oneof = "LoginMethod", tags = "3, 4")]
+    pub method: Option<LoginMethod>,
+    #[prost(presence)]
+    pub presence: prost::Presence,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+pub enum LoginMethod {
+    #[prost(string, tag = "3")]
+    Password(String),
+    #[prost(message, tag = "4")]
+    Delegated(Credentials),
+}
+
+#[test]
+fn check_initialized() {
+    fn credentials(user: &str) -> Credentials {
+        let mut credentials = Credentials {
+            user: user.to_owned(),
+            ..Credentials::default()
+        };
+        credentials.presence.insert(1);
+        credentials
+    }
+
+    fn missing(buf: &[u8]) -> String {
+        Login::decode(buf)
+            .unwrap()
+            .check_initialized()
+            .expect_err("missing required field")
+            .to_string()
+    }
+
+    let mut login = Login {
+        credentials: credentials("alice"),
+        fallbacks: vec![credentials("bob")],
+        method: Some(LoginMethod::Delegated(credentials(""))),
+        ..Login::default()
+    };
+    login.presence.insert(1);
+    login.check_initialized().unwrap();
+
+    // Required fields are always encoded, so their presence survives a round trip, even when
+    // they hold their default value.
+    let buf = login.encode_to_vec();
+    let decoded = Login::decode(&*buf).unwrap();
+    assert_eq!(decoded, login);
+    decoded.check_initialized().unwrap();
+
+    assert_eq!(
+        missing(&[]),
+        "failed to decode Protobuf message: Login.credentials: missing required field"
+    );
+    assert_eq!(
+        missing(&[0x0a, 0x00]),
+        "failed to decode Protobuf message: Credentials.user: Login.credentials: \
+         missing required field"
+    );
+    assert_eq!(
+        missing(&[0x0a, 0x02, 0x0a, 0x00, 0x12, 0x00]),
+        "failed to decode Protobuf message: Credentials.user: Login.fallbacks: \
+         missing required field"
+    );
+    assert_eq!(
+        missing(&[0x0a, 0x02, 0x0a, 0x00, 0x22, 0x00]),
+        "failed to decode Protobuf message: Credentials.user: LoginMethod.Delegated: \
+         Login.method: missing required field"
+    );
+
+    // Clearing the message forgets which fields were set.
+    login.clear();
+    login.check_initialized().unwrap_err();
+}
+
+#[test]
+fn check_merge_repeated_reuse() {
+    use prost::bytes::Buf;
+    use prost::encoding::{decode_key, message, DecodeContext};
+
+    let decode = |messages: &[Basic], items: &mut Vec<Basic>, pool: &mut Vec<Basic>| {
+        let mut buf = Vec::new();
+        message::encode_repeated(1, messages, &mut buf);
+        let mut buf = &buf[..];
+        while buf.has_remaining() {
+            let (_, wire_type) = decode_key(&mut buf).unwrap();
+            message::merge_repeated_reuse(
+                wire_type,
+                items,
+                pool,
+                &mut buf,
+                DecodeContext::default(),
+            )
+            .unwrap();
+        }
+    };
+
+    let first = vec![
+        Basic {
+            int32: 1,
+            bools: vec![true; 16],
+            string: "first".to_owned(),
+            ..Basic::default()
+        };
+        2
+    ];
+    let second = vec![
+        Basic {
+            string: "second".to_owned(),
+            ..Basic::default()
+        },
+        Basic::default(),
+        Basic {
+            int32: 3,
+            ..Basic::default()
+        },
+    ];
+
+    let mut items = Vec::new();
+    let mut pool = Vec::new();
+    decode(&first, &mut items, &mut pool);
+    assert_eq!(items, first);
+
+    // The pooled messages are cleared before they are reused, and keep their capacity.
+    pool.append(&mut items);
+    decode(&second, &mut items, &mut pool);
+    assert_eq!(items, second);
+    assert!(pool.is_empty());
+    assert!(items[..2].iter().all(|item| item.bools.capacity() >= 16));
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Singular {
+    #[prost(int32, tag = "1")]
+    pub int32: i32,
+    #[prost(string, tag = "2")]
+    pub string: String,
+    #[prost(bytes = "vec", optional, tag = "3")]
+    pub bytes: Option<Vec<u8>>,
+    #[prost(fixed64, tag = "4")]
+    pub fixed64: u64,
+    #[prost(message, optional, tag = "5")]
+    pub message: Option<Basic>,
+    #[prost(oneof = "SingularChoice", tags = "6, 7")]
+    pub choice: Option<SingularChoice>,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+pub enum SingularChoice {
+    #[prost(string, tag = "6")]
+    Name(String),
+    #[prost(int64, tag = "7")]
+    Id(i64),
+}
+
+#[test]
+fn check_repeated_singular_fields() {
+    use prost::encoding::{bytes, fixed64, int32, message, string};
+
+    let mut buf = Vec::new();
+    for value in &[1, -2, 3] {
+        int32::encode(1, value, &mut buf);
+    }
+    for value in &["first", "second"] {
+        string::encode(2, &value.to_string(), &mut buf);
+    }
+    bytes::encode(3, &b"long first value".to_vec(), &mut buf);
+    bytes::encode(3, &b"short".to_vec(), &mut buf);
+    fixed64::encode(4, &u64::MAX, &mut buf);
+    fixed64::encode(4, &0, &mut buf);
+    let first = Basic {
+        int32: 1,
+        string: "first".to_owned(),
+        ..Basic::default()
+    };
+    let second = Basic {
+        int32: 2,
+        bools: vec![true],
+        ..Basic::default()
+    };
+    message::encode(5, &first, &mut buf);
+    message::encode(5, &second, &mut buf);
+    string::encode(6, &"name".to_owned(), &mut buf);
+    prost::encoding::int64::encode(7, &-7, &mut buf);
+
+    // The last occurrence of a scalar field wins, including within a oneof, while occurrences of
+    // a message field are merged.
+    assert_eq!(
+        Singular::decode(&*buf).unwrap(),
+        Singular {
+            int32: 3,
+            string: "second".to_owned(),
+            bytes: Some(b"short".to_vec()),
+            fixed64: 0,
+            message: Some(Basic {
+                int32: 2,
+                string: "first".to_owned(),
+                bools: vec![true],
+                ..Basic::default()
+            }),
+            choice: Some(SingularChoice::Id(-7)),
+        }
+    );
+}
+
+#[test]
+fn check_out_of_order_fields() {
+    let msg = Singular {
+        int32: 1,
+        string: "two".to_owned(),
+        bytes: Some(vec![3]),
+        fixed64: 4,
+        message: Some(Basic {
+            int32: 5,
+            ..Basic::default()
+        }),
+        choice: Some(SingularChoice::Name("six".to_owned())),
+    };
+
+    // Split the encoding into its fields, and decode them in reverse and in a shuffled order.
+    let buf = msg.encode_to_vec();
+    let fields = prost::RawFieldIter::new(&buf)
+        .map(|field| field.unwrap().raw.to_vec())
+        .collect::<Vec<_>>();
+    assert_eq!(fields.len(), 6);
+    for order in &[[5, 4, 3, 2, 1, 0], [2, 0, 1, 5, 3, 4]] {
+        let reordered = order
+            .iter()
+            .flat_map(|&i| fields[i].iter().copied())
+            .collect::<Vec<u8>>();
+        assert_eq!(Singular::decode(&*reordered).unwrap(), msg);
+    }
+}
+
+#[test]
+fn check_empty_length_delimited_fields() {
+    // Each empty field is followed by another field, which must still be decoded.
+    let buf = [
+        0x1a, 0x00, 0x08, 0x01, 0x2a, 0x00, 0x21, 0, 0, 0, 0, 0, 0, 0, 0x80, 0x32, 0x00,
+    ];
+    let msg = Singular::decode(&buf[..]).unwrap();
+    assert_eq!(
+        msg,
+        Singular {
+            int32: 1,
+            bytes: Some(Vec::new()),
+            fixed64: 1 << 63,
+            message: Some(Basic::default()),
+            choice: Some(SingularChoice::Name(String::new())),
+            ..Singular::default()
+        }
+    );
+
+    // Empty values of fields with explicit presence are distinct from absent fields, and are
+    // re-encoded, while an empty string without a label is indistinguishable from an absent one.
+    assert_eq!(
+        msg.encode_to_vec(),
+        [0x08, 0x01, 0x1a, 0x00, 0x21, 0, 0, 0, 0, 0, 0, 0, 0x80, 0x2a, 0x00, 0x32, 0x00]
+    );
+    assert_eq!(
+        Singular::decode(&[0x12, 0x00][..]).unwrap(),
+        Singular::default()
+    );
+    assert_eq!(Singular::default().encoded_len(), 0);
+
+    // An empty message is a valid length-delimited message.
+    let mut buf = &[0x00, 0x08, 0x02][..];
+    assert_eq!(
+        Singular::decode_length_delimited(&mut buf).unwrap(),
+        Singular::default()
+    );
+    assert_eq!(buf, [0x08, 0x02]);
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Envelope {
+    #[prost(string, tag = "1")]
+    pub destination: String,
+    #[prost(message, optional, tag = "2")]
+    pub payload: Option<prost::LazyField<Basic>>,
+}
+
+#[test]
+fn check_lazy_field() {
+    let payload = Basic {
+        int32: 7,
+        string: "payload".to_owned(),
+        ..Basic::default()
+    };
+    let msg = Envelope {
+        destination: "upstream".to_owned(),
+        payload: Some(payload.clone().into()),
+    };
+    let encoded = Bytes::from(msg.encode_to_vec());
+
+    // The payload is stored undecoded, as a slice of the source buffer, and re-encoded verbatim.
+    let mut decoded = Envelope::decode(encoded.clone()).unwrap();
+    let lazy = decoded.payload.as_mut().unwrap();
+    let raw = lazy.encoded().expect("payload decoded eagerly");
+    let source = encoded.as_ptr_range();
+    assert!(source.contains(&raw.as_ptr()));
+    assert_eq!(decoded.encode_to_vec(), encoded);
+    assert_eq!(decoded, msg);
+
+    let lazy = decoded.payload.as_mut().unwrap();
+    assert_eq!(*lazy.get_mut().unwrap(), payload);
+    lazy.get_mut().unwrap().int32 = 8;
+    let reencoded = Envelope::decode(&*decoded.encode_to_vec()).unwrap();
+    assert_eq!(reencoded.payload.unwrap().into_inner().unwrap().int32, 8);
+}
+
+#[derive(Clone, PartialEq, Message)]
+#[prost(try_from_bytes)]
+pub struct Reading {
+    #[prost(int32, tag = "1")]
+    pub value: i32,
+    #[prost(string, tag = "2")]
+    pub unit: String,
+}
+
+#[test]
+fn check_try_from_slice() {
+    use core::convert::{TryFrom, TryInto};
+
+    fn decode_generic<'a, M>(buf: &'a [u8]) -> Result<M, prost::DecodeError>
+    where
+        M: TryFrom<&'a [u8], Error = prost::DecodeError>,
+    {
+        buf.try_into()
+    }
+
+    let msg = Reading {
+        value: 42,
+        unit: "kPa".to_string(),
+    };
+    let buf = msg.encode_to_vec();
+    assert_eq!(Reading::try_from(&buf[..]).unwrap(), msg);
+    assert_eq!(decode_generic::<Reading>(&buf).unwrap(), msg);
+    decode_generic::<Reading>(&buf[..buf.len() - 1]).expect_err("truncated message");
 }
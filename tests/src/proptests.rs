@@ -0,0 +1,353 @@
+//! Property tests which round trip messages holding every kind of field through their encoding.
+
+use proptest::prelude::*;
+use prost::alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
+use prost::bytes::Bytes;
+use prost::{Message, Oneof};
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Scalars {
+    #[prost(int32, tag = "1")]
+    pub int32: i32,
+    #[prost(int64, tag = "2")]
+    pub int64: i64,
+    #[prost(uint32, tag = "3")]
+    pub uint32: u32,
+    #[prost(uint64, tag = "4")]
+    pub uint64: u64,
+    #[prost(sint32, tag = "5")]
+    pub sint32: i32,
+    #[prost(sint64, tag = "6")]
+    pub sint64: i64,
+    #[prost(fixed32, tag = "7")]
+    pub fixed32: u32,
+    #[prost(fixed64, tag = "8")]
+    pub fixed64: u64,
+    #[prost(sfixed32, tag = "9")]
+    pub sfixed32: i32,
+    #[prost(sfixed64, tag = "10")]
+    pub sfixed64: i64,
+    #[prost(float, tag = "11")]
+    pub float: f32,
+    #[prost(double, tag = "12")]
+    pub double: f64,
+    #[prost(bool, tag = "13")]
+    pub bool: bool,
+    #[prost(string, tag = "14")]
+    pub string: String,
+    #[prost(bytes = "vec", tag = "15")]
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Fields {
+    #[prost(message, optional, tag = "1")]
+    pub scalars: Option<Scalars>,
+    #[prost(sint64, optional, tag = "2")]
+    pub optional_sint64: Option<i64>,
+    #[prost(double, optional, tag = "3")]
+    pub optional_double: Option<f64>,
+    #[prost(string, optional, tag = "4")]
+    pub optional_string: Option<String>,
+    #[prost(int32, repeated, tag = "5")]
+    pub packed_int32: Vec<i32>,
+    #[prost(sint64, repeated, tag = "6")]
+    pub packed_sint64: Vec<i64>,
+    #[prost(fixed32, repeated, tag = "7")]
+    pub packed_fixed32: Vec<u32>,
+    #[prost(float, repeated, tag = "8")]
+    pub packed_float: Vec<f32>,
+    #[prost(uint64, repeated, packed = "false", tag = "9")]
+    pub unpacked_uint64: Vec<u64>,
+    #[prost(sfixed64, repeated, packed = "false", tag = "10")]
+    pub unpacked_sfixed64: Vec<i64>,
+    #[prost(string, repeated, tag = "11")]
+    pub strings: Vec<String>,
+    #[prost(bytes = "bytes", repeated, tag = "12")]
+    pub bytes: Vec<Bytes>,
+    #[prost(message, repeated, tag = "13")]
+    pub messages: Vec<Scalars>,
+    #[prost(btree_map = "string, sint32", tag = "14")]
+    pub scalar_map: BTreeMap<String, i32>,
+    #[prost(btree_map = "int64, message", tag = "15")]
+    pub message_map: BTreeMap<i64, Scalars>,
+    #[prost(oneof = "Choice", tags = "16, 17, 18")]
+    pub choice: Option<Choice>,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+pub enum Choice {
+    #[prost(sfixed32, tag = "16")]
+    Number(i32),
+    #[prost(string, tag = "17")]
+    Text(String),
+    #[prost(message, tag = "18")]
+    Message(Scalars),
+}
+
+/// Generates integers, choosing the boundary values half of the time.
+macro_rules! integer {
+    ($ty:ty) => {
+        prop_oneof![
+            proptest::sample::select(vec![0, 1, <$ty>::MIN, <$ty>::MAX]),
+            any::<$ty>(),
+        ]
+    };
+}
+
+/// Generates floating point numbers other than NaN, which is not equal to itself, choosing the
+/// boundary values half of the time.
+macro_rules! float {
+    ($ty:ident) => {
+        prop_oneof![
+            proptest::sample::select(vec![
+                0.0,
+                -0.0,
+                <$ty>::MIN,
+                <$ty>::MAX,
+                <$ty>::MIN_POSITIVE,
+                <$ty>::INFINITY,
+                <$ty>::NEG_INFINITY,
+            ]),
+            proptest::num::$ty::POSITIVE
+                | proptest::num::$ty::NEGATIVE
+                | proptest::num::$ty::NORMAL
+                | proptest::num::$ty::SUBNORMAL
+                | proptest::num::$ty::ZERO
+                | proptest::num::$ty::INFINITE,
+        ]
+    };
+}
+
+/// Generates collections, which are empty half of the time.
+fn collection<S>(element: S) -> impl Strategy<Value = Vec<S::Value>>
+where
+    S: Strategy,
+    S::Value: Clone,
+{
+    prop_oneof![Just(Vec::new()), proptest::collection::vec(element, 1..8)]
+}
+
+fn string() -> impl Strategy<Value = String> {
+    prop_oneof![Just(String::new()), any::<String>()]
+}
+
+prop_compose! {
+    fn integers()(
+        int32 in integer!(i32),
+        int64 in integer!(i64),
+        uint32 in integer!(u32),
+        uint64 in integer!(u64),
+        sint32 in integer!(i32),
+        sint64 in integer!(i64),
+        fixed32 in integer!(u32),
+        fixed64 in integer!(u64),
+        sfixed32 in integer!(i32),
+        sfixed64 in integer!(i64),
+    ) -> Scalars {
+        Scalars {
+            int32,
+            int64,
+            uint32,
+            uint64,
+            sint32,
+            sint64,
+            fixed32,
+            fixed64,
+            sfixed32,
+            sfixed64,
+            ..Scalars::default()
+        }
+    }
+}
+
+prop_compose! {
+    fn scalars()(
+        integers in integers(),
+        float in float!(f32),
+        double in float!(f64),
+        bool in any::<bool>(),
+        string in string(),
+        bytes in collection(any::<u8>()),
+    ) -> Scalars {
+        Scalars {
+            float,
+            double,
+            bool,
+            string,
+            bytes,
+            ..integers
+        }
+    }
+}
+
+fn choice() -> impl Strategy<Value = Choice> {
+    prop_oneof![
+        integer!(i32).prop_map(Choice::Number),
+        string().prop_map(Choice::Text),
+        scalars().prop_map(Choice::Message),
+    ]
+}
+
+prop_compose! {
+    fn singular_fields()(
+        scalars in proptest::option::of(scalars()),
+        optional_sint64 in proptest::option::of(integer!(i64)),
+        optional_double in proptest::option::of(float!(f64)),
+        optional_string in proptest::option::of(string()),
+        choice in proptest::option::of(choice()),
+    ) -> Fields {
+        Fields {
+            scalars,
+            optional_sint64,
+            optional_double,
+            optional_string,
+            choice,
+            ..Fields::default()
+        }
+    }
+}
+
+prop_compose! {
+    fn fields()(
+        singular in singular_fields(),
+        packed_int32 in collection(integer!(i32)),
+        packed_sint64 in collection(integer!(i64)),
+        packed_fixed32 in collection(integer!(u32)),
+        packed_float in collection(float!(f32)),
+        unpacked_uint64 in collection(integer!(u64)),
+        unpacked_sfixed64 in collection(integer!(i64)),
+        strings in collection(string()),
+        bytes in collection(collection(any::<u8>()).prop_map(Bytes::from)),
+        messages in collection(scalars()),
+        scalar_map in collection((string(), integer!(i32))),
+        message_map in collection((integer!(i64), scalars())),
+    ) -> Fields {
+        Fields {
+            packed_int32,
+            packed_sint64,
+            packed_fixed32,
+            packed_float,
+            unpacked_uint64,
+            unpacked_sfixed64,
+            strings,
+            bytes,
+            messages,
+            scalar_map: scalar_map.into_iter().collect(),
+            message_map: message_map.into_iter().collect(),
+            ..singular
+        }
+    }
+}
+
+/// Returns `b` if it is set, or `a` otherwise, as proto3 scalars holding their default value are
+/// not encoded.
+fn last_set<T>(a: T, b: T) -> T
+where
+    T: Default + PartialEq,
+{
+    if b == T::default() {
+        a
+    } else {
+        b
+    }
+}
+
+/// Merges `b` into `a` field by field, as the Protobuf specification describes the result of
+/// decoding the concatenation of their encodings.
+fn merge_scalars(a: Scalars, b: Scalars) -> Scalars {
+    Scalars {
+        int32: last_set(a.int32, b.int32),
+        int64: last_set(a.int64, b.int64),
+        uint32: last_set(a.uint32, b.uint32),
+        uint64: last_set(a.uint64, b.uint64),
+        sint32: last_set(a.sint32, b.sint32),
+        sint64: last_set(a.sint64, b.sint64),
+        fixed32: last_set(a.fixed32, b.fixed32),
+        fixed64: last_set(a.fixed64, b.fixed64),
+        sfixed32: last_set(a.sfixed32, b.sfixed32),
+        sfixed64: last_set(a.sfixed64, b.sfixed64),
+        float: last_set(a.float, b.float),
+        double: last_set(a.double, b.double),
+        bool: last_set(a.bool, b.bool),
+        string: last_set(a.string, b.string),
+        bytes: last_set(a.bytes, b.bytes),
+    }
+}
+
+/// Like `merge_scalars`: singular fields are replaced, optional fields are replaced if set,
+/// repeated fields are concatenated, map entries are replaced by key, and message fields,
+/// including a message held by both oneofs, are merged recursively.
+fn merge_fields(a: Fields, b: Fields) -> Fields {
+    fn concat<T>(mut a: Vec<T>, b: Vec<T>) -> Vec<T> {
+        a.extend(b);
+        a
+    }
+    fn extend<K: Ord, V>(mut a: BTreeMap<K, V>, b: BTreeMap<K, V>) -> BTreeMap<K, V> {
+        a.extend(b);
+        a
+    }
+    Fields {
+        scalars: match (a.scalars, b.scalars) {
+            (Some(a), Some(b)) => Some(merge_scalars(a, b)),
+            (a, b) => b.or(a),
+        },
+        optional_sint64: b.optional_sint64.or(a.optional_sint64),
+        optional_double: b.optional_double.or(a.optional_double),
+        optional_string: b.optional_string.or(a.optional_string),
+        packed_int32: concat(a.packed_int32, b.packed_int32),
+        packed_sint64: concat(a.packed_sint64, b.packed_sint64),
+        packed_fixed32: concat(a.packed_fixed32, b.packed_fixed32),
+        packed_float: concat(a.packed_float, b.packed_float),
+        unpacked_uint64: concat(a.unpacked_uint64, b.unpacked_uint64),
+        unpacked_sfixed64: concat(a.unpacked_sfixed64, b.unpacked_sfixed64),
+        strings: concat(a.strings, b.strings),
+        bytes: concat(a.bytes, b.bytes),
+        messages: concat(a.messages, b.messages),
+        scalar_map: extend(a.scalar_map, b.scalar_map),
+        message_map: extend(a.message_map, b.message_map),
+        choice: match (a.choice, b.choice) {
+            (Some(Choice::Message(a)), Some(Choice::Message(b))) => {
+                Some(Choice::Message(merge_scalars(a, b)))
+            }
+            (a, b) => b.or(a),
+        },
+    }
+}
+
+proptest! {
+    #[test]
+    fn roundtrip_scalars(msg in scalars()) {
+        let buf = msg.encode_to_vec();
+        prop_assert_eq!(buf.len(), msg.encoded_len());
+        prop_assert_eq!(Scalars::decode(&*buf).unwrap(), msg);
+    }
+
+    #[test]
+    fn roundtrip_fields(msg in fields()) {
+        let buf = msg.encode_to_vec();
+        prop_assert_eq!(buf.len(), msg.encoded_len());
+        prop_assert_eq!(&Fields::decode(&*buf).unwrap(), &msg);
+
+        let canonical = msg.encode_canonical_to_vec();
+        prop_assert_eq!(canonical.len(), buf.len());
+        prop_assert_eq!(&Fields::decode(&*canonical).unwrap(), &msg);
+
+        let delimited = msg.encode_length_delimited_to_vec();
+        prop_assert_eq!(Fields::decode_length_delimited(&*delimited).unwrap(), msg);
+    }
+
+    // Decoding the concatenation of two encodings is the same as merging them. The strategies
+    // are boxed, as two unboxed `fields()` value trees overflow the test thread's stack.
+    #[test]
+    fn concatenation_merges(a in fields().boxed(), b in fields().boxed()) {
+        let mut buf = a.encode_to_vec();
+        b.encode(&mut buf).unwrap();
+        let expected = merge_fields(a.clone(), b.clone());
+        prop_assert_eq!(&Fields::decode(&*buf).unwrap(), &expected);
+
+        let mut merged = a;
+        merged.merge(&*b.encode_to_vec()).unwrap();
+        prop_assert_eq!(&merged, &expected);
+    }
+}
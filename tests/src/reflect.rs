@@ -0,0 +1,177 @@
+//! Tests for visiting the fields of messages with `Reflect::reflect`.
+
+use core::fmt::Display;
+
+use prost::alloc::collections::BTreeMap;
+use prost::alloc::{borrow::ToOwned, boxed::Box, format, string::String, vec, vec::Vec};
+use prost::reflect::{FieldVisitor, Reflect};
+use prost::{Enumeration, Message, Oneof};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enumeration)]
+pub enum Kind {
+    #[prost(name = "KIND_UNSPECIFIED")]
+    Unspecified = 0,
+    #[prost(name = "KIND_LEAF")]
+    Leaf = 1,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+pub enum Payload {
+    #[prost(string, tag = "10")]
+    PayloadText(String),
+    #[prost(message, tag = "11")]
+    PayloadNode(Box<Node>),
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Node {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(enumeration = "Kind", tag = "2")]
+    pub kind: i32,
+    #[prost(sint64, repeated, tag = "3")]
+    pub values: Vec<i64>,
+    #[prost(bytes = "vec", tag = "4")]
+    pub data: Vec<u8>,
+    #[prost(message, repeated, tag = "5")]
+    pub children: Vec<Node>,
+    #[prost(btree_map = "string, int32", tag = "6")]
+    pub counts: BTreeMap<String, i32>,
+    #[prost(double, optional, tag = "7")]
+    pub weight: Option<f64>,
+    #[prost(oneof = "Payload", tags = "10, 11")]
+    pub payload: Option<Payload>,
+}
+
+/// Records the visited fields as lines of text, recursing into embedded messages.
+#[derive(Default)]
+struct Recorder {
+    lines: Vec<String>,
+    depth: usize,
+}
+
+impl Recorder {
+    fn record(&mut self, tag: u32, name: &str, value: impl Display) {
+        let indent = "  ".repeat(self.depth);
+        self.lines
+            .push(format!("{}{} {}: {}", indent, tag, name, value));
+    }
+}
+
+impl FieldVisitor for Recorder {
+    fn visit_bool(&mut self, tag: u32, name: &str, value: bool) {
+        self.record(tag, name, value);
+    }
+    fn visit_i32(&mut self, tag: u32, name: &str, value: i32) {
+        self.record(tag, name, value);
+    }
+    fn visit_i64(&mut self, tag: u32, name: &str, value: i64) {
+        self.record(tag, name, value);
+    }
+    fn visit_u32(&mut self, tag: u32, name: &str, value: u32) {
+        self.record(tag, name, value);
+    }
+    fn visit_u64(&mut self, tag: u32, name: &str, value: u64) {
+        self.record(tag, name, value);
+    }
+    fn visit_f32(&mut self, tag: u32, name: &str, value: f32) {
+        self.record(tag, name, value);
+    }
+    fn visit_f64(&mut self, tag: u32, name: &str, value: f64) {
+        self.record(tag, name, value);
+    }
+    fn visit_str(&mut self, tag: u32, name: &str, value: &str) {
+        self.record(tag, name, format!("{:?}", value));
+    }
+    fn visit_bytes(&mut self, tag: u32, name: &str, value: &[u8]) {
+        self.record(tag, name, format!("{:?}", value));
+    }
+    fn visit_enum(&mut self, tag: u32, name: &str, value: i32, variant: Option<&'static str>) {
+        self.record(tag, name, format!("{} ({:?})", value, variant));
+    }
+    fn visit_message(&mut self, tag: u32, name: &str, value: &dyn Reflect) {
+        self.record(tag, name, "{");
+        self.depth += 1;
+        value.reflect(self);
+        self.depth -= 1;
+    }
+    fn begin_repeated(&mut self, tag: u32, name: &str, len: usize) {
+        self.record(tag, name, format!("[{}]", len));
+    }
+    fn end_repeated(&mut self, tag: u32, name: &str) {
+        self.record(tag, name, "end");
+    }
+}
+
+fn reflect(message: &dyn Reflect) -> Vec<String> {
+    let mut recorder = Recorder::default();
+    message.reflect(&mut recorder);
+    recorder.lines
+}
+
+fn leaf(name: &str) -> Node {
+    Node {
+        name: name.to_owned(),
+        kind: Kind::Leaf as i32,
+        ..Node::default()
+    }
+}
+
+#[test]
+fn default_fields_are_skipped() {
+    assert_eq!(reflect(&Node::default()), Vec::<String>::new());
+}
+
+#[test]
+fn fields_in_tag_order() {
+    let node = Node {
+        name: "root".to_owned(),
+        kind: 7,
+        values: vec![-1, 2],
+        data: vec![0, 255],
+        children: vec![leaf("a")],
+        counts: vec![("x".to_owned(), 0), ("y".to_owned(), 3)]
+            .into_iter()
+            .collect(),
+        weight: Some(0.0),
+        payload: Some(Payload::PayloadNode(Box::new(leaf("b")))),
+    };
+    assert_eq!(
+        reflect(&node),
+        [
+            "1 name: \"root\"",
+            "2 kind: 7 (None)",
+            "3 values: [2]",
+            "3 values: -1",
+            "3 values: 2",
+            "3 values: end",
+            "4 data: [0, 255]",
+            "5 children: [1]",
+            "5 children: {",
+            "  1 name: \"a\"",
+            "  2 kind: 1 (Some(\"KIND_LEAF\"))",
+            "5 children: end",
+            "6 counts: [2]",
+            "6 counts: {",
+            "  1 key: \"x\"",
+            "  2 value: 0",
+            "6 counts: {",
+            "  1 key: \"y\"",
+            "  2 value: 3",
+            "6 counts: end",
+            "7 weight: 0",
+            "11 payload_node: {",
+            "  1 name: \"b\"",
+            "  2 kind: 1 (Some(\"KIND_LEAF\"))",
+        ]
+    );
+}
+
+#[test]
+fn wrapper_types() {
+    // The well-known wrapper types have a single field, named `value`.
+    assert_eq!(reflect(&42u32), ["1 value: 42"]);
+    assert_eq!(reflect(&"text".to_owned()), ["1 value: \"text\""]);
+    assert_eq!(reflect(&0i64), Vec::<String>::new());
+    assert_eq!(reflect(&()), Vec::<String>::new());
+}
@@ -0,0 +1,142 @@
+//! Tests for serializing messages to the Protobuf text format with `ToTextFormat::to_text_format`.
+
+use prost::alloc::collections::BTreeMap;
+use prost::alloc::{borrow::ToOwned, boxed::Box, string::String, vec, vec::Vec};
+use prost::text_format::ToTextFormat;
+use prost::{Enumeration, Message, Oneof, UnknownFieldList};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enumeration)]
+pub enum Kind {
+    #[prost(name = "KIND_UNSPECIFIED")]
+    Unspecified = 0,
+    #[prost(name = "KIND_LEAF")]
+    Leaf = 1,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+pub enum Payload {
+    #[prost(string, tag = "10")]
+    PayloadText(String),
+    #[prost(message, tag = "11")]
+    PayloadNode(Box<Node>),
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Node {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(enumeration = "Kind", tag = "2")]
+    pub kind: i32,
+    #[prost(sint64, repeated, tag = "3")]
+    pub values: Vec<i64>,
+    #[prost(bytes = "vec", tag = "4")]
+    pub data: Vec<u8>,
+    #[prost(message, repeated, tag = "5")]
+    pub children: Vec<Node>,
+    #[prost(btree_map = "string, int32", tag = "6")]
+    pub counts: BTreeMap<String, i32>,
+    #[prost(double, optional, tag = "7")]
+    pub weight: Option<f64>,
+    #[prost(oneof = "Payload", tags = "10, 11")]
+    pub payload: Option<Payload>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PartialNode {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(unknown_fields)]
+    pub unknown_fields: UnknownFieldList,
+}
+
+fn leaf(name: &str) -> Node {
+    Node {
+        name: name.to_owned(),
+        kind: Kind::Leaf as i32,
+        ..Node::default()
+    }
+}
+
+#[test]
+fn text_format() {
+    let mut counts = BTreeMap::new();
+    counts.insert("a".to_owned(), 1);
+    counts.insert("b".to_owned(), 0);
+    let node = Node {
+        name: "root".to_owned(),
+        values: vec![-1, 2],
+        data: b"\x00\"\n".to_vec(),
+        children: vec![leaf("first"), Node::default()],
+        counts,
+        weight: Some(0.0),
+        payload: Some(Payload::PayloadNode(Box::new(leaf("nested")))),
+        ..Node::default()
+    };
+    assert_eq!(
+        node.to_text_format(),
+        r#"name: "root"
+values: -1
+values: 2
+data: "\000\"\n"
+children {
+  name: "first"
+  kind: KIND_LEAF
+}
+children {
+}
+counts {
+  key: "a"
+  value: 1
+}
+counts {
+  key: "b"
+  value: 0
+}
+weight: 0
+payload_node {
+  name: "nested"
+  kind: KIND_LEAF
+}
+"#
+    );
+
+    assert_eq!(Node::default().to_text_format(), "");
+    let node = Node {
+        kind: 7,
+        payload: Some(Payload::PayloadText(String::new())),
+        ..Node::default()
+    };
+    assert_eq!(node.to_text_format(), "kind: 7\npayload_text: \"\"\n");
+}
+
+#[test]
+fn text_format_unknown_fields() {
+    let node = Node {
+        name: "root".to_owned(),
+        kind: Kind::Leaf as i32,
+        data: vec![1, 2],
+        children: vec![leaf("child")],
+        ..Node::default()
+    };
+    let partial = PartialNode::decode(&*node.encode_to_vec()).unwrap();
+    assert_eq!(
+        partial.to_text_format(),
+        "name: \"root\"\n2: 1\n4: \"\\001\\002\"\n5: \"\\n\\005child\\020\\001\"\n"
+    );
+}
+
+#[test]
+fn text_format_well_known_types() {
+    let descriptor = prost_types::FieldDescriptorProto {
+        name: Some("id".to_owned()),
+        number: Some(1),
+        r#type: Some(prost_types::field_descriptor_proto::Type::Int64 as i32),
+        ..prost_types::FieldDescriptorProto::default()
+    };
+    assert_eq!(
+        descriptor.to_text_format(),
+        "name: \"id\"\nnumber: 1\ntype: TYPE_INT64\n"
+    );
+    assert_eq!(42u32.to_text_format(), "value: 42\n");
+    assert_eq!(0u32.to_text_format(), "");
+}
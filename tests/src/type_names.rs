@@ -0,0 +1,19 @@
+use prost::Name;
+
+mod type_names {
+    include!(concat!(env!("OUT_DIR"), "/type_names.v1.rs"));
+}
+
+#[test]
+fn test_type_names() {
+    assert_eq!(type_names::Outer::NAME, "Outer");
+    assert_eq!(type_names::Outer::PACKAGE, "type_names.v1");
+    assert_eq!(type_names::Outer::full_name(), "type_names.v1.Outer");
+
+    assert_eq!(type_names::outer::Inner::NAME, "Outer.Inner");
+    assert_eq!(type_names::outer::Inner::PACKAGE, "type_names.v1");
+    assert_eq!(
+        type_names::outer::Inner::type_url(),
+        "type.googleapis.com/type_names.v1.Outer.Inner"
+    );
+}
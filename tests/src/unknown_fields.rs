@@ -0,0 +1,151 @@
+//! Tests for retaining unknown fields with `#[prost(unknown_fields)]`.
+
+use prost::alloc::{borrow::ToOwned, string::String, vec, vec::Vec};
+use prost::bytes::Bytes;
+use prost::{Message, UnknownField, UnknownFieldList};
+
+/// A newer version of a message, with fields the older version does not know about.
+#[derive(Clone, PartialEq, Message)]
+pub struct NewVersion {
+    #[prost(int32, tag = "1")]
+    pub id: i32,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(uint64, tag = "3")]
+    pub added_varint: u64,
+    #[prost(bytes = "vec", tag = "4")]
+    pub added_bytes: Vec<u8>,
+    #[prost(fixed32, tag = "5")]
+    pub added_fixed32: u32,
+    #[prost(fixed64, tag = "6")]
+    pub added_fixed64: u64,
+}
+
+/// An older version of `NewVersion`, which retains the fields it does not know about.
+#[derive(Clone, PartialEq, Message)]
+pub struct OldVersion {
+    #[prost(int32, tag = "1")]
+    pub id: i32,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(unknown_fields)]
+    pub unknown_fields: UnknownFieldList,
+}
+
+/// An older version of `NewVersion`, which discards the fields it does not know about.
+#[derive(Clone, PartialEq, Message)]
+pub struct OldVersionWithoutUnknownFields {
+    #[prost(int32, tag = "1")]
+    pub id: i32,
+    #[prost(string, tag = "2")]
+    pub name: String,
+}
+
+fn new_version() -> NewVersion {
+    NewVersion {
+        id: 42,
+        name: "forty two".to_owned(),
+        added_varint: 300,
+        added_bytes: vec![1, 2, 3],
+        added_fixed32: 7,
+        added_fixed64: 8,
+    }
+}
+
+#[test]
+fn unknown_fields_round_trip() {
+    let buf = new_version().encode_to_vec();
+
+    let mut old = OldVersion::decode(&*buf).unwrap();
+    assert_eq!(old.id, 42);
+    assert_eq!(old.name, "forty two");
+    assert_eq!(
+        old.unknown_fields.iter().collect::<Vec<_>>(),
+        vec![
+            (3, &UnknownField::Varint(300)),
+            (
+                4,
+                &UnknownField::LengthDelimited(Bytes::from_static(&[1, 2, 3]))
+            ),
+            (5, &UnknownField::ThirtyTwoBit(7)),
+            (6, &UnknownField::SixtyFourBit(8)),
+        ]
+    );
+
+    // Mutating a known field does not disturb the unknown fields.
+    old.id = 43;
+    let reencoded = old.encode_to_vec();
+    assert_eq!(reencoded.len(), old.encoded_len());
+
+    let new = NewVersion::decode(&*reencoded).unwrap();
+    assert_eq!(
+        new,
+        NewVersion {
+            id: 43,
+            ..new_version()
+        }
+    );
+}
+
+#[test]
+fn unknown_groups_round_trip() {
+    // Field 7 is a group containing a varint field 1 and a nested group 2 with a varint field 3.
+    let buf: &[u8] = &[0x3b, 0x08, 0x01, 0x13, 0x18, 0x02, 0x14, 0x3c];
+    let msg = OldVersion::decode(buf).unwrap();
+
+    let mut inner = UnknownFieldList::new();
+    inner.push(3, UnknownField::Varint(2));
+    let mut group = UnknownFieldList::new();
+    group.push(1, UnknownField::Varint(1));
+    group.push(2, UnknownField::Group(inner));
+    assert_eq!(
+        msg.unknown_fields.iter().collect::<Vec<_>>(),
+        vec![(7, &UnknownField::Group(group))]
+    );
+    assert_eq!(msg.encode_to_vec(), buf);
+}
+
+#[test]
+fn unknown_fields_are_opt_in() {
+    let buf = new_version().encode_to_vec();
+    let old = OldVersionWithoutUnknownFields::decode(&*buf).unwrap();
+    assert_eq!(
+        old.encode_to_vec(),
+        NewVersion {
+            id: 42,
+            name: "forty two".to_owned(),
+            ..NewVersion::default()
+        }
+        .encode_to_vec()
+    );
+}
+
+#[test]
+fn unknown_fields_clear() {
+    let mut old = OldVersion::decode(&*new_version().encode_to_vec()).unwrap();
+    assert!(!old.unknown_fields.is_empty());
+    old.id = 0;
+    old.name.clear();
+    assert!(!old.is_default());
+    old.clear();
+    assert!(old.unknown_fields.is_empty());
+    assert!(old.is_default());
+    assert_eq!(old, OldVersion::default());
+}
+
+#[test]
+fn unknown_fields_eq_ignores_order_between_tags() {
+    let varint: &[u8] = &[0x18, 0xac, 0x02];
+    let bytes: &[u8] = &[0x22, 0x03, 0x01, 0x02, 0x03];
+    let fixed32: &[u8] = &[0x2d, 0x07, 0x00, 0x00, 0x00];
+
+    let ordered = OldVersion::decode(&*[varint, bytes, fixed32].concat()).unwrap();
+    let reordered = OldVersion::decode(&*[fixed32, varint, bytes].concat()).unwrap();
+    assert_ne!(ordered.encode_to_vec(), reordered.encode_to_vec());
+    assert_eq!(ordered, reordered);
+
+    // The order of values sharing a tag is significant, as for a repeated field.
+    let first = OldVersion::decode(&[0x18, 0x01, 0x18, 0x02][..]).unwrap();
+    let second = OldVersion::decode(&[0x18, 0x02, 0x18, 0x01][..]).unwrap();
+    assert_ne!(first, second);
+}